@@ -1,5 +1,13 @@
-use criterion::{criterion_group, criterion_main, Criterion};
-use std::fs;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use arrow2::{
+    array::{Float64Array, Int64Array, Utf8Array},
+    chunk::Chunk,
+    datatypes::{DataType, Field, Schema},
+};
+use maw::csv_in::{CsvConfig, CsvReader};
+use maw::writer_parquet::{ParquetWriter, ParquetWriterConfig};
+use parquet2::compression::Compression;
+use std::{fs, sync::Arc};
 use tempfile::tempdir;
 
 fn create_test_csv_data(rows: usize) -> String {
@@ -30,5 +38,76 @@ fn benchmark_csv_processing(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_csv_processing);
+fn benchmark_read_buffer(c: &mut Criterion) {
+    let temp_dir = tempdir().unwrap();
+    let input_file = temp_dir.path().join("input.csv");
+    fs::write(&input_file, create_test_csv_data(100_000)).unwrap();
+    
+    // Reading through the parser's small reads versus a 4 MB buffer
+    let mut group = c.benchmark_group("read_buffer");
+    for read_buffer in [None, Some(4 * 1024 * 1024)] {
+        let label = read_buffer.map_or("none".to_string(), |bytes| format!("{}MB", bytes / (1024 * 1024)));
+        let config = CsvConfig {
+            delimiter: Some(b','),
+            read_buffer,
+            ..CsvConfig::default()
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(label), &config, |b, config| {
+            b.iter(|| {
+                let mut reader = CsvReader::new(&input_file, config).unwrap();
+                while reader.read_batch().unwrap().is_some() {}
+            })
+        });
+    }
+    group.finish();
+}
+
+fn benchmark_parquet_encode_threads(c: &mut Criterion) {
+    let temp_dir = tempdir().unwrap();
+    let output_file = temp_dir.path().join("output.parquet");
+    let schema = Arc::new(Schema::from(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("value", DataType::Float64, false),
+        Field::new("score", DataType::Int64, false),
+    ]));
+    let rows = 100_000;
+    let batch = Chunk::new(vec![
+        Int64Array::from_vec((0..rows).collect()).boxed(),
+        Utf8Array::<i32>::from_iter_values((0..rows).map(|i| format!("name_{}", i))).boxed(),
+        Float64Array::from_vec((0..rows).map(|i| i as f64 * 1.5).collect()).boxed(),
+        Int64Array::from_vec((0..rows).map(|i| i % 100).collect()).boxed(),
+    ]);
+
+    // zstd at a high level, where compressing is most of the work
+    let mut group = c.benchmark_group("parquet_encode_threads");
+    group.sample_size(10);
+    for encode_threads in [1, 4] {
+        let config = ParquetWriterConfig {
+            compression: Compression::Zstd,
+            zstd_level: 9,
+            row_group_size: 4 * 1024 * 1024,
+            overwrite: true,
+            encode_threads,
+            ..ParquetWriterConfig::default()
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(encode_threads), &config, |b, config| {
+            b.iter(|| {
+                let mut writer = ParquetWriter::new(&output_file, schema.clone(), config).unwrap();
+                for _ in 0..10 {
+                    writer.write_batch(&batch).unwrap();
+                }
+                writer.finish().unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_csv_processing,
+    benchmark_read_buffer,
+    benchmark_parquet_encode_threads
+);
 criterion_main!(benches);
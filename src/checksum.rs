@@ -0,0 +1,223 @@
+use crate::error::{MawError, Result};
+use arrow2::{
+    array::{get_display, Array},
+    chunk::Chunk,
+    datatypes::Schema,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use xxhash_rust::xxh3::{xxh3_128, Xxh3};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Xxh3,
+}
+
+impl ChecksumAlgorithm {
+    /// Extension of the sidecar file written next to each output.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Xxh3 => "xxh3",
+        }
+    }
+}
+
+enum Hasher {
+    Sha256(Box<Sha256>),
+    Xxh3(Box<Xxh3>),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Box::default()),
+            ChecksumAlgorithm::Xxh3 => Hasher::Xxh3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+            Hasher::Xxh3(hasher) => hasher.update(bytes),
+        }
+    }
+
+    fn hex_digest(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            Hasher::Xxh3(hasher) => format!("{:016x}", hasher.digest()),
+        }
+    }
+}
+
+/// A writer that hashes every byte passed through to the inner writer, so
+/// an output's checksum is known without reading the file back.
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Option<(ChecksumAlgorithm, Hasher)>,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W, algorithm: Option<ChecksumAlgorithm>) -> Self {
+        Self {
+            inner,
+            hasher: algorithm.map(|a| (a, Hasher::new(a))),
+        }
+    }
+
+    /// Writes the `<output>.<algorithm>` sidecar for `path`, in the same
+    /// `<hex>  <file name>` layout as `sha256sum`, and returns the inner
+    /// writer. Does nothing beyond unwrapping when no checksum was requested.
+    pub fn finish(self, path: &Path) -> Result<W> {
+        if let Some((algorithm, hasher)) = self.hasher {
+            let file_name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            fs::write(
+                sidecar_path(path, algorithm),
+                format!("{}  {}\n", hasher.hex_digest(), file_name),
+            )?;
+        }
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some((_, hasher)) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An order-independent hash of a dataset (`--content-hash`). Each row is
+/// hashed on its own, with its columns taken by name, and the row hashes
+/// are summed, so the same rows give the same digest whatever order the
+/// inputs, rows or columns come in. A sum rather than an XOR keeps
+/// duplicated rows from cancelling each other out.
+pub struct ContentHasher {
+    /// Column indices sorted by column name
+    order: Vec<usize>,
+    /// Name and type of each column, hashed with its values
+    labels: Vec<String>,
+    sum: u128,
+    rows: u64,
+}
+
+impl ContentHasher {
+    pub fn new(schema: &Schema) -> Self {
+        let mut order: Vec<usize> = (0..schema.fields.len()).collect();
+        order.sort_by(|&a, &b| schema.fields[a].name.cmp(&schema.fields[b].name));
+        let labels = schema
+            .fields
+            .iter()
+            .map(|f| format!("{}:{:?}", f.name, f.data_type))
+            .collect();
+
+        Self {
+            order,
+            labels,
+            sum: 0,
+            rows: 0,
+        }
+    }
+
+    pub fn update(&mut self, chunk: &Chunk<Box<dyn Array>>) -> Result<()> {
+        let displays: Vec<_> = chunk
+            .arrays()
+            .iter()
+            .map(|array| get_display(array.as_ref(), ""))
+            .collect();
+        let mut row_bytes = Vec::new();
+        let mut value = String::new();
+
+        for row in 0..chunk.len() {
+            row_bytes.clear();
+            for &column in &self.order {
+                // Lengths keep values from running into each other
+                let label = &self.labels[column];
+                row_bytes.extend_from_slice(&(label.len() as u64).to_le_bytes());
+                row_bytes.extend_from_slice(label.as_bytes());
+                if chunk.arrays()[column].is_null(row) {
+                    row_bytes.push(0);
+                    continue;
+                }
+                value.clear();
+                displays[column](&mut value, row).map_err(|e| MawError::Arrow(e.to_string()))?;
+                row_bytes.push(1);
+                row_bytes.extend_from_slice(&(value.len() as u64).to_le_bytes());
+                row_bytes.extend_from_slice(value.as_bytes());
+            }
+            self.sum = self.sum.wrapping_add(xxh3_128(&row_bytes));
+        }
+
+        self.rows += chunk.len() as u64;
+        Ok(())
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    pub fn hex_digest(&self) -> String {
+        format!("{:032x}", self.sum)
+    }
+}
+
+/// Path of the checksum sidecar for an output file, e.g. `out.csv.sha256`.
+pub fn sidecar_path(path: &Path, algorithm: ChecksumAlgorithm) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(algorithm.extension());
+    PathBuf::from(sidecar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_checksum_writer() {
+        let temp_dir = tempdir().unwrap();
+        let output = temp_dir.path().join("out.csv");
+
+        let mut writer = ChecksumWriter::new(Vec::new(), Some(ChecksumAlgorithm::Sha256));
+        writer.write_all(b"a,b\n").unwrap();
+        writer.write_all(b"1,2\n").unwrap();
+        let bytes = writer.finish(&output).unwrap();
+
+        let expected: String = Sha256::digest(&bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let sidecar = fs::read_to_string(temp_dir.path().join("out.csv.sha256")).unwrap();
+        assert_eq!(sidecar, format!("{}  out.csv\n", expected));
+
+        let mut writer = ChecksumWriter::new(Vec::new(), Some(ChecksumAlgorithm::Xxh3));
+        writer.write_all(&bytes).unwrap();
+        writer.finish(&output).unwrap();
+
+        let sidecar = fs::read_to_string(temp_dir.path().join("out.csv.xxh3")).unwrap();
+        let expected = format!("{:016x}", xxhash_rust::xxh3::xxh3_64(&bytes));
+        assert!(sidecar.starts_with(&expected));
+    }
+}
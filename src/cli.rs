@@ -1,7 +1,101 @@
-use clap::{Parser, ValueEnum};
+use crate::{checksum::ChecksumAlgorithm, discover::FileFormat, fsutil::is_stdout};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+/// Top-level parser: either a subcommand, or the `concat` arguments given
+/// directly as in `maw a.csv b.csv -o out.parquet`.
+#[derive(Parser)]
+#[command(
+    name = "maw",
+    about = "A high-performance CLI for streaming and concatenating CSV and Parquet files",
+    version = env!("CARGO_PKG_VERSION"),
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct App {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub cli: Cli,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Concatenate inputs into one output (the default)
+    Concat(Cli),
+    /// Convert a single input file to another format
+    Convert(Cli),
+    /// Print the unified schema and per-file row counts without writing output
+    Inspect(Cli),
+    /// Print per-column null counts and value ranges without writing output
+    Stats(Cli),
+    /// Join two inputs on a key column
+    Join(JoinArgs),
+    /// Print the first rows of the inputs as a table
+    Head(PreviewArgs),
+    /// Print the last rows of the inputs as a table
+    Tail(PreviewArgs),
+}
+
+/// Arguments of `join`, which takes its two inputs by name rather than
+/// positionally; the other options apply to both sides.
+#[derive(Args)]
+#[command(mut_arg("inputs", |arg| arg.required(false).hide(true)))]
+pub struct JoinArgs {
+    /// Left input file, directory, or glob
+    #[arg(long)]
+    pub left: String,
+
+    /// Right input file, directory, or glob. Its columns named like a left
+    /// column get a `_right` suffix
+    #[arg(long)]
+    pub right: String,
+
+    /// Key column to join on, present in both inputs
+    #[arg(long)]
+    pub on: String,
+
+    /// Which rows to keep
+    #[arg(long, value_enum, default_value = "inner")]
+    pub join_type: JoinType,
+
+    #[command(flatten)]
+    pub cli: Cli,
+}
+
+/// Arguments of `head` and `tail`.
+#[derive(Args)]
+pub struct PreviewArgs {
+    /// Number of rows to print
+    #[arg(short = 'n', long = "rows", default_value = "10")]
+    pub rows: usize,
+
+    #[command(flatten)]
+    pub cli: Cli,
+}
+
+impl App {
+    /// The command to run; bare arguments mean `concat`.
+    pub fn into_command(self) -> Command {
+        self.command.unwrap_or(Command::Concat(self.cli))
+    }
+}
+
+impl Command {
+    pub fn cli(&self) -> &Cli {
+        match self {
+            Command::Concat(cli)
+            | Command::Convert(cli)
+            | Command::Inspect(cli)
+            | Command::Stats(cli) => cli,
+            Command::Join(args) => &args.cli,
+            Command::Head(args) | Command::Tail(args) => &args.cli,
+        }
+    }
+}
+
 #[derive(Parser)]
 #[command(
     name = "maw",
@@ -9,11 +103,12 @@ use std::path::PathBuf;
     version = env!("CARGO_PKG_VERSION")
 )]
 pub struct Cli {
-    /// Input files, directories, or globs. Use '-' for stdin.
+    /// Input files, directories, or globs. Use '-' for stdin, or '@FILE' to
+    /// read inputs from FILE, one per line.
     #[arg(required = true)]
     pub inputs: Vec<String>,
 
-    /// Output file path
+    /// Output file path, or '-' for stdout
     #[arg(short = 'o', long = "out")]
     pub out: Option<PathBuf>,
 
@@ -21,11 +116,21 @@ pub struct Cli {
     #[arg(long = "out-format", value_enum)]
     pub out_format: Option<OutputFormat>,
 
+    /// Format of data read from stdin ('-')
+    #[arg(long, value_enum, default_value = "csv")]
+    pub stdin_format: StdinFormat,
+
     // CSV input options
     /// CSV delimiter character
     #[arg(long)]
     pub delimiter: Option<char>,
 
+    /// Delimiter for CSV files matching a glob, e.g. 'europe/*.csv=;'.
+    /// Repeatable; the first matching glob wins. Files without one use
+    /// --delimiter, or have theirs sniffed.
+    #[arg(long, value_name = "GLOB=CHAR")]
+    pub delimiter_for: Vec<String>,
+
     /// CSV quote character
     #[arg(long)]
     pub quote: Option<char>,
@@ -34,14 +139,32 @@ pub struct Cli {
     #[arg(long)]
     pub no_headers: bool,
 
+    /// With --no-headers, use the first row as headers when it looks like
+    /// labels above typed data
+    #[arg(long, requires = "no_headers")]
+    pub detect_header: bool,
+
     /// Text encoding for CSV files
     #[arg(long, default_value = "utf8")]
     pub encoding: String,
 
+    /// Encoding for CSV files matching a glob, e.g. 'legacy/*.csv=latin1'.
+    /// Repeatable; the first matching glob wins.
+    #[arg(long, value_name = "GLOB=ENCODING")]
+    pub encoding_for: Vec<String>,
+
+    /// Strip a UTF-8 BOM from the start of input fields
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub strip_bom: bool,
+
     /// NA/null values to recognize
     #[arg(long, default_value = "NA,null,\\N")]
     pub na: String,
 
+    /// How to read empty CSV fields
+    #[arg(long, value_enum, default_value = "null")]
+    pub empty_as: EmptyAs,
+
     // Schema options
     /// Columns to include (whitelist)
     #[arg(long)]
@@ -55,45 +178,245 @@ pub struct Cli {
     #[arg(long)]
     pub rename: Vec<String>,
 
+    /// Rename columns with a substitution applied after --rename, e.g.
+    /// 's/^col_//' (flags: g for every match, i to ignore case)
+    #[arg(long, value_name = "s/PATTERN/REPLACEMENT/")]
+    pub rename_regex: Vec<String>,
+
+    /// Convert every column name to lowercase, after any renames
+    #[arg(long, conflicts_with = "uppercase_columns")]
+    pub lowercase_columns: bool,
+
+    /// Convert every column name to uppercase, after any renames
+    #[arg(long)]
+    pub uppercase_columns: bool,
+
+    /// Replace nulls in a column with a default of the column's type, e.g.
+    /// 'count=0,name=unknown'. Names refer to columns after renames.
+    #[arg(long, value_name = "COLUMN=VALUE,...")]
+    pub fill: Vec<String>,
+
+    /// Add a column computed from numeric columns, e.g. 'total = price *
+    /// qty', with + - * /, parentheses and numbers. Division by zero gives
+    /// null. Repeatable; names refer to columns after renames.
+    #[arg(long, value_name = "NAME=EXPR")]
+    pub compute: Vec<String>,
+
+    /// Convert timestamp columns that have a timezone to this one: UTC, an
+    /// offset like +02:00, or a name like Europe/Paris
+    #[arg(long, value_name = "TZ")]
+    pub tz_convert: Option<String>,
+
+    /// Read timestamps without a timezone as wall-clock times in this one
+    #[arg(long, value_name = "TZ")]
+    pub assume_tz: Option<String>,
+
     /// Reorder columns alphabetically
     #[arg(long)]
     pub reorder: bool,
 
+    /// Explicit output column order (after renames); unlisted columns follow
+    #[arg(long, value_name = "COLS", conflicts_with = "reorder")]
+    pub column_order: Option<String>,
+
+    /// With --column-order, drop columns that aren't listed
+    #[arg(long, requires = "column_order")]
+    pub drop_unlisted: bool,
+
     /// Coerce type conflicts to strings
     #[arg(long)]
     pub stringify_conflicts: bool,
 
+    /// Fail if a column's type differs between inputs (e.g. Int32 in one
+    /// and Int64 or Float64 in another) instead of widening it; a column
+    /// that is all null in some inputs still takes the others' type
+    #[arg(long, visible_alias = "abort-on-schema-widening", conflicts_with = "stringify_conflicts")]
+    pub no_widening: bool,
+
+    /// Keep a column non-nullable in the output when every input has it
+    /// and declares it non-nullable, as Parquet's required columns are;
+    /// otherwise every output column is nullable. A value such a column
+    /// can't convert fails the run under --coerce-errors null
+    #[arg(long)]
+    pub preserve_non_null: bool,
+
+    /// Dictionary encode text columns whose sampled values mostly repeat,
+    /// saving memory and letting Parquet output use dictionary pages
+    #[arg(long)]
+    pub dictionary_strings: bool,
+
+    /// Leave out columns that are null or missing in more than this
+    /// fraction of the sampled rows, e.g. 0.9. Parquet inputs count every
+    /// row, using the null counts in their metadata
+    #[arg(long, value_name = "FRACTION")]
+    pub drop_null_columns: Option<f64>,
+
+    /// Add a text column of this name holding the path of each row's input
+    #[arg(long, value_name = "NAME")]
+    pub source_column: Option<String>,
+
+    /// Write --source-column paths relative to this directory; inputs
+    /// outside it keep their absolute path, with a warning
+    #[arg(long, value_name = "DIR", requires = "source_column")]
+    pub relative_to: Option<PathBuf>,
+
+    /// Add a column numbering rows from 0 in the order they are written,
+    /// running on across inputs and rolled or partitioned files. It is
+    /// named row_number unless given as --row-number=NAME. With concurrent
+    /// readers, only --reproducible numbers rows the same from run to run.
+    #[arg(
+        long,
+        value_name = "NAME",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "row_number",
+        conflicts_with_all = ["resume", "append"]
+    )]
+    pub row_number: Option<String>,
+
+    /// Put the --row-number column last instead of first
+    #[arg(long, requires = "row_number")]
+    pub row_number_last: bool,
+
+    /// What to do with values that can't be converted to the unified type,
+    /// such as "abc" in an integer column
+    #[arg(long, value_enum, default_value = "null")]
+    pub coerce_errors: CoerceErrors,
+
+    /// Write rows skipped by --coerce-errors skip, and CSV rows or JSON
+    /// lines that can't be read, to this CSV file, with a `reason` column
+    #[arg(long, value_name = "PATH")]
+    pub rejects: Option<PathBuf>,
+
+    /// How to write nested (List, Struct, Map) columns, e.g. from Parquet
+    #[arg(long, value_enum, default_value = "json")]
+    pub nested: Nested,
+
     /// Number of rows to sample for schema inference
     #[arg(long, default_value = "1000")]
     pub infer_rows: usize,
 
-    // Rolling output options
-    /// Roll output files by size (bytes)
+    /// Keep each file's sampled schema in this file and reuse it on later
+    /// runs while the file's size and modification time are unchanged
+    #[arg(long, value_name = "PATH")]
+    pub schema_cache: Option<PathBuf>,
+
+    /// Reshape wide rows into long ones: id_cols:value_cols, e.g. id:q1,q2.
+    /// Output columns are the ids, then `variable` and `value`. With no
+    /// value columns, every non-id column is unpivoted.
+    #[arg(long, value_name = "ID_COLS:VALUE_COLS")]
+    pub unpivot: Option<String>,
+
+    /// With --unpivot, leave out rows whose value is null
+    #[arg(long, requires = "unpivot")]
+    pub unpivot_drop_nulls: bool,
+
+    /// Reshape long rows into wide ones: index_cols:column:value, e.g.
+    /// id:quarter:sales. Buffers all rows, within --mem-budget.
+    #[arg(long, value_name = "INDEX:COLUMNS:VALUES", conflicts_with = "unpivot")]
+    pub pivot: Option<String>,
+
+    /// Write a random sample of rows: a fraction (0.01) or a row count (1000)
+    #[arg(long, value_name = "FRACTION|COUNT")]
+    pub sample: Option<String>,
+
+    /// Seed for --sample, so the same rows are picked on every run. Implies
+    /// --reproducible, so the rows are also written in the same order
     #[arg(long)]
+    pub seed: Option<u64>,
+
+    // Rolling output options
+    /// Roll output files by size (bytes), writing numbered files into the
+    /// -o directory
+    #[arg(long, requires = "out", conflicts_with = "resume")]
     pub roll_by_bytes: Option<u64>,
 
-    /// Roll output files by row count
-    #[arg(long)]
+    /// Roll output files by row count, writing numbered files into the -o
+    /// directory
+    #[arg(long, requires = "out", conflicts_with = "resume")]
     pub roll_by_rows: Option<u64>,
 
+    /// Compact many small inputs into numbered files of about this many
+    /// rows each in the -o directory. Unlike --roll-by-rows, files only
+    /// end between batches of --batch-size rows, so no batch is split.
+    #[arg(long, value_name = "ROWS", requires = "out", conflicts_with_all = ["resume", "roll_by_rows"])]
+    pub coalesce: Option<u64>,
+
+    /// How rolled and partition files are named, e.g.
+    /// 'part-{index:05}-{date}.{ext}'. Placeholders: {index} (or {index:05}
+    /// zero-padded), {date}, {timestamp}, {partition} and {ext}
+    #[arg(long, value_name = "TEMPLATE")]
+    pub name_template: Option<String>,
+
+    /// Add new files to a rolled or partitioned output directory that
+    /// already has some, numbered after them, instead of failing on the
+    /// names they share
+    #[arg(long, requires = "out", conflicts_with_all = ["resume", "overwrite"])]
+    pub append: bool,
+
+    /// Write one directory of output per distinct value of these columns
+    /// under the -o directory, e.g. 'year,region' writes
+    /// year=2024/region=eu/part-00000.csv
+    #[arg(long, value_name = "COLS", requires = "out", conflicts_with_all = ["resume", "checksum"])]
+    pub partition_by: Option<String>,
+
+    /// With --partition-by, how many partition files may be open at once.
+    /// Opening another closes the least recently written one, which is
+    /// reopened if more of its rows arrive.
+    #[arg(long, default_value = "64")]
+    pub max_open_files: usize,
+
     // Compression options
-    /// Compression algorithm
-    #[arg(long, value_enum, default_value = "none")]
-    pub compression: Compression,
+    /// Compression algorithm (Parquet pages, or the whole CSV output file);
+    /// snappy for Parquet and none for CSV unless given
+    #[arg(long, value_enum)]
+    pub compression: Option<Compression>,
 
-    /// ZSTD compression level (1-19)
+    /// ZSTD compression level (1-22)
     #[arg(long, default_value = "3")]
     pub zstd_level: u32,
 
+    /// Compression level for the chosen codec (zstd 1-22, gzip 0-9)
+    #[arg(long)]
+    pub compression_level: Option<u32>,
+
+    /// Write out a partial Parquet row group after N seconds without a flush,
+    /// trading compression for latency with a slow producer
+    #[arg(long, value_name = "SECS")]
+    pub flush_interval: Option<f64>,
+
+    /// Give byte-identical output for identical inputs: inputs are read one
+    /// at a time in order rather than concurrently, which is slower when
+    /// there are many inputs
+    #[arg(long, alias = "preserve-order", conflicts_with = "flush_interval")]
+    pub reproducible: bool,
+
     // Performance options
     /// Number of concurrent readers
     #[arg(long, default_value = "4")]
     pub concurrency: usize,
 
-    /// Writer buffer size in MB
+    /// Writer buffer size in MB, for each output file open at once
     #[arg(long, default_value = "64")]
     pub writer_buffer: usize,
 
+    /// Threads that encode and compress Parquet columns, for each output
+    /// file open at once. Row groups are still written in order, so the
+    /// output is the same as with 1.
+    #[arg(long, value_name = "N", default_value = "1")]
+    pub parquet_encode_threads: usize,
+
+    /// Read CSV and JSON input files this many MB at a time, which helps
+    /// on network filesystems; by default the parser's small reads go
+    /// straight to the file
+    #[arg(long, value_name = "MB")]
+    pub read_buffer: Option<usize>,
+
+    /// Rows per batch read from CSV, JSON and Parquet inputs. Small
+    /// batches mean more work for the writer, large ones more memory.
+    #[arg(long, default_value = "64000")]
+    pub batch_size: usize,
+
     /// Memory budget in MB
     #[arg(long, default_value = "1024")]
     pub mem_budget: usize,
@@ -106,6 +429,23 @@ pub struct Cli {
     #[arg(long)]
     pub follow_symlinks: bool,
 
+    /// Also read hidden files and directories, whose names start with '.',
+    /// in input directories
+    #[arg(long)]
+    pub include_hidden: bool,
+
+    /// Process only the first N discovered files, in the order inputs were
+    /// given with each directory or glob sorted
+    #[arg(long, value_name = "N")]
+    pub first_n_files: Option<usize>,
+
+    /// Log and skip inputs that fail to read, then exit with status 2 listing
+    /// them, rather than stopping at the first. Rows read from a file before
+    /// its error are already written, so they are kept, and the list says
+    /// how many there were.
+    #[arg(long)]
+    pub continue_on_error: bool,
+
     // State and resume options
     /// State file path for resumable operations
     #[arg(long)]
@@ -115,10 +455,140 @@ pub struct Cli {
     #[arg(long)]
     pub resume: bool,
 
+    /// Save the state file every N seconds while running, not just at the end
+    #[arg(long, value_name = "SECS", requires = "state")]
+    pub checkpoint_interval: Option<f64>,
+
     /// Verify output integrity
     #[arg(long)]
     pub verify: bool,
 
+    /// CSV output delimiter (defaults to the input's delimiter)
+    #[arg(long)]
+    pub out_delimiter: Option<char>,
+
+    /// Copy delimited text to CSV field by field, changing only the
+    /// delimiter and quoting, e.g. TSV to CSV, which is much faster than
+    /// parsing values. Values are kept exactly as written. Writes commas
+    /// unless --out-delimiter says otherwise. Inputs that aren't all CSV
+    /// files with one header take the normal path, as do runs that check
+    /// rows with --ragged, --validate-field-count or --max-columns.
+    #[arg(
+        long,
+        visible_alias = "tsv-to-csv",
+        conflicts_with_all = [
+            "no_headers", "columns", "exclude", "rename", "rename_regex", "lowercase_columns",
+            "uppercase_columns", "fill", "compute", "tz_convert", "assume_tz", "reorder", "column_order",
+            "drop_null_columns", "source_column", "rejects", "unpivot", "pivot", "sample", "roll_by_bytes",
+            "roll_by_rows", "partition_by", "state", "float_format", "float_precision", "write_schema_sidecar",
+            "row_number", "coalesce",
+        ]
+    )]
+    pub transcode: bool,
+
+    /// Record terminator for CSV output
+    #[arg(long, value_enum, default_value = "lf")]
+    pub line_terminator: LineTerminator,
+
+    /// Quoting style for CSV output
+    #[arg(long, value_enum, default_value = "necessary")]
+    pub quote_style: QuoteStyle,
+
+    /// Prepend a UTF-8 BOM to CSV output files
+    #[arg(long)]
+    pub write_bom: bool,
+
+    /// Digits after the decimal point (or of the mantissa, for scientific)
+    /// when writing floats to CSV
+    #[arg(long, value_name = "N")]
+    pub float_precision: Option<usize>,
+
+    /// Notation for floats in CSV output
+    #[arg(long, value_enum, default_value = "auto")]
+    pub float_format: FloatFormat,
+
+    /// Token for NaN floats, recognized in CSV input and written in CSV output
+    #[arg(long, default_value = "NaN")]
+    pub nan_as: String,
+
+    /// Token for infinite floats, recognized in CSV input and written in CSV
+    /// output, with a leading '-' for negative infinity
+    #[arg(long, default_value = "inf")]
+    pub inf_as: String,
+
+    /// Read and write NaN and infinite floats as nulls
+    #[arg(long)]
+    pub non_finite_as_null: bool,
+
+    /// Read every CSV column as text instead of inferring numbers and
+    /// booleans, keeping values like leading zeros exactly as written
+    #[arg(long)]
+    pub no_type_inference: bool,
+
+    /// Infer the types of only the first N CSV columns, or of the listed
+    /// ones, reading the rest as text; speeds up very wide files
+    #[arg(long, value_name = "N|COLS", conflicts_with = "no_type_inference")]
+    pub infer_columns: Option<String>,
+
+    /// Read CSV columns of fixed-point numbers, such as prices, as exact
+    /// decimals instead of floats
+    #[arg(long, conflicts_with = "no_type_inference")]
+    pub infer_decimal: bool,
+
+    /// Read these CSV columns, named as in the input, as decimals
+    #[arg(long, value_name = "COLS")]
+    pub decimal_columns: Option<String>,
+
+    /// Fail on a CSV input whose header or any row has more than N
+    /// columns, which usually means the delimiter is wrong
+    #[arg(long, value_name = "N")]
+    pub max_columns: Option<usize>,
+
+    /// Fail on a CSV row with more or fewer fields than the header, naming
+    /// its line; with --rejects the row goes there instead
+    #[arg(long, conflicts_with = "ragged")]
+    pub validate_field_count: bool,
+
+    /// What to do with CSV rows that have more or fewer fields than the
+    /// header
+    #[arg(long, value_enum, default_value = "error")]
+    pub ragged: Ragged,
+
+    /// Fsync output files and their directory after writing
+    #[arg(long)]
+    pub fsync: bool,
+
+    /// Replace output files that already exist
+    #[arg(long, conflicts_with = "no_clobber")]
+    pub overwrite: bool,
+
+    /// Refuse to replace existing output files (the default)
+    #[arg(long)]
+    pub no_clobber: bool,
+
+    /// Write a checksum sidecar file next to each output
+    #[arg(long, value_enum)]
+    pub checksum: Option<Checksum>,
+
+    /// After writing each Parquet file, read its schema back and fail
+    /// unless it matches the output schema exactly
+    #[arg(long)]
+    pub verify_schema: bool,
+
+    /// Write each column's type next to a CSV output, as
+    /// <output>.schema.json, for loaders to type the CSV with
+    #[arg(
+        long,
+        visible_alias = "output-schema-json",
+        conflicts_with_all = ["pivot", "partition_by", "roll_by_rows", "roll_by_bytes", "coalesce"]
+    )]
+    pub write_schema_sidecar: bool,
+
+    /// Directory for temporary files (default: the output's directory, so
+    /// finished files are moved into place atomically)
+    #[arg(long, value_name = "PATH")]
+    pub temp_dir: Option<PathBuf>,
+
     // Output options
     /// Show progress bar
     #[arg(long, default_value = "true")]
@@ -128,18 +598,46 @@ pub struct Cli {
     #[arg(long)]
     pub no_progress: bool,
 
+    /// How progress is shown: live bars, or plain or JSON lines on stderr
+    #[arg(long, value_enum, default_value = "bar")]
+    pub progress_style: ProgressStyle,
+
+    /// Log a progress line every N seconds (works without a TTY)
+    #[arg(long, value_name = "SECS")]
+    pub heartbeat: Option<f64>,
+
     /// JSON structured logging
     #[arg(long)]
     pub json_logs: bool,
 
-    /// Print execution plan and exit
-    #[arg(long)]
+    /// Print the execution plan as a tree and exit: the inputs and their
+    /// readers, how their schemas unify, the transforms and the writer.
+    /// Inputs are sampled but no rows are written
+    #[arg(long, alias = "explain")]
     pub plan: bool,
 
+    /// How --plan prints the plan
+    #[arg(long, value_enum, default_value = "text", requires = "plan")]
+    pub plan_format: PlanFormat,
+
     /// Dry run (don't write output)
     #[arg(long)]
     pub dry_run: bool,
 
+    /// Print the row count of each input and the total, writing no output
+    #[arg(long, conflicts_with = "dry_run")]
+    pub count: bool,
+
+    /// Print a hash of the combined rows that doesn't depend on input, row
+    /// or column order, writing no output
+    #[arg(long, conflicts_with_all = ["dry_run", "count"])]
+    pub content_hash: bool,
+
+    /// Check each Parquet input for corruption by reading its footer and
+    /// every page header, writing no output; fails if any file is corrupt
+    #[arg(long, conflicts_with_all = ["dry_run", "count", "content_hash"])]
+    pub validate: bool,
+
     /// Verbose output (use multiple times for more verbosity)
     #[arg(short = 'v', long, action = clap::ArgAction::Count)]
     pub verbose: u8,
@@ -163,6 +661,159 @@ pub enum Compression {
     Zstd,
 }
 
+#[derive(Clone, Copy, ValueEnum, Debug, Serialize, Deserialize)]
+pub enum StdinFormat {
+    Csv,
+    Json,
+    Parquet,
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, Serialize, Deserialize)]
+pub enum LineTerminator {
+    Lf,
+    Crlf,
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, Serialize, Deserialize)]
+pub enum QuoteStyle {
+    Necessary,
+    Always,
+    Never,
+    NonNumeric,
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, Serialize, Deserialize)]
+pub enum FloatFormat {
+    /// Shortest text that reads back as the same value, or fixed when
+    /// --float-precision is given
+    Auto,
+    /// Fixed-point, with 6 decimal places unless --float-precision is given
+    Fixed,
+    /// Scientific notation, e.g. 1.5e3
+    Scientific,
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq)]
+pub enum PlanFormat {
+    /// One step per line, indented under the step it belongs to
+    Text,
+    /// The same tree as nested JSON objects
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ProgressStyle {
+    /// An overall bar, with a sub-bar per file while several are read
+    Bar,
+    /// A line of text per finished file
+    Plain,
+    /// A JSON object per finished file
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, Serialize, Deserialize)]
+pub enum EmptyAs {
+    Null,
+    String,
+}
+
+/// What to do with a CSV row whose field count differs from the header's.
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Ragged {
+    /// Fail on the row
+    #[default]
+    Error,
+    /// Fill missing fields of a short row with nulls; a long row still fails
+    Pad,
+    /// Also drop the fields of a long row past the header's
+    Truncate,
+}
+
+/// What to do with a value that can't be converted to its column's type.
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum CoerceErrors {
+    /// Write a null in its place
+    #[default]
+    Null,
+    /// Drop the whole row
+    Skip,
+    /// Stop with an error naming the value and column
+    Fail,
+}
+
+/// Which rows `join` keeps.
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum JoinType {
+    /// Only rows whose key is in both inputs
+    #[default]
+    Inner,
+    /// Every left row, with nulls in the right columns when unmatched
+    Left,
+}
+
+/// How List, Struct and Map columns, which have no flat representation,
+/// are written.
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Nested {
+    /// Write each value as a JSON string
+    #[default]
+    Json,
+    /// Stop with an error naming the column
+    Error,
+    /// Leave the column out
+    Drop,
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, Serialize, Deserialize)]
+pub enum Checksum {
+    Sha256,
+    Xxh3,
+}
+
+impl Cli {
+    /// Whether the output goes to stdout (`-o -`), leaving logs to stderr.
+    pub fn writes_to_stdout(&self) -> bool {
+        self.out.as_deref().is_some_and(is_stdout)
+    }
+
+    /// Whether output must be the same on every run, by `--reproducible`
+    /// (or `--preserve-order`) or implied by a `--seed`.
+    pub fn is_reproducible(&self) -> bool {
+        self.reproducible || self.seed.is_some()
+    }
+}
+
+impl From<StdinFormat> for FileFormat {
+    fn from(format: StdinFormat) -> Self {
+        match format {
+            StdinFormat::Csv => FileFormat::Csv,
+            StdinFormat::Json => FileFormat::Json,
+            StdinFormat::Parquet => FileFormat::Parquet,
+        }
+    }
+}
+
+impl From<Checksum> for ChecksumAlgorithm {
+    fn from(checksum: Checksum) -> Self {
+        match checksum {
+            Checksum::Sha256 => ChecksumAlgorithm::Sha256,
+            Checksum::Xxh3 => ChecksumAlgorithm::Xxh3,
+        }
+    }
+}
+
+impl OutputFormat {
+    /// The compression used without `--compression`: snappy, the usual
+    /// choice, for Parquet pages, and none for CSV, which is often read by
+    /// tools that can't decompress it.
+    pub fn default_compression(&self) -> Compression {
+        match self {
+            OutputFormat::Csv => Compression::None,
+            OutputFormat::Parquet => Compression::Snappy,
+        }
+    }
+}
+
 impl std::fmt::Display for OutputFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
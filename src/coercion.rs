@@ -1,120 +1,433 @@
+use crate::cli::CoerceErrors;
+use crate::rejects::RejectedRow;
 use crate::error::{MawError, Result};
-use crate::schema::UnifiedSchema;
+use crate::expr::Computed;
+use crate::discover::normalize_path;
+use crate::rejects::SharedRejects;
+use crate::schema::{parse_decimal, rescale_decimal, ColumnRenames, UnifiedSchema};
+use crate::timezone::Zone;
+use crate::transform::render;
 use arrow2::{
-    array::*,
-    datatypes::{DataType, Schema},
+    array::{growable::make_growable, *},
+    datatypes::{DataType, Field, IntegerType, Schema, TimeUnit},
     chunk::Chunk,
+    temporal_conversions::timestamp_s_to_datetime_opt,
+    types::NativeType,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 pub struct BatchAligner {
     unified_schema: Arc<UnifiedSchema>,
-    column_mapping: HashMap<String, String>, // original -> unified
+    column_mapping: ColumnRenames, // original -> unified
     include_columns: Option<Vec<String>>,
     exclude_columns: Option<Vec<String>>,
     stringify_conflicts: bool,
+    coerce_errors: CoerceErrors,
+    rejects: Option<SharedRejects>,
+    assume_tz: Option<Zone>,
+    /// Output column -> one-value array of its `--fill` default
+    fills: HashMap<String, Box<dyn Array>>,
+    /// Columns computed from the others once a chunk is aligned
+    computed: Vec<Computed>,
+    /// Column holding each row's input path, and the directory the paths
+    /// are relative to
+    source: Option<(String, Option<PathBuf>)>,
+}
+
+/// A value that couldn't be converted in a row that is being skipped, kept
+/// for the rejects file.
+struct FailedValue {
+    row: usize,
+    /// Index among the output columns
+    column: usize,
+    value: String,
+    reason: String,
 }
 
 impl BatchAligner {
     pub fn new(
         unified_schema: Arc<UnifiedSchema>,
-        column_mapping: HashMap<String, String>,
+        column_mapping: impl Into<ColumnRenames>,
         include_columns: Option<Vec<String>>,
         exclude_columns: Option<Vec<String>>,
         stringify_conflicts: bool,
     ) -> Self {
         Self {
             unified_schema,
-            column_mapping,
+            column_mapping: column_mapping.into(),
             include_columns,
             exclude_columns,
             stringify_conflicts,
+            coerce_errors: CoerceErrors::Null,
+            rejects: None,
+            assume_tz: None,
+            fills: HashMap::new(),
+            computed: Vec::new(),
+            source: None,
         }
     }
 
-    pub fn align_batch(&self, batch: Chunk<Box<dyn Array>>) -> Result<Chunk<Box<dyn Array>>> {
-        let mut aligned_columns = Vec::new();
-        let mut aligned_fields = Vec::new();
+    /// Sets what happens to values that can't be converted to their
+    /// unified type (`--coerce-errors`). They become nulls by default.
+    pub fn coerce_errors(mut self, coerce_errors: CoerceErrors) -> Self {
+        self.coerce_errors = coerce_errors;
+        self
+    }
 
-        for field in &self.unified_schema.schema.fields {
-            let column_name = &field.name;
-            let target_type = field.data_type();
+    /// Writes rows skipped under `CoerceErrors::Skip` to a rejects file,
+    /// with the reason they were skipped.
+    pub fn rejects(mut self, rejects: SharedRejects) -> Self {
+        self.rejects = Some(rejects);
+        self
+    }
 
-            // Check if column should be included
-            if let Some(include) = &self.include_columns {
-                if !include.contains(column_name) {
-                    continue;
-                }
-            }
+    /// Reads naive timestamps going into a zoned column as wall-clock
+    /// times in `zone` (`--assume-tz`).
+    pub fn assume_tz(mut self, zone: Zone) -> Self {
+        self.assume_tz = Some(zone);
+        self
+    }
 
-            // Check if column should be excluded
-            if let Some(exclude) = &self.exclude_columns {
-                if exclude.contains(column_name) {
-                    continue;
+    /// Fills `column` with the path of the input set by `set_source`,
+    /// relative to `relative_to` when the input is inside it.
+    pub fn source_column(mut self, column: String, relative_to: Option<PathBuf>) -> Self {
+        self.source = Some((column, relative_to));
+        self
+    }
+
+    /// Sets the input the next chunks come from, for the source column.
+    pub fn set_source(&mut self, path: &Path) {
+        if let Some((column, relative_to)) = &self.source {
+            let shown = source_path(path, relative_to.as_deref());
+            self.fills.insert(column.clone(), Utf8Array::<i32>::from_slice([shown]).boxed());
+        }
+    }
+
+    /// Fills each computed column, already in the unified schema, from the
+    /// other output columns. Fails on a column an expression reads that
+    /// isn't in the output.
+    pub fn compute(mut self, computed: &[Computed]) -> Result<Self> {
+        for computed in computed {
+            for column in computed.columns() {
+                if !self.output_fields().any(|f| f.name == column) {
+                    return Err(MawError::Config(format!(
+                        "--compute {}: column '{}' is not in the output",
+                        computed.name, column
+                    )));
                 }
             }
+        }
+        self.computed = computed.to_vec();
+        Ok(self)
+    }
 
-            // Find the source column (handle renames)
-            let source_column = self.find_source_column(column_name);
-            
-            let aligned_array = if let Some(source_idx) = source_column {
-                if source_idx < batch.len() {
-                    self.coerce_column(
-                        &*batch.arrays()[source_idx],
-                        &arrow2::datatypes::DataType::Utf8, // Simplified - would need proper schema
-                        target_type,
-                        batch.len(),
-                    )?
-                } else {
-                    // Column doesn't exist in source - create null column
-                    self.create_null_column(target_type, batch.len())?
+    /// Replaces nulls in each named output column with its default, given
+    /// as text and parsed as the column's type. Fails on a column that isn't
+    /// in the output or a default that doesn't parse.
+    pub fn fill(mut self, defaults: &[(String, String)]) -> Result<Self> {
+        for (column, value) in defaults {
+            let field = self.output_fields().find(|f| f.name == *column).ok_or_else(|| {
+                MawError::Config(format!("--fill column '{}' is not in the output", column))
+            })?;
+            let text = Utf8Array::<i32>::from_slice([value.as_str()]);
+            let parsed = self
+                .coerce_column(&text, field.data_type(), 1)
+                .ok()
+                .filter(|parsed| !parsed.is_null(0))
+                .ok_or_else(|| {
+                    MawError::Config(format!(
+                        "--fill value '{}' is not a valid {:?} for column '{}'",
+                        value,
+                        field.data_type(),
+                        column
+                    ))
+                })?;
+            self.fills.insert(column.clone(), parsed);
+        }
+        Ok(self)
+    }
+
+    /// One line per step the aligner takes besides casting to the unified
+    /// schema, for `--plan`.
+    pub fn describe(&self) -> Vec<String> {
+        let mut steps = self.column_mapping.describe();
+        if let Some(columns) = &self.include_columns {
+            steps.push(format!("keep columns {}", columns.join(",")));
+        }
+        if let Some(columns) = &self.exclude_columns {
+            steps.push(format!("drop columns {}", columns.join(",")));
+        }
+        if let Some(zone) = &self.assume_tz {
+            steps.push(format!("read naive timestamps as {}", zone));
+        }
+        match self.coerce_errors {
+            CoerceErrors::Null => {}
+            CoerceErrors::Skip => steps.push("skip rows with unconvertible values".to_string()),
+            CoerceErrors::Fail => steps.push("fail on unconvertible values".to_string()),
+        }
+        for computed in &self.computed {
+            steps.push(format!("compute {} = {}", computed.name, computed.expression));
+        }
+        let mut fills: Vec<_> = self.fills.iter().collect();
+        fills.sort_by(|a, b| a.0.cmp(b.0));
+        for (column, value) in fills {
+            let value = render(&get_display(value.as_ref(), "null"), 0);
+            steps.push(format!("fill {}={}", column, value));
+        }
+        steps
+    }
+
+    /// Aligns a chunk read from one input to the unified schema. Columns are
+    /// matched by name (after renames); unified columns missing from the
+    /// input become null columns and values are coerced to the unified type.
+    /// Values that can't be converted are handled by the `coerce_errors`
+    /// policy.
+    pub fn align_batch(
+        &self,
+        batch: Chunk<Box<dyn Array>>,
+        source_columns: &[String],
+    ) -> Result<Chunk<Box<dyn Array>>> {
+        let num_rows = batch.len();
+        let mut aligned_columns = Vec::new();
+        let mut skipped_rows = vec![false; num_rows];
+        let mut failed_values = Vec::new();
+        let renamed: Vec<String> = source_columns
+            .iter()
+            .map(|name| self.column_mapping.apply(name))
+            .collect();
+
+        for field in self.output_fields() {
+            let target_type = field.data_type();
+
+            let aligned_array = match renamed.iter().position(|name| *name == field.name) {
+                Some(source_idx) if source_idx < batch.arrays().len() => {
+                    let source = &*batch.arrays()[source_idx];
+                    let coerced = self.coerce_column(source, target_type, num_rows)?;
+                    if let (DataType::Decimal(_, from_scale), DataType::Decimal(_, scale)) =
+                        (source.data_type(), target_type)
+                    {
+                        check_decimals_fit(&field.name, source, coerced.as_ref(), (*from_scale, *scale))?;
+                    }
+                    if source.data_type() != target_type {
+                        self.handle_failed_values(
+                            (aligned_columns.len(), field),
+                            source,
+                            coerced.as_ref(),
+                            &mut skipped_rows,
+                            &mut failed_values,
+                        )?;
+                    }
+                    coerced
                 }
-            } else {
                 // Column doesn't exist in source - create null column
-                self.create_null_column(target_type, batch.len())?
+                _ => self.create_null_column(target_type, num_rows)?,
+            };
+            let aligned_array = match self.fills.get(&field.name) {
+                Some(default) => fill_nulls(aligned_array, default.as_ref()),
+                None => aligned_array,
             };
 
             aligned_columns.push(aligned_array);
-            aligned_fields.push(field.clone());
         }
+        self.compute_columns(&mut aligned_columns, num_rows)?;
 
-        let _aligned_schema = Schema::from(aligned_fields);
-        Ok(Chunk::new(aligned_columns))
+        let mut aligned = Chunk::new(aligned_columns);
+        if skipped_rows.contains(&true) {
+            if let Some(rejects) = &self.rejects {
+                write_rejects(rejects, &aligned, failed_values)?;
+            }
+            aligned = drop_rows(&aligned, &skipped_rows);
+        }
+        self.encode_dictionaries(aligned)
     }
 
-    fn find_source_column(&self, unified_name: &str) -> Option<usize> {
-        // First try direct match
-        if let Some(_original) = self.column_mapping.get(unified_name) {
-            return Some(0); // Simplified - would need proper column index lookup
+    /// Writes rows the reader set aside to the rejects file, matching their
+    /// fields to output columns by position in `source_columns`.
+    pub fn reject_rows(&self, source_columns: &[String], rows: Vec<RejectedRow>) -> Result<()> {
+        let Some(rejects) = self.rejects.as_ref().filter(|_| !rows.is_empty()) else {
+            return Ok(());
+        };
+        let renamed: Vec<String> = source_columns
+            .iter()
+            .map(|name| self.column_mapping.apply(name))
+            .collect();
+        let positions: Vec<Option<usize>> = self
+            .output_fields()
+            .map(|field| renamed.iter().position(|name| *name == field.name))
+            .collect();
+
+        let mut rejects = rejects
+            .lock()
+            .map_err(|_| MawError::Schema("Rejects file was poisoned".to_string()))?;
+        for row in rows {
+            let values: Vec<String> = positions
+                .iter()
+                .map(|position| position.and_then(|i| row.fields.get(i)).cloned().unwrap_or_default())
+                .collect();
+            rejects.write(&values, &row.reason)?;
         }
-        
-        // Try reverse mapping
-        for (_original, mapped) in &self.column_mapping {
-            if mapped == unified_name {
-                return Some(0); // Simplified - would need proper column index lookup
+        Ok(())
+    }
+
+    /// Replaces each computed column, null until now, with its values,
+    /// in order, so a computed column can read an earlier one.
+    fn compute_columns(&self, columns: &mut [Box<dyn Array>], num_rows: usize) -> Result<()> {
+        if self.computed.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<&str> = self.output_fields().map(|f| f.name.as_str()).collect();
+        for computed in &self.computed {
+            // Left out of the output by --columns or --exclude
+            let Some(index) = names.iter().position(|name| *name == computed.name) else {
+                continue;
+            };
+            let values = computed.evaluate(num_rows, &|column| {
+                let position = names.iter().position(|name| *name == column).ok_or_else(|| {
+                    MawError::Schema(format!("--compute column '{}' is not in the output", column))
+                })?;
+                let array = columns[position].as_ref();
+                let target = match array.data_type() {
+                    DataType::Float32 | DataType::Float64 | DataType::Decimal(_, _) => DataType::Float64,
+                    _ => DataType::Int64,
+                };
+                self.coerce_column(array, &target, num_rows)
+            })?;
+            columns[index] = match self.fills.get(&computed.name) {
+                Some(default) => fill_nulls(values, default.as_ref()),
+                None => values,
+            };
+        }
+        Ok(())
+    }
+
+    /// Dictionary encodes the columns picked for it when sampling, once
+    /// everything else about the chunk is settled.
+    fn encode_dictionaries(&self, chunk: Chunk<Box<dyn Array>>) -> Result<Chunk<Box<dyn Array>>> {
+        if self.unified_schema.dictionary_columns.is_empty() {
+            return Ok(chunk);
+        }
+        let columns = self
+            .output_fields()
+            .zip(chunk.into_arrays())
+            .map(|(field, array)| {
+                if self.is_dictionary(field) {
+                    dictionary_encode(downcast::<Utf8Array<i32>>(array.as_ref())?)
+                } else {
+                    Ok(array)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Chunk::new(columns))
+    }
+
+    fn is_dictionary(&self, field: &Field) -> bool {
+        field.data_type == DataType::Utf8 && self.unified_schema.dictionary_columns.contains(&field.name)
+    }
+
+    /// Finds values a conversion turned into nulls and applies the policy:
+    /// fails on the first one, marks its row to be skipped (noting the value
+    /// when there is a rejects file), or leaves the null in place.
+    fn handle_failed_values(
+        &self,
+        (index, field): (usize, &Field),
+        source: &dyn Array,
+        coerced: &dyn Array,
+        skipped_rows: &mut [bool],
+        failed_values: &mut Vec<FailedValue>,
+    ) -> Result<()> {
+        if coerced.null_count() == source.null_count() {
+            return Ok(());
+        }
+        // A non-nullable column can't take the null, so the value fails
+        let policy = match self.coerce_errors {
+            CoerceErrors::Null if field.is_nullable => return Ok(()),
+            CoerceErrors::Null => CoerceErrors::Fail,
+            policy => policy,
+        };
+
+        let failed = (0..source.len()).filter(|&i| !source.is_null(i) && coerced.is_null(i));
+        for row in failed {
+            if policy == CoerceErrors::Fail || self.rejects.is_some() {
+                let value = display_value(source, row)?;
+                let reason = format!(
+                    "Cannot convert '{}' in column '{}' to {:?}",
+                    value,
+                    field.name,
+                    coerced.data_type()
+                );
+                if policy == CoerceErrors::Fail {
+                    return Err(MawError::Schema(reason));
+                }
+                failed_values.push(FailedValue { row, column: index, value, reason });
             }
+            skipped_rows[row] = true;
         }
-        
-        None
+        Ok(())
     }
 
-    fn coerce_column(
+    /// The schema of aligned chunks, i.e. the unified schema after
+    /// `--columns`/`--exclude` filtering, with dictionary encoded columns.
+    pub fn output_schema(&self) -> Schema {
+        let fields = self
+            .output_fields()
+            .map(|field| {
+                if self.is_dictionary(field) {
+                    Field::new(&field.name, dictionary_type(), field.is_nullable)
+                } else {
+                    field.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        Schema::from(fields)
+    }
+
+    fn output_fields(&self) -> impl Iterator<Item = &Field> {
+        self.unified_schema.schema.fields.iter().filter(move |field| {
+            // Check if column should be included
+            if let Some(include) = &self.include_columns {
+                if !include.contains(&field.name) {
+                    return false;
+                }
+            }
+
+            // Check if column should be excluded
+            if let Some(exclude) = &self.exclude_columns {
+                if exclude.contains(&field.name) {
+                    return false;
+                }
+            }
+
+            true
+        })
+    }
+
+    /// Converts a column to `target_type` with the same rules used when
+    /// aligning batches.
+    pub fn coerce_column(
         &self,
         array: &dyn Array,
-        source_type: &DataType,
         target_type: &DataType,
         num_rows: usize,
     ) -> Result<Box<dyn Array>> {
+        let source_type = array.data_type();
         if source_type == target_type {
-            // For now, create a new array of the same type - this is simplified
-            return self.create_null_column(target_type, num_rows);
+            return Ok(array.to_boxed());
+        }
+
+        // Narrow large offsets first so the rules below only see i32 offsets
+        if let Some(narrowed) = narrow_offsets(array)? {
+            return self.coerce_column(narrowed.as_ref(), target_type, num_rows);
         }
 
         match (source_type, target_type) {
+            // An all-null column fits any type
+            (DataType::Null, _) => self.create_null_column(target_type, num_rows),
+
             // String to other types
             (DataType::Utf8, DataType::Int64) => {
-                let string_array = array.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+                let string_array = downcast::<Utf8Array<i32>>(array)?;
                 let int_values: Vec<Option<i64>> = (0..num_rows)
                     .map(|i| {
                         if string_array.is_null(i) {
@@ -127,7 +440,7 @@ impl BatchAligner {
                 Ok(Box::new(Int64Array::from(int_values)))
             }
             (DataType::Utf8, DataType::Float64) => {
-                let string_array = array.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+                let string_array = downcast::<Utf8Array<i32>>(array)?;
                 let float_values: Vec<Option<f64>> = (0..num_rows)
                     .map(|i| {
                         if string_array.is_null(i) {
@@ -140,7 +453,7 @@ impl BatchAligner {
                 Ok(Box::new(Float64Array::from(float_values)))
             }
             (DataType::Utf8, DataType::Boolean) => {
-                let string_array = array.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+                let string_array = downcast::<Utf8Array<i32>>(array)?;
                 let bool_values: Vec<Option<bool>> = (0..num_rows)
                     .map(|i| {
                         if string_array.is_null(i) {
@@ -155,7 +468,7 @@ impl BatchAligner {
 
             // Integer to float
             (DataType::Int64, DataType::Float64) => {
-                let int_array = array.as_any().downcast_ref::<Int64Array>().unwrap();
+                let int_array = downcast::<Int64Array>(array)?;
                 let float_values: Vec<Option<f64>> = (0..num_rows)
                     .map(|i| {
                         if int_array.is_null(i) {
@@ -168,33 +481,110 @@ impl BatchAligner {
                 Ok(Box::new(Float64Array::from(float_values)))
             }
 
-            // Any type to string
-            (_, DataType::Utf8) => {
-                let string_values: Vec<Option<&str>> = (0..num_rows)
-                    .map(|i| {
-                        if array.is_null(i) {
-                            None
-                        } else {
-                            Some("converted") // Simplified - would need proper string conversion
-                        }
-                    })
-                    .collect();
-                Ok(Box::new(Utf8Array::<i32>::from(string_values)))
+            // Narrower integers and booleans, as read from Parquet, widen
+            // to whatever numeric type the inputs were unified to
+            (
+                DataType::Boolean | DataType::Int8 | DataType::Int16 | DataType::Int32,
+                DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::Float32
+                | DataType::Float64,
+            ) => widen_integers(array, target_type),
+
+            (DataType::Float32, DataType::Float64) => {
+                let float_array = downcast::<Float32Array>(array)?;
+                let values = float_array.iter().map(|v| v.map(|v| *v as f64));
+                Ok(Box::new(Float64Array::from_trusted_len_iter(values)))
             }
 
-            // Default: return as string if stringify_conflicts is enabled
-            _ if self.stringify_conflicts => {
-                let string_values: Vec<Option<&str>> = (0..num_rows)
-                    .map(|i| {
-                        if array.is_null(i) {
-                            None
-                        } else {
-                            Some("converted") // Simplified - would need proper string conversion
-                        }
+            // Dates and timestamps to the unified unit and timezone. Zoned
+            // values are UTC instants, so only the label changes with the zone
+            (
+                DataType::Date32 | DataType::Date64 | DataType::Timestamp(_, _),
+                DataType::Timestamp(to_unit, to_tz),
+            ) => {
+                let (from_unit, mut values): (TimeUnit, Vec<Option<i64>>) = match source_type {
+                    DataType::Date32 => {
+                        let date_array = downcast::<Int32Array>(array)?;
+                        let seconds = date_array.iter().map(|v| v.map(|d| *d as i64 * 86_400));
+                        (TimeUnit::Second, seconds.collect())
+                    }
+                    // Date64 is milliseconds since the epoch
+                    DataType::Date64 => {
+                        let date_array = downcast::<Int64Array>(array)?;
+                        (TimeUnit::Millisecond, date_array.iter().map(|v| v.copied()).collect())
+                    }
+                    DataType::Timestamp(unit, _) => {
+                        let ts_array = downcast::<Int64Array>(array)?;
+                        (*unit, ts_array.iter().map(|v| v.copied()).collect())
+                    }
+                    _ => unreachable!(),
+                };
+                // Naive timestamps only reach a zoned column through
+                // --assume-tz, as wall-clock times in that zone
+                if let (DataType::Timestamp(_, None), Some(_), Some(zone)) =
+                    (source_type, to_tz, &self.assume_tz)
+                {
+                    values = localize(values, from_unit, zone);
+                }
+
+                // Instants too far out for the finer unit become nulls
+                let (from, to) = (units_per_second(from_unit), units_per_second(*to_unit));
+                let converted: Vec<Option<i64>> = values
+                    .into_iter()
+                    .map(|v| {
+                        v.and_then(|t| if to >= from { t.checked_mul(to / from) } else { Some(t / (from / to)) })
                     })
                     .collect();
-                Ok(Box::new(Utf8Array::<i32>::from(string_values)))
+                Ok(Box::new(Int64Array::from(converted).to(target_type.clone())))
+            }
+
+            // Decimals rescale to the unified scale. A later CSV batch can
+            // need more digits than the sample did, and values that don't
+            // fit become nulls
+            (DataType::Decimal(_, from_scale), DataType::Decimal(precision, scale)) => {
+                let decimal_array = downcast::<Int128Array>(array)?;
+                let values = decimal_array
+                    .iter()
+                    .map(|v| v.and_then(|v| rescale_decimal(*v, *from_scale, *precision, *scale)));
+                Ok(Box::new(Int128Array::from_trusted_len_iter(values).to(target_type.clone())))
             }
+            (DataType::Utf8, DataType::Decimal(precision, scale)) => {
+                let string_array = downcast::<Utf8Array<i32>>(array)?;
+                let values = string_array.iter().map(|v| {
+                    let (value, _, from_scale) = parse_decimal(v?)?;
+                    rescale_decimal(value, from_scale, *precision, *scale)
+                });
+                Ok(Box::new(Int128Array::from_trusted_len_iter(values).to(target_type.clone())))
+            }
+            // Integers are decimals of scale 0; those too wide for the
+            // precision become nulls, like decimals that don't fit
+            (
+                DataType::Boolean | DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64,
+                DataType::Decimal(precision, scale),
+            ) => {
+                let values = integer_values(array)?
+                    .into_iter()
+                    .map(|v| v.and_then(|v| rescale_decimal(v as i128, 0, *precision, *scale)));
+                Ok(Box::new(Int128Array::from_iter(values).to(target_type.clone())))
+            }
+            (DataType::Decimal(_, scale), DataType::Float64) => {
+                let decimal_array = downcast::<Int128Array>(array)?;
+                let divisor = 10f64.powi(*scale as i32);
+                let values = decimal_array.iter().map(|v| v.map(|v| *v as f64 / divisor));
+                Ok(Box::new(Float64Array::from_trusted_len_iter(values)))
+            }
+
+            // Nested values have no flat form, so they're written as JSON
+            (_, DataType::Utf8) if is_nested(source_type) => jsonify_column(array),
+
+            // Any type to string
+            (_, DataType::Utf8) => Ok(stringify_column(array, num_rows)?),
+
+            // Default: return as string if stringify_conflicts is enabled
+            _ if self.stringify_conflicts => Ok(stringify_column(array, num_rows)?),
 
             _ => Err(MawError::Schema(format!(
                 "Cannot coerce {:?} to {:?}",
@@ -204,30 +594,343 @@ impl BatchAligner {
     }
 
     fn create_null_column(&self, data_type: &DataType, num_rows: usize) -> Result<Box<dyn Array>> {
-        match data_type {
-            DataType::Utf8 => {
-                let nulls: Vec<Option<&str>> = vec![None; num_rows];
-                Ok(Box::new(Utf8Array::<i32>::from(nulls)))
-            }
-            DataType::Int64 => {
-                let nulls: Vec<Option<i64>> = vec![None; num_rows];
-                Ok(Box::new(Int64Array::from(nulls)))
+        Ok(new_null_array(data_type.clone(), num_rows))
+    }
+}
+
+/// Writes each row with a failed value to the rejects file, showing the
+/// failed values as they were read.
+fn write_rejects(
+    rejects: &SharedRejects,
+    aligned: &Chunk<Box<dyn Array>>,
+    failed_values: Vec<FailedValue>,
+) -> Result<()> {
+    let mut rows: BTreeMap<usize, Vec<FailedValue>> = BTreeMap::new();
+    for failed in failed_values {
+        rows.entry(failed.row).or_default().push(failed);
+    }
+
+    let mut rejects = rejects
+        .lock()
+        .map_err(|_| MawError::Schema("Rejects file was poisoned".to_string()))?;
+    for (row, failed) in rows {
+        let mut values = aligned
+            .arrays()
+            .iter()
+            .map(|array| display_value(array.as_ref(), row))
+            .collect::<Result<Vec<_>>>()?;
+        let mut reasons = Vec::with_capacity(failed.len());
+        for failed in failed {
+            values[failed.column] = failed.value;
+            reasons.push(failed.reason);
+        }
+        rejects.write(&values, &reasons.join("; "))?;
+    }
+    Ok(())
+}
+
+/// Fails on a decimal that didn't fit its unified type. Unification widens
+/// decimals to hold every input, so only a text batch typed with more
+/// digits after the point than the sample can fail, and that is never
+/// silently nulled.
+fn check_decimals_fit(
+    column: &str,
+    source: &dyn Array,
+    coerced: &dyn Array,
+    (from_scale, scale): (usize, usize),
+) -> Result<()> {
+    let Some(row) = (0..source.len()).find(|&i| !source.is_null(i) && coerced.is_null(i)) else {
+        return Ok(());
+    };
+    Err(MawError::Schema(format!(
+        "Column '{}' has {}, with {} digits after the point where the sample had {}; raise --infer-rows to sample it",
+        column,
+        display_value(source, row)?,
+        from_scale,
+        scale
+    )))
+}
+
+/// A value as text, or an empty string for null.
+fn display_value(array: &dyn Array, row: usize) -> Result<String> {
+    let mut value = String::new();
+    get_display(array, "")(&mut value, row).map_err(|e| MawError::Arrow(e.to_string()))?;
+    Ok(value)
+}
+
+/// How an input's path is shown in the source column: as discovered, or
+/// relative to `base`. A path outside `base` is shown absolute, with a
+/// warning.
+fn source_path(path: &Path, base: Option<&Path>) -> String {
+    let Some(base) = base.filter(|_| path != Path::new("-")) else {
+        return path.display().to_string();
+    };
+    let absolute = path
+        .canonicalize()
+        .map(|p| normalize_path(&p))
+        .unwrap_or_else(|_| path.to_path_buf());
+    match absolute.strip_prefix(base) {
+        Ok(relative) => relative.display().to_string(),
+        Err(_) => {
+            tracing::warn!(
+                "{} is outside --relative-to {}; using its absolute path",
+                absolute.display(),
+                base.display()
+            );
+            absolute.display().to_string()
+        }
+    }
+}
+
+/// The type of a dictionary encoded text column.
+fn dictionary_type() -> DataType {
+    DataType::Dictionary(IntegerType::Int32, Box::new(DataType::Utf8), false)
+}
+
+/// Stores each distinct value of `array` once, with rows as keys into them.
+fn dictionary_encode(array: &Utf8Array<i32>) -> Result<Box<dyn Array>> {
+    let mut dictionary = MutableDictionaryArray::<i32, MutableUtf8Array<i32>>::new();
+    dictionary.try_extend(array.iter()).map_err(|e| MawError::Arrow(e.to_string()))?;
+    Ok(DictionaryArray::<i32>::from(dictionary).boxed())
+}
+
+/// Copies `array` with its nulls replaced by the one value in `default`.
+fn fill_nulls(array: Box<dyn Array>, default: &dyn Array) -> Box<dyn Array> {
+    if array.null_count() == 0 {
+        return array;
+    }
+    let mut growable = make_growable(&[array.as_ref(), default], false, array.len());
+    for row in 0..array.len() {
+        if array.is_null(row) {
+            growable.extend(1, 0, 1);
+        } else {
+            growable.extend(0, row, 1);
+        }
+    }
+    growable.as_box()
+}
+
+/// Copies `chunk` without the rows marked in `skip`.
+fn drop_rows(chunk: &Chunk<Box<dyn Array>>, skip: &[bool]) -> Chunk<Box<dyn Array>> {
+    let columns = chunk
+        .arrays()
+        .iter()
+        .map(|array| {
+            let mut growable = make_growable(&[array.as_ref()], false, chunk.len());
+            for (row, _) in skip.iter().enumerate().filter(|(_, &skip)| !skip) {
+                growable.extend(0, row, 1);
             }
-            DataType::Float64 => {
-                let nulls: Vec<Option<f64>> = vec![None; num_rows];
-                Ok(Box::new(Float64Array::from(nulls)))
+            growable.as_box()
+        })
+        .collect();
+    Chunk::new(columns)
+}
+
+/// Downcasts `array` to the concrete array type `T`, failing with an error
+/// that names both types instead of panicking on a mismatch.
+pub fn downcast<T: Array + 'static>(array: &dyn Array) -> Result<&T> {
+    array.as_any().downcast_ref::<T>().ok_or_else(|| {
+        let expected = std::any::type_name::<T>();
+        MawError::Arrow(format!(
+            "Expected {}, found an array of type {:?}",
+            expected.rsplit("::").next().unwrap_or(expected),
+            array.data_type()
+        ))
+    })
+}
+
+/// Converts a `LargeUtf8` or `LargeBinary` array to its i32-offset
+/// counterpart, or returns `None` for any other array.
+fn narrow_offsets(array: &dyn Array) -> Result<Option<Box<dyn Array>>> {
+    let overflow = |e: arrow2::error::Error| {
+        MawError::Arrow(format!("{:?} column too large for 32-bit offsets: {}", array.data_type(), e))
+    };
+
+    match array.data_type() {
+        DataType::LargeUtf8 => {
+            let large = downcast::<Utf8Array<i64>>(array)?;
+            let mut narrow = MutableUtf8Array::<i32>::with_capacity(large.len());
+            narrow.try_extend(large.iter()).map_err(overflow)?;
+            Ok(Some(narrow.as_box()))
+        }
+        DataType::LargeBinary => {
+            let large = downcast::<BinaryArray<i64>>(array)?;
+            let mut narrow = MutableBinaryArray::<i32>::with_capacity(large.len());
+            narrow.try_extend(large.iter()).map_err(overflow)?;
+            Ok(Some(narrow.as_box()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Reads an integer or boolean column as `i64` values.
+fn integer_values(array: &dyn Array) -> Result<Vec<Option<i64>>> {
+    fn values<T: NativeType + Into<i64>>(array: &dyn Array) -> Result<Vec<Option<i64>>> {
+        let array = downcast::<PrimitiveArray<T>>(array)?;
+        Ok(array.iter().map(|v| v.map(|v| (*v).into())).collect())
+    }
+
+    match array.data_type() {
+        DataType::Boolean => {
+            let bool_array = downcast::<BooleanArray>(array)?;
+            Ok(bool_array.iter().map(|v| v.map(i64::from)).collect())
+        }
+        DataType::Int8 => values::<i8>(array),
+        DataType::Int16 => values::<i16>(array),
+        DataType::Int32 => values::<i32>(array),
+        _ => values::<i64>(array),
+    }
+}
+
+/// Widens an integer or boolean column to another integer or float type.
+/// Values the target type can't hold exactly, such as 300 as an Int8 or
+/// 2^24 + 1 as a Float32, become nulls, which are then handled as failed
+/// conversions.
+fn widen_integers(array: &dyn Array, target_type: &DataType) -> Result<Box<dyn Array>> {
+    fn convert<T: NativeType>(values: &[Option<i64>], convert: impl Fn(i64) -> Option<T>) -> Box<dyn Array> {
+        Box::new(PrimitiveArray::<T>::from_iter(values.iter().map(|v| v.and_then(&convert))))
+    }
+
+    let values = integer_values(array)?;
+    Ok(match target_type {
+        DataType::Int8 => convert(&values, |v| i8::try_from(v).ok()),
+        DataType::Int16 => convert(&values, |v| i16::try_from(v).ok()),
+        DataType::Int32 => convert(&values, |v| i32::try_from(v).ok()),
+        DataType::Float32 => convert(&values, |v| (v as f32 as i64 == v).then_some(v as f32)),
+        DataType::Float64 => convert(&values, |v| (v as f64 as i64 == v).then_some(v as f64)),
+        _ => Box::new(Int64Array::from(values)),
+    })
+}
+
+/// Converts wall-clock times in `zone` to UTC instants. Times the clocks
+/// skipped become nulls.
+fn localize(values: Vec<Option<i64>>, unit: TimeUnit, zone: &Zone) -> Vec<Option<i64>> {
+    let per_second = units_per_second(unit);
+    values
+        .into_iter()
+        .map(|value| {
+            let value = value?;
+            let local = timestamp_s_to_datetime_opt(value.div_euclid(per_second))?;
+            let offset = zone.offset_at_local(&local)?;
+            Some(value - offset.local_minus_utc() as i64 * per_second)
+        })
+        .collect()
+}
+
+fn units_per_second(unit: TimeUnit) -> i64 {
+    match unit {
+        TimeUnit::Second => 1,
+        TimeUnit::Millisecond => 1_000,
+        TimeUnit::Microsecond => 1_000_000,
+        TimeUnit::Nanosecond => 1_000_000_000,
+    }
+}
+
+/// Whether values of `data_type` hold other values: lists, structs and maps.
+pub fn is_nested(data_type: &DataType) -> bool {
+    matches!(
+        data_type.to_logical_type(),
+        DataType::List(_)
+            | DataType::LargeList(_)
+            | DataType::FixedSizeList(_, _)
+            | DataType::Struct(_)
+            | DataType::Map(_, _)
+    )
+}
+
+/// Renders every value of a nested column as a JSON string, keeping nulls
+/// as nulls.
+fn jsonify_column(array: &dyn Array) -> Result<Box<dyn Array>> {
+    let values = (0..array.len())
+        .map(|i| {
+            if array.is_null(i) {
+                Ok(None)
+            } else {
+                Ok(Some(json_value(array, i)?.to_string()))
             }
-            DataType::Boolean => {
-                let nulls: Vec<Option<bool>> = vec![None; num_rows];
-                Ok(Box::new(BooleanArray::from(nulls)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Box::new(Utf8Array::<i32>::from(values)))
+}
+
+/// The JSON form of one value: lists become arrays, structs and maps become
+/// objects, numbers and booleans stay as they are (non-finite floats become
+/// null) and anything else is its display text.
+fn json_value(array: &dyn Array, row: usize) -> Result<serde_json::Value> {
+    use serde_json::Value;
+
+    if array.is_null(row) {
+        return Ok(Value::Null);
+    }
+
+    let list = |items: Box<dyn Array>| {
+        (0..items.len())
+            .map(|i| json_value(items.as_ref(), i))
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array)
+    };
+
+    Ok(match array.data_type().to_logical_type() {
+        DataType::Boolean => Value::from(downcast::<BooleanArray>(array)?.value(row)),
+        DataType::Int8 => Value::from(downcast::<Int8Array>(array)?.value(row)),
+        DataType::Int16 => Value::from(downcast::<Int16Array>(array)?.value(row)),
+        DataType::Int32 => Value::from(downcast::<Int32Array>(array)?.value(row)),
+        DataType::Int64 => Value::from(downcast::<Int64Array>(array)?.value(row)),
+        DataType::UInt8 => Value::from(downcast::<UInt8Array>(array)?.value(row)),
+        DataType::UInt16 => Value::from(downcast::<UInt16Array>(array)?.value(row)),
+        DataType::UInt32 => Value::from(downcast::<UInt32Array>(array)?.value(row)),
+        DataType::UInt64 => Value::from(downcast::<UInt64Array>(array)?.value(row)),
+        DataType::Float32 => Value::from(downcast::<Float32Array>(array)?.value(row) as f64),
+        DataType::Float64 => Value::from(downcast::<Float64Array>(array)?.value(row)),
+        DataType::List(_) => list(downcast::<ListArray<i32>>(array)?.value(row))?,
+        DataType::LargeList(_) => list(downcast::<ListArray<i64>>(array)?.value(row))?,
+        DataType::FixedSizeList(_, _) => list(downcast::<FixedSizeListArray>(array)?.value(row))?,
+        DataType::Struct(fields) => {
+            let values = downcast::<StructArray>(array)?.values();
+            let mut object = serde_json::Map::new();
+            for (field, values) in fields.iter().zip(values) {
+                object.insert(field.name.clone(), json_value(values.as_ref(), row)?);
             }
-            _ => {
-                // Default to string for unknown types
-                let nulls: Vec<Option<&str>> = vec![None; num_rows];
-                Ok(Box::new(Utf8Array::<i32>::from(nulls)))
+            Value::Object(object)
+        }
+        // Map entries are structs of a key and a value
+        DataType::Map(_, _) => {
+            let entries = downcast::<MapArray>(array)?.value(row);
+            let entries = downcast::<StructArray>(entries.as_ref())?;
+            let mut object = serde_json::Map::new();
+            for i in 0..entries.len() {
+                let key = match json_value(entries.values()[0].as_ref(), i)? {
+                    Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                object.insert(key, json_value(entries.values()[1].as_ref(), i)?);
             }
+            Value::Object(object)
+        }
+        _ => {
+            let mut value = String::new();
+            get_display(array, "")(&mut value, row).map_err(|e| MawError::Arrow(e.to_string()))?;
+            Value::String(value)
+        }
+    })
+}
+
+/// Renders every value of `array` as text, keeping nulls as nulls.
+fn stringify_column(array: &dyn Array, num_rows: usize) -> Result<Box<dyn Array>> {
+    let display = get_display(array, "");
+    let mut string_values: Vec<Option<String>> = Vec::with_capacity(num_rows);
+
+    for i in 0..num_rows {
+        if array.is_null(i) {
+            string_values.push(None);
+        } else {
+            let mut value = String::new();
+            display(&mut value, i).map_err(|e| MawError::Arrow(e.to_string()))?;
+            string_values.push(Some(value));
         }
     }
+
+    Ok(Box::new(Utf8Array::<i32>::from(string_values)))
 }
 
 #[cfg(test)]
@@ -235,24 +938,27 @@ mod tests {
     use super::*;
     use arrow2::{
         array::{Int64Array, Utf8Array},
-        datatypes::{DataType, Schema},
-        record_batch::RecordBatch,
+        datatypes::{DataType, Field, Schema},
     };
     use std::collections::HashMap;
     use std::sync::Arc;
 
     #[test]
     fn test_batch_alignment() {
-        let schema = Schema::new(vec![
+        let first = Schema::from(vec![
             Field::new("a", DataType::Int64, true),
             Field::new("b", DataType::Utf8, true),
         ]);
+        let second = Schema::from(vec![
+            Field::new("b", DataType::Utf8, true),
+            Field::new("c", DataType::Float64, true),
+        ]);
         
         let a = Int64Array::from_slice([1, 2, 3]);
         let b = Utf8Array::<i32>::from_slice(["x", "y", "z"]);
-        let batch = RecordBatch::new(Arc::new(schema), vec![Arc::new(a), Arc::new(b)]);
+        let batch = Chunk::new(vec![a.boxed(), b.boxed()]);
 
-        let unified_schema = Arc::new(UnifiedSchema::new());
+        let unified_schema = Arc::new(UnifiedSchema::from_schemas(&[first, second], false).unwrap());
         let column_mapping = HashMap::new();
         let aligner = BatchAligner::new(
             unified_schema,
@@ -262,7 +968,309 @@ mod tests {
             false,
         );
 
-        let aligned = aligner.align_batch(batch).unwrap();
-        assert_eq!(aligned.num_rows(), 3);
+        let source_columns = vec!["a".to_string(), "b".to_string()];
+        let aligned = aligner.align_batch(batch, &source_columns).unwrap();
+        assert_eq!(aligned.len(), 3);
+        assert_eq!(aligned.arrays().len(), 3);
+        assert_eq!(aligned.arrays()[2].null_count(), 3);
+
+        let b = aligned.arrays()[1].as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(b.value(2), "z");
+    }
+
+    #[test]
+    fn test_parquet_type_widening() {
+        let narrow = Schema::from(vec![
+            Field::new("n", DataType::Int32, true),
+            Field::new("f", DataType::Float32, true),
+            Field::new("t", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+        ]);
+        let wide = Schema::from(vec![
+            Field::new("n", DataType::Float64, true),
+            Field::new("f", DataType::Float64, true),
+            Field::new("t", DataType::Date32, true),
+        ]);
+        let unified_schema = Arc::new(UnifiedSchema::from_schemas(&[narrow, wide], false).unwrap());
+        let aligner = BatchAligner::new(unified_schema, HashMap::new(), None, None, false);
+
+        let batch = Chunk::new(vec![
+            Int32Array::from([Some(7), None]).boxed(),
+            Float32Array::from_slice([1.5, 2.5]).boxed(),
+            Int64Array::from_slice([2_000_000, 3_000_000])
+                .to(DataType::Timestamp(TimeUnit::Microsecond, None))
+                .boxed(),
+        ]);
+        let columns = vec!["n".to_string(), "f".to_string(), "t".to_string()];
+        let aligned = aligner.align_batch(batch, &columns).unwrap();
+
+        let n = aligned.arrays()[0].as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(n.get(0), Some(7.0));
+        assert_eq!(n.get(1), None);
+        let f = aligned.arrays()[1].as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(f.value(1), 2.5);
+        let t = aligned.arrays()[2].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(t.data_type(), &DataType::Timestamp(TimeUnit::Microsecond, None));
+        assert_eq!(t.value(0), 2_000_000);
+    }
+
+    #[test]
+    fn test_lossy_integer_widening() {
+        let unified = Schema::from(vec![
+            Field::new("small", DataType::Int8, true),
+            Field::new("f", DataType::Float32, true),
+        ]);
+        let unified_schema = Arc::new(UnifiedSchema::from_schemas(&[unified], false).unwrap());
+        let aligner = |policy| {
+            BatchAligner::new(unified_schema.clone(), HashMap::new(), None, None, false).coerce_errors(policy)
+        };
+        // 300 doesn't fit an Int8, and 2^24 + 1 has no exact Float32
+        let batch = || {
+            Chunk::new(vec![
+                Int32Array::from_slice([5, 300]).boxed(),
+                Int32Array::from_slice([16_777_217, 3]).boxed(),
+            ])
+        };
+        let columns = vec!["small".to_string(), "f".to_string()];
+
+        let aligned = aligner(CoerceErrors::Null).align_batch(batch(), &columns).unwrap();
+        let small = aligned.arrays()[0].as_any().downcast_ref::<Int8Array>().unwrap();
+        assert_eq!(small.iter().collect::<Vec<_>>(), [Some(&5), None]);
+        let f = aligned.arrays()[1].as_any().downcast_ref::<Float32Array>().unwrap();
+        assert_eq!(f.iter().collect::<Vec<_>>(), [None, Some(&3.0)]);
+
+        let aligned = aligner(CoerceErrors::Skip).align_batch(batch(), &columns).unwrap();
+        assert_eq!(aligned.len(), 0);
+
+        let error = aligner(CoerceErrors::Fail).align_batch(batch(), &columns).unwrap_err();
+        assert!(error.to_string().contains("'300'"), "{}", error);
+    }
+
+    #[test]
+    fn test_decimal_and_zoned_timestamp() {
+        let utc = Some("UTC".to_string());
+        let first = Schema::from(vec![
+            Field::new("price", DataType::Decimal(5, 1), true),
+            Field::new("at", DataType::Timestamp(TimeUnit::Second, utc.clone()), true),
+        ]);
+        let second = Schema::from(vec![
+            Field::new("price", DataType::Decimal(6, 3), true),
+            Field::new("at", DataType::Timestamp(TimeUnit::Millisecond, utc.clone()), true),
+        ]);
+        let unified_schema = Arc::new(UnifiedSchema::from_schemas(&[first, second], false).unwrap());
+        let aligner = BatchAligner::new(unified_schema, HashMap::new(), None, None, false);
+
+        let output = aligner.output_schema();
+        assert_eq!(output.fields[0].data_type(), &DataType::Decimal(7, 3));
+        assert_eq!(
+            output.fields[1].data_type(),
+            &DataType::Timestamp(TimeUnit::Millisecond, utc.clone())
+        );
+
+        // 12.5 and 1970-01-01T00:00:10Z
+        let batch = Chunk::new(vec![
+            Int128Array::from_slice([125]).to(DataType::Decimal(5, 1)).boxed(),
+            Int64Array::from_slice([10])
+                .to(DataType::Timestamp(TimeUnit::Second, utc))
+                .boxed(),
+        ]);
+        let columns = vec!["price".to_string(), "at".to_string()];
+        let aligned = aligner.align_batch(batch, &columns).unwrap();
+
+        let price = aligned.arrays()[0].as_any().downcast_ref::<Int128Array>().unwrap();
+        assert_eq!(price.value(0), 12_500);
+        let at = aligned.arrays()[1].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(at.value(0), 10_000);
+    }
+
+    #[test]
+    fn test_timestamp_too_far_for_unit() {
+        let seconds = Schema::from(vec![Field::new("at", DataType::Timestamp(TimeUnit::Second, None), true)]);
+        let nanoseconds = Schema::from(vec![Field::new("at", DataType::Timestamp(TimeUnit::Nanosecond, None), true)]);
+        let unified_schema = Arc::new(UnifiedSchema::from_schemas(&[seconds, nanoseconds], false).unwrap());
+        let aligner = |policy| {
+            BatchAligner::new(unified_schema.clone(), HashMap::new(), None, None, false).coerce_errors(policy)
+        };
+        // Ten billion seconds is in 2286, past the last nanoseconds reach
+        let batch = || {
+            Chunk::new(vec![Int64Array::from_slice([10, 10_000_000_000])
+                .to(DataType::Timestamp(TimeUnit::Second, None))
+                .boxed()])
+        };
+        let columns = vec!["at".to_string()];
+
+        let aligned = aligner(CoerceErrors::Null).align_batch(batch(), &columns).unwrap();
+        let at = aligned.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(at.iter().collect::<Vec<_>>(), [Some(&10_000_000_000), None]);
+
+        let aligned = aligner(CoerceErrors::Skip).align_batch(batch(), &columns).unwrap();
+        assert_eq!(aligned.len(), 1);
+
+        let error = aligner(CoerceErrors::Fail).align_batch(batch(), &columns).unwrap_err();
+        assert!(error.to_string().contains("in column 'at'"), "{}", error);
+    }
+
+    #[test]
+    fn test_failed_value_in_non_nullable_column() {
+        let mut accumulator = crate::schema::SchemaAccumulator::new(false).preserve_non_null(true);
+        accumulator
+            .register(0, &Schema::from(vec![Field::new("id", DataType::Int64, false)]))
+            .unwrap();
+        let unified_schema = Arc::new(accumulator.finish());
+        let aligner = |policy| {
+            BatchAligner::new(unified_schema.clone(), HashMap::new(), None, None, false).coerce_errors(policy)
+        };
+        let batch = || Chunk::new(vec![Utf8Array::<i32>::from_slice(["1", "x"]).boxed()]);
+        let columns = vec!["id".to_string()];
+
+        // Nulling the value would break the column's declaration
+        let error = aligner(CoerceErrors::Null).align_batch(batch(), &columns).unwrap_err();
+        assert_eq!(error.to_string(), "Schema error: Cannot convert 'x' in column 'id' to Int64");
+
+        let aligned = aligner(CoerceErrors::Skip).align_batch(batch(), &columns).unwrap();
+        assert_eq!(aligned.len(), 1);
+        assert_eq!(aligned.arrays()[0].null_count(), 0);
+    }
+
+    #[test]
+    fn test_integer_too_wide_for_decimal() {
+        let unified = Schema::from(vec![Field::new("price", DataType::Decimal(5, 1), true)]);
+        let unified_schema = Arc::new(UnifiedSchema::from_schemas(&[unified], false).unwrap());
+        let aligner = |policy| {
+            BatchAligner::new(unified_schema.clone(), HashMap::new(), None, None, false).coerce_errors(policy)
+        };
+        // 123456.0 needs seven digits, and i64::MAX overflows the scaling
+        let batch = || Chunk::new(vec![Int64Array::from_slice([12, 123_456, i64::MAX]).boxed()]);
+        let columns = vec!["price".to_string()];
+
+        let aligned = aligner(CoerceErrors::Null).align_batch(batch(), &columns).unwrap();
+        let price = aligned.arrays()[0].as_any().downcast_ref::<Int128Array>().unwrap();
+        assert_eq!(price.iter().collect::<Vec<_>>(), [Some(&120), None, None]);
+
+        let aligned = aligner(CoerceErrors::Skip).align_batch(batch(), &columns).unwrap();
+        assert_eq!(aligned.len(), 1);
+
+        let error = aligner(CoerceErrors::Fail).align_batch(batch(), &columns).unwrap_err();
+        assert!(error.to_string().contains("'123456'"), "{}", error);
+    }
+
+    #[test]
+    fn test_large_offsets() {
+        let schema = Schema::from(vec![
+            Field::new("s", DataType::LargeUtf8, true),
+            Field::new("b", DataType::LargeBinary, true),
+        ]);
+        let unified_schema = Arc::new(UnifiedSchema::from_schemas(&[schema], false).unwrap());
+        let aligner = BatchAligner::new(unified_schema, HashMap::new(), None, None, false);
+
+        let batch = Chunk::new(vec![
+            Utf8Array::<i64>::from([Some("x"), None]).boxed(),
+            BinaryArray::<i64>::from([Some(b"ab".as_ref()), Some(b"".as_ref())]).boxed(),
+        ]);
+        let columns = vec!["s".to_string(), "b".to_string()];
+        let aligned = aligner.align_batch(batch, &columns).unwrap();
+
+        let s = aligned.arrays()[0].as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(s.get(0), Some("x"));
+        assert_eq!(s.get(1), None);
+        let b = aligned.arrays()[1].as_any().downcast_ref::<BinaryArray<i32>>().unwrap();
+        assert_eq!(b.value(0), b"ab");
+    }
+
+    #[test]
+    fn test_downcast_mismatch() {
+        let array = Int32Array::from_slice([1, 2]);
+        let err = downcast::<Utf8Array<i32>>(&array).unwrap_err();
+        assert!(matches!(err, MawError::Arrow(_)));
+        assert!(err.to_string().contains("Expected Utf8Array<i32>, found an array of type Int32"));
+
+        assert_eq!(downcast::<Int32Array>(&array).unwrap().value(1), 2);
+    }
+
+    #[test]
+    fn test_coerce_errors() {
+        let unified = Schema::from(vec![
+            Field::new("n", DataType::Int64, true),
+            Field::new("s", DataType::Utf8, true),
+        ]);
+        let unified_schema = Arc::new(UnifiedSchema::from_schemas(&[unified], false).unwrap());
+        let batch = || {
+            Chunk::new(vec![
+                Utf8Array::<i32>::from([Some("42"), Some("abc"), None]).boxed(),
+                Utf8Array::<i32>::from_slice(["a", "b", "c"]).boxed(),
+            ])
+        };
+        let columns = vec!["n".to_string(), "s".to_string()];
+        let aligner = |policy| {
+            BatchAligner::new(unified_schema.clone(), HashMap::new(), None, None, false).coerce_errors(policy)
+        };
+
+        let aligned = aligner(CoerceErrors::Null).align_batch(batch(), &columns).unwrap();
+        let n = aligned.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(n.iter().collect::<Vec<_>>(), [Some(&42), None, None]);
+
+        // Only the unconvertible row goes; an originally null value stays
+        let aligned = aligner(CoerceErrors::Skip).align_batch(batch(), &columns).unwrap();
+        assert_eq!(aligned.len(), 2);
+        let n = aligned.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(n.iter().collect::<Vec<_>>(), [Some(&42), None]);
+        let s = aligned.arrays()[1].as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(s.iter().collect::<Vec<_>>(), [Some("a"), Some("c")]);
+
+        let error = aligner(CoerceErrors::Fail).align_batch(batch(), &columns).unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("'abc'") && message.contains("'n'"), "{}", message);
+    }
+
+    #[test]
+    fn test_fill() {
+        let unified = Schema::from(vec![
+            Field::new("n", DataType::Int64, true),
+            Field::new("s", DataType::Utf8, true),
+        ]);
+        let unified_schema = Arc::new(UnifiedSchema::from_schemas(&[unified], false).unwrap());
+        let aligner = |defaults: &[(&str, &str)]| {
+            let defaults: Vec<(String, String)> =
+                defaults.iter().map(|(c, v)| (c.to_string(), v.to_string())).collect();
+            BatchAligner::new(unified_schema.clone(), HashMap::new(), None, None, false).fill(&defaults)
+        };
+
+        // The missing string column is filled too
+        let batch = Chunk::new(vec![Int64Array::from([Some(1), None]).boxed()]);
+        let aligned = aligner(&[("n", "0"), ("s", "unknown")])
+            .unwrap()
+            .align_batch(batch, &["n".to_string()])
+            .unwrap();
+        let n = aligned.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(n.iter().collect::<Vec<_>>(), [Some(&1), Some(&0)]);
+        let s = aligned.arrays()[1].as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(s.iter().collect::<Vec<_>>(), [Some("unknown"), Some("unknown")]);
+
+        assert!(aligner(&[("n", "zero")]).is_err());
+        assert!(aligner(&[("missing", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_dictionary_columns() {
+        let unified = Schema::from(vec![
+            Field::new("n", DataType::Int64, true),
+            Field::new("s", DataType::Utf8, true),
+        ]);
+        let mut unified_schema = UnifiedSchema::from_schemas(&[unified], false).unwrap();
+        unified_schema.dictionary_columns.insert("s".to_string());
+        let aligner = BatchAligner::new(Arc::new(unified_schema), HashMap::new(), None, None, false);
+        assert!(matches!(aligner.output_schema().fields[1].data_type, DataType::Dictionary(..)));
+
+        let batch = Chunk::new(vec![
+            Int64Array::from([Some(1), Some(2), Some(3)]).boxed(),
+            Utf8Array::<i32>::from([Some("x"), None, Some("x")]).boxed(),
+        ]);
+        let aligned = aligner
+            .align_batch(batch, &["n".to_string(), "s".to_string()])
+            .unwrap();
+        let s = aligned.arrays()[1].as_any().downcast_ref::<DictionaryArray<i32>>().unwrap();
+        assert_eq!(s.values().len(), 1);
+        let values: Vec<_> = (0..s.len()).map(|i| display_value(s, i).unwrap()).collect();
+        assert_eq!(values, ["x", "", "x"]);
+        assert!(s.is_null(1));
     }
 }
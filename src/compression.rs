@@ -0,0 +1,230 @@
+use crate::{
+    cli::Compression,
+    error::{MawError, Result},
+};
+use bzip2::read::MultiBzDecoder;
+use flate2::write::GzEncoder;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+/// Default gzip level when none is given, matching the gzip tool.
+const DEFAULT_GZIP_LEVEL: u32 = 6;
+
+/// The levels a codec accepts, or `None` if it takes no level at all.
+pub fn level_range(codec: &Compression) -> Option<RangeInclusive<u32>> {
+    match codec {
+        Compression::Zstd => Some(1..=22),
+        Compression::Gzip => Some(0..=9),
+        Compression::Snappy | Compression::None => None,
+    }
+}
+
+/// Resolves the level to use for `codec` from `--compression-level`, falling
+/// back to `--zstd-level` for zstd, and checks it against the codec's range.
+pub fn resolve_level(
+    codec: &Compression,
+    compression_level: Option<u32>,
+    zstd_level: u32,
+) -> Result<Option<u32>> {
+    let Some(range) = level_range(codec) else {
+        return match compression_level {
+            Some(level) => Err(MawError::Config(format!(
+                "Compression level {} given, but {} does not take a level",
+                level, codec
+            ))),
+            None => Ok(None),
+        };
+    };
+
+    let level = match codec {
+        Compression::Zstd => compression_level.unwrap_or(zstd_level),
+        _ => compression_level.unwrap_or(DEFAULT_GZIP_LEVEL),
+    };
+
+    if !range.contains(&level) {
+        return Err(MawError::Config(format!(
+            "Invalid {} compression level {}: must be between {} and {}",
+            codec,
+            level,
+            range.start(),
+            range.end()
+        )));
+    }
+
+    Ok(Some(level))
+}
+
+/// Rough compressed-to-raw size ratio of `codec` on typical tabular data,
+/// for estimates only.
+pub fn estimated_ratio(codec: &Compression) -> f64 {
+    match codec {
+        Compression::None => 1.0,
+        Compression::Snappy => 0.5,
+        Compression::Gzip => 0.3,
+        Compression::Zstd => 0.25,
+    }
+}
+
+/// Whether an input is bzip2-compressed as a whole, going by its name
+/// (`data.csv.bz2`).
+pub fn is_bzip2(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("bz2"))
+}
+
+/// Bytes buffered when reading without a configured size, as `BufReader`
+/// does by default.
+pub const DEFAULT_READ_BUFFER: usize = 8 * 1024;
+
+/// Opens an input for reading, decompressing bzip2 inputs as they are read
+/// so a large file is never held in memory. Streams concatenated by
+/// parallel compressors like pbzip2 are read one after another. With
+/// `read_buffer`, the file is read that many bytes at a time, which
+/// network filesystems serve faster than many small reads.
+pub fn open_input(path: &Path, read_buffer: Option<usize>) -> Result<Box<dyn Read + Send>> {
+    let file = File::open(path)?;
+    if is_bzip2(path) {
+        let capacity = read_buffer.unwrap_or(DEFAULT_READ_BUFFER);
+        Ok(Box::new(MultiBzDecoder::new(BufReader::with_capacity(capacity, file))))
+    } else if let Some(capacity) = read_buffer {
+        Ok(Box::new(BufReader::with_capacity(capacity, file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// The codec implied by a compressed output extension such as
+/// `out.csv.gz`, if any.
+pub fn codec_from_extension(path: &Path) -> Option<Compression> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "gz" => Some(Compression::Gzip),
+        "zst" => Some(Compression::Zstd),
+        "sz" => Some(Compression::Snappy),
+        _ => None,
+    }
+}
+
+/// A streaming encoder for compressed text output.
+pub enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(Box<zstd::Encoder<'static, W>>),
+    Snappy(Box<snap::write::FrameEncoder<W>>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(inner: W, codec: &Compression, level: Option<u32>) -> Result<Self> {
+        Ok(match codec {
+            Compression::None => Encoder::Plain(inner),
+            Compression::Gzip => Encoder::Gzip(GzEncoder::new(
+                inner,
+                flate2::Compression::new(level.unwrap_or(DEFAULT_GZIP_LEVEL)),
+            )),
+            Compression::Zstd => Encoder::Zstd(Box::new(zstd::Encoder::new(
+                inner,
+                level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| l as i32),
+            )?)),
+            Compression::Snappy => Encoder::Snappy(Box::new(snap::write::FrameEncoder::new(inner))),
+        })
+    }
+
+    /// Writes any trailing frame data and returns the inner writer.
+    pub fn finish(self) -> Result<W> {
+        Ok(match self {
+            Encoder::Plain(inner) => inner,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+            Encoder::Snappy(encoder) => encoder
+                .into_inner()
+                .map_err(|e| MawError::Io(e.into_error()))?,
+        })
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(inner) => inner.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+            Encoder::Snappy(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(inner) => inner.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+            Encoder::Snappy(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_levels() {
+        assert_eq!(resolve_level(&Compression::Zstd, None, 3).unwrap(), Some(3));
+        assert_eq!(resolve_level(&Compression::Zstd, Some(22), 3).unwrap(), Some(22));
+        assert_eq!(resolve_level(&Compression::Gzip, None, 3).unwrap(), Some(6));
+        assert_eq!(resolve_level(&Compression::Gzip, Some(0), 3).unwrap(), Some(0));
+        assert_eq!(resolve_level(&Compression::Snappy, None, 3).unwrap(), None);
+        assert_eq!(resolve_level(&Compression::None, None, 30).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_levels() {
+        assert!(resolve_level(&Compression::Zstd, Some(0), 3).is_err());
+        assert!(resolve_level(&Compression::Zstd, Some(23), 3).is_err());
+        assert!(resolve_level(&Compression::Zstd, None, 30).is_err());
+        assert!(resolve_level(&Compression::Gzip, Some(10), 3).is_err());
+
+        let err = resolve_level(&Compression::Snappy, Some(1), 3).unwrap_err();
+        assert!(matches!(err, MawError::Config(_)));
+        assert!(err.to_string().contains("snappy does not take a level"));
+    }
+
+    #[test]
+    fn test_encoder_round_trip() {
+        use std::io::Read;
+
+        let data = b"a,b\n1,2\n".repeat(100);
+
+        let mut encoder = Encoder::new(Vec::new(), &Compression::Gzip, Some(9)).unwrap();
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let mut encoder = Encoder::new(Vec::new(), &Compression::Zstd, Some(3)).unwrap();
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(zstd::decode_all(compressed.as_slice()).unwrap(), data);
+
+        let mut encoder = Encoder::new(Vec::new(), &Compression::Snappy, None).unwrap();
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut decoded = Vec::new();
+        snap::read::FrameDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        assert!(matches!(
+            codec_from_extension(Path::new("out.csv.gz")),
+            Some(Compression::Gzip)
+        ));
+        assert!(codec_from_extension(Path::new("out.csv")).is_none());
+    }
+}
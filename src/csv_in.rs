@@ -1,62 +1,293 @@
-use crate::error::Result;
+use crate::{
+    cli::Ragged,
+    compression,
+    error::{MawError, Result},
+    rejects::RejectedRow,
+    schema::{parse_decimal, MAX_DECIMAL_PRECISION},
+};
 use arrow2::{
-    array::{Array, BooleanArray, Float64Array, Int64Array, Utf8Array},
+    array::{Array, BooleanArray, Float64Array, Int128Array, Int64Array, Utf8Array},
     datatypes::DataType,
     chunk::Chunk,
 };
 use csv::{ByteRecord, ReaderBuilder};
 use encoding_rs::{Encoding, UTF_8};
+use globset::{GlobBuilder, GlobMatcher};
 use std::{
-    fs::File,
-    io::Read,
+    collections::VecDeque,
+    io::{Cursor, Read},
     path::Path,
 };
 
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+/// Delimiters tried, in order of preference, when none is configured.
+const CANDIDATE_DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+/// Bytes read from the start of a file when sniffing its delimiter.
+const SNIFF_BYTES: u64 = 16 * 1024;
+
+/// Bytes from the start of an input checked for signs of binary data.
+const BINARY_SAMPLE_BYTES: u64 = 1024;
+
+/// Share of control bytes in that sample above which the input is taken
+/// for binary data; text has next to none.
+const BINARY_CONTROL_SHARE: f64 = 0.05;
+
+/// Rows sampled below the first one when detecting a header row.
+const HEADER_SAMPLE_ROWS: usize = 100;
+
 pub struct CsvReader {
     reader: csv::Reader<Box<dyn Read + Send>>,
     headers: Vec<String>,
+    /// Records read ahead while detecting a header, returned before the rest
+    pending: VecDeque<ByteRecord>,
     batch_size: usize,
     na_values: Vec<String>,
     encoding: &'static Encoding,
+    strip_bom: bool,
+    empty_as_null: bool,
+    nan_token: String,
+    inf_token: String,
+    non_finite_as_null: bool,
+    infer_types: bool,
+    infer_columns: Option<InferColumns>,
+    infer_decimal: bool,
+    decimal_columns: Vec<String>,
+    max_columns: Option<usize>,
+    validate_field_count: bool,
+    reject_rows: bool,
+    ragged: Ragged,
+    /// Rows set aside for having the wrong field count, until taken
+    rejected_rows: Vec<RejectedRow>,
+    delimiter: u8,
+    /// Positions are in decompressed bytes, which don't match the file size
+    compressed: bool,
 }
 
+#[derive(Debug, Clone)]
 pub struct CsvConfig {
     pub delimiter: Option<u8>,
+    /// Delimiters for files matching a glob, overriding `delimiter`
+    pub delimiter_overrides: Vec<(GlobMatcher, u8)>,
     pub quote: Option<u8>,
     pub has_headers: bool,
+    /// Without headers, use the first row as headers anyway when it looks
+    /// like labels above typed data
+    pub detect_header: bool,
     pub encoding: String,
+    /// Encodings for files matching a glob, overriding `encoding`
+    pub encoding_overrides: Vec<(GlobMatcher, String)>,
     pub na_values: Vec<String>,
     pub batch_size: usize,
+    pub strip_bom: bool,
+    /// Read empty fields as nulls rather than empty strings
+    pub empty_as_null: bool,
+    /// Read as NaN in float columns, besides the spellings Rust parses
+    pub nan_token: String,
+    /// Read as infinity in float columns, or negative infinity with a
+    /// leading '-'
+    pub inf_token: String,
+    /// Read NaN and infinite floats as nulls
+    pub non_finite_as_null: bool,
+    /// Classify columns as integers, floats or booleans; without it every
+    /// column is read as text, exactly as written
+    pub infer_types: bool,
+    /// Classify only these columns, reading the rest as text
+    pub infer_columns: Option<InferColumns>,
+    /// Read columns of fixed-point numbers, such as `19.99`, as decimals
+    /// rather than floats, which can't hold every value exactly
+    pub infer_decimal: bool,
+    /// Read these columns as decimals, even when inference is off for them
+    /// or their values are whole
+    pub decimal_columns: Vec<String>,
+    /// Fail on a header or row wider than this, as a wrong delimiter can
+    /// split a file into far more columns than it has
+    pub max_columns: Option<usize>,
+    /// Check each row's field count against the header's, failing on a row
+    /// that differs
+    pub validate_field_count: bool,
+    /// Set rows that can't be read aside for the rejects file instead of
+    /// failing: CSV rows with the wrong field count that `ragged` doesn't
+    /// read anyway, and JSON lines that aren't objects
+    pub reject_rows: bool,
+    /// Which rows with the wrong field count are read anyway, padded with
+    /// nulls or cut to the header's width
+    pub ragged: Ragged,
+    /// Bytes read from a CSV or JSON file at a time, if not left to the
+    /// parser
+    pub read_buffer: Option<usize>,
+}
+
+/// The columns whose types are inferred (`--infer-columns`), which on a
+/// very wide file saves classifying the ones nobody needs typed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InferColumns {
+    /// The first N columns
+    First(usize),
+    /// Columns with these names, as they appear in the input
+    Named(Vec<String>),
+}
+
+impl InferColumns {
+    /// Reads a count, or else a comma-separated list of names.
+    pub fn parse(spec: &str) -> Self {
+        match spec.trim().parse() {
+            Ok(count) => InferColumns::First(count),
+            Err(_) => InferColumns::Named(spec.split(',').map(|s| s.trim().to_string()).collect()),
+        }
+    }
+
+    fn includes(&self, index: usize, name: &str) -> bool {
+        match self {
+            InferColumns::First(count) => index < *count,
+            InferColumns::Named(names) => names.iter().any(|n| n == name),
+        }
+    }
 }
 
 impl Default for CsvConfig {
     fn default() -> Self {
         Self {
             delimiter: None,
+            delimiter_overrides: Vec::new(),
             quote: None,
             has_headers: true,
+            detect_header: false,
             encoding: "utf8".to_string(),
+            encoding_overrides: Vec::new(),
             na_values: vec!["NA".to_string(), "null".to_string(), "\\N".to_string()],
             batch_size: 64_000,
+            strip_bom: true,
+            empty_as_null: true,
+            nan_token: "NaN".to_string(),
+            inf_token: "inf".to_string(),
+            non_finite_as_null: false,
+            infer_types: true,
+            infer_columns: None,
+            infer_decimal: false,
+            decimal_columns: Vec::new(),
+            max_columns: None,
+            validate_field_count: false,
+            reject_rows: false,
+            ragged: Ragged::Error,
+            read_buffer: None,
         }
     }
 }
 
+impl CsvConfig {
+    /// The encoding of the file at `path`: that of the first matching
+    /// override, or the global one.
+    pub fn encoding_for(&self, path: &Path) -> &str {
+        self.encoding_overrides
+            .iter()
+            .find(|(glob, _)| glob.is_match(path))
+            .map_or(&self.encoding, |(_, encoding)| encoding)
+    }
+
+    /// The configured delimiter of the file at `path`: that of the first
+    /// matching override, or the global one. `None` means it is sniffed.
+    pub fn delimiter_for(&self, path: &Path) -> Option<u8> {
+        self.delimiter_overrides
+            .iter()
+            .find(|(glob, _)| glob.is_match(path))
+            .map(|(_, delimiter)| *delimiter)
+            .or(self.delimiter)
+    }
+}
+
+/// Parses an `--encoding-for` override of the form `glob=encoding`. A
+/// relative glob matches at any depth, so `legacy/*.csv` matches
+/// `data/legacy/a.csv`.
+pub fn parse_encoding_override(spec: &str) -> Result<(GlobMatcher, String)> {
+    let (glob, encoding) = parse_glob_override("--encoding-for", "encoding", spec)?;
+    Ok((glob, encoding.trim().to_string()))
+}
+
+/// Parses a `--delimiter-for` override of the form `glob=char`, matching
+/// globs as `--encoding-for` does.
+pub fn parse_delimiter_override(spec: &str) -> Result<(GlobMatcher, u8)> {
+    let (glob, delimiter) = parse_glob_override("--delimiter-for", "char", spec)?;
+    match delimiter.as_bytes() {
+        [delimiter] => Ok((glob, *delimiter)),
+        _ => Err(MawError::Config(format!(
+            "Invalid --delimiter-for '{}', the delimiter must be a single ASCII character",
+            spec
+        ))),
+    }
+}
+
+/// Fails on an input whose first bytes are largely NUL and other control
+/// characters, such as an image or a Parquet file named `.csv`, which would
+/// otherwise be parsed into nonsense. The sampled bytes are put back, so
+/// this works on stdin too.
+fn check_text(mut reader: Box<dyn Read + Send>) -> Result<Box<dyn Read + Send>> {
+    let mut sample = Vec::with_capacity(BINARY_SAMPLE_BYTES as usize);
+    (&mut reader).take(BINARY_SAMPLE_BYTES).read_to_end(&mut sample)?;
+
+    let control = sample
+        .iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c)) || b == 0x7f)
+        .count();
+    if !sample.is_empty() && control as f64 / sample.len() as f64 > BINARY_CONTROL_SHARE {
+        return Err(MawError::InvalidInput(format!(
+            "The input looks like binary data, not CSV: {} of its first {} bytes are NUL or control characters; \
+             is it the right file?",
+            control,
+            sample.len()
+        )));
+    }
+    Ok(Box::new(Cursor::new(sample).chain(reader)))
+}
+
+/// Splits `glob=value` at its last `=`, for the `flag` overriding an
+/// option for matching files. The value is left untrimmed, as a delimiter
+/// may be a space or tab.
+fn parse_glob_override(flag: &str, value: &str, spec: &str) -> Result<(GlobMatcher, String)> {
+    let (pattern, setting) = spec.rsplit_once('=').ok_or_else(|| {
+        MawError::Config(format!("Invalid {} '{}', expected glob={}", flag, spec, value))
+    })?;
+    let pattern = pattern.trim();
+    let pattern = if Path::new(pattern).is_absolute() || pattern.starts_with("**") {
+        pattern.to_string()
+    } else {
+        format!("**/{}", pattern.trim_start_matches("./"))
+    };
+    let glob = GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| MawError::Config(format!("Invalid {} glob '{}': {}", flag, pattern, e)))?;
+    Ok((glob.compile_matcher(), setting.to_string()))
+}
+
 impl CsvReader {
     pub fn new<P: AsRef<Path>>(path: P, config: &CsvConfig) -> Result<Self> {
         let path = path.as_ref();
+        let is_stdin = path.to_string_lossy() == "-";
         
-        let reader: Box<dyn Read + Send> = if path.to_string_lossy() == "-" {
+        let reader: Box<dyn Read + Send> = if is_stdin {
             Box::new(std::io::stdin())
         } else {
-            Box::new(File::open(path)?)
+            compression::open_input(path, config.read_buffer)?
         };
+        let reader = check_text(reader)?;
 
         let mut builder = ReaderBuilder::new();
         
-        if let Some(delimiter) = config.delimiter {
-            builder.delimiter(delimiter);
-        }
+        // Stdin can't be rewound after sniffing, so it defaults to a comma
+        let delimiter = match config.delimiter_for(path) {
+            Some(delimiter) => delimiter,
+            None if is_stdin => b',',
+            None => input_delimiter(path)?,
+        };
+        builder.delimiter(delimiter);
+        // Headers are read through the csv crate only when the input has
+        // them, so a headerless input keeps its first row as data
+        builder.has_headers(config.has_headers);
+        // Field counts are then checked against the header below, rather
+        // than by the parser, which would fail on any row that differs
+        builder.flexible(config.validate_field_count || config.reject_rows || config.ragged != Ragged::Error);
         
         if let Some(quote) = config.quote {
             builder.quote(quote);
@@ -64,43 +295,153 @@ impl CsvReader {
 
         let mut reader = builder.from_reader(reader);
         
-        // Read headers
+        // Read headers; headerless inputs get theirs below
         let headers = if config.has_headers {
             reader.headers()?.iter()
                 .map(|h| h.to_string())
                 .collect()
         } else {
-            // Generate synthetic headers
-            let first_record = reader.byte_headers()?;
-            (0..first_record.len())
-                .map(|i| format!("col_{}", i + 1))
-                .collect()
+            Vec::new()
         };
 
-        let encoding = match config.encoding.to_lowercase().as_str() {
+        let encoding = match config.encoding_for(path).to_lowercase().as_str() {
             "utf8" | "utf-8" => UTF_8,
             "latin1" | "iso-8859-1" => encoding_rs::WINDOWS_1252,
             _ => UTF_8,
         };
 
-        Ok(Self {
+        let mut csv_reader = Self {
             reader,
             headers,
+            pending: VecDeque::new(),
             batch_size: config.batch_size,
             na_values: config.na_values.clone(),
             encoding,
-        })
+            strip_bom: config.strip_bom,
+            empty_as_null: config.empty_as_null,
+            nan_token: config.nan_token.clone(),
+            inf_token: config.inf_token.clone(),
+            non_finite_as_null: config.non_finite_as_null,
+            infer_types: config.infer_types,
+            infer_columns: config.infer_columns.clone(),
+            infer_decimal: config.infer_decimal,
+            decimal_columns: config.decimal_columns.clone(),
+            max_columns: config.max_columns,
+            validate_field_count: config.validate_field_count,
+            reject_rows: config.reject_rows,
+            ragged: config.ragged,
+            rejected_rows: Vec::new(),
+            delimiter,
+            compressed: compression::is_bzip2(path),
+        };
+
+        if !config.has_headers {
+            csv_reader.headers = csv_reader.headerless_headers(config.detect_header)?;
+        }
+        csv_reader.check_width(csv_reader.headers.len(), "The header")?;
+
+        Ok(csv_reader)
+    }
+
+    /// Fails if a header or row of `width` columns is over `max_columns`.
+    fn check_width(&self, width: usize, what: &str) -> Result<()> {
+        match self.max_columns {
+            Some(max) if width > max => Err(MawError::InvalidInput(format!(
+                "{} has {} columns, more than --max-columns {}; is '{}' the right delimiter?",
+                what,
+                width,
+                max,
+                (self.delimiter as char).escape_default()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Names the columns of an input read without headers: `col_1`, `col_2`
+    /// and so on, or the first row's values if header detection is on and
+    /// that row looks like a header.
+    fn headerless_headers(&mut self, detect: bool) -> Result<Vec<String>> {
+        let width = self.reader.byte_headers()?.len();
+
+        if detect {
+            for _ in 0..=HEADER_SAMPLE_ROWS {
+                let mut record = ByteRecord::new();
+                if !self.reader.read_byte_record(&mut record)? {
+                    break;
+                }
+                self.pending.push_back(record);
+            }
+
+            if self.first_row_is_header()? {
+                let first = self.pending.pop_front().unwrap();
+                tracing::info!("Detected a header row in input without headers");
+                return first.iter().map(|field| self.decode_field(field)).collect();
+            }
+        }
+
+        Ok((0..width).map(|i| format!("col_{}", i + 1)).collect())
+    }
+
+    /// A first row is taken as a header when every value in it is a
+    /// non-empty label that doesn't parse as a number or boolean, while at
+    /// least one column holds only such typed values in the rows below.
+    fn first_row_is_header(&self) -> Result<bool> {
+        let Some(first) = self.pending.front() else {
+            return Ok(false);
+        };
+
+        let is_typed = |value: &str| {
+            self.parse_float(value).is_some() || value.parse::<bool>().is_ok()
+        };
+
+        let mut has_typed_column = false;
+        for col_idx in 0..first.len() {
+            let label = self.decode_field(&first[col_idx])?;
+            if label.is_empty() || is_typed(&label) {
+                return Ok(false);
+            }
+
+            let mut seen = 0;
+            let mut all_typed = true;
+            for record in self.pending.iter().skip(1) {
+                let value = match record.get(col_idx) {
+                    Some(field) => self.decode_field(field)?,
+                    None => continue,
+                };
+                if value.is_empty() || self.na_values.contains(&value) {
+                    continue;
+                }
+                seen += 1;
+                all_typed &= is_typed(&value);
+            }
+            has_typed_column |= seen > 0 && all_typed;
+        }
+
+        Ok(has_typed_column)
     }
 
     pub fn read_batch(&mut self) -> Result<Option<Chunk<Box<dyn Array>>>> {
         let mut records = Vec::with_capacity(self.batch_size);
         
-        for _ in 0..self.batch_size {
+        while records.len() < self.batch_size {
+            if let Some(record) = self.pending.pop_front() {
+                if self.check_field_count(&record)? {
+                    records.push(record);
+                }
+                continue;
+            }
+
             let mut record = ByteRecord::new();
             if !self.reader.read_byte_record(&mut record)? {
                 break;
             }
-            records.push(record);
+            if self.max_columns.is_some() {
+                let line = record.position().map_or(0, |p| p.line());
+                self.check_width(record.len(), &format!("Line {}", line))?;
+            }
+            if self.check_field_count(&record)? {
+                records.push(record);
+            }
         }
 
         if records.is_empty() {
@@ -112,6 +453,38 @@ impl CsvReader {
         Ok(Some(batch))
     }
 
+    /// Fails on a row whose field count differs from the header's, unless
+    /// the `ragged` policy reads it anyway, or sets it aside and returns
+    /// false under `reject_rows`.
+    fn check_field_count(&mut self, record: &ByteRecord) -> Result<bool> {
+        let tolerated = match self.ragged {
+            // Checked by the parser, unless validating or rejecting
+            Ragged::Error => !self.validate_field_count && !self.reject_rows,
+            Ragged::Pad => record.len() < self.headers.len(),
+            Ragged::Truncate => true,
+        };
+        if tolerated || record.len() == self.headers.len() {
+            return Ok(true);
+        }
+        let reason = format!(
+            "Line {} has {} fields, but the header has {}",
+            record.position().map_or(0, |p| p.line()),
+            record.len(),
+            self.headers.len()
+        );
+        if !self.reject_rows {
+            return Err(MawError::InvalidInput(reason));
+        }
+        let fields = record.iter().map(|field| self.decode_field(field)).collect::<Result<_>>()?;
+        self.rejected_rows.push(RejectedRow { fields, reason });
+        Ok(false)
+    }
+
+    /// Rows set aside since the last call for having the wrong field count.
+    pub fn take_rejected_rows(&mut self) -> Vec<RejectedRow> {
+        std::mem::take(&mut self.rejected_rows)
+    }
+
     fn records_to_batch(&self, records: &[ByteRecord]) -> Result<Chunk<Box<dyn Array>>> {
         let num_columns = self.headers.len();
         let mut columns: Vec<Box<dyn Array>> = Vec::with_capacity(num_columns);
@@ -126,7 +499,10 @@ impl CsvReader {
                     let field = &record[col_idx];
                     let field_str = self.decode_field(field)?;
                     
-                    if self.na_values.contains(&field_str) {
+                    if field_str.is_empty() && !self.empty_as_null {
+                        values.push(Some(field_str));
+                        nulls.push(false);
+                    } else if field_str.is_empty() || self.na_values.contains(&field_str) {
                         values.push(None);
                         nulls.push(true);
                     } else {
@@ -140,7 +516,17 @@ impl CsvReader {
             }
 
             // Infer column type and create array
-            let array = self.create_column_array(&values, &nulls)?;
+            let infer = self.infer_types
+                && self.infer_columns.as_ref().is_none_or(|c| c.includes(col_idx, column_name));
+            let decimal = self.decimal_columns.contains(column_name).then(|| decimal_array(&values));
+            let array = match decimal {
+                Some(Ok(array)) => array,
+                Some(Err(reason)) => {
+                    tracing::warn!("--decimal-columns column '{}' {}; inferring its type instead", column_name, reason);
+                    self.create_column_array(&values, &nulls, infer)?
+                }
+                None => self.create_column_array(&values, &nulls, infer)?,
+            };
             columns.push(array);
         }
 
@@ -154,14 +540,15 @@ impl CsvReader {
     }
 
     fn decode_field(&self, field: &[u8]) -> Result<String> {
-        // Handle BOM
-        let field = if field.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        // Handle BOM. The csv parser already drops one at the very start of
+        // the input, so this only catches BOMs embedded later on.
+        let field = if self.strip_bom && field.starts_with(&UTF8_BOM) {
             &field[3..]
         } else {
             field
         };
 
-        let (decoded, _, had_errors) = self.encoding.decode(field);
+        let (decoded, had_errors) = self.encoding.decode_without_bom_handling(field);
         if had_errors {
             tracing::warn!("Encoding errors detected in field, using lossy conversion");
         }
@@ -172,22 +559,23 @@ impl CsvReader {
         &self,
         values: &[Option<String>],
         nulls: &[bool],
+        infer: bool,
     ) -> Result<Box<dyn Array>> {
         // Try to infer the best type for this column
-        let mut has_strings = false;
+        let mut has_strings = !infer;
         let mut has_ints = false;
         let mut has_floats = false;
         let mut has_bools = false;
 
         for (value, is_null) in values.iter().zip(nulls.iter()) {
-            if *is_null {
+            if *is_null || has_strings {
                 continue;
             }
             
             if let Some(val) = value {
                 if val.parse::<i64>().is_ok() {
                     has_ints = true;
-                } else if val.parse::<f64>().is_ok() {
+                } else if self.parse_float(val).is_some() {
                     has_floats = true;
                 } else if val.parse::<bool>().is_ok() {
                     has_bools = true;
@@ -204,10 +592,13 @@ impl CsvReader {
                 .map(|v| v.as_ref().map(|s| s.as_str()))
                 .collect();
             Ok(Box::new(Utf8Array::<i32>::from(string_values)))
+        } else if let Some(Ok(array)) = (has_floats && self.infer_decimal).then(|| decimal_array(values)) {
+            Ok(array)
         } else if has_floats {
             // Float array
             let float_values: Vec<Option<f64>> = values.iter()
-                .map(|v| v.as_ref().and_then(|s| s.parse().ok()))
+                .map(|v| v.as_ref().and_then(|s| self.parse_float(s)))
+                .map(|v| v.filter(|f| f.is_finite() || !self.non_finite_as_null))
                 .collect();
             Ok(Box::new(Float64Array::from(float_values)))
         } else if has_ints {
@@ -231,9 +622,159 @@ impl CsvReader {
         }
     }
 
+    /// Parses a float, also accepting the configured NaN and infinity
+    /// tokens. Non-finite values are returned even when they are to be read
+    /// as nulls, so they still count as floats during inference.
+    fn parse_float(&self, value: &str) -> Option<f64> {
+        if value == self.nan_token {
+            Some(f64::NAN)
+        } else if value == self.inf_token {
+            Some(f64::INFINITY)
+        } else if value.strip_prefix('-') == Some(self.inf_token.as_str()) {
+            Some(f64::NEG_INFINITY)
+        } else {
+            value.parse().ok()
+        }
+    }
+
     pub fn get_headers(&self) -> &[String] {
         &self.headers
     }
+
+    /// The delimiter in use, either configured or sniffed from the input.
+    pub fn delimiter(&self) -> u8 {
+        self.delimiter
+    }
+
+    /// Number of input bytes consumed so far, or `None` for a compressed
+    /// input, whose decompressed position says little about the file.
+    pub fn bytes_read(&self) -> Option<u64> {
+        (!self.compressed).then(|| self.reader.position().byte())
+    }
+
+    /// Where the next record to be returned starts, in the (decompressed)
+    /// input. A run resumed from it with `skip_to` reads on from there.
+    pub fn record_offset(&self) -> u64 {
+        match self.pending.front() {
+            Some(record) => record_start(record),
+            None => self.reader.position().byte(),
+        }
+    }
+
+    /// Skips the records that start before byte `offset`, as saved by an
+    /// earlier run from `record_offset`. They are parsed to find where
+    /// they end, as a compressed input can't be seeked, but not
+    /// converted.
+    pub fn skip_to(&mut self, offset: u64) -> Result<()> {
+        while self.pending.front().is_some_and(|record| record_start(record) < offset) {
+            self.pending.pop_front();
+        }
+        if !self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut record = ByteRecord::new();
+        while self.reader.read_byte_record(&mut record)? {
+            if record_start(&record) >= offset {
+                self.pending.push_back(record);
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn record_start(record: &ByteRecord) -> u64 {
+    record.position().map_or(0, |p| p.byte())
+}
+
+/// Reads a column of fixed-point numbers as a decimal with enough digits
+/// before and after the point for every value. Fails, saying why, on a
+/// value that isn't one.
+fn decimal_array(values: &[Option<String>]) -> std::result::Result<Box<dyn Array>, String> {
+    let parsed = values
+        .iter()
+        .map(|v| match v {
+            Some(value) => parse_decimal(value)
+                .map(Some)
+                .ok_or_else(|| format!("has '{}', which isn't a fixed-point number", value)),
+            None => Ok(None),
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let integer_digits = parsed.iter().flatten().map(|(_, digits, _)| *digits).max().unwrap_or(0);
+    let scale = parsed.iter().flatten().map(|(_, _, scale)| *scale).max().unwrap_or(0);
+    let precision = (integer_digits + scale).max(1);
+    if precision > MAX_DECIMAL_PRECISION {
+        return Err(format!("needs {} digits, more than a decimal holds", precision));
+    }
+    let unscaled = parsed
+        .into_iter()
+        .map(|v| v.map(|(value, _, value_scale)| value * 10i128.pow((scale - value_scale) as u32)));
+    Ok(Int128Array::from_iter(unscaled).to(DataType::Decimal(precision, scale)).boxed())
+}
+
+/// The delimiter a file's extension implies, looking past a compression
+/// suffix: `|` for `.psv`. Other extensions say nothing about it.
+pub fn extension_delimiter(path: &Path) -> Option<u8> {
+    let path = if compression::is_bzip2(path) || compression::codec_from_extension(path).is_some() {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    };
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "psv" => Some(b'|'),
+        _ => None,
+    }
+}
+
+/// The delimiter of a CSV input without a configured one: the one its
+/// extension implies, else sniffed from its first lines.
+pub fn input_delimiter(path: &Path) -> Result<u8> {
+    match extension_delimiter(path) {
+        Some(delimiter) => Ok(delimiter),
+        None => sniff_delimiter(path),
+    }
+}
+
+/// Guesses the delimiter of a CSV file by parsing its first lines with each
+/// candidate and keeping the one that yields the most fields with a
+/// consistent count per line. Falls back to a comma.
+pub fn sniff_delimiter<P: AsRef<Path>>(path: P) -> Result<u8> {
+    let mut sample = Vec::new();
+    compression::open_input(path.as_ref(), None)?.take(SNIFF_BYTES).read_to_end(&mut sample)?;
+
+    // Drop a trailing partial line so it can't skew the field counts. Lines
+    // may end in a bare \r (classic Mac), which the parser also accepts.
+    if sample.len() as u64 == SNIFF_BYTES {
+        if let Some(end) = sample.iter().rposition(|&b| matches!(b, b'\n' | b'\r')) {
+            sample.truncate(end + 1);
+        }
+    }
+
+    let mut best = (b',', 1);
+    for &candidate in &CANDIDATE_DELIMITERS {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(candidate)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(sample.as_slice());
+
+        let counts: Vec<usize> = reader
+            .byte_records()
+            .filter_map(|r| r.ok())
+            .map(|r| r.len())
+            .collect();
+
+        let Some(&first) = counts.first() else {
+            continue;
+        };
+        let consistent = counts.iter().all(|&c| c == first);
+        if consistent && first > best.1 {
+            best = (candidate, first);
+        }
+    }
+
+    Ok(best.0)
 }
 
 #[cfg(test)]
@@ -252,8 +793,35 @@ mod tests {
         let mut reader = CsvReader::new(&csv_file, &config).unwrap();
         
         let batch = reader.read_batch().unwrap().unwrap();
-        assert_eq!(batch.num_rows(), 2);
-        assert_eq!(batch.num_columns(), 3);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.arrays().len(), 3);
+    }
+
+    #[test]
+    fn test_last_record_without_newline() {
+        let temp_dir = tempdir().unwrap();
+        let inputs = [
+            ("plain.csv", "a,b\n1,x\n2,y\n3,z"),
+            ("quoted.csv", "a,b\n1,x\n2,y\n3,\"z\""),
+            ("crlf.csv", "a,b\r\n1,x\r\n2,y\r\n3,z"),
+            ("cr.csv", "a,b\r1,x\r2,y\r3,z\r"),
+        ];
+        for (name, content) in inputs {
+            let csv_file = temp_dir.path().join(name);
+            fs::write(&csv_file, content).unwrap();
+
+            // Including batch sizes that end a batch right before the last record
+            for batch_size in 1..=4 {
+                let config = CsvConfig { batch_size, ..CsvConfig::default() };
+                let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+                let mut values = Vec::new();
+                while let Some(batch) = reader.read_batch().unwrap() {
+                    let b = batch.arrays()[1].as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+                    values.extend(b.iter().map(|v| v.unwrap().to_string()));
+                }
+                assert_eq!(values, ["x", "y", "z"], "{} in batches of {}", name, batch_size);
+            }
+        }
     }
 
     #[test]
@@ -267,12 +835,310 @@ mod tests {
         let mut reader = CsvReader::new(&csv_file, &config).unwrap();
         
         let batch = reader.read_batch().unwrap().unwrap();
-        assert_eq!(batch.num_rows(), 2);
-        assert_eq!(batch.num_columns(), 3);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.arrays().len(), 3);
         
         let headers = reader.get_headers();
         assert_eq!(headers[0], "col_1");
         assert_eq!(headers[1], "col_2");
         assert_eq!(headers[2], "col_3");
     }
+
+    #[test]
+    fn test_sniff_delimiter() {
+        let temp_dir = tempdir().unwrap();
+
+        let semicolon = temp_dir.path().join("semicolon.csv");
+        fs::write(&semicolon, "a;b;c\n1;2,5;3\n4;5;6\n").unwrap();
+        assert_eq!(sniff_delimiter(&semicolon).unwrap(), b';');
+
+        let tab = temp_dir.path().join("tab.tsv");
+        fs::write(&tab, "a\tb\n1\t2\n").unwrap();
+        assert_eq!(sniff_delimiter(&tab).unwrap(), b'\t');
+
+        let single = temp_dir.path().join("single.csv");
+        fs::write(&single, "a\n1\n").unwrap();
+        assert_eq!(sniff_delimiter(&single).unwrap(), b',');
+
+        let config = CsvConfig::default();
+        let reader = CsvReader::new(&semicolon, &config).unwrap();
+        assert_eq!(reader.delimiter(), b';');
+        assert_eq!(reader.get_headers(), &["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_cr_line_endings() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("mac.csv");
+        let mut content = String::from("a;b;c\r");
+        for i in 0..2000 {
+            content.push_str(&format!("{};namexx {};{}\r", i, i, i * 2));
+        }
+        fs::write(&csv_file, content).unwrap();
+
+        // The sniffing sample ends partway through a line, which has to be
+        // dropped at its \r for the line to count as a line
+        assert_eq!(sniff_delimiter(&csv_file).unwrap(), b';');
+
+        let mut reader = CsvReader::new(&csv_file, &CsvConfig::default()).unwrap();
+        assert_eq!(reader.get_headers(), &["a", "b", "c"]);
+        let mut rows = 0;
+        while let Some(batch) = reader.read_batch().unwrap() {
+            rows += batch.len();
+        }
+        assert_eq!(rows, 2000);
+    }
+
+    #[test]
+    fn test_strip_bom_configurable() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "a\n\u{feff}x\n").unwrap();
+
+        let config = CsvConfig::default();
+        let reader = CsvReader::new(&csv_file, &config).unwrap();
+        assert_eq!(reader.decode_field("\u{feff}x".as_bytes()).unwrap(), "x");
+
+        let config = CsvConfig {
+            strip_bom: false,
+            ..CsvConfig::default()
+        };
+        let reader = CsvReader::new(&csv_file, &config).unwrap();
+        assert_eq!(reader.decode_field("\u{feff}x".as_bytes()).unwrap(), "\u{feff}x");
+    }
+
+    #[test]
+    fn test_empty_as() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "a,b\nx,\ny,NA\n").unwrap();
+
+        let config = CsvConfig::default();
+        let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+        let batch = reader.read_batch().unwrap().unwrap();
+        assert!(batch.arrays()[1].is_null(0));
+        assert!(batch.arrays()[1].is_null(1));
+
+        let config = CsvConfig {
+            empty_as_null: false,
+            ..CsvConfig::default()
+        };
+        let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+        let batch = reader.read_batch().unwrap().unwrap();
+        let b = batch.arrays()[1].as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert!(b.is_valid(0));
+        assert_eq!(b.value(0), "");
+        assert!(b.is_null(1));
+    }
+
+    #[test]
+    fn test_non_finite_floats() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "x\n1.5\nNaN\ninf\n-Infinity\n").unwrap();
+
+        let mut reader = CsvReader::new(&csv_file, &CsvConfig::default()).unwrap();
+        let batch = reader.read_batch().unwrap().unwrap();
+        let x = batch.arrays()[0].as_any().downcast_ref::<Float64Array>().unwrap();
+        assert!(x.value(1).is_nan());
+        assert_eq!(x.value(2), f64::INFINITY);
+        assert_eq!(x.value(3), f64::NEG_INFINITY);
+
+        let config = CsvConfig {
+            inf_token: "Infinity".to_string(),
+            non_finite_as_null: true,
+            ..CsvConfig::default()
+        };
+        let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+        let batch = reader.read_batch().unwrap().unwrap();
+        let x = batch.arrays()[0].as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(x.get(0), Some(1.5));
+        assert_eq!(x.null_count(), 3);
+    }
+
+    #[test]
+    fn test_no_type_inference() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "zip,flag
+00501,true
+10001,false
+").unwrap();
+
+        let config = CsvConfig {
+            infer_types: false,
+            ..CsvConfig::default()
+        };
+        let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+        let batch = reader.read_batch().unwrap().unwrap();
+        let zip = batch.arrays()[0].as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(zip.value(0), "00501");
+        assert_eq!(batch.arrays()[1].data_type(), &DataType::Utf8);
+    }
+
+    #[test]
+    fn test_infer_columns() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "a,b,c\n1,2.5,true\n2,3.5,false\n").unwrap();
+
+        let types = |infer_columns: &str| {
+            let config = CsvConfig {
+                infer_columns: Some(InferColumns::parse(infer_columns)),
+                ..CsvConfig::default()
+            };
+            let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+            let batch = reader.read_batch().unwrap().unwrap();
+            batch.arrays().iter().map(|a| a.data_type().clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(types("0"), vec![DataType::Utf8, DataType::Utf8, DataType::Utf8]);
+        assert_eq!(types("2"), vec![DataType::Int64, DataType::Float64, DataType::Utf8]);
+        assert_eq!(types("c, a"), vec![DataType::Int64, DataType::Utf8, DataType::Boolean]);
+    }
+
+    #[test]
+    fn test_infer_decimal() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "price,qty,ratio\n19.99,1,0.5\n0.10,2,1e3\n-5,30,2.25\n").unwrap();
+
+        let config = CsvConfig {
+            infer_decimal: true,
+            decimal_columns: vec!["qty".to_string()],
+            ..CsvConfig::default()
+        };
+        let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+        let batch = reader.read_batch().unwrap().unwrap();
+        let price = batch.arrays()[0].as_any().downcast_ref::<Int128Array>().unwrap();
+        assert_eq!(price.data_type(), &DataType::Decimal(4, 2));
+        assert_eq!(price.values().as_slice(), [1999, 10, -500]);
+        assert_eq!(batch.arrays()[1].data_type(), &DataType::Decimal(2, 0));
+        // 1e3 isn't fixed-point, so the column stays a float
+        assert_eq!(batch.arrays()[2].data_type(), &DataType::Float64);
+
+        assert_eq!(parse_decimal("+007.250"), Some((7250, 1, 3)));
+        assert_eq!(parse_decimal(".5"), Some((5, 0, 1)));
+        for invalid in ["", ".", "-", "1.2.3", "1e3", "NaN", "1,5"] {
+            assert_eq!(parse_decimal(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_detect_header() {
+        let temp_dir = tempdir().unwrap();
+        let labelled = temp_dir.path().join("labelled.csv");
+        fs::write(&labelled, "id,score\n1,2.5\n2,3.5\n").unwrap();
+
+        let config = CsvConfig {
+            has_headers: false,
+            detect_header: true,
+            ..CsvConfig::default()
+        };
+        let mut reader = CsvReader::new(&labelled, &config).unwrap();
+        assert_eq!(reader.get_headers(), ["id", "score"]);
+        let batch = reader.read_batch().unwrap().unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.arrays()[0].data_type(), &DataType::Int64);
+
+        // Without detection the label row stays data
+        let config = CsvConfig {
+            detect_header: false,
+            ..config
+        };
+        let mut reader = CsvReader::new(&labelled, &config).unwrap();
+        assert_eq!(reader.get_headers(), ["col_1", "col_2"]);
+        assert_eq!(reader.read_batch().unwrap().unwrap().len(), 3);
+
+        // Text all the way down is not a header
+        let text = temp_dir.path().join("text.csv");
+        fs::write(&text, "a,b\nc,d\n").unwrap();
+        let config = CsvConfig {
+            detect_header: true,
+            ..config
+        };
+        let mut reader = CsvReader::new(&text, &config).unwrap();
+        assert_eq!(reader.get_headers(), ["col_1", "col_2"]);
+        assert_eq!(reader.read_batch().unwrap().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_ragged_rows() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("ragged.csv");
+        fs::write(&csv_file, "a,b,c\n1,2,3\n4,5\n6\n").unwrap();
+
+        // Strict by default
+        let mut reader = CsvReader::new(&csv_file, &CsvConfig::default()).unwrap();
+        assert!(reader.read_batch().is_err());
+
+        let config = CsvConfig {
+            ragged: Ragged::Pad,
+            ..CsvConfig::default()
+        };
+        let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+        let batch = reader.read_batch().unwrap().unwrap();
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch.arrays()[1].null_count(), 1);
+        assert_eq!(batch.arrays()[2].null_count(), 2);
+
+        // A long row needs truncate
+        fs::write(&csv_file, "a,b\n1,2,3\n4\n").unwrap();
+        let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+        let error = reader.read_batch().err().unwrap();
+        assert_eq!(error.to_string(), "Invalid input: Line 2 has 3 fields, but the header has 2");
+
+        let config = CsvConfig {
+            ragged: Ragged::Truncate,
+            ..CsvConfig::default()
+        };
+        let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+        let batch = reader.read_batch().unwrap().unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.arrays().len(), 2);
+    }
+
+    #[test]
+    fn test_skip_to() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "a\n1\n2\n3\n4\n").unwrap();
+        let config = CsvConfig {
+            batch_size: 2,
+            ..CsvConfig::default()
+        };
+
+        let mut reader = CsvReader::new(&csv_file, &config).unwrap();
+        reader.read_batch().unwrap();
+        let offset = reader.record_offset();
+
+        let mut resumed = CsvReader::new(&csv_file, &config).unwrap();
+        resumed.skip_to(offset).unwrap();
+        let batch = resumed.read_batch().unwrap().unwrap();
+        let values = batch.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(values.values().as_slice(), &[3, 4]);
+        assert!(resumed.read_batch().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_binary_input() {
+        let temp_dir = tempdir().unwrap();
+        let binary = temp_dir.path().join("image.csv");
+        let mut content = b"\x89PNG\r\n\x1a\n".to_vec();
+        content.extend((0..2000u32).map(|i| (i * 7 % 256) as u8));
+        fs::write(&binary, content).unwrap();
+
+        let config = CsvConfig {
+            delimiter: Some(b','),
+            ..CsvConfig::default()
+        };
+        let error = CsvReader::new(&binary, &config).err().unwrap();
+        assert!(error.to_string().contains("looks like binary data, not CSV"), "{}", error);
+
+        // Text, including tabs and a form feed, reads as before
+        let text = temp_dir.path().join("text.csv");
+        fs::write(&text, "a\tb\n1\t\x0c2\n").unwrap();
+        let mut reader = CsvReader::new(&text, &CsvConfig::default()).unwrap();
+        assert_eq!(reader.get_headers(), ["a", "b"]);
+        assert_eq!(reader.read_batch().unwrap().unwrap().len(), 1);
+    }
 }
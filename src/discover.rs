@@ -1,9 +1,20 @@
-use crate::error::Result;
+use crate::{
+    compression::{codec_from_extension, is_bzip2},
+    error::{MawError, Result},
+};
 use globwalk::GlobWalkerBuilder;
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
 use tracing::{debug, info};
 use walkdir::WalkDir;
 
+/// Leading bytes of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
 #[derive(Debug, Clone)]
 pub struct InputFile {
     pub path: PathBuf,
@@ -11,26 +22,59 @@ pub struct InputFile {
     pub size: u64,
 }
 
+impl InputFile {
+    /// The input's size in bytes, or `None` when it can't be known up front,
+    /// as for stdin.
+    pub fn known_size(&self) -> Option<u64> {
+        (self.path.to_string_lossy() != "-").then_some(self.size)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum FileFormat {
     Csv,
     Parquet,
+    Json,
 }
 
 impl FileFormat {
     pub fn from_extension(path: &Path) -> Option<Self> {
+        // Compressed CSV (`data.csv.bz2`) is decompressed while it's read
+        if is_bzip2(path) {
+            return match Self::from_extension(&path.with_extension("")) {
+                Some(FileFormat::Csv) => Some(FileFormat::Csv),
+                _ => None,
+            };
+        }
+
         match path.extension().and_then(|ext| ext.to_str()) {
-            Some("csv") | Some("tsv") => Some(FileFormat::Csv),
+            Some("csv") | Some("tsv") | Some("psv") => Some(FileFormat::Csv),
             Some("parquet") => Some(FileFormat::Parquet),
+            Some("jsonl") | Some("ndjson") => Some(FileFormat::Json),
             _ => None,
         }
     }
+
+    /// Short lowercase name, as recorded in state files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileFormat::Csv => "csv",
+            FileFormat::Parquet => "parquet",
+            FileFormat::Json => "json",
+        }
+    }
 }
 
 pub struct DiscoveryConfig {
     pub recursive: bool,
     pub follow_symlinks: bool,
     pub max_depth: Option<usize>,
+    /// Keep only this many of the discovered files, in discovery order
+    pub max_files: Option<usize>,
+    pub stdin_format: FileFormat,
+    /// Walk into hidden files and directories (named with a leading `.`),
+    /// such as `.git` or `.cache`, when discovering a directory
+    pub include_hidden: bool,
 }
 
 impl Default for DiscoveryConfig {
@@ -39,6 +83,9 @@ impl Default for DiscoveryConfig {
             recursive: true,
             follow_symlinks: false,
             max_depth: None,
+            max_files: None,
+            stdin_format: FileFormat::Csv,
+            include_hidden: false,
         }
     }
 }
@@ -47,23 +94,40 @@ pub fn discover_inputs(
     inputs: &[String],
     config: &DiscoveryConfig,
 ) -> Result<Vec<InputFile>> {
+    let inputs = expand_input_lists(inputs)?;
     let mut discovered = Vec::new();
 
-    for input in inputs {
+    if inputs.iter().filter(|i| i.as_str() == "-").count() > 1 {
+        return Err(MawError::InvalidInput(
+            "stdin ('-') can only be given once".to_string(),
+        ));
+    }
+
+    for input in &inputs {
         if input == "-" {
+            // Parquet needs a seekable footer, which a pipe can't provide
+            if config.stdin_format == FileFormat::Parquet {
+                return Err(MawError::InvalidInput(
+                    "Parquet can't be read from stdin because its footer must be seekable; \
+                     write it to a file first"
+                        .to_string(),
+                ));
+            }
+
             // Handle stdin
             discovered.push(InputFile {
                 path: PathBuf::from("-"),
-                format: FileFormat::Csv, // Assume CSV for stdin
+                format: config.stdin_format.clone(),
                 size: 0, // Unknown size for stdin
             });
             continue;
         }
 
-        let path = PathBuf::from(input);
+        let path = normalize_path(Path::new(input));
         
         if path.is_file() {
             // Single file
+            reject_compressed_parquet(&path)?;
             if let Some(format) = FileFormat::from_extension(&path) {
                 let size = std::fs::metadata(&path)?.len();
                 discovered.push(InputFile {
@@ -80,14 +144,21 @@ pub fn discover_inputs(
             discovered.extend(files);
         } else {
             // Try as glob pattern
-            let files = discover_glob(input, config)?;
+            let files = discover_glob(&path.to_string_lossy(), config)?;
             discovered.extend(files);
         }
     }
 
-    // Remove duplicates and sort
-    discovered.sort_by(|a, b| a.path.cmp(&b.path));
-    discovered.dedup_by(|a, b| a.path == b.path);
+    // Inputs are concatenated in the order given, so duplicates are removed
+    // without sorting; directories and globs were sorted as they expanded
+    let mut seen = HashSet::new();
+    discovered.retain(|file| seen.insert(dedup_key(&file.path)));
+    if let Some(max_files) = config.max_files {
+        if discovered.len() > max_files {
+            info!("Keeping the first {} of {} input files", max_files, discovered.len());
+            discovered.truncate(max_files);
+        }
+    }
 
     info!("Discovered {} input files", discovered.len());
     for file in &discovered {
@@ -100,6 +171,30 @@ pub fn discover_inputs(
     Ok(discovered)
 }
 
+/// Replaces each `@FILE` input with the inputs listed in FILE, one path,
+/// directory or glob per line, for more inputs than fit on a command line.
+/// Blank lines and `#` comments are skipped.
+fn expand_input_lists(inputs: &[String]) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for input in inputs {
+        let Some(list) = input.strip_prefix('@') else {
+            expanded.push(input.clone());
+            continue;
+        };
+        let contents = std::fs::read_to_string(list).map_err(|e| {
+            MawError::InvalidInput(format!("Failed to read input list {}: {}", list, e))
+        })?;
+        expanded.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from),
+        );
+    }
+    Ok(expanded)
+}
+
 fn discover_directory(
     dir: &Path,
     config: &DiscoveryConfig,
@@ -108,17 +203,21 @@ fn discover_directory(
     
     let walker = WalkDir::new(dir)
         .follow_links(config.follow_symlinks)
-        .max_depth(config.max_depth.unwrap_or(usize::MAX));
+        .max_depth(config.max_depth.unwrap_or(usize::MAX))
+        .into_iter()
+        // The directory given is walked even if it is hidden itself
+        .filter_entry(|entry| config.include_hidden || entry.depth() == 0 || !is_hidden(entry.file_name()));
 
     for entry in walker {
         let entry = entry?;
         let path = entry.path();
         
         if path.is_file() {
+            reject_compressed_parquet(path)?;
             if let Some(format) = FileFormat::from_extension(path) {
                 let size = entry.metadata()?.len();
                 files.push(InputFile {
-                    path: path.to_path_buf(),
+                    path: normalize_path(path),
                     format,
                     size,
                 });
@@ -126,9 +225,14 @@ fn discover_directory(
         }
     }
 
+    files.sort_by(|a, b| a.path.cmp(&b.path));
     Ok(files)
 }
 
+fn is_hidden(name: &std::ffi::OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
 fn discover_glob(
     pattern: &str,
     config: &DiscoveryConfig,
@@ -144,10 +248,11 @@ fn discover_glob(
         let path = entry.path();
         
         if path.is_file() {
+            reject_compressed_parquet(path)?;
             if let Some(format) = FileFormat::from_extension(path) {
                 let size = entry.metadata()?.len();
                 files.push(InputFile {
-                    path: path.to_path_buf(),
+                    path: normalize_path(path),
                     format,
                     size,
                 });
@@ -155,13 +260,73 @@ fn discover_glob(
         }
     }
 
+    files.sort_by(|a, b| a.path.cmp(&b.path));
     Ok(files)
 }
 
+/// Drops a Windows extended-length prefix (`\\?\C:\...`, or
+/// `\\?\UNC\server\share\...` for network shares) when the path means the
+/// same without it, so paths print and compare the way users write them.
+/// Paths longer than `MAX_PATH` keep the prefix they need to be opened.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    dunce::simplified(path).to_path_buf()
+}
+
+/// What two paths to the same file have in common: the path with symlinks
+/// resolved, so a file reached both directly and through a symlinked
+/// directory is one input. Windows filesystems also ignore case, so
+/// `C:\a.csv` and `c:\A.CSV` are one input there.
+fn dedup_key(path: &Path) -> PathBuf {
+    let resolved = std::fs::canonicalize(path)
+        .map_or_else(|_| path.to_path_buf(), |resolved| normalize_path(&resolved));
+    if cfg!(windows) {
+        PathBuf::from(resolved.to_string_lossy().to_lowercase())
+    } else {
+        resolved
+    }
+}
+
+/// Parquet is read from its footer, which a compressed stream can't seek
+/// to, so a Parquet file compressed as a whole is rejected up front: either
+/// by its name (`data.parquet.gz`) or by gzip bytes behind a `.parquet` name.
+fn reject_compressed_parquet(path: &Path) -> Result<()> {
+    let codec = codec_from_extension(path)
+        .map(|codec| codec.to_string())
+        .or_else(|| is_bzip2(path).then(|| "bzip2".to_string()));
+    if let Some(codec) = codec {
+        if FileFormat::from_extension(&path.with_extension("")) == Some(FileFormat::Parquet) {
+            return Err(MawError::InvalidInput(format!(
+                "{} is a {}-compressed Parquet file; its footer can't be read from a \
+                 compressed stream, so decompress it first",
+                path.display(),
+                codec
+            )));
+        }
+        return Ok(());
+    }
+
+    if FileFormat::from_extension(path) != Some(FileFormat::Parquet) {
+        return Ok(());
+    }
+
+    let mut magic = [0; 2];
+    let is_gzip = File::open(path)?.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+    if is_gzip {
+        return Err(MawError::InvalidInput(format!(
+            "{} is gzip-compressed rather than a Parquet file; its footer can't be read \
+             from a gzip stream, so decompress it first",
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
+
 fn format_name(format: &FileFormat) -> &'static str {
     match format {
         FileFormat::Csv => "CSV",
         FileFormat::Parquet => "Parquet",
+        FileFormat::Json => "JSON",
     }
 }
 
@@ -185,6 +350,27 @@ mod tests {
         assert_eq!(discovered[0].format, FileFormat::Csv);
     }
 
+    #[test]
+    fn test_stdin_only_once() {
+        let inputs = vec!["-".to_string(), "-".to_string()];
+        let config = DiscoveryConfig::default();
+        assert!(discover_inputs(&inputs, &config).is_err());
+
+        let inputs = vec!["-".to_string()];
+        let config = DiscoveryConfig {
+            stdin_format: FileFormat::Json,
+            ..DiscoveryConfig::default()
+        };
+        let discovered = discover_inputs(&inputs, &config).unwrap();
+        assert_eq!(discovered[0].format, FileFormat::Json);
+
+        let config = DiscoveryConfig {
+            stdin_format: FileFormat::Parquet,
+            ..DiscoveryConfig::default()
+        };
+        assert!(discover_inputs(&inputs, &config).is_err());
+    }
+
     #[test]
     fn test_discover_directory() {
         let temp_dir = tempdir().unwrap();
@@ -202,4 +388,106 @@ mod tests {
         assert!(discovered.iter().any(|f| f.format == FileFormat::Csv));
         assert!(discovered.iter().any(|f| f.format == FileFormat::Parquet));
     }
+
+    #[test]
+    fn test_skip_hidden() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(temp_dir.path().join("visible.csv"), "a\n1\n").unwrap();
+        fs::write(temp_dir.path().join(".hidden.csv"), "a\n2\n").unwrap();
+        fs::create_dir(temp_dir.path().join(".cache")).unwrap();
+        fs::write(temp_dir.path().join(".cache").join("stray.csv"), "a\n3\n").unwrap();
+
+        let inputs = vec![temp_dir.path().to_string_lossy().to_string()];
+        let names = |config: &DiscoveryConfig| {
+            let discovered = discover_inputs(&inputs, config).unwrap();
+            discovered
+                .iter()
+                .map(|f| f.path.strip_prefix(temp_dir.path()).unwrap().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&DiscoveryConfig::default()), [PathBuf::from("visible.csv")]);
+
+        let config = DiscoveryConfig {
+            include_hidden: true,
+            ..DiscoveryConfig::default()
+        };
+        assert_eq!(
+            names(&config),
+            [
+                Path::new(".cache").join("stray.csv"),
+                PathBuf::from(".hidden.csv"),
+                PathBuf::from("visible.csv"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reject_gzipped_parquet() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let temp_dir = tempdir().unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"PAR1 fake parquet data PAR1").unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let config = DiscoveryConfig::default();
+        for name in ["test.parquet.gz", "test.parquet"] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, &gzipped).unwrap();
+
+            let inputs = vec![path.to_string_lossy().to_string()];
+            match discover_inputs(&inputs, &config) {
+                Err(MawError::InvalidInput(message)) => {
+                    assert!(message.contains("decompress it first"), "{}", message)
+                }
+                other => panic!("expected InvalidInput, got {:?}", other),
+            }
+            fs::remove_file(&path).unwrap();
+        }
+
+        // Other compressed files are still skipped as unsupported
+        let csv_gz = temp_dir.path().join("test.csv.gz");
+        fs::write(&csv_gz, &gzipped).unwrap();
+        let inputs = vec![csv_gz.to_string_lossy().to_string()];
+        assert!(discover_inputs(&inputs, &config).unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_duplicate() {
+        let temp_dir = tempdir().unwrap();
+        let real = temp_dir.path().join("real");
+        fs::create_dir(&real).unwrap();
+        fs::write(real.join("test.csv"), "a\n1\n").unwrap();
+        std::os::unix::fs::symlink(&real, temp_dir.path().join("link")).unwrap();
+
+        let inputs = vec![temp_dir.path().to_string_lossy().to_string()];
+        let config = DiscoveryConfig {
+            follow_symlinks: true,
+            ..DiscoveryConfig::default()
+        };
+        let discovered = discover_inputs(&inputs, &config).unwrap();
+
+        // link/test.csv sorts first, so it is the path kept
+        assert_eq!(discovered.len(), 1);
+        assert_eq!(discovered[0].path, temp_dir.path().join("link").join("test.csv"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_path_forms() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "a\n1\n").unwrap();
+
+        // Canonical paths have the extended-length form, \\?\C:\...
+        let verbatim = fs::canonicalize(&csv_file).unwrap().to_string_lossy().to_string();
+        let plain = verbatim.trim_start_matches(r"\\?\").to_string();
+        let inputs = vec![verbatim, plain.to_uppercase(), plain.clone()];
+        let discovered = discover_inputs(&inputs, &DiscoveryConfig::default()).unwrap();
+
+        assert_eq!(discovered.len(), 1);
+        assert_eq!(discovered[0].path, PathBuf::from(plain));
+    }
 }
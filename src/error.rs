@@ -41,8 +41,56 @@ pub enum MawError {
     #[error("Glob error: {0}")]
     Glob(#[from] globwalk::GlobError),
 
+    #[error("The file was removed or renamed after it was discovered")]
+    Vanished,
+
     #[error("Join error: {0}")]
     Join(#[from] tokio::task::JoinError),
+
+    #[error("{} of {total} inputs failed and were skipped: {}", .failed.len(), .failed.join(", "))]
+    PartialFailure { failed: Vec<String>, total: usize },
+
+    #[error("{path}{}: {source}", .line.map(|l| format!(":line {}", l)).unwrap_or_default())]
+    InFile {
+        path: String,
+        line: Option<u64>,
+        #[source]
+        source: Box<MawError>,
+    },
+}
+
+impl MawError {
+    /// Names the input file an error came from, with the line when the CSV
+    /// parser knows it. An error that already names its file is unchanged.
+    pub fn in_file(self, path: impl Into<String>) -> Self {
+        let line = match &self {
+            MawError::InFile { .. } => return self,
+            MawError::Csv(e) => e.position().map(|p| p.line()),
+            _ => None,
+        };
+        MawError::InFile {
+            path: path.into(),
+            line,
+            source: Box::new(self),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, MawError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_file() {
+        let mut reader = csv::ReaderBuilder::new().from_reader("a,b\n1,2\n3,4,5\n".as_bytes());
+        let csv_error = reader.records().find_map(|r| r.err()).unwrap();
+
+        let error = MawError::from(csv_error).in_file("file2.csv");
+        assert!(error.to_string().starts_with("file2.csv:line 3: CSV parsing error"));
+
+        let error = MawError::Schema("bad".to_string()).in_file("a.csv").in_file("b.csv");
+        assert_eq!(error.to_string(), "a.csv: Schema error: bad");
+    }
+}
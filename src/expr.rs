@@ -0,0 +1,417 @@
+use crate::{
+    error::{MawError, Result},
+    schema::TypeKind,
+};
+use arrow2::array::{Array, Float64Array, Int64Array};
+
+/// A column computed per row from an arithmetic expression over numeric
+/// columns (`--compute 'total = price * qty'`). Expressions have `+ - * /`,
+/// unary minus, parentheses, numbers and column names, which are bare
+/// words or double-quoted. Integers stay integers, so `/` between them
+/// truncates; any float operand makes the result a float. Division by
+/// zero, and integer overflow, give null.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Computed {
+    pub name: String,
+    /// The expression as given, for `--plan`
+    pub expression: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Int(i64),
+    Float(f64),
+    Column(String),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Whether a computed value is an integer or a float.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Numeric {
+    Int,
+    Float,
+}
+
+impl Numeric {
+    /// The numeric kind of a column of `kind`, or None if it isn't numeric.
+    /// All-null columns count as integers.
+    pub fn of(kind: &TypeKind) -> Option<Self> {
+        match kind {
+            TypeKind::Null | TypeKind::I8 | TypeKind::I16 | TypeKind::I32 | TypeKind::I64 => Some(Numeric::Int),
+            TypeKind::F32 | TypeKind::F64 | TypeKind::Decimal(_, _) => Some(Numeric::Float),
+            _ => None,
+        }
+    }
+
+    pub fn type_kind(self) -> TypeKind {
+        match self {
+            Numeric::Int => TypeKind::I64,
+            Numeric::Float => TypeKind::F64,
+        }
+    }
+}
+
+/// Column values an expression is evaluated over, or its result.
+enum Values {
+    Int(Vec<Option<i64>>),
+    Float(Vec<Option<f64>>),
+}
+
+impl Values {
+    fn into_floats(self) -> Vec<Option<f64>> {
+        match self {
+            Values::Int(values) => values.into_iter().map(|v| v.map(|v| v as f64)).collect(),
+            Values::Float(values) => values,
+        }
+    }
+}
+
+impl Computed {
+    /// Parses `name = expression`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = |reason: &str| MawError::Config(format!("Invalid --compute '{}': {}", spec, reason));
+        let (name, expr_text) = spec.split_once('=').ok_or_else(|| invalid("expected name = expression"))?;
+        let name = name.trim().trim_matches('"');
+        if name.is_empty() {
+            return Err(invalid("the new column needs a name"));
+        }
+
+        let tokens = tokenize(expr_text).map_err(|reason| invalid(&reason))?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expr = parser.sum().map_err(|reason| invalid(&reason))?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(invalid(&format!("unexpected {}", token)));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            expression: expr_text.trim().to_string(),
+            expr,
+        })
+    }
+
+    /// The columns the expression reads, in order of first use.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.expr.visit_columns(&mut |name| {
+            if !columns.contains(&name) {
+                columns.push(name);
+            }
+        });
+        columns
+    }
+
+    /// The kind of the result, given each column's; fails on a column that
+    /// isn't numeric or isn't known.
+    pub fn result_type(&self, kind_of: &dyn Fn(&str) -> Option<TypeKind>) -> Result<Numeric> {
+        self.expr.result_type(&|column| {
+            let kind = kind_of(column).ok_or_else(|| {
+                MawError::Config(format!("--compute {}: no column '{}' in the input", self.name, column))
+            })?;
+            Numeric::of(&kind).ok_or_else(|| {
+                MawError::Config(format!(
+                    "--compute {}: column '{}' is {:?}, not a number",
+                    self.name,
+                    column,
+                    kind.to_arrow_type()
+                ))
+            })
+        })
+    }
+
+    /// Evaluates the expression over `rows` rows. `column` gives each
+    /// column's values as an Int64 or Float64 array.
+    pub fn evaluate(&self, rows: usize, column: &dyn Fn(&str) -> Result<Box<dyn Array>>) -> Result<Box<dyn Array>> {
+        Ok(match self.expr.evaluate(rows, column)? {
+            Values::Int(values) => Int64Array::from(values).boxed(),
+            Values::Float(values) => Float64Array::from(values).boxed(),
+        })
+    }
+}
+
+impl Expr {
+    fn visit_columns<'a>(&'a self, visit: &mut dyn FnMut(&'a str)) {
+        match self {
+            Expr::Int(_) | Expr::Float(_) => {}
+            Expr::Column(name) => visit(name),
+            Expr::Neg(operand) => operand.visit_columns(visit),
+            Expr::Binary(left, _, right) => {
+                left.visit_columns(visit);
+                right.visit_columns(visit);
+            }
+        }
+    }
+
+    fn result_type(&self, column: &dyn Fn(&str) -> Result<Numeric>) -> Result<Numeric> {
+        match self {
+            Expr::Int(_) => Ok(Numeric::Int),
+            Expr::Float(_) => Ok(Numeric::Float),
+            Expr::Column(name) => column(name),
+            Expr::Neg(operand) => operand.result_type(column),
+            Expr::Binary(left, _, right) => match (left.result_type(column)?, right.result_type(column)?) {
+                (Numeric::Int, Numeric::Int) => Ok(Numeric::Int),
+                _ => Ok(Numeric::Float),
+            },
+        }
+    }
+
+    fn evaluate(&self, rows: usize, column: &dyn Fn(&str) -> Result<Box<dyn Array>>) -> Result<Values> {
+        Ok(match self {
+            Expr::Int(value) => Values::Int(vec![Some(*value); rows]),
+            Expr::Float(value) => Values::Float(vec![Some(*value); rows]),
+            Expr::Column(name) => {
+                let array = column(name)?;
+                if let Some(ints) = array.as_any().downcast_ref::<Int64Array>() {
+                    Values::Int(ints.iter().map(|v| v.copied()).collect())
+                } else if let Some(floats) = array.as_any().downcast_ref::<Float64Array>() {
+                    Values::Float(floats.iter().map(|v| v.copied()).collect())
+                } else {
+                    return Err(MawError::Schema(format!(
+                        "Column '{}' is {:?}, not a number",
+                        name,
+                        array.data_type()
+                    )));
+                }
+            }
+            Expr::Neg(operand) => match operand.evaluate(rows, column)? {
+                Values::Int(values) => Values::Int(values.into_iter().map(|v| v?.checked_neg()).collect()),
+                Values::Float(values) => Values::Float(values.into_iter().map(|v| v.map(|v| -v)).collect()),
+            },
+            Expr::Binary(left, op, right) => {
+                match (left.evaluate(rows, column)?, right.evaluate(rows, column)?) {
+                    (Values::Int(left), Values::Int(right)) => Values::Int(
+                        left.into_iter()
+                            .zip(right)
+                            .map(|(l, r)| {
+                                let (l, r) = (l?, r?);
+                                match op {
+                                    Op::Add => l.checked_add(r),
+                                    Op::Sub => l.checked_sub(r),
+                                    Op::Mul => l.checked_mul(r),
+                                    Op::Div => l.checked_div(r),
+                                }
+                            })
+                            .collect(),
+                    ),
+                    (left, right) => Values::Float(
+                        left.into_floats()
+                            .into_iter()
+                            .zip(right.into_floats())
+                            .map(|(l, r)| {
+                                let (l, r) = (l?, r?);
+                                match op {
+                                    Op::Add => Some(l + r),
+                                    Op::Sub => Some(l - r),
+                                    Op::Mul => Some(l * r),
+                                    Op::Div => (r != 0.0).then(|| l / r),
+                                }
+                            })
+                            .collect(),
+                    ),
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Name(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "'{}'", number),
+            Token::Name(name) => write!(f, "'{}'", name),
+            Token::Op(op) => write!(f, "'{}'", op.symbol()),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+impl Op {
+    fn symbol(self) -> char {
+        match self {
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+        }
+    }
+}
+
+fn tokenize(expr: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' => {
+                chars.next();
+                let op = match c {
+                    '+' => Op::Add,
+                    '-' => Op::Sub,
+                    '*' => Op::Mul,
+                    _ => Op::Div,
+                };
+                tokens.push(Token::Op(op));
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                let name: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                tokens.push(Token::Name(name));
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                    number.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                    name.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Name(name));
+            }
+            c => return Err(format!("unexpected '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens: sums of products of factors.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Takes the next token if it is one of `ops`.
+    fn take_op(&mut self, ops: [Op; 2]) -> Option<Op> {
+        match self.tokens.get(self.position) {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                self.position += 1;
+                Some(*op)
+            }
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.take_op([Op::Add, Op::Sub]) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while let Some(op) = self.take_op([Op::Mul, Op::Div]) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> std::result::Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(number)) => match number.parse::<i64>() {
+                Ok(value) => Ok(Expr::Int(value)),
+                Err(_) => number
+                    .parse::<f64>()
+                    .map(Expr::Float)
+                    .map_err(|_| format!("'{}' is not a number", number)),
+            },
+            Some(Token::Name(name)) => Ok(Expr::Column(name)),
+            Some(Token::Op(Op::Sub)) => Ok(Expr::Neg(Box::new(self.factor()?))),
+            Some(Token::Open) => {
+                let expr = self.sum()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("unmatched '('".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Err("the expression ends early".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(spec: &str, columns: &[(&str, Box<dyn Array>)]) -> Box<dyn Array> {
+        let computed = Computed::parse(spec).unwrap();
+        let rows = columns.first().map_or(1, |(_, array)| array.len());
+        computed
+            .evaluate(rows, &|name| {
+                Ok(columns.iter().find(|(n, _)| *n == name).unwrap().1.clone())
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_compute() {
+        let computed = Computed::parse("total = price * (qty + 1) - \"unit fee\"").unwrap();
+        assert_eq!(computed.name, "total");
+        assert_eq!(computed.expression, "price * (qty + 1) - \"unit fee\"");
+        assert_eq!(computed.columns(), ["price", "qty", "unit fee"]);
+
+        let kind_of = |name: &str| match name {
+            "price" => Some(TypeKind::I64),
+            "qty" => Some(TypeKind::I32),
+            "unit fee" => Some(TypeKind::F64),
+            _ => None,
+        };
+        assert_eq!(computed.result_type(&kind_of).unwrap(), Numeric::Float);
+        let computed = Computed::parse("n = qty * -2").unwrap();
+        assert_eq!(computed.result_type(&kind_of).unwrap(), Numeric::Int);
+        let computed = Computed::parse("n = missing + 1").unwrap();
+        assert!(computed.result_type(&kind_of).is_err());
+
+        let ints = Int64Array::from(vec![Some(7), Some(4), None]).boxed();
+        let zeros = Int64Array::from(vec![Some(2), Some(0), Some(1)]).boxed();
+        let result = evaluate("q = a / b", &[("a", ints.clone()), ("b", zeros)]);
+        assert_eq!(result.as_ref(), &Int64Array::from(vec![Some(3), None, None]) as &dyn Array);
+
+        let floats = Float64Array::from(vec![Some(1.5), Some(2.0), Some(0.5)]).boxed();
+        let result = evaluate("p = a * b + 1", &[("a", ints), ("b", floats)]);
+        assert_eq!(result.as_ref(), &Float64Array::from(vec![Some(11.5), Some(9.0), None]) as &dyn Array);
+
+        for invalid in ["total", "= a + b", "t = a +", "t = (a", "t = a b", "t = a % b", "t = 1.2.3"] {
+            assert!(Computed::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use crate::error::{MawError, Result};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+use tempfile::{NamedTempFile, PersistError};
+
+/// Prefix of every temporary file, so leftovers are easy to recognize.
+const TEMP_PREFIX: &str = ".maw-";
+
+/// Whether `path` is `-`, which writes the output to stdout.
+pub fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Where an output's bytes go: a file, or stdout for `-o -`.
+pub enum OutputSink {
+    File(File),
+    Stdout(io::Stdout),
+}
+
+impl OutputSink {
+    /// Fsyncs the output and the directory holding it; stdout has nothing
+    /// to sync.
+    pub fn sync(&self, path: &Path) -> Result<()> {
+        if let OutputSink::File(file) = self {
+            file.sync_all()?;
+            sync_parent_dir(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputSink::File(file) => file.write(buf),
+            OutputSink::Stdout(stdout) => stdout.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputSink::File(file) => file.flush(),
+            OutputSink::Stdout(stdout) => stdout.flush(),
+        }
+    }
+}
+
+/// Creates an output file. Unless `overwrite` is set, an existing file is
+/// left untouched and reported as an error instead of being truncated.
+pub fn create_output_file(path: &Path, overwrite: bool) -> Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    options.open(path).map_err(|e| match e.kind() {
+        ErrorKind::AlreadyExists => already_exists(path),
+        _ => MawError::Io(e),
+    })
+}
+
+/// Fails early if `path` exists and may not be replaced, for outputs that are
+/// only created once they are complete.
+pub fn check_no_clobber(path: &Path, overwrite: bool) -> Result<()> {
+    if !overwrite && path.exists() {
+        return Err(already_exists(path));
+    }
+    Ok(())
+}
+
+fn already_exists(path: &Path) -> MawError {
+    MawError::InvalidInput(format!(
+        "Output file '{}' already exists; pass --overwrite to replace it",
+        path.display()
+    ))
+}
+
+/// Where temporary files for `output` go: `--temp-dir` when given, else the
+/// output's own directory, so a finished file is moved into place with an
+/// atomic rename on the same filesystem.
+pub fn temp_dir_for(output: &Path, temp_dir: Option<&Path>) -> PathBuf {
+    match (temp_dir, output.parent()) {
+        (Some(dir), _) => dir.to_path_buf(),
+        (None, Some(parent)) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Checks that temporary files can be created in `dir`, so a bad
+/// `--temp-dir` is reported before any work is done.
+pub fn check_temp_dir(dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(MawError::Config(format!(
+            "Temporary directory '{}' does not exist or is not a directory",
+            dir.display()
+        )));
+    }
+
+    create_temp_file(dir).map(drop).map_err(|e| {
+        MawError::Config(format!(
+            "Temporary directory '{}' is not writable: {}",
+            dir.display(),
+            e
+        ))
+    })
+}
+
+/// Creates a temporary file in `dir`. It is removed when dropped unless it
+/// is moved into place with [`persist_temp_file`].
+pub fn create_temp_file(dir: &Path) -> Result<NamedTempFile> {
+    Ok(tempfile::Builder::new().prefix(TEMP_PREFIX).tempfile_in(dir)?)
+}
+
+/// Moves a finished temporary file to `path`. Unless `overwrite` is set, an
+/// existing file there is kept and reported. When the temporary directory is
+/// on another filesystem the file is copied instead of renamed.
+pub fn persist_temp_file(temp: NamedTempFile, path: &Path, overwrite: bool) -> Result<File> {
+    let error = match persist(temp, path, overwrite) {
+        Ok(file) => return Ok(file),
+        Err(error) => error,
+    };
+    if error.error.kind() != ErrorKind::CrossesDevices {
+        return Err(persist_error(error, path));
+    }
+
+    let mut source = error.file;
+    source.rewind()?;
+    copy_into_place(&mut source, path, overwrite)
+}
+
+/// Copies `source` to a temporary file beside `path`, fsyncs it and renames
+/// it into place, so `path` never holds a partial copy. The copy is removed
+/// if any step fails.
+fn copy_into_place(source: &mut impl Read, path: &Path, overwrite: bool) -> Result<File> {
+    let mut copy = create_temp_file(&temp_dir_for(path, None))?;
+    io::copy(source, &mut copy)?;
+    copy.as_file().sync_all()?;
+    persist(copy, path, overwrite).map_err(|e| persist_error(e, path))
+}
+
+fn persist(temp: NamedTempFile, path: &Path, overwrite: bool) -> std::result::Result<File, PersistError> {
+    if overwrite {
+        temp.persist(path)
+    } else {
+        temp.persist_noclobber(path)
+    }
+}
+
+fn persist_error(error: PersistError, path: &Path) -> MawError {
+    if error.error.kind() == ErrorKind::AlreadyExists {
+        already_exists(path)
+    } else {
+        MawError::Io(error.error)
+    }
+}
+
+/// Fsyncs the directory containing `path` so a newly created or renamed
+/// entry survives a crash. This is a no-op on platforms that can't open
+/// directories for syncing.
+pub fn sync_parent_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        std::fs::File::open(parent)?.sync_all()?;
+    }
+
+    #[cfg(not(unix))]
+    let _ = path;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sync_parent_dir() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("out.csv");
+        fs::write(&file, "a\n1\n").unwrap();
+
+        sync_parent_dir(&file).unwrap();
+        sync_parent_dir(Path::new("relative.csv")).unwrap();
+    }
+
+    #[test]
+    fn test_create_output_file_policy() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("out.csv");
+
+        create_output_file(&file, false).unwrap();
+        fs::write(&file, "keep").unwrap();
+
+        let err = create_output_file(&file, false).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "keep");
+
+        create_output_file(&file, true).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "");
+    }
+
+    #[test]
+    fn test_temp_files() {
+        let temp_dir = tempdir().unwrap();
+        let scratch = temp_dir.path().join("scratch");
+        let output = temp_dir.path().join("out.parquet");
+
+        assert!(matches!(check_temp_dir(&scratch), Err(MawError::Config(_))));
+        fs::create_dir(&scratch).unwrap();
+        check_temp_dir(&scratch).unwrap();
+
+        assert_eq!(temp_dir_for(&output, Some(&scratch)), scratch);
+        assert_eq!(temp_dir_for(&output, None), temp_dir.path());
+        assert_eq!(temp_dir_for(Path::new("out.parquet"), None), Path::new("."));
+
+        let temp = create_temp_file(&scratch).unwrap();
+        assert!(temp.path().starts_with(&scratch));
+        fs::write(temp.path(), "done").unwrap();
+        persist_temp_file(temp, &output, false).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "done");
+        assert_eq!(fs::read_dir(&scratch).unwrap().count(), 0);
+
+        let temp = create_temp_file(&scratch).unwrap();
+        let err = persist_temp_file(temp, &output, false).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(fs::read_to_string(&output).unwrap(), "done");
+    }
+
+    #[test]
+    fn test_copy_into_place() {
+        let temp_dir = tempdir().unwrap();
+        let output = temp_dir.path().join("out.parquet");
+
+        copy_into_place(&mut "copied".as_bytes(), &output, false).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "copied");
+
+        // A copy that can't be moved into place is removed, and the
+        // existing file is left alone
+        let err = copy_into_place(&mut "again".as_bytes(), &output, false).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(fs::read_to_string(&output).unwrap(), "copied");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        copy_into_place(&mut "again".as_bytes(), &output, true).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "again");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+}
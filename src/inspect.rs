@@ -0,0 +1,788 @@
+use crate::{
+    checksum::ContentHasher,
+    cli::Compression,
+    coercion::downcast,
+    compression::estimated_ratio,
+    discover::{FileFormat, InputFile},
+    error::{MawError, Result},
+    reader::InputReader,
+    state::ResumePoint,
+    stream::{stream_chunks, StreamBuilder},
+    transform::render,
+};
+use arrow2::{
+    array::{get_display, Array, Float64Array, Int64Array, Utf8Array},
+    chunk::Chunk,
+    datatypes::{DataType, Schema},
+};
+use parquet2::{
+    metadata::FileMetaData,
+    read::{get_page_iterator, read_metadata},
+};
+use std::{collections::VecDeque, fmt::Write, fs::File, path::Path};
+
+/// Describes the inputs without writing anything: the unified schema, then
+/// each file's format and row count. Parquet files are described from their
+/// footer alone, including row groups, compression and encodings.
+pub fn inspect(builder: &StreamBuilder) -> Result<String> {
+    let files = builder.discover()?;
+    if files.is_empty() {
+        return Err(MawError::InvalidInput("No input files found".to_string()));
+    }
+
+    let unified = builder.unify(&files)?;
+    let mut stdin_reader = unified.stdin_reader;
+    let mut report = String::new();
+
+    writeln!(report, "Schema:").unwrap();
+    for field in &unified.schema.schema.fields {
+        writeln!(report, "  {}: {:?}", field.name, field.data_type).unwrap();
+    }
+    if !unified.schema.promotions.is_empty() {
+        writeln!(report, "Promotions:").unwrap();
+        for promotion in &unified.schema.promotions {
+            writeln!(report, "  {}", promotion).unwrap();
+        }
+    }
+    if !unified.schema.warnings.is_empty() {
+        writeln!(report, "Warnings:").unwrap();
+        for warning in &unified.schema.warnings {
+            writeln!(report, "  {}", warning).unwrap();
+        }
+    }
+    if !unified.schema.dropped_columns.is_empty() {
+        writeln!(report, "Dropped (mostly null):").unwrap();
+        for column in &unified.schema.dropped_columns {
+            let fraction = unified.schema.null_fractions[column];
+            writeln!(report, "  {}: {:.1}% null", column, fraction * 100.0).unwrap();
+        }
+    }
+
+    writeln!(report, "Files:").unwrap();
+    let mut total_rows = 0;
+    for file in &files {
+        if file.format == FileFormat::Parquet {
+            let metadata = read_parquet_metadata(&file.path)?;
+            total_rows += metadata.num_rows;
+            writeln!(
+                report,
+                "  {} (parquet): {} rows",
+                file.path.display(),
+                metadata.num_rows
+            )
+            .unwrap();
+            describe_parquet(&mut report, &metadata);
+            continue;
+        }
+
+        let mut reader = match stdin_reader.take() {
+            Some(reader) if file.path.to_string_lossy() == "-" => reader,
+            other => {
+                stdin_reader = other;
+                InputReader::open(file, builder.get_csv_config(), builder.get_batch_size())?
+            }
+        };
+
+        let mut rows = 0;
+        while let Some(batch) = reader.read_batch()? {
+            rows += batch.len();
+        }
+        total_rows += rows;
+
+        writeln!(
+            report,
+            "  {} ({}): {} rows",
+            file.path.display(),
+            file.format.name(),
+            rows
+        )
+        .unwrap();
+    }
+
+    writeln!(report, "Total: {} rows in {} files", total_rows, files.len()).unwrap();
+    Ok(report)
+}
+
+/// Counts the rows of every input without writing anything (`--count`).
+/// Rows go through the readers and the aligner as in a real run, so rows a
+/// run would drop aren't counted. Reports each file, then the total.
+pub fn count(builder: &StreamBuilder) -> Result<String> {
+    let files = builder.discover()?;
+    if files.is_empty() {
+        return Err(MawError::InvalidInput("No input files found".to_string()));
+    }
+
+    let unified = builder.unify(&files)?;
+    let mut stdin_reader = unified.stdin_reader;
+    let aligner = builder.aligner(unified.schema)?;
+    let mut report = String::new();
+
+    let mut total_rows = 0;
+    for file in &files {
+        let path = file.path.to_string_lossy();
+        let mut reader = match stdin_reader.take() {
+            Some(reader) if path == "-" => reader,
+            other => {
+                stdin_reader = other;
+                InputReader::open(file, builder.get_csv_config(), builder.get_batch_size())
+                    .map_err(|e| e.in_file(path.clone()))?
+            }
+        };
+
+        let mut rows = 0;
+        while let Some(batch) = reader.read_batch().map_err(|e| e.in_file(path.clone()))? {
+            let names = reader.column_names();
+            rows += aligner.align_batch(batch, &names).map_err(|e| e.in_file(path.clone()))?.len();
+        }
+        total_rows += rows;
+        writeln!(report, "{}: {} rows", path, rows).unwrap();
+    }
+
+    writeln!(report, "Total: {} rows", total_rows).unwrap();
+    Ok(report)
+}
+
+/// The outcome of `--validate`: a line per Parquet input, and how many of
+/// them failed.
+pub struct Validation {
+    pub report: String,
+    pub failed: usize,
+}
+
+/// Checks every Parquet input for corruption without writing anything
+/// (`--validate`). Other inputs are left out, as only Parquet has a structure
+/// to check short of reading every row.
+pub fn validate(builder: &StreamBuilder) -> Result<Validation> {
+    let files: Vec<InputFile> = builder
+        .discover()?
+        .into_iter()
+        .filter(|file| file.format == FileFormat::Parquet)
+        .collect();
+    if files.is_empty() {
+        return Err(MawError::InvalidInput("No Parquet inputs to validate".to_string()));
+    }
+
+    let mut report = String::new();
+    let mut failed = 0;
+    for file in &files {
+        match validate_parquet(&file.path) {
+            Ok((row_groups, pages)) => writeln!(
+                report,
+                "OK {}: {} row groups, {} pages",
+                file.path.display(),
+                row_groups,
+                pages
+            )
+            .unwrap(),
+            Err(e) => {
+                failed += 1;
+                writeln!(report, "FAILED {}: {}", file.path.display(), e).unwrap();
+            }
+        }
+    }
+
+    writeln!(report, "{} of {} Parquet files passed", files.len() - failed, files.len()).unwrap();
+    Ok(Validation { report, failed })
+}
+
+/// Reads the footer, then the header of every page of every column chunk,
+/// each of which must lie within the file. Returns how many row groups and
+/// pages there are.
+fn validate_parquet(path: &Path) -> Result<(usize, usize)> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let metadata = read_metadata(&mut file).map_err(MawError::Parquet2)?;
+
+    let mut pages = 0;
+    for (index, row_group) in metadata.row_groups.iter().enumerate() {
+        for column in row_group.columns() {
+            let name = column.descriptor().path_in_schema.join(".");
+            let corrupt = |problem: String| {
+                MawError::InvalidInput(format!("row group {}, column '{}': {}", index, name, problem))
+            };
+
+            let (start, length) = column.byte_range();
+            if start.saturating_add(length) > file_size {
+                return Err(corrupt("column chunk ends past the end of the file".to_string()));
+            }
+            // No page can be larger than the chunk holding it
+            let reader = get_page_iterator(column, &mut file, None, Vec::new(), length as usize)
+                .map_err(|e| corrupt(e.to_string()))?;
+            for page in reader {
+                page.map_err(|e| corrupt(e.to_string()))?;
+                pages += 1;
+            }
+        }
+    }
+
+    Ok((metadata.row_groups.len(), pages))
+}
+
+fn read_parquet_metadata(path: &Path) -> Result<FileMetaData> {
+    let mut file = File::open(path)?;
+    read_metadata(&mut file).map_err(MawError::Parquet2)
+}
+
+/// Row groups, then each column chunk's codec and the encodings used by any
+/// of its row groups.
+fn describe_parquet(report: &mut String, metadata: &FileMetaData) {
+    writeln!(report, "    Row groups: {}", metadata.row_groups.len()).unwrap();
+
+    let Some(first) = metadata.row_groups.first() else {
+        return;
+    };
+    for (i, column) in first.columns().iter().enumerate() {
+        let mut encodings = Vec::new();
+        for row_group in &metadata.row_groups {
+            for encoding in row_group.columns()[i].column_encoding() {
+                if !encodings.contains(encoding) {
+                    encodings.push(*encoding);
+                }
+            }
+        }
+
+        writeln!(
+            report,
+            "    {}: {:?}, encodings {:?}",
+            column.descriptor().path_in_schema.join("."),
+            column.compression(),
+            encodings
+        )
+        .unwrap();
+    }
+}
+
+/// Estimates a run's output without writing it: each input's first batch is
+/// sampled to learn its row count (extrapolated from the bytes read, or
+/// exact from a Parquet footer) and the average width of an output row, in
+/// CSV and in Parquet, scaled by the expected ratio of each format's
+/// compression.
+pub fn estimate_output(
+    builder: &StreamBuilder,
+    csv_compression: &Compression,
+    parquet_compression: &Compression,
+) -> Result<String> {
+    let files = builder.discover()?;
+    if files.is_empty() {
+        return Err(MawError::InvalidInput("No input files found".to_string()));
+    }
+
+    let unified = builder.unify(&files)?;
+    let mut aligner = builder.aligner(unified.schema.clone())?;
+    let output_schema = aligner.output_schema();
+    let mut stdin_reader = unified.stdin_reader;
+
+    let mut rows = 0.0;
+    let mut csv_bytes = 0.0;
+    let mut parquet_bytes = 0.0;
+    for file in &files {
+        let mut reader = match stdin_reader.take() {
+            Some(reader) if file.path.to_string_lossy() == "-" => reader,
+            other => {
+                stdin_reader = other;
+                InputReader::open(file, builder.get_csv_config(), builder.get_batch_size())?
+            }
+        };
+
+        let Some(batch) = reader.read_batch()? else {
+            continue;
+        };
+        aligner.set_source(&file.path);
+        let names = reader.column_names();
+        let sampled = batch.len() as f64;
+        let (csv_width, parquet_width) = row_widths(&aligner.align_batch(batch, &names)?);
+
+        let file_rows = match (file.format.clone(), file.known_size(), reader.bytes_read()) {
+            (FileFormat::Parquet, _, _) => read_parquet_metadata(&file.path)?.num_rows as f64,
+            (_, Some(size), Some(read)) if read > 0 => sampled * (size as f64 / read as f64).max(1.0),
+            (_, Some(size), None) if sampled >= builder.get_batch_size() as f64 => {
+                size as f64 / text_row_width(file, csv_width, output_schema.fields.len())
+            }
+            // A sample that ended early, or stdin, which can't be measured
+            _ => sampled,
+        };
+
+        rows += file_rows.round();
+        csv_bytes += file_rows.round() * csv_width;
+        parquet_bytes += file_rows.round() * parquet_width;
+    }
+
+    let header: usize = output_schema.fields.iter().map(|f| f.name.len() + 1).sum();
+    let known_bytes: u64 = files.iter().filter_map(InputFile::known_size).sum();
+
+    let mut report = String::new();
+    writeln!(report, "Dry run mode: no output will be written").unwrap();
+    writeln!(report, "Inputs: {} files, {} bytes", files.len(), known_bytes).unwrap();
+    writeln!(report, "Output columns: {}", output_schema.fields.len()).unwrap();
+    writeln!(report, "Estimated rows: {}", rows).unwrap();
+    writeln!(
+        report,
+        "Estimated CSV output: ~{} bytes ({})",
+        ((csv_bytes + header as f64) * estimated_ratio(csv_compression)).round(),
+        csv_compression
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "Estimated Parquet output: ~{} bytes ({})",
+        (parquet_bytes * estimated_ratio(parquet_compression)).round(),
+        parquet_compression
+    )
+    .unwrap();
+    Ok(report)
+}
+
+/// Average bytes per row of `chunk` as CSV text and as Parquet values.
+fn row_widths(chunk: &Chunk<Box<dyn Array>>) -> (f64, f64) {
+    let mut csv = 0;
+    let mut parquet = 0;
+    let mut value = String::new();
+
+    for array in chunk.arrays() {
+        let display = get_display::<String>(array.as_ref(), "");
+        for row in 0..array.len() {
+            if array.is_null(row) {
+                continue;
+            }
+            value.clear();
+            if display(&mut value, row).is_err() {
+                continue;
+            }
+            csv += value.len();
+            parquet += parquet_value_width(array.data_type(), value.len());
+        }
+    }
+
+    let rows = chunk.len().max(1) as f64;
+    // A delimiter after every value but the last, then a newline
+    let separators = chunk.arrays().len() as f64;
+    (csv as f64 / rows + separators, parquet as f64 / rows)
+}
+
+/// Plain-encoded size of a non-null Parquet value.
+fn parquet_value_width(data_type: &DataType, rendered: usize) -> usize {
+    match data_type {
+        DataType::Boolean | DataType::Int8 | DataType::UInt8 => 1,
+        DataType::Int16 | DataType::UInt16 => 2,
+        DataType::Int32 | DataType::UInt32 | DataType::Float32 | DataType::Date32 => 4,
+        DataType::Int64
+        | DataType::UInt64
+        | DataType::Float64
+        | DataType::Date64
+        | DataType::Timestamp(_, _) => 8,
+        DataType::Decimal(_, _) => 16,
+        // Length-prefixed bytes
+        _ => rendered + 4,
+    }
+}
+
+/// Bytes per row of a text input, for formats whose reader can't report
+/// how far it has read. JSON repeats every key on every row.
+fn text_row_width(file: &InputFile, csv_width: f64, columns: usize) -> f64 {
+    let width = match file.format {
+        FileFormat::Json => csv_width + (columns * 6) as f64,
+        _ => csv_width,
+    };
+    width.max(1.0)
+}
+
+/// Running minimum and maximum of a column, for the types that have them.
+enum Bounds {
+    Int(Option<(i64, i64)>),
+    Float(Option<(f64, f64)>),
+    Str(Option<(String, String)>),
+    None,
+}
+
+impl Bounds {
+    fn for_type(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Int64 => Bounds::Int(None),
+            DataType::Float64 => Bounds::Float(None),
+            DataType::Utf8 => Bounds::Str(None),
+            _ => Bounds::None,
+        }
+    }
+
+    fn update(&mut self, array: &dyn Array) -> Result<()> {
+        match self {
+            Bounds::Int(bounds) => {
+                downcast::<Int64Array>(array)?.iter().flatten().for_each(|v| widen(bounds, *v));
+            }
+            Bounds::Float(bounds) => {
+                downcast::<Float64Array>(array)?.iter().flatten().for_each(|v| widen(bounds, *v));
+            }
+            Bounds::Str(bounds) => {
+                let array = downcast::<Utf8Array<i32>>(array)?;
+                array.iter().flatten().for_each(|v| widen(bounds, v.to_string()));
+            }
+            Bounds::None => {}
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        fn range<T: ToString>(bounds: &Option<(T, T)>) -> String {
+            match bounds {
+                Some((min, max)) => format!("min {}, max {}", min.to_string(), max.to_string()),
+                None => "no values".to_string(),
+            }
+        }
+
+        match self {
+            Bounds::Int(bounds) => range(bounds),
+            Bounds::Float(bounds) => range(bounds),
+            Bounds::Str(bounds) => range(bounds),
+            Bounds::None => "no range".to_string(),
+        }
+    }
+}
+
+fn widen<T: PartialOrd + Clone>(bounds: &mut Option<(T, T)>, value: T) {
+    match bounds {
+        Some((min, max)) => {
+            if value < *min {
+                *min = value;
+            } else if value > *max {
+                *max = value;
+            }
+        }
+        None => *bounds = Some((value.clone(), value)),
+    }
+}
+
+/// Streams every row of the inputs and reports, per output column, the null
+/// count and the smallest and largest value of numeric and string columns.
+pub fn stats(builder: StreamBuilder) -> Result<String> {
+    let mut stream = stream_chunks(builder)?;
+    let fields = stream.schema().fields.clone();
+
+    let mut nulls = vec![0; fields.len()];
+    let mut bounds: Vec<Bounds> = fields.iter().map(|f| Bounds::for_type(&f.data_type)).collect();
+    let mut rows = 0;
+
+    for chunk in &mut stream {
+        let chunk = chunk?;
+        rows += chunk.len();
+
+        for (i, array) in chunk.arrays().iter().enumerate() {
+            nulls[i] += array.null_count();
+            bounds[i].update(array.as_ref())?;
+        }
+    }
+
+    let mut report = String::new();
+    writeln!(report, "Rows: {}", rows).unwrap();
+    for ((field, nulls), bounds) in fields.iter().zip(&nulls).zip(&bounds) {
+        writeln!(
+            report,
+            "  {}: {:?}, {} nulls, {}",
+            field.name,
+            field.data_type,
+            nulls,
+            bounds.describe()
+        )
+        .unwrap();
+    }
+
+    Ok(report)
+}
+
+/// Streams every row of the inputs and reports their order-independent
+/// content hash (`--content-hash`), without writing anything.
+pub fn content_hash(builder: StreamBuilder) -> Result<String> {
+    let mut stream = stream_chunks(builder)?;
+    let mut hasher = ContentHasher::new(stream.schema());
+
+    for chunk in &mut stream {
+        hasher.update(&chunk?)?;
+    }
+
+    Ok(format!("Content hash: {} ({} rows)\n", hasher.hex_digest(), hasher.rows()))
+}
+
+/// Longest value a preview table shows before cutting it short.
+const PREVIEW_WIDTH: usize = 40;
+
+/// Prints the first `rows` rows of the inputs as a table (`maw head`),
+/// reading no further than it needs to.
+pub fn head(builder: StreamBuilder, rows: usize) -> Result<String> {
+    let mut stream = stream_chunks(builder)?;
+    let schema = stream.schema().clone();
+    let mut chunks = Vec::new();
+    let mut remaining = rows;
+
+    while remaining > 0 {
+        let Some(chunk) = stream.next() else {
+            break;
+        };
+        let chunk = chunk?;
+        let length = chunk.len().min(remaining);
+        remaining -= length;
+        chunks.push(slice_chunk(&chunk, 0, length));
+    }
+
+    Ok(preview_table(&schema, &chunks))
+}
+
+/// Prints the last `rows` rows of the inputs as a table (`maw tail`). Files
+/// are read from the last one back until there are enough rows; a Parquet
+/// file is read from the row group that holds its last rows, found from
+/// the footer, while other formats are read through.
+pub fn tail(builder: &StreamBuilder, rows: usize) -> Result<String> {
+    let files = builder.discover()?;
+    if files.is_empty() {
+        return Err(MawError::InvalidInput("No input files found".to_string()));
+    }
+
+    let unified = builder.unify(&files)?;
+    let mut stdin_reader = unified.stdin_reader;
+    let mut aligner = builder.aligner(unified.schema)?;
+    let schema = aligner.output_schema();
+
+    let mut chunks = VecDeque::new();
+    let mut remaining = rows;
+    for file in files.iter().rev() {
+        if remaining == 0 {
+            break;
+        }
+        let path = file.path.to_string_lossy();
+        let row_group = match file.format {
+            FileFormat::Parquet if path != "-" => {
+                let metadata = read_parquet_metadata(&file.path).map_err(|e| e.in_file(path.clone()))?;
+                last_row_groups(&metadata, remaining)
+            }
+            _ => 0,
+        };
+        let mut reader = match stdin_reader.take() {
+            Some(reader) if path == "-" => reader,
+            other => {
+                stdin_reader = other;
+                let start = ResumePoint {
+                    row_group,
+                    ..ResumePoint::default()
+                };
+                InputReader::open_at(file, builder.get_csv_config(), builder.get_batch_size(), start)
+                    .map_err(|e| e.in_file(path.clone()))?
+            }
+        };
+
+        // Keep only the chunks that hold the file's last `remaining` rows
+        aligner.set_source(&file.path);
+        let mut kept = VecDeque::new();
+        let mut kept_rows = 0;
+        while let Some(batch) = reader.read_batch().map_err(|e| e.in_file(path.clone()))? {
+            let names = reader.column_names();
+            let chunk = aligner.align_batch(batch, &names).map_err(|e| e.in_file(path.clone()))?;
+            kept_rows += chunk.len();
+            kept.push_back(chunk);
+            while kept.front().is_some_and(|c| kept_rows - c.len() >= remaining) {
+                kept_rows -= kept.pop_front().map_or(0, |c| c.len());
+            }
+        }
+        if let Some(first) = kept.front_mut() {
+            let skip = kept_rows.saturating_sub(remaining);
+            *first = slice_chunk(first, skip, first.len() - skip);
+            kept_rows -= skip;
+        }
+        remaining -= kept_rows;
+        for chunk in kept.into_iter().rev() {
+            chunks.push_front(chunk);
+        }
+    }
+
+    Ok(preview_table(&schema, &Vec::from(chunks)))
+}
+
+/// The first of the row groups that together hold a Parquet file's last
+/// `rows` rows.
+fn last_row_groups(metadata: &FileMetaData, rows: usize) -> usize {
+    let mut total = 0;
+    for (index, row_group) in metadata.row_groups.iter().enumerate().rev() {
+        total += row_group.num_rows();
+        if total >= rows {
+            return index;
+        }
+    }
+    0
+}
+
+fn slice_chunk(chunk: &Chunk<Box<dyn Array>>, offset: usize, length: usize) -> Chunk<Box<dyn Array>> {
+    if offset == 0 && length == chunk.len() {
+        return chunk.clone();
+    }
+    Chunk::new(chunk.arrays().iter().map(|a| a.sliced(offset, length)).collect())
+}
+
+/// Lays rows out under their column names, padded to line up. Nulls show
+/// as `null` and long values are cut short.
+fn preview_table(schema: &Schema, chunks: &[Chunk<Box<dyn Array>>]) -> String {
+    let mut cells: Vec<Vec<String>> = vec![schema.fields.iter().map(|f| f.name.clone()).collect()];
+    for chunk in chunks {
+        let displays: Vec<_> = chunk
+            .arrays()
+            .iter()
+            .map(|array| get_display::<String>(array.as_ref(), "null"))
+            .collect();
+        for row in 0..chunk.len() {
+            cells.push(displays.iter().map(|display| truncate(render(display, row))).collect());
+        }
+    }
+
+    let widths: Vec<usize> = (0..schema.fields.len())
+        .map(|column| cells.iter().map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+    let mut table = String::new();
+    for (index, row) in cells.iter().enumerate() {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:width$}", value, width = width))
+            .collect();
+        writeln!(table, "{}", line.join(" | ").trim_end()).unwrap();
+        if index == 0 {
+            let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+            writeln!(table, "{}", rule.join("-+-")).unwrap();
+        }
+    }
+    table
+}
+
+fn truncate(value: String) -> String {
+    if value.chars().count() <= PREVIEW_WIDTH {
+        return value;
+    }
+    let mut cut: String = value.chars().take(PREVIEW_WIDTH - 1).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_inspect_parquet_footer() {
+        use crate::writer_parquet::{ParquetWriter, ParquetWriterConfig};
+        use arrow2::{
+            chunk::Chunk,
+            datatypes::{Field, Schema},
+        };
+        use std::sync::Arc;
+
+        let temp_dir = tempdir().unwrap();
+        let parquet_file = temp_dir.path().join("test.parquet");
+        let schema = Arc::new(Schema::from(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let config = ParquetWriterConfig {
+            row_group_size: 1,
+            compression: parquet2::compression::Compression::Snappy,
+            ..ParquetWriterConfig::default()
+        };
+        let mut writer = ParquetWriter::new(&parquet_file, schema, &config).unwrap();
+        for ids in [[1, 2], [3, 4]] {
+            writer
+                .write_batch(&Chunk::new(vec![
+                    Int64Array::from_slice(ids).boxed(),
+                    Utf8Array::<i32>::from_slice(["a", "b"]).boxed(),
+                ]))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let builder = StreamBuilder::new([parquet_file.to_string_lossy().to_string()]);
+        let report = inspect(&builder).unwrap();
+
+        assert!(report.contains("(parquet): 4 rows"));
+        assert!(report.contains("Row groups: 2"));
+        assert!(report.contains("    id: Snappy, encodings"));
+        assert!(report.contains("    name: Snappy, encodings"));
+        assert!(report.contains("Total: 4 rows in 1 files"));
+    }
+
+    #[test]
+    fn test_stats() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "id,name\n3,b\n1,NA\n2,a\n").unwrap();
+
+        let builder = StreamBuilder::new([csv_file.to_string_lossy().to_string()]);
+        let report = stats(builder).unwrap();
+
+        assert!(report.contains("Rows: 3"));
+        assert!(report.contains("id: Int64, 0 nulls, min 1, max 3"));
+        assert!(report.contains("name: Utf8, 1 nulls, min a, max b"));
+    }
+
+    #[test]
+    fn test_tail_reads_last_row_groups() {
+        use crate::writer_parquet::{ParquetWriter, ParquetWriterConfig};
+        use arrow2::datatypes::Field;
+        use std::sync::Arc;
+
+        let temp_dir = tempdir().unwrap();
+        let parquet_file = temp_dir.path().join("test.parquet");
+        let schema = Arc::new(Schema::from(vec![Field::new("id", DataType::Int64, true)]));
+        let config = ParquetWriterConfig {
+            row_group_size: 1,
+            ..ParquetWriterConfig::default()
+        };
+        let mut writer = ParquetWriter::new(&parquet_file, schema, &config).unwrap();
+        for ids in [[1, 2, 3], [4, 5, 6], [7, 8, 9]] {
+            writer.write_batch(&Chunk::new(vec![Int64Array::from_slice(ids).boxed()])).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let metadata = read_parquet_metadata(&parquet_file).unwrap();
+        assert_eq!(last_row_groups(&metadata, 2), 2);
+        assert_eq!(last_row_groups(&metadata, 4), 1);
+        assert_eq!(last_row_groups(&metadata, 100), 0);
+
+        let builder = StreamBuilder::new([parquet_file.to_string_lossy().to_string()]);
+        assert_eq!(tail(&builder, 4).unwrap(), "id\n--\n6\n7\n8\n9\n");
+        assert_eq!(head(builder, 2).unwrap(), "id\n--\n1\n2\n");
+    }
+
+    #[test]
+    fn test_preview_table() {
+        let temp_dir = tempdir().unwrap();
+        let csv1 = temp_dir.path().join("a.csv");
+        let csv2 = temp_dir.path().join("b.csv");
+        fs::write(&csv1, "id,name\n1,first\n2,\n").unwrap();
+        fs::write(&csv2, format!("id,name\n3,{}\n", "x".repeat(50))).unwrap();
+
+        let builder = StreamBuilder::new([temp_dir.path().to_string_lossy().to_string()]);
+        let table = tail(&builder, 2).unwrap();
+        let long = format!("{}…", "x".repeat(PREVIEW_WIDTH - 1));
+        assert_eq!(
+            table,
+            format!("id | name\n---+-{}\n2  | null\n3  | {}\n", "-".repeat(PREVIEW_WIDTH), long)
+        );
+    }
+
+    #[test]
+    fn test_estimate_output() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        let content: String = (0..1000).map(|i| format!("{:04},name{:04}\n", i, i)).collect();
+        fs::write(&csv_file, format!("id,name\n{}", content)).unwrap();
+
+        let builder = StreamBuilder::new([csv_file.to_string_lossy().to_string()]).batch_size(100);
+        let report = estimate_output(&builder, &Compression::None, &Compression::None).unwrap();
+
+        assert!(report.contains("Output columns: 2"));
+        let rows: f64 = report
+            .lines()
+            .find_map(|line| line.strip_prefix("Estimated rows: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        // Extrapolated from the first 100 rows
+        assert!((900.0..=1100.0).contains(&rows), "{}", rows);
+        assert!(report.contains("Estimated Parquet output: ~"));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{
+    cli::JoinType,
+    error::{MawError, Result},
+    transform::{approximate_bytes, render},
+};
+use arrow2::{
+    array::{get_display, growable::make_growable, new_null_array, Array},
+    chunk::Chunk,
+    datatypes::{DataType, Field, Schema},
+};
+use std::collections::HashMap;
+
+/// Added to a right column's name when the left input has one like it.
+const RIGHT_SUFFIX: &str = "_right";
+
+/// Where one side of an output row comes from: a `(chunk, row)` of that
+/// side's chunks, or `None` for the nulls of an unmatched row.
+type RowSource = Option<(usize, usize)>;
+
+/// Joins two inputs on a key column (`maw join`). One side is buffered and
+/// indexed by key while the other streams past it, so only the buffered
+/// side, which should be the smaller, has to fit in memory. Keys match by
+/// their text, so `1` in an integer column matches `"1"` in a text one, and
+/// null keys match nothing.
+///
+/// The output has every left column, then the right columns except the key.
+/// A right column named like a left column gets a `_right` suffix.
+pub struct HashJoin {
+    join_type: JoinType,
+    buffered_left: bool,
+    buffered_key: usize,
+    streamed_key: usize,
+    left_width: usize,
+    right_columns: Vec<usize>,
+    schema: Schema,
+    max_bytes: usize,
+    buffered: Vec<Chunk<Box<dyn Array>>>,
+    buffered_bytes: usize,
+    /// Key -> (chunk, row) of every buffered row with that key
+    index: HashMap<String, Vec<(usize, usize)>>,
+    /// Which buffered rows found a match, kept only when unmatched buffered
+    /// rows are output
+    matched: Vec<Vec<bool>>,
+}
+
+impl HashJoin {
+    /// Builds a join of chunks of `left` and `right` on the `on` column,
+    /// buffering the left side if `buffered_left` and the right otherwise, up
+    /// to `max_bytes`.
+    pub fn new(
+        left: &Schema,
+        right: &Schema,
+        on: &str,
+        join_type: JoinType,
+        buffered_left: bool,
+        max_bytes: usize,
+    ) -> Result<Self> {
+        let position = |schema: &Schema, side: &str| {
+            schema.fields.iter().position(|f| f.name == on).ok_or_else(|| {
+                MawError::Config(format!("--on column '{}' is not in the {} input", on, side))
+            })
+        };
+        let left_key = position(left, "left")?;
+        let right_key = position(right, "right")?;
+
+        let right_columns: Vec<usize> = (0..right.fields.len()).filter(|&i| i != right_key).collect();
+        let mut fields = left.fields.clone();
+        for &i in &right_columns {
+            let field = &right.fields[i];
+            let taken = |name: &str| fields.iter().chain(&right.fields).any(|f| f.name == name);
+            let name = if left.fields.iter().any(|f| f.name == field.name) {
+                let suffixed = format!("{}{}", field.name, RIGHT_SUFFIX);
+                if taken(&suffixed) {
+                    return Err(MawError::Config(format!(
+                        "Right column '{}' is also in the left input, and '{}' is taken too",
+                        field.name, suffixed
+                    )));
+                }
+                suffixed
+            } else {
+                field.name.clone()
+            };
+            // Unmatched left rows leave the right columns null
+            fields.push(Field::new(name, field.data_type().clone(), true));
+        }
+
+        let (buffered_key, streamed_key) = if buffered_left {
+            (left_key, right_key)
+        } else {
+            (right_key, left_key)
+        };
+
+        Ok(Self {
+            join_type,
+            buffered_left,
+            buffered_key,
+            streamed_key,
+            left_width: left.fields.len(),
+            right_columns,
+            schema: Schema::from(fields),
+            max_bytes,
+            buffered: Vec::new(),
+            buffered_bytes: 0,
+            index: HashMap::new(),
+            matched: Vec::new(),
+        })
+    }
+
+    pub fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Buffers a chunk of the buffered side. All of it must be pushed before
+    /// anything is probed.
+    pub fn push(&mut self, chunk: Chunk<Box<dyn Array>>) -> Result<()> {
+        self.buffered_bytes += chunk.arrays().iter().map(|a| approximate_bytes(a.as_ref())).sum::<usize>();
+        if self.buffered_bytes > self.max_bytes {
+            return Err(MawError::InvalidInput(format!(
+                "join buffers its smaller input, which exceeded the --mem-budget of {} MB",
+                self.max_bytes / (1024 * 1024)
+            )));
+        }
+
+        let chunk_id = self.buffered.len();
+        {
+            let keys = chunk.arrays()[self.buffered_key].as_ref();
+            let display = get_display::<String>(keys, "");
+            for row in 0..chunk.len() {
+                if !keys.is_null(row) {
+                    self.index.entry(render(&display, row)).or_default().push((chunk_id, row));
+                }
+            }
+        }
+
+        if self.buffered_left && self.join_type == JoinType::Left {
+            self.matched.push(vec![false; chunk.len()]);
+        }
+        self.buffered.push(chunk);
+        Ok(())
+    }
+
+    /// Joins a chunk of the streamed side against the buffered rows.
+    pub fn probe(&mut self, chunk: &Chunk<Box<dyn Array>>) -> Chunk<Box<dyn Array>> {
+        let keep_unmatched = !self.buffered_left && self.join_type == JoinType::Left;
+        let keys = chunk.arrays()[self.streamed_key].as_ref();
+        let display = get_display::<String>(keys, "");
+
+        let mut streamed_rows = Vec::new();
+        let mut buffered_rows = Vec::new();
+        for row in 0..chunk.len() {
+            let matches = (!keys.is_null(row))
+                .then(|| self.index.get(&render(&display, row)))
+                .flatten();
+            match matches {
+                Some(matches) => {
+                    for &(buffered_chunk, buffered_row) in matches {
+                        streamed_rows.push(Some((0, row)));
+                        buffered_rows.push(Some((buffered_chunk, buffered_row)));
+                        if let Some(matched) = self.matched.get_mut(buffered_chunk) {
+                            matched[buffered_row] = true;
+                        }
+                    }
+                }
+                None if keep_unmatched => {
+                    streamed_rows.push(Some((0, row)));
+                    buffered_rows.push(None);
+                }
+                None => {}
+            }
+        }
+
+        let buffered: Vec<_> = self.buffered.iter().collect();
+        if self.buffered_left {
+            self.assemble(&buffered, &buffered_rows, &[chunk], &streamed_rows)
+        } else {
+            self.assemble(&[chunk], &streamed_rows, &buffered, &buffered_rows)
+        }
+    }
+
+    /// The buffered left rows that matched nothing, once everything has been
+    /// probed. Only a left join that buffered the left side has any.
+    pub fn finish(&self) -> Option<Chunk<Box<dyn Array>>> {
+        let unmatched: Vec<RowSource> = self
+            .matched
+            .iter()
+            .enumerate()
+            .flat_map(|(chunk, matched)| {
+                matched
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &matched)| !matched)
+                    .map(move |(row, _)| Some((chunk, row)))
+            })
+            .collect();
+        if unmatched.is_empty() {
+            return None;
+        }
+
+        let buffered: Vec<_> = self.buffered.iter().collect();
+        Some(self.assemble(&buffered, &unmatched, &[], &vec![None; unmatched.len()]))
+    }
+
+    fn assemble(
+        &self,
+        left: &[&Chunk<Box<dyn Array>>],
+        left_rows: &[RowSource],
+        right: &[&Chunk<Box<dyn Array>>],
+        right_rows: &[RowSource],
+    ) -> Chunk<Box<dyn Array>> {
+        let fields = &self.schema.fields;
+        let mut columns: Vec<Box<dyn Array>> = (0..self.left_width)
+            .map(|column| gather(left, column, left_rows, fields[column].data_type()))
+            .collect();
+        for (i, &column) in self.right_columns.iter().enumerate() {
+            let data_type = fields[self.left_width + i].data_type();
+            columns.push(gather(right, column, right_rows, data_type));
+        }
+        Chunk::new(columns)
+    }
+}
+
+/// Copies one column of the given rows, with nulls for rows without a
+/// source.
+fn gather(
+    chunks: &[&Chunk<Box<dyn Array>>],
+    column: usize,
+    rows: &[RowSource],
+    data_type: &DataType,
+) -> Box<dyn Array> {
+    if chunks.is_empty() {
+        return new_null_array(data_type.clone(), rows.len());
+    }
+    let sources: Vec<&dyn Array> = chunks.iter().map(|c| c.arrays()[column].as_ref()).collect();
+    let mut growable = make_growable(&sources, true, rows.len());
+    for row in rows {
+        match *row {
+            Some((chunk, row)) => growable.extend(chunk, row, 1),
+            None => growable.extend_validity(1),
+        }
+    }
+    growable.as_box()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow2::array::{Int64Array, Utf8Array};
+
+    fn schema(names: &[&str]) -> Schema {
+        Schema::from(
+            names
+                .iter()
+                .map(|name| Field::new(*name, DataType::Utf8, true))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn strings(array: &dyn Array) -> Vec<Option<&str>> {
+        array
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .unwrap()
+            .iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_left_join_buffering_left() {
+        let left = Schema::from(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::Utf8, true),
+        ]);
+        let right = schema(&["id", "city", "name"]);
+        let mut join = HashJoin::new(&left, &right, "id", JoinType::Left, true, usize::MAX).unwrap();
+        let names: Vec<&str> = join.output_schema().fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "name", "city", "name_right"]);
+
+        join.push(Chunk::new(vec![
+            Int64Array::from(vec![Some(1), Some(2), None]).boxed(),
+            Utf8Array::<i32>::from_slice(["alice", "bob", "carol"]).boxed(),
+        ]))
+        .unwrap();
+
+        // Integer keys match their text on the other side
+        let joined = join.probe(&Chunk::new(vec![
+            Utf8Array::<i32>::from_slice(["2", "3"]).boxed(),
+            Utf8Array::<i32>::from_slice(["paris", "rome"]).boxed(),
+            Utf8Array::<i32>::from_slice(["x", "y"]).boxed(),
+        ]));
+        assert_eq!(joined.len(), 1);
+        assert_eq!(strings(joined.arrays()[1].as_ref()), vec![Some("bob")]);
+        assert_eq!(strings(joined.arrays()[2].as_ref()), vec![Some("paris")]);
+        assert_eq!(strings(joined.arrays()[3].as_ref()), vec![Some("x")]);
+
+        let unmatched = join.finish().unwrap();
+        assert_eq!(strings(unmatched.arrays()[1].as_ref()), vec![Some("alice"), Some("carol")]);
+        assert_eq!(strings(unmatched.arrays()[2].as_ref()), vec![None, None]);
+    }
+
+    #[test]
+    fn test_colliding_column_names() {
+        let left = schema(&["id", "name", "city"]);
+        let right = schema(&["id", "city", "name", "zip"]);
+        let join = HashJoin::new(&left, &right, "id", JoinType::Inner, true, usize::MAX).unwrap();
+        let names: Vec<&str> = join.output_schema().fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "name", "city", "city_right", "name_right", "zip"]);
+
+        // A suffixed name that is already taken fails rather than being
+        // silently dropped or duplicated
+        let right = schema(&["id", "name", "name_right"]);
+        let err = HashJoin::new(&left, &right, "id", JoinType::Inner, true, usize::MAX).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Configuration error: Right column 'name' is also in the left input, and 'name_right' is taken too"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{
+    compression::DEFAULT_READ_BUFFER,
+    error::{MawError, Result},
+    rejects::RejectedRow,
+};
+use arrow2::{
+    array::{Array, BooleanArray, Float64Array, Int64Array, Utf8Array},
+    chunk::Chunk,
+};
+use serde_json::{Map, Value};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+/// Reads newline-delimited JSON objects into chunks. Columns are fixed by
+/// the keys seen in the first batch; later keys are ignored with a warning.
+pub struct JsonReader {
+    lines: std::io::Lines<BufReader<Box<dyn Read + Send>>>,
+    headers: Vec<String>,
+    batch_size: usize,
+    line_number: u64,
+    reject_rows: bool,
+    /// Lines set aside for not holding a JSON object, until taken
+    rejected_rows: Vec<RejectedRow>,
+}
+
+impl JsonReader {
+    /// Reads `path` `read_buffer` bytes at a time, or 8 KB by default.
+    pub fn new<P: AsRef<Path>>(path: P, batch_size: usize, read_buffer: Option<usize>) -> Result<Self> {
+        let path = path.as_ref();
+
+        let reader: Box<dyn Read + Send> = if path.to_string_lossy() == "-" {
+            Box::new(std::io::stdin())
+        } else {
+            Box::new(File::open(path)?)
+        };
+
+        Ok(Self {
+            lines: BufReader::with_capacity(read_buffer.unwrap_or(DEFAULT_READ_BUFFER), reader).lines(),
+            headers: Vec::new(),
+            batch_size,
+            line_number: 0,
+            reject_rows: false,
+            rejected_rows: Vec::new(),
+        })
+    }
+
+    /// Sets aside lines that don't hold a JSON object, for the rejects
+    /// file, instead of failing on them.
+    pub fn reject_rows(mut self, reject: bool) -> Self {
+        self.reject_rows = reject;
+        self
+    }
+
+    pub fn read_batch(&mut self) -> Result<Option<Chunk<Box<dyn Array>>>> {
+        let mut objects = Vec::with_capacity(self.batch_size);
+
+        while objects.len() < self.batch_size {
+            let line = match self.lines.next() {
+                Some(line) => line?,
+                None => break,
+            };
+            self.line_number += 1;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let error = match serde_json::from_str::<Value>(&line) {
+                Ok(Value::Object(map)) => {
+                    objects.push(map);
+                    continue;
+                }
+                Ok(other) => MawError::InvalidInput(format!(
+                    "line {}: expected a JSON object, found {}",
+                    self.line_number, other
+                )),
+                Err(e) => MawError::from(e),
+            };
+            if !self.reject_rows {
+                return Err(error);
+            }
+            self.rejected_rows.push(RejectedRow {
+                fields: Vec::new(),
+                reason: format!("Line {} is not a JSON object: {}", self.line_number, line.trim()),
+            });
+        }
+
+        if objects.is_empty() {
+            return Ok(None);
+        }
+
+        if self.headers.is_empty() {
+            for object in &objects {
+                for key in object.keys() {
+                    if !self.headers.contains(key) {
+                        self.headers.push(key.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(Some(self.objects_to_batch(&objects)))
+    }
+
+    fn objects_to_batch(&self, objects: &[Map<String, Value>]) -> Chunk<Box<dyn Array>> {
+        for object in objects {
+            if let Some(key) = object.keys().find(|k| !self.headers.contains(k)) {
+                tracing::warn!("Ignoring JSON key '{}' not present in the first batch", key);
+            }
+        }
+
+        let columns = self
+            .headers
+            .iter()
+            .map(|name| {
+                let values: Vec<Option<&Value>> = objects
+                    .iter()
+                    .map(|o| o.get(name).filter(|v| !v.is_null()))
+                    .collect();
+                create_column_array(&values)
+            })
+            .collect();
+
+        Chunk::new(columns)
+    }
+
+    pub fn get_headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    /// Lines rejected since the last call.
+    pub fn take_rejected_rows(&mut self) -> Vec<RejectedRow> {
+        std::mem::take(&mut self.rejected_rows)
+    }
+}
+
+fn create_column_array(values: &[Option<&Value>]) -> Box<dyn Array> {
+    let present = || values.iter().flatten();
+
+    if present().all(|v| v.is_i64()) {
+        let ints: Vec<Option<i64>> = values.iter().map(|v| v.and_then(|v| v.as_i64())).collect();
+        Box::new(Int64Array::from(ints))
+    } else if present().all(|v| v.is_number()) {
+        let floats: Vec<Option<f64>> = values.iter().map(|v| v.and_then(|v| v.as_f64())).collect();
+        Box::new(Float64Array::from(floats))
+    } else if present().all(|v| v.is_boolean()) {
+        let bools: Vec<Option<bool>> = values.iter().map(|v| v.and_then(|v| v.as_bool())).collect();
+        Box::new(BooleanArray::from(bools))
+    } else {
+        // Strings keep their raw text; anything nested is kept as JSON
+        let strings: Vec<Option<String>> = values
+            .iter()
+            .map(|v| {
+                v.map(|v| match v {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+            })
+            .collect();
+        Box::new(Utf8Array::<i32>::from(strings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_json_reader() {
+        let temp_dir = tempdir().unwrap();
+        let json_file = temp_dir.path().join("test.jsonl");
+        fs::write(
+            &json_file,
+            "{\"a\": 1, \"b\": \"x\"}\n\n{\"a\": 2, \"b\": \"y\", \"c\": true}\n",
+        )
+        .unwrap();
+
+        let mut reader = JsonReader::new(&json_file, 1000, None).unwrap();
+        let batch = reader.read_batch().unwrap().unwrap();
+
+        assert_eq!(batch.len(), 2);
+        assert_eq!(reader.get_headers(), &["a", "b", "c"]);
+
+        let a = batch.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(a.value(1), 2);
+        assert!(batch.arrays()[2].is_null(0));
+        assert!(reader.read_batch().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rejected_lines() {
+        let temp_dir = tempdir().unwrap();
+        let json_file = temp_dir.path().join("test.jsonl");
+        fs::write(&json_file, "{\"a\": 1}\n{\"a\": \n[2]\n{\"a\": 3}\n").unwrap();
+
+        let mut reader = JsonReader::new(&json_file, 1000, None).unwrap();
+        assert!(reader.read_batch().is_err());
+
+        let mut reader = JsonReader::new(&json_file, 1000, None).unwrap().reject_rows(true);
+        let batch = reader.read_batch().unwrap().unwrap();
+        assert_eq!(batch.len(), 2);
+
+        let rejected = reader.take_rejected_rows();
+        assert_eq!(rejected.len(), 2);
+        assert_eq!(rejected[0].reason, "Line 2 is not a JSON object: {\"a\":");
+        assert_eq!(rejected[1].reason, "Line 3 is not a JSON object: [2]");
+        assert!(reader.take_rejected_rows().is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+//! Streaming concatenation and conversion of CSV, JSON lines and Parquet
+//! files. The `maw` binary is a thin CLI over [`pipeline::Pipeline`];
+//! embedders can consume unified chunks directly via [`stream::stream_chunks`],
+//! or collect them into memory with [`stream::collect_table`].
+
+pub mod checksum;
+pub mod cli;
+pub mod coercion;
+pub mod compression;
+pub mod csv_in;
+pub mod discover;
+pub mod error;
+pub mod expr;
+pub mod fsutil;
+pub mod inspect;
+pub mod join;
+pub mod json_in;
+pub mod naming;
+pub mod parquet_in;
+pub mod partition;
+pub mod pipeline;
+pub mod plan;
+pub mod progress;
+pub mod reader;
+pub mod rejects;
+pub mod rolling;
+pub mod schema;
+pub mod schema_cache;
+pub mod state;
+pub mod stream;
+pub mod timezone;
+pub mod transcode;
+pub mod transform;
+pub mod writer_csv;
+pub mod writer_parquet;
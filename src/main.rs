@@ -1,26 +1,14 @@
 use anyhow::Result;
 use clap::Parser;
 use tracing::{info, Level};
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::{fmt, fmt::writer::BoxMakeWriter, EnvFilter};
 
-mod cli;
-mod discover;
-mod error;
-mod schema;
-mod csv_in;
-mod parquet_in;
-mod writer_csv;
-mod writer_parquet;
-mod coercion;
-mod pipeline;
-mod state;
-mod progress;
-
-use cli::Cli;
+use maw::cli::{App, Cli, Command, JoinArgs};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let command = App::parse().into_command();
+    let cli = command.cli();
 
     // Initialize logging
     let filter = if cli.verbose > 0 {
@@ -36,42 +24,100 @@ async fn main() -> Result<()> {
         EnvFilter::from_default_env()
     };
 
+    // Output written to stdout must not be mixed with log lines
+    let writer = if cli.writes_to_stdout() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
     if cli.json_logs {
-        let subscriber = fmt().json().with_env_filter(filter).finish();
+        let subscriber = fmt().json().with_env_filter(filter).with_writer(writer).finish();
         tracing::subscriber::set_global_default(subscriber)?;
     } else {
-        let subscriber = fmt().with_env_filter(filter).finish();
+        let subscriber = fmt().with_env_filter(filter).with_writer(writer).finish();
         tracing::subscriber::set_global_default(subscriber)?;
     }
 
     info!("Starting maw v{}", env!("CARGO_PKG_VERSION"));
 
     // Execute the main logic
-    match execute(cli).await {
+    match execute(command).await {
         Ok(()) => {
             info!("Operation completed successfully");
             Ok(())
         }
         Err(e) => {
             tracing::error!("Operation failed: {}", e);
-            std::process::exit(1);
+            // Some inputs were skipped but the rest were written
+            let partial = matches!(e.downcast_ref(), Some(maw::error::MawError::PartialFailure { .. }));
+            std::process::exit(if partial { 2 } else { 1 });
         }
     }
 }
 
-async fn execute(cli: Cli) -> Result<()> {
-    use crate::pipeline::Pipeline;
+async fn execute(command: Command) -> Result<()> {
+    use maw::{inspect, pipeline::Pipeline, stream::StreamBuilder};
+
+    match command {
+        Command::Concat(cli) => concat(cli).await,
+        Command::Convert(cli) => {
+            Pipeline::new(cli).convert().await?;
+            Ok(())
+        }
+        Command::Inspect(cli) => {
+            print!("{}", inspect::inspect(&StreamBuilder::from_cli(&cli)?)?);
+            Ok(())
+        }
+        Command::Stats(cli) => {
+            print!("{}", inspect::stats(StreamBuilder::from_cli(&cli)?)?);
+            Ok(())
+        }
+        Command::Join(args) => {
+            let JoinArgs { left, right, on, join_type, cli } = args;
+            Pipeline::new(cli).join(&left, &right, &on, join_type).await?;
+            Ok(())
+        }
+        Command::Head(args) => {
+            print!("{}", inspect::head(StreamBuilder::from_cli(&args.cli)?, args.rows)?);
+            Ok(())
+        }
+        Command::Tail(args) => {
+            print!("{}", inspect::tail(&StreamBuilder::from_cli(&args.cli)?, args.rows)?);
+            Ok(())
+        }
+    }
+}
+
+async fn concat(cli: Cli) -> Result<()> {
+    use maw::{inspect, pipeline::Pipeline, stream::StreamBuilder};
     
     if cli.plan {
-        info!("Plan mode: would process {} inputs", cli.inputs.len());
-        for input in &cli.inputs {
-            info!("  - {}", input);
-        }
+        print!("{}", Pipeline::new(cli).plan()?);
         return Ok(());
     }
 
     if cli.dry_run {
-        info!("Dry run mode: would process inputs without writing output");
+        print!("{}", Pipeline::new(cli).dry_run()?);
+        return Ok(());
+    }
+
+    if cli.count {
+        print!("{}", inspect::count(&StreamBuilder::from_cli(&cli)?)?);
+        return Ok(());
+    }
+
+    if cli.content_hash {
+        print!("{}", inspect::content_hash(StreamBuilder::from_cli(&cli)?)?);
+        return Ok(());
+    }
+
+    if cli.validate {
+        let validation = inspect::validate(&StreamBuilder::from_cli(&cli)?)?;
+        print!("{}", validation.report);
+        if validation.failed > 0 {
+            anyhow::bail!("{} Parquet files failed validation", validation.failed);
+        }
         return Ok(());
     }
 
@@ -0,0 +1,180 @@
+use crate::error::{MawError, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Names rolled and partition files get without `--name-template`.
+pub const DEFAULT_TEMPLATE: &str = "part-{index:05}.{ext}";
+
+/// How rolled and partition output files are named (`--name-template`),
+/// e.g. `part-{index:05}-{date}.{ext}`. The placeholders are:
+///
+/// - `{index}`: the file's number in its directory, from 0. `{index:05}`
+///   zero-pads it to 5 digits.
+/// - `{date}` and `{timestamp}`: when the run started, in UTC, as
+///   `20240131` and `20240131T235959Z`.
+/// - `{partition}`: the file's partition values, as `year=2024_region=eu`.
+/// - `{ext}`: the extension of the output format and compression, such as
+///   `csv.gz`.
+#[derive(Debug, Clone)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+    date: String,
+    timestamp: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    /// The file's index, zero-padded to this width
+    Index(usize),
+    Date,
+    Timestamp,
+    Partition,
+    Extension,
+}
+
+impl NameTemplate {
+    /// Parses `template`, failing on unknown placeholders, unmatched braces
+    /// or a path separator. Every name needs an `{index}`, since a directory
+    /// may get several files, and `{partition}` only has a value when
+    /// `partitioned`.
+    pub fn parse(template: &str, partitioned: bool) -> Result<Self> {
+        let invalid = |reason: String| MawError::Config(format!("--name-template '{}': {}", template, reason));
+        if template.contains(['/', '\\']) {
+            return Err(invalid("must be a file name, not a path".to_string()));
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                return Err(invalid("unmatched '}'".to_string()));
+            }
+            let end = start + rest[start..].find('}').ok_or_else(|| invalid("unmatched '{'".to_string()))?;
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let name = &rest[start + 1..end];
+            let part = parse_placeholder(name).ok_or_else(|| invalid(format!("unknown placeholder {{{}}}", name)))?;
+            if part == Part::Partition && !partitioned {
+                return Err(invalid("{partition} needs --partition-by".to_string()));
+            }
+            parts.push(part);
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        if !parts.iter().any(|part| matches!(part, Part::Index(_))) {
+            return Err(invalid("needs an {index} to tell files apart".to_string()));
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let started = chrono::DateTime::from_timestamp(now as i64, 0).unwrap_or_default();
+        Ok(Self {
+            parts,
+            date: started.format("%Y%m%d").to_string(),
+            timestamp: started.format("%Y%m%dT%H%M%SZ").to_string(),
+        })
+    }
+
+    /// The name of the `index`th file of `partition` (a partition directory
+    /// such as `year=2024/region=eu`, or empty), with extension `ext`.
+    pub fn render(&self, index: usize, partition: &str, ext: &str) -> String {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => name.push_str(text),
+                Part::Index(width) => name.push_str(&format!("{:0width$}", index, width = *width)),
+                Part::Date => name.push_str(&self.date),
+                Part::Timestamp => name.push_str(&self.timestamp),
+                Part::Partition => name.push_str(&partition.replace('/', "_")),
+                Part::Extension => name.push_str(ext),
+            }
+        }
+        name
+    }
+
+    /// The index of a file named `name` by this template in `partition`
+    /// with extension `ext`, from any run, so `{date}` and `{timestamp}`
+    /// match any such value. None if the template doesn't make that name.
+    pub fn index_of(&self, name: &str, partition: &str, ext: &str) -> Option<usize> {
+        let mut rest = name;
+        let mut index = None;
+        for part in &self.parts {
+            rest = match part {
+                Part::Text(text) => rest.strip_prefix(text.as_str())?,
+                Part::Index(_) => {
+                    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+                    index = Some(rest[..digits].parse().ok()?);
+                    &rest[digits..]
+                }
+                Part::Date => skip_pattern(rest, "dddddddd")?,
+                Part::Timestamp => skip_pattern(rest, "ddddddddTddddddZ")?,
+                Part::Partition => rest.strip_prefix(partition.replace('/', "_").as_str())?,
+                Part::Extension => rest.strip_prefix(ext)?,
+            };
+        }
+        rest.is_empty().then_some(index?)
+    }
+}
+
+/// What follows a prefix of `value` shaped like `pattern`, where `d` stands
+/// for any digit and other characters for themselves.
+fn skip_pattern<'a>(value: &'a str, pattern: &str) -> Option<&'a str> {
+    let prefix = value.get(..pattern.len())?;
+    let matches = prefix
+        .bytes()
+        .zip(pattern.bytes())
+        .all(|(c, p)| if p == b'd' { c.is_ascii_digit() } else { c == p });
+    matches.then(|| &value[pattern.len()..])
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE, false).expect("the default template is valid")
+    }
+}
+
+fn parse_placeholder(name: &str) -> Option<Part> {
+    match name {
+        "index" => Some(Part::Index(0)),
+        "date" => Some(Part::Date),
+        "timestamp" => Some(Part::Timestamp),
+        "partition" => Some(Part::Partition),
+        "ext" => Some(Part::Extension),
+        _ => {
+            let width = name.strip_prefix("index:0")?;
+            width.parse().ok().map(Part::Index)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_template() {
+        let template = NameTemplate::parse("{partition}-{index:03}-{date}.{ext}", true).unwrap();
+        let name = template.render(7, "year=2024/region=eu", "csv.gz");
+        assert!(name.starts_with("year=2024_region=eu-007-"), "{}", name);
+        assert!(name.ends_with(".csv.gz"), "{}", name);
+        assert_eq!(name.len(), "year=2024_region=eu-007-20240131.csv.gz".len());
+
+        assert_eq!(NameTemplate::default().render(12, "", "parquet"), "part-00012.parquet");
+        assert_eq!(NameTemplate::parse("{index}", false).unwrap().render(12, "", "csv"), "12");
+
+        for invalid in ["part.{ext}", "{index}-{month}", "{index", "index}", "a/{index}", "{index:5}"] {
+            assert!(NameTemplate::parse(invalid, true).is_err(), "{}", invalid);
+        }
+        assert!(NameTemplate::parse("{partition}-{index}", false).is_err());
+
+        // Names from an earlier run give back their index
+        assert_eq!(template.index_of(&name, "year=2024/region=eu", "csv.gz"), Some(7));
+        assert_eq!(template.index_of("year=2024_region=eu-007-20230101.csv.gz", "year=2024/region=eu", "csv.gz"), Some(7));
+        assert_eq!(template.index_of(&name, "year=2023/region=eu", "csv.gz"), None);
+        assert_eq!(template.index_of(&name, "year=2024/region=eu", "csv"), None);
+        assert_eq!(NameTemplate::default().index_of("part-00012.csv", "", "csv"), Some(12));
+        assert_eq!(NameTemplate::default().index_of("notes.txt", "", "csv"), None);
+    }
+}
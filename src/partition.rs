@@ -0,0 +1,259 @@
+use crate::{
+    cli::OutputFormat,
+    error::{MawError, Result},
+    rolling::{OutputFiles, RollingWriter},
+    transform::{approximate_bytes, gather_rows, render},
+};
+use arrow2::{
+    array::{get_display, Array},
+    chunk::Chunk,
+};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::PathBuf,
+    sync::Arc,
+};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
+};
+
+/// Directory value of a null partition value, as Hive names it.
+const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Rows queued for a partition's writer, holding their share of the byte
+/// budget until they're written.
+type Queued = (Chunk<Box<dyn Array>>, OwnedSemaphorePermit);
+
+/// Writes rows into one directory per distinct value of the partition
+/// columns (`--partition-by`), e.g. `DIR/year=2024/region=eu/part-00000.csv`.
+/// Each partition's files are named and rolled as `files` says.
+///
+/// Each open partition has its own writer task, fed through an unbounded
+/// queue so that a partition that is slow to write doesn't hold up the
+/// others. What the queues hold together is capped by a byte budget
+/// instead, so routing only waits when the writers fall behind overall. At
+/// most `max_open` partitions have a file open at once: opening another
+/// first closes the one least recently written to, and a closed partition
+/// that gets more rows is appended to (CSV) or continued in its next part
+/// (Parquet, which can't be appended to, and rolled output).
+pub struct PartitionedWriter {
+    dir: PathBuf,
+    columns: Vec<(String, usize)>,
+    files: OutputFiles,
+    max_open: usize,
+    budget: Arc<Semaphore>,
+    budget_bytes: usize,
+    open: HashMap<String, OpenPartition>,
+    /// Index of the next file of each partition that has been closed
+    parts: HashMap<String, usize>,
+    /// Counts writes, to find the least recently written partition
+    clock: u64,
+    runtime: Handle,
+}
+
+struct OpenPartition {
+    queue: mpsc::UnboundedSender<Queued>,
+    /// Returns the index of the partition's next file
+    task: JoinHandle<Result<usize>>,
+    last_write: u64,
+}
+
+impl PartitionedWriter {
+    /// Must be created on a thread of the tokio runtime, which runs the
+    /// partitions' writer tasks.
+    pub fn new(
+        dir: PathBuf,
+        partition_by: &[String],
+        files: OutputFiles,
+        max_open: usize,
+        max_bytes: usize,
+    ) -> Result<Self> {
+        if max_open == 0 {
+            return Err(MawError::Config("--max-open-files must be at least 1".to_string()));
+        }
+        let columns = partition_by
+            .iter()
+            .map(|name| {
+                let index = files.schema.fields.iter().position(|f| f.name == *name).ok_or_else(|| {
+                    MawError::Config(format!("--partition-by column '{}' is not in the output", name))
+                })?;
+                Ok((name.clone(), index))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Permits are counted in a u32, and a chunk larger than the whole
+        // budget still has to fit
+        let budget_bytes = max_bytes.clamp(1, u32::MAX as usize);
+
+        Ok(Self {
+            dir,
+            columns,
+            files,
+            max_open,
+            budget: Arc::new(Semaphore::new(budget_bytes)),
+            budget_bytes,
+            open: HashMap::new(),
+            parts: HashMap::new(),
+            clock: 0,
+            runtime: Handle::current(),
+        })
+    }
+
+    /// Splits `batch` by partition and queues each partition's rows with
+    /// its writer, waiting while the queues are over the byte budget.
+    pub fn write_batch(&mut self, batch: &Chunk<Box<dyn Array>>) -> Result<()> {
+        for (partition, rows) in self.split(batch) {
+            let rows: Vec<(usize, usize)> = rows.into_iter().map(|row| (0, row)).collect();
+            let chunk = gather_rows(&[batch], &rows);
+            let bytes = chunk.arrays().iter().map(|a| approximate_bytes(a.as_ref())).sum::<usize>();
+            let permit = self
+                .runtime
+                .block_on(self.budget.clone().acquire_many_owned(bytes.clamp(1, self.budget_bytes) as u32))
+                .expect("the budget semaphore is never closed");
+
+            self.clock += 1;
+            if !self.open.contains_key(&partition) {
+                self.open_partition(&partition)?;
+            }
+            let open = self.open.get_mut(&partition).expect("partition was just opened");
+            open.last_write = self.clock;
+            if open.queue.send((chunk, permit)).is_err() {
+                // The writer stopped early, so it has the error to report
+                let open = self.open.remove(&partition).expect("partition is open");
+                self.runtime.block_on(open.task)??;
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes every partition, waiting for their queues to be written.
+    pub fn finish(mut self) -> Result<()> {
+        let mut result = Ok(());
+        for (_, open) in self.open.drain() {
+            drop(open.queue);
+            let finished = self.runtime.block_on(open.task).map_err(MawError::from).and_then(|r| r);
+            if let (Ok(()), Err(e)) = (&result, finished) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Groups the rows of `batch` by their partition directory, in order of
+    /// first appearance.
+    fn split(&self, batch: &Chunk<Box<dyn Array>>) -> Vec<(String, Vec<usize>)> {
+        let displays: Vec<_> = self
+            .columns
+            .iter()
+            .map(|(_, index)| {
+                let array = batch.arrays()[*index].as_ref();
+                (array, get_display::<String>(array, ""))
+            })
+            .collect();
+
+        let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for row in 0..batch.len() {
+            let mut partition = String::new();
+            for ((name, _), (array, display)) in self.columns.iter().zip(&displays) {
+                let value = if array.is_null(row) {
+                    NULL_PARTITION.to_string()
+                } else {
+                    escape_path_value(&render(display, row))
+                };
+                if !partition.is_empty() {
+                    partition.push('/');
+                }
+                let _ = write!(partition, "{}={}", escape_path_value(name), value);
+            }
+            let position = *positions.entry(partition.clone()).or_insert_with(|| {
+                groups.push((partition, Vec::new()));
+                groups.len() - 1
+            });
+            groups[position].1.push(row);
+        }
+        groups
+    }
+
+    /// Starts a writer task for `partition`, first closing the least
+    /// recently written partition if `max_open` are already open.
+    fn open_partition(&mut self, partition: &str) -> Result<()> {
+        if self.open.len() >= self.max_open {
+            let idle = self
+                .open
+                .iter()
+                .min_by_key(|(_, open)| open.last_write)
+                .map(|(partition, _)| partition.clone())
+                .expect("max_open is at least 1");
+            let open = self.open.remove(&idle).expect("partition is open");
+            drop(open.queue);
+            // Wait for its file to close, so no more than max_open are open
+            let next_index = self.runtime.block_on(open.task)??;
+            self.parts.insert(idle, next_index);
+        }
+
+        let dir = self.dir.join(partition);
+        std::fs::create_dir_all(&dir)?;
+        let mut files = self.files.clone();
+        let first_index = match self.parts.get(partition) {
+            // A reopened CSV partition that isn't rolled carries on in the
+            // same file
+            Some(next_index) if matches!(files.format, OutputFormat::Csv) && files.roll.is_none() => {
+                files.csv_config.append = true;
+                next_index - 1
+            }
+            Some(next_index) => *next_index,
+            None => files.first_index(&dir, partition)?,
+        };
+        let partition_dir = partition.to_string();
+        let (queue, mut rx) = mpsc::unbounded_channel::<Queued>();
+        let task = tokio::task::spawn_blocking(move || {
+            let mut writer = RollingWriter::new(dir, &partition_dir, files, first_index);
+            while let Some((chunk, _permit)) = rx.blocking_recv() {
+                writer.write_batch(&chunk)?;
+            }
+            writer.finish()
+        });
+
+        self.open.insert(
+            partition.to_string(),
+            OpenPartition {
+                queue,
+                task,
+                last_write: self.clock,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Escapes the characters that can't appear in a directory name, and the
+/// `=` and `%` that partition directories use themselves, as `%XX`.
+fn escape_path_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '%' | '=') {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                let _ = write!(escaped, "%{:02X}", byte);
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_path_value() {
+        assert_eq!(escape_path_value("eu-west 1"), "eu-west 1");
+        assert_eq!(escape_path_value("a/b=c%"), "a%2Fb%3Dc%25");
+        assert_eq!(escape_path_value("tab\there"), "tab%09here");
+    }
+}
@@ -1,49 +1,152 @@
 use crate::{
-    cli::{Cli, OutputFormat},
-    csv_in::{CsvConfig, CsvReader},
-    discover::{discover_inputs, DiscoveryConfig, InputFile},
+    cli::{Cli, Compression, JoinType, LineTerminator, OutputFormat, QuoteStyle},
+    compression::{codec_from_extension, resolve_level},
+    csv_in::{extension_delimiter, input_delimiter, parse_delimiter_override},
+    discover::{FileFormat, InputFile},
     error::{MawError, Result},
-    parquet_in::ParquetReader,
-    schema::UnifiedSchema,
+    fsutil::{check_no_clobber, check_temp_dir},
+    inspect::estimate_output,
+    join::HashJoin,
+    naming::{NameTemplate, DEFAULT_TEMPLATE},
+    partition::PartitionedWriter,
+    plan::PlanNode,
+    progress::{spawn_heartbeat, Counters, GlobalProgress, ProgressTracker},
+    reader::InputReader,
+    rejects::{RejectsWriter, SharedRejects},
+    rolling::{OutputFiles, RollLimits, RollingWriter},
+    schema::{SchemaSidecar, UnifiedSchema},
+    state::{spawn_checkpoint, InputProgress, ProcessingState, ResumePoint, StateManager},
+    stream::{split_list, stream_chunks, StreamBuilder, Unified},
+    transcode,
+    transform::{Pivot, RowNumbers, Sampler, Unpivot},
     writer_csv::{CsvWriter, CsvWriterConfig},
     writer_parquet::{ParquetWriter, ParquetWriterConfig},
 };
-use arrow2::{array::Array, chunk::Chunk};
+use arrow2::{array::Array, chunk::Chunk, datatypes::Schema};
+use clap::ValueEnum;
 use std::{
-    path::PathBuf,
-    sync::Arc,
+    cell::RefCell,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
 };
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, time::Instant};
+
+type SharedState = Arc<Mutex<ProcessingState>>;
+
+/// What readers send the writer: rows, with how far their input has been
+/// read once they are written, or that an input was read to its end.
+enum Message {
+    Rows(Chunk<Box<dyn Array>>, Option<InputProgress>),
+    Done(InputProgress),
+}
+
+/// The outcome of the writer waiting on the readers' channel.
+enum Received {
+    Batch(Chunk<Box<dyn Array>>),
+    /// The flush deadline passed before a batch arrived
+    Idle,
+    Closed,
+}
+
+/// The transforms rows go through once aligned, in order: readers unpivot
+/// their chunks, then the writer samples, pivots and numbers them.
+struct Transforms {
+    unpivot: Option<Unpivot>,
+    sampler: Option<Sampler>,
+    pivot: Option<Pivot>,
+    row_numbers: Option<RowNumbers>,
+}
+
+impl Transforms {
+    /// One line per transform, for `--plan`.
+    fn describe(&self) -> Vec<String> {
+        let mut steps = Vec::new();
+        steps.extend(self.unpivot.as_ref().map(Unpivot::describe));
+        steps.extend(self.sampler.as_ref().map(Sampler::describe));
+        steps.extend(self.pivot.as_ref().map(Pivot::describe));
+        steps.extend(self.row_numbers.as_ref().map(RowNumbers::describe));
+        steps
+    }
+}
 
 pub struct Pipeline {
     cli: Cli,
     unified_schema: Arc<UnifiedSchema>,
+    /// Whether a state file from an earlier run exists to resume from,
+    /// checked before this run can checkpoint its own
+    resuming: bool,
+    /// Set when inputs changed since the state was saved, so the run starts
+    /// over and replaces the output rather than appending to it
+    restarted: AtomicBool,
 }
 
 impl Pipeline {
     pub fn new(cli: Cli) -> Self {
+        let resuming = cli.resume && cli.state.as_ref().is_some_and(|p| p.exists());
         Self {
             cli,
             unified_schema: Arc::new(UnifiedSchema::new()),
+            resuming,
+            restarted: AtomicBool::new(false),
         }
     }
 
     pub async fn execute(&self) -> Result<()> {
-        // Discover input files
-        let discovery_config = DiscoveryConfig {
-            recursive: !self.cli.no_recursive,
-            follow_symlinks: self.cli.follow_symlinks,
-            max_depth: None,
-        };
+        // Validate options before touching any input or output
+        self.compression_level(&self.output_format()?)?;
+        self.roll_limits()?;
+        self.name_template()?;
+        self.check_append()?;
+        if let Some(temp_dir) = &self.cli.temp_dir {
+            check_temp_dir(temp_dir)?;
+        }
+        self.check_stdout_output()?;
+        if self.cli.reproducible && self.cli.sample.is_some() && self.cli.seed.is_none() {
+            return Err(MawError::Config(
+                "--reproducible with --sample needs a --seed".to_string(),
+            ));
+        }
+        if self.cli.write_schema_sidecar && !matches!(self.output_format()?, OutputFormat::Csv) {
+            return Err(MawError::Config(
+                "--write-schema-sidecar describes CSV output; Parquet output carries its own schema".to_string(),
+            ));
+        }
 
-        let input_files = discover_inputs(&self.cli.inputs, &discovery_config)?;
+        // Only this path writes --rejects, so only here are unreadable rows
+        // set aside for it rather than failing the run
+        let builder = StreamBuilder::from_cli(&self.cli)?.reject_rows(self.cli.rejects.is_some());
+
+        // Discover input files
+        let input_files = builder.discover()?;
         
         if input_files.is_empty() {
             return Err(MawError::InvalidInput("No input files found".to_string()));
         }
 
-        // Build unified schema from all inputs
-        let unified_schema = self.build_unified_schema(&input_files).await?;
+        if self.cli.transcode {
+            let blocker = match self.output_format()? {
+                OutputFormat::Csv => transcode::blocker(&input_files, builder.get_csv_config())?,
+                OutputFormat::Parquet => Some("the output is Parquet".to_string()),
+            };
+            match blocker {
+                None => return self.transcode(&builder, &input_files).await,
+                Some(reason) => tracing::info!("Not transcoding field by field, as {}", reason),
+            }
+        }
+
+        let total_inputs = input_files.len();
+
+        // Build unified schema from all inputs, leaving out any that failed
+        let unified = self.build_unified_schema(&builder, &input_files).await?;
+        let mut failed: Vec<String> = unified.failed.iter().map(|p| p.to_string_lossy().to_string()).collect();
+        let input_files: Vec<InputFile> = input_files
+            .into_iter()
+            .filter(|f| !unified.failed.contains(&f.path))
+            .collect();
         
         // Create output writer
         let output_path = self.cli.out.clone()
@@ -51,152 +154,1228 @@ impl Pipeline {
         
         let output_format = self.determine_output_format(&output_path)?;
         
+        let mut state_manager = StateManager::new(
+            self.cli.state.as_ref().map(|p| p.to_string_lossy().to_string()),
+        );
+        let state = self.prepare_state(&mut state_manager, &input_files, &output_path, &output_format)?;
+        
+        let checkpoint = match (self.cli.checkpoint_interval, &state, &self.cli.state) {
+            (Some(secs), Some(state), Some(path)) => Some(spawn_checkpoint(
+                state.clone(),
+                path.to_string_lossy().to_string(),
+                parse_interval("--checkpoint-interval", secs)?,
+            )),
+            _ => None,
+        };
+        
+        let dropped_columns = unified.schema.dropped_columns.clone();
+
         // Set up concurrent processing
-        self.process_files_concurrently(&input_files, &unified_schema, &output_path, output_format).await
+        let result = self
+            .process_files_concurrently(&builder, &input_files, unified, &output_path, output_format, state.clone())
+            .await;
+        
+        // Stop checkpointing before the final save so it can't be overwritten
+        if let Some(handle) = checkpoint {
+            handle.abort();
+            let _ = handle.await;
+        }
+        
+        // Save progress even on failure, so the run can be resumed
+        if let Some(state) = &state {
+            let state = state.lock().map_err(|_| poisoned_state())?.clone();
+            state_manager.save_state(&state)?;
+        }
+        
+        failed.extend(result?);
+        if !dropped_columns.is_empty() {
+            tracing::info!(
+                "Left out {} mostly-null columns: {}",
+                dropped_columns.len(),
+                dropped_columns.join(", ")
+            );
+        }
+        if !failed.is_empty() {
+            return Err(MawError::PartialFailure {
+                failed,
+                total: total_inputs,
+            });
+        }
+        Ok(())
     }
 
-    async fn build_unified_schema(&self, _input_files: &[InputFile]) -> Result<UnifiedSchema> {
-        // For now, create a simple unified schema
-        // In a real implementation, we would sample each file and build the schema
-        Ok(UnifiedSchema::new())
+    /// Copies the inputs to the CSV output field by field (`--transcode`),
+    /// once `transcode::blocker` has found nothing in the way.
+    async fn transcode(&self, builder: &StreamBuilder, input_files: &[InputFile]) -> Result<()> {
+        let output_path = self.cli.out.clone().unwrap_or_else(|| PathBuf::from("output"));
+        let mut config = self.csv_writer_config(input_files, &output_path)?;
+        config.delimiter = match self.cli.out_delimiter {
+            Some(delimiter) => delimiter as u8,
+            None => extension_delimiter(&output_path).unwrap_or(b','),
+        };
+        let csv = builder.get_csv_config().clone();
+        let files = input_files.to_vec();
+
+        let rows = tokio::task::spawn_blocking(move || -> Result<u64> {
+            let mut writer = CsvWriter::new(&output_path, &config)?;
+            let rows = transcode::transcode(&files, &csv, &mut writer)?;
+            writer.finish()?;
+            Ok(rows)
+        })
+        .await??;
+        tracing::info!("Transcoded {} rows field by field", rows);
+        Ok(())
+    }
+
+    /// Converts a single input file to `--out`, rejecting anything that would
+    /// merge several inputs into one output.
+    pub async fn convert(&self) -> Result<()> {
+        if self.cli.out.is_none() {
+            return Err(MawError::Config(
+                "convert needs an output path; pass -o/--out".to_string(),
+            ));
+        }
+
+        let input_files = StreamBuilder::from_cli(&self.cli)?.discover()?;
+        if input_files.len() != 1 {
+            return Err(MawError::InvalidInput(format!(
+                "convert takes exactly one input file, found {}",
+                input_files.len()
+            )));
+        }
+
+        self.execute().await
+    }
+
+    /// Joins the `left` and `right` inputs on the `on` column into `--out`,
+    /// buffering whichever side's files are smaller.
+    pub async fn join(&self, left: &str, right: &str, on: &str, join_type: JoinType) -> Result<()> {
+        let Some(output_path) = &self.cli.out else {
+            return Err(MawError::Config(
+                "join needs an output path; pass -o/--out".to_string(),
+            ));
+        };
+        self.compression_level(&self.output_format()?)?;
+        if let Some(temp_dir) = &self.cli.temp_dir {
+            check_temp_dir(temp_dir)?;
+        }
+        self.check_stdout_output()?;
+
+        let left = StreamBuilder::from_cli(&self.cli)?.inputs([left]);
+        let right = StreamBuilder::from_cli(&self.cli)?.inputs([right]);
+        let left_files = left.discover()?;
+        let right_files = right.discover()?;
+        let total_size = |files: &[InputFile]| files.iter().map(|f| f.size).sum::<u64>();
+        let buffered_left = total_size(&left_files) < total_size(&right_files);
+        let input_files: Vec<InputFile> = left_files.into_iter().chain(right_files).collect();
+
+        let left = stream_chunks(left)?;
+        let right = stream_chunks(right)?;
+        let mut join = HashJoin::new(
+            left.schema(),
+            right.schema(),
+            on,
+            join_type,
+            buffered_left,
+            self.cli.mem_budget * 1024 * 1024,
+        )?;
+        let output_schema = Arc::new(join.output_schema().clone());
+        let output_format = self.determine_output_format(output_path)?;
+
+        let (tx, rx) = mpsc::channel::<Message>(8);
+        let counters = Counters::default();
+        let writer_handle = self
+            .spawn_writer(
+                &input_files,
+                output_path,
+                output_format,
+                output_schema,
+                None,
+                None,
+                self.row_numbers(),
+                rx,
+                None,
+                &counters,
+            )
+            .await?;
+
+        let join_handle = tokio::task::spawn_blocking(move || -> Result<()> {
+            let (buffered, streamed) = if buffered_left { (left, right) } else { (right, left) };
+            for chunk in buffered {
+                join.push(chunk?)?;
+            }
+            for chunk in streamed {
+                let joined = join.probe(&chunk?);
+                if !joined.is_empty() && tx.blocking_send(Message::Rows(joined, None)).is_err() {
+                    return Ok(()); // Channel closed
+                }
+            }
+            if let Some(unmatched) = join.finish() {
+                let _ = tx.blocking_send(Message::Rows(unmatched, None));
+            }
+            Ok(())
+        });
+
+        join_handle.await??;
+        writer_handle.await??;
+        tracing::info!("Joined {} rows", counters.rows());
+        Ok(())
+    }
+
+    /// Describes what `execute` would produce, without writing anything.
+    pub fn dry_run(&self) -> Result<String> {
+        self.compression_level(&self.output_format()?)?;
+        let builder = StreamBuilder::from_cli(&self.cli)?;
+        estimate_output(
+            &builder,
+            &self.output_compression(&OutputFormat::Csv),
+            &self.output_compression(&OutputFormat::Parquet),
+        )
+    }
+
+    /// Describes how `execute` would run as a tree (`--plan`): the inputs and
+    /// their readers, how their schemas unify, the transforms and the
+    /// writer. Inputs are sampled, but nothing is written.
+    pub fn plan(&self) -> Result<String> {
+        self.compression_level(&self.output_format()?)?;
+        self.check_stdout_output()?;
+        let builder = StreamBuilder::from_cli(&self.cli)?.reject_rows(self.cli.rejects.is_some());
+        let input_files = builder.discover()?;
+        if input_files.is_empty() {
+            return Err(MawError::InvalidInput("No input files found".to_string()));
+        }
+        let unified = builder.unify(&input_files)?;
+        let output_schema = builder.aligner(unified.schema.clone())?.output_schema();
+        let input_files: Vec<InputFile> = input_files
+            .into_iter()
+            .filter(|f| !unified.failed.contains(&f.path))
+            .collect();
+        let output_path = self.cli.out.clone().unwrap_or_else(|| PathBuf::from("output"));
+        let output_format = self.determine_output_format(&output_path)?;
+
+        let mut inputs = PlanNode::new(format!("inputs: {} discovered", input_files.len() + unified.failed.len()));
+        for path in &unified.failed {
+            inputs.push_leaf(format!("skip {}: failed to sample", path.display()));
+        }
+        for file in &input_files {
+            let mut reader = format!("{} reader: {}", file.format.name(), file.path.display());
+            if file.format == FileFormat::Csv && file.path.to_string_lossy() != "-" {
+                let delimiter = match builder.get_csv_config().delimiter_for(&file.path) {
+                    Some(delimiter) => delimiter,
+                    None => input_delimiter(&file.path)?,
+                };
+                reader.push_str(&format!(" (delimiter '{}')", delimiter as char));
+            }
+            inputs.push_leaf(reader);
+        }
+
+        let mut unify = PlanNode::new(format!("unify: {} columns", output_schema.fields.len()));
+        for field in &output_schema.fields {
+            let mut column = PlanNode::new(format!("{}: {:?}", field.name, field.data_type()));
+            if self.cli.source_column.as_ref() == Some(&field.name) {
+                column.push_leaf(match &self.cli.relative_to {
+                    Some(dir) => format!("input paths relative to {}", dir.display()),
+                    None => "input paths".to_string(),
+                });
+                unify.push(column);
+                continue;
+            }
+            if let Some(computed) = builder.get_computed().iter().find(|c| c.name == field.name) {
+                column.push_leaf(format!("computed from {}", computed.expression));
+                unify.push(column);
+                continue;
+            }
+            let unified_type = unified
+                .schema
+                .schema
+                .fields
+                .iter()
+                .find(|f| f.name == field.name)
+                .map(|f| f.data_type());
+            for (path, sample) in &unified.samples {
+                let path = path.display();
+                match sample.fields.iter().find(|f| f.name == field.name) {
+                    Some(source) if Some(source.data_type()) != unified_type => {
+                        column.push_leaf(format!("cast from {:?} in {}", source.data_type(), path));
+                    }
+                    Some(_) => {}
+                    None => column.push_leaf(format!("nulls in {}, which lacks it", path)),
+                }
+            }
+            unify.push(column);
+        }
+
+        let mut transforms = PlanNode::new("transforms");
+        let steps = builder
+            .describe_transforms(&unified.schema)?
+            .into_iter()
+            .chain(self.transforms(&output_schema)?.describe());
+        for step in steps {
+            transforms.push_leaf(step);
+        }
+        if transforms.children.is_empty() {
+            transforms.label.push_str(": none");
+        }
+
+        let mut writer = match output_format {
+            OutputFormat::Csv => PlanNode::new(format!(
+                "csv writer (delimiter '{}', compression {})",
+                self.output_delimiter(&input_files, &output_path)? as char,
+                self.output_compression(&OutputFormat::Csv)
+            )),
+            OutputFormat::Parquet => PlanNode::new(format!(
+                "parquet writer (compression {})",
+                self.output_compression(&OutputFormat::Parquet)
+            )),
+        };
+        if let Some(columns) = &self.cli.partition_by {
+            writer.push_leaf(format!(
+                "partition by {}, with at most {} files open",
+                columns, self.cli.max_open_files
+            ));
+        }
+        if let Some(rows) = self.cli.roll_by_rows {
+            writer.push_leaf(format!("roll to a new file every {} rows", rows));
+        }
+        if let Some(rows) = self.cli.coalesce {
+            writer.push_leaf(format!("coalesce into files of about {} rows", rows));
+        }
+        if let Some(bytes) = self.cli.roll_by_bytes {
+            writer.push_leaf(format!("roll to a new file every {} bytes", bytes));
+        }
+        if self.cli.partition_by.is_some() || !self.roll_limits()?.is_none() {
+            let template = self.cli.name_template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+            writer.push_leaf(format!("files named {}", template));
+            if self.cli.append {
+                writer.push_leaf("numbered after the files already there");
+            }
+        }
+        if let Some(path) = &self.cli.rejects {
+            writer.push_leaf(format!("rejected rows to {}", path.display()));
+        }
+        if let Some(checksum) = self.cli.checksum {
+            writer.push_leaf(format!("{:?} checksum sidecar", checksum).to_lowercase());
+        }
+
+        let output = if self.cli.writes_to_stdout() {
+            "output: stdout".to_string()
+        } else {
+            format!("output: {}", output_path.display())
+        };
+
+        let mut root = PlanNode::new("Plan mode");
+        root.push(inputs);
+        root.push(unify);
+        root.push(transforms);
+        root.push(writer);
+        root.push_leaf(output);
+        root.render(self.cli.plan_format)
+    }
+
+    /// Rejects options that need the output to be a named file when it goes
+    /// to stdout.
+    fn check_stdout_output(&self) -> Result<()> {
+        if !self.cli.writes_to_stdout() {
+            return Ok(());
+        }
+        if self.cli.checksum.is_some() {
+            return Err(MawError::Config(
+                "--checksum writes a file next to the output, so it can't be used with -o -"
+                    .to_string(),
+            ));
+        }
+        if self.cli.write_schema_sidecar {
+            return Err(MawError::Config(
+                "--write-schema-sidecar writes a file next to the output, so it can't be used with -o -"
+                    .to_string(),
+            ));
+        }
+        if self.cli.resume {
+            return Err(MawError::Config(
+                "--resume appends to the output, so it can't be used with -o -".to_string(),
+            ));
+        }
+        if self.cli.partition_by.is_some() {
+            return Err(MawError::Config(
+                "--partition-by writes a directory, so it can't be used with -o -".to_string(),
+            ));
+        }
+        if !self.roll_limits()?.is_none() {
+            return Err(MawError::Config(
+                "--roll-by-rows, --roll-by-bytes and --coalesce write a directory, so they can't be used with -o -"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn roll_limits(&self) -> Result<RollLimits> {
+        if self.cli.roll_by_rows == Some(0) || self.cli.roll_by_bytes == Some(0) || self.cli.coalesce == Some(0) {
+            return Err(MawError::Config(
+                "--roll-by-rows, --roll-by-bytes and --coalesce must be at least 1".to_string(),
+            ));
+        }
+        Ok(RollLimits {
+            rows: self.cli.roll_by_rows.or(self.cli.coalesce),
+            bytes: self.cli.roll_by_bytes,
+            whole_batches: self.cli.coalesce.is_some(),
+        })
+    }
+
+    /// How rolled and partition files are named. Fails on an invalid
+    /// template, or one given when the output is a single file.
+    fn name_template(&self) -> Result<NameTemplate> {
+        let partitioned = self.cli.partition_by.is_some();
+        match &self.cli.name_template {
+            Some(_) if !partitioned && self.roll_limits()?.is_none() => Err(MawError::Config(
+                "--name-template names rolled or partitioned files; use it with --roll-by-rows, --roll-by-bytes, --coalesce or --partition-by"
+                    .to_string(),
+            )),
+            Some(template) => NameTemplate::parse(template, partitioned),
+            None => NameTemplate::parse(DEFAULT_TEMPLATE, partitioned),
+        }
+    }
+
+    /// Fails on `--append` to a single output file, which only directory
+    /// outputs support.
+    fn check_append(&self) -> Result<()> {
+        if self.cli.append && self.cli.partition_by.is_none() && self.roll_limits()?.is_none() {
+            return Err(MawError::Config(
+                "--append adds files to a directory output; use it with --roll-by-rows, --roll-by-bytes, --coalesce or --partition-by"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether this run continues an earlier one from its state file.
+    fn is_resuming(&self) -> bool {
+        self.resuming && !self.restarted.load(Ordering::Relaxed)
+    }
+
+    /// Loads the state file when resuming, or starts a fresh one when
+    /// `--state` is given. Returns `None` when no state is being kept.
+    fn prepare_state(
+        &self,
+        state_manager: &mut StateManager,
+        input_files: &[InputFile],
+        output_path: &Path,
+        output_format: &OutputFormat,
+    ) -> Result<Option<SharedState>> {
+        if self.cli.state.is_none() {
+            if self.cli.resume {
+                return Err(MawError::Config("--resume requires --state".to_string()));
+            }
+            return Ok(None);
+        }
+
+        let loaded = if self.cli.resume {
+            if matches!(output_format, OutputFormat::Parquet) {
+                return Err(MawError::Config(
+                    "--resume is only supported for CSV output".to_string(),
+                ));
+            }
+            state_manager.load_state()?
+        } else {
+            None
+        };
+
+        // The output already holds rows from a changed input, and can only
+        // be appended to, so a changed input means starting over
+        let loaded = match loaded {
+            Some(mut state) => {
+                state.check_version()?;
+                let changed = state.changed_files();
+                for path in &changed {
+                    tracing::warn!("{} changed since the state was recorded; starting over", path);
+                }
+                self.restarted.store(!changed.is_empty(), Ordering::Relaxed);
+                Some(state).filter(|_| changed.is_empty())
+            }
+            None => None,
+        };
+
+        let mut state = match loaded {
+            Some(state) => state,
+            None => state_manager.create_state(
+                output_path.to_string_lossy().to_string(),
+                output_format.to_string(),
+            ),
+        };
+
+        for file in input_files {
+            let path = file.path.to_string_lossy().to_string();
+            if state.get_file_state(&path).is_none() {
+                state.add_file(path, file.format.name().to_string(), file.size);
+            }
+        }
+
+        Ok(Some(Arc::new(Mutex::new(state))))
+    }
+
+    async fn build_unified_schema(
+        &self,
+        builder: &StreamBuilder,
+        input_files: &[InputFile],
+    ) -> Result<Unified> {
+        builder.unify(input_files)
     }
 
     fn determine_output_format(&self, path: &PathBuf) -> Result<OutputFormat> {
         if let Some(format) = &self.cli.out_format {
             return Ok(format.clone());
         }
+        // A partitioned or rolled output is a directory, whose name says
+        // nothing
+        if self.cli.partition_by.is_some() || !self.roll_limits()?.is_none() {
+            return Ok(OutputFormat::Csv);
+        }
+
+        // Look past a compression suffix, so `out.csv.gz` is still CSV
+        let codec = codec_from_extension(path);
+        let path = match codec {
+            Some(_) => path.with_extension(""),
+            None => path.to_path_buf(),
+        };
 
-        match path.extension().and_then(|ext| ext.to_str()) {
-            Some("csv") => Ok(OutputFormat::Csv),
-            Some("parquet") => Ok(OutputFormat::Parquet),
-            _ => Ok(OutputFormat::Csv), // Default to CSV
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("csv") | Some("psv") => Ok(OutputFormat::Csv),
+            // Parquet compresses its pages, not the whole file, so a
+            // compressed suffix would name a file that isn't compressed
+            Some("parquet") => match codec {
+                Some(codec) => Err(MawError::Config(format!(
+                    "Parquet output can't be compressed as a whole file; name it '{}' and pass --compression {} to compress its pages",
+                    path.display(),
+                    codec
+                ))),
+                None => Ok(OutputFormat::Parquet),
+            },
+            // Only the implicit default output name and stdout may go without
+            // an extension
+            None if self.cli.out.is_none() || self.cli.writes_to_stdout() => Ok(OutputFormat::Csv),
+            _ => {
+                let supported = OutputFormat::value_variants()
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Err(MawError::Config(format!(
+                    "Cannot infer output format from '{}'; use a known extension or pass --out-format (supported formats: {})",
+                    path.display(),
+                    supported
+                )))
+            }
         }
     }
 
     async fn process_files_concurrently(
         &self,
+        builder: &StreamBuilder,
         input_files: &[InputFile],
-        _unified_schema: &UnifiedSchema,
+        unified: Unified,
         output_path: &PathBuf,
         output_format: OutputFormat,
-    ) -> Result<()> {
-        let (tx, rx) = mpsc::channel::<Chunk<Box<dyn Array>>>(8); // Bounded channel
+        state: Option<SharedState>,
+    ) -> Result<Vec<String>> {
+        let (tx, rx) = mpsc::channel::<Message>(8); // Bounded channel
+        let counters = Counters::default();
+        
+        let heartbeat = match self.cli.heartbeat {
+            Some(secs) => Some(spawn_heartbeat(counters.clone(), parse_interval("--heartbeat", secs)?)),
+            None => None,
+        };
+        
+        let style = (self.cli.progress && !self.cli.no_progress).then_some(self.cli.progress_style);
+        let progress = Arc::new(ProgressTracker::with_style(style, GlobalProgress::for_inputs(input_files)));
         
+        let result = self
+            .run_readers_and_writer(builder, input_files, unified, output_path, output_format, tx, rx, &counters, &progress, state)
+            .await;
+        
+        if let Some(handle) = heartbeat {
+            handle.abort();
+        }
+        progress.finish().await?;
+        
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_readers_and_writer(
+        &self,
+        builder: &StreamBuilder,
+        input_files: &[InputFile],
+        unified: Unified,
+        output_path: &PathBuf,
+        output_format: OutputFormat,
+        tx: mpsc::Sender<Message>,
+        rx: mpsc::Receiver<Message>,
+        counters: &Counters,
+        progress: &Arc<ProgressTracker>,
+        state: Option<SharedState>,
+    ) -> Result<Vec<String>> {
+        let mut output_schema = Arc::new(builder.aligner(unified.schema.clone())?.output_schema());
+        let rejects = match &self.cli.rejects {
+            Some(path) => {
+                let columns: Vec<String> = output_schema.fields.iter().map(|f| f.name.clone()).collect();
+                let writer = RejectsWriter::create(path, &columns, self.cli.overwrite)?;
+                Some(Arc::new(Mutex::new(writer)))
+            }
+            None => None,
+        };
+        let Transforms {
+            unpivot,
+            sampler,
+            pivot,
+            row_numbers,
+        } = self.transforms(&output_schema)?;
+        if let Some(unpivot) = &unpivot {
+            output_schema = Arc::new(unpivot.output_schema().clone());
+        }
+        let unpivot = unpivot.map(Arc::new);
+        
+        let sidecar = match (self.cli.write_schema_sidecar, &row_numbers) {
+            (false, _) => None,
+            (true, Some(numbers)) => Some(SchemaSidecar::new(&numbers.output_schema(&output_schema)?)),
+            (true, None) => Some(SchemaSidecar::new(&output_schema)),
+        };
+        // A resumed or restarted run replaces the sidecar it wrote before
+        let sidecar_overwrite =
+            self.cli.overwrite || self.resuming || self.restarted.load(Ordering::Relaxed);
+        if sidecar.is_some() {
+            check_no_clobber(&SchemaSidecar::path(output_path), sidecar_overwrite)?;
+        }
+
         // Spawn readers
-        let reader_handles = self.spawn_readers(input_files, tx).await?;
+        let reader_handles = self
+            .spawn_readers(builder, input_files, unified, unpivot, rejects.clone(), tx, counters, progress, state.clone())
+            .await?;
         
         // Spawn writer
-        let writer_handle = self.spawn_writer(output_path, output_format, rx).await?;
+        let writer_handle = self
+            .spawn_writer(
+                input_files,
+                output_path,
+                output_format,
+                output_schema,
+                sampler,
+                pivot,
+                row_numbers,
+                rx,
+                state,
+                counters,
+            )
+            .await?;
         
         // Wait for all readers to complete
-        for handle in reader_handles {
-            handle.await??;
+        let mut failed = Vec::new();
+        for (path, sent, handle) in reader_handles {
+            match handle.await? {
+                Ok(()) => {}
+                Err(e) if self.cli.continue_on_error => {
+                    tracing::warn!("Skipping input that failed: {}", e);
+                    // Rows already sent can't be taken back out of the output
+                    match sent.load(Ordering::Relaxed) {
+                        0 => failed.push(path),
+                        rows => failed.push(format!("{} (its first {} rows were written)", path, rows)),
+                    }
+                }
+                Err(e) => return Err(e),
+            }
         }
         
         // Wait for writer to complete
         writer_handle.await??;
+        if let Some(sidecar) = sidecar {
+            sidecar.write(output_path, self.cli.temp_dir.as_deref(), sidecar_overwrite, self.cli.fsync)?;
+        }
         
-        Ok(())
+        if let (Some(rejects), Some(path)) = (rejects, &self.cli.rejects) {
+            let rows = rejects
+                .lock()
+                .map_err(|_| MawError::Schema("Rejects file was poisoned".to_string()))?
+                .finish()?;
+            if rows > 0 {
+                tracing::warn!("Rejected {} rows; see {}", rows, path.display());
+            }
+        }
+        
+        Ok(failed)
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn spawn_readers(
         &self,
+        builder: &StreamBuilder,
         input_files: &[InputFile],
-        tx: mpsc::Sender<Chunk<Box<dyn Array>>>,
-    ) -> Result<Vec<tokio::task::JoinHandle<Result<()>>>> {
+        unified: Unified,
+        unpivot: Option<Arc<Unpivot>>,
+        rejects: Option<SharedRejects>,
+        tx: mpsc::Sender<Message>,
+        counters: &Counters,
+        progress: &Arc<ProgressTracker>,
+        state: Option<SharedState>,
+    ) -> Result<Vec<(String, Arc<AtomicU64>, tokio::task::JoinHandle<Result<()>>)>> {
         let mut handles = Vec::new();
+        let mut stdin_reader = unified.stdin_reader;
+        let runtime = tokio::runtime::Handle::current();
+        // Files are read concurrently, so each gets its own sub-bar
+        let per_file_bars = input_files.len() > 1;
+        // Unless output must be reproducible, when they take turns so their
+        // chunks reach the writer in the same order on every run
+        let turns = self.cli.is_reproducible().then(|| Arc::new(Turns::default()));
         
         for file in input_files {
+            let path = file.path.to_string_lossy().to_string();
+            
+            // When resuming, finished files are skipped and the others pick
+            // up after the last row group or CSV record that was written
+            let mut start = ResumePoint::default();
+            if let (true, Some(state)) = (self.cli.resume, &state) {
+                let state = state.lock().map_err(|_| poisoned_state())?;
+                if state.is_file_processed(&path) {
+                    tracing::info!("Skipping already processed file: {}", path);
+                    continue;
+                }
+                start = state.resume_point(&path);
+            }
+            
+            let reader_path = path.clone();
+            // Rows this input has sent to the writer
+            let sent = Arc::new(AtomicU64::new(0));
+            let reader_sent = sent.clone();
+            let turns = turns.clone();
+            let turn = handles.len();
+            let checkpointing = state.is_some();
             let tx_clone = tx.clone();
-            let file_path = file.path.clone();
-            let format = file.format.clone();
-            let batch_size = 64_000; // Default batch size
+            let unpivot = unpivot.clone();
+            let counters = counters.clone();
+            let progress = progress.clone();
+            let runtime = runtime.clone();
+            let file_progress = per_file_bars.then(|| progress.file_tracker(path.clone(), file.known_size()));
+            let mut aligner = builder.aligner(unified.schema.clone())?;
+            if let Some(rejects) = &rejects {
+                aligner = aligner.rejects(rejects.clone());
+            }
+            aligner.set_source(&file.path);
+            let csv_config = builder.get_csv_config().clone();
+            let batch_size = builder.get_batch_size();
+            let file = file.clone();
+            
+            // Stdin was already opened (and its first batch read) for sampling
+            let primed = if file.path.to_string_lossy() == "-" {
+                stdin_reader.take()
+            } else {
+                None
+            };
             
             let handle = tokio::task::spawn_blocking(move || {
-                match format {
-                    crate::discover::FileFormat::Csv => {
-                        let config = CsvConfig::default();
-                        let mut reader = CsvReader::new(&file_path, &config)?;
-                        
-                        loop {
-                            match reader.read_batch()? {
-                                Some(batch) => {
-                                    if tx_clone.blocking_send(batch).is_err() {
-                                        break; // Channel closed
-                                    }
-                                }
-                                None => break,
-                            }
+                let _turn = turns.as_deref().map(|turns| turns.wait(turn));
+                let mut reader = match primed {
+                    Some(reader) => reader,
+                    None => InputReader::open_at(&file, &csv_config, batch_size, start)
+                        .map_err(|e| e.in_file(path.clone()))?,
+                };
+                if let Some(rows) = reader.total_rows() {
+                    runtime.block_on(progress.add_total_rows(rows));
+                }
+                // A resumed input starts partway through
+                let mut last_position = reader.position(file.size).unwrap_or(0);
+                let mut row_group = start.row_group;
+                // Rows sent since progress was last reported
+                let mut unreported_rows = 0u64;
+                
+                // Errors name the file (and line) they came from
+                while let Some(batch) = reader.read_batch().map_err(|e| e.in_file(path.clone()))? {
+                    let mut bytes = 0;
+                    if let Some(position) = reader.position(file.size) {
+                        bytes = position - last_position;
+                        counters.add_bytes(bytes);
+                        last_position = position;
+                        if let Some(file_progress) = &file_progress {
+                            file_progress.update(position);
                         }
                     }
-                    crate::discover::FileFormat::Parquet => {
-                        let mut reader = ParquetReader::new(&file_path, batch_size)?;
-                        
-                        loop {
-                            match reader.read_batch()? {
-                                Some(batch) => {
-                                    if tx_clone.blocking_send(batch).is_err() {
-                                        break; // Channel closed
-                                    }
-                                }
-                                None => break,
-                            }
+                    runtime.block_on(progress.update_file_progress(bytes, batch.len() as u64))?;
+                    unreported_rows += batch.len() as u64;
+                    
+                    let names = reader.column_names();
+                    aligner.reject_rows(&names, reader.take_rejected_rows())?;
+                    let mut aligned = aligner.align_batch(batch, &names).map_err(|e| e.in_file(path.clone()))?;
+                    if let Some(unpivot) = &unpivot {
+                        aligned = unpivot.apply(&aligned, &aligner)?;
+                    }
+                    
+                    // Each Parquet row group counts once all its rows are
+                    // sent, and CSV inputs count after every batch
+                    let progress = match (reader.completed_row_groups(), reader.record_offset()) {
+                        _ if !checkpointing => None,
+                        (Some(completed), _) if completed > row_group => {
+                            row_group = completed;
+                            Some(InputProgress::RowGroups {
+                                path: path.clone(),
+                                end: completed,
+                                rows: unreported_rows,
+                            })
                         }
+                        (None, Some(offset)) => Some(InputProgress::Offset {
+                            path: path.clone(),
+                            offset,
+                            rows: unreported_rows,
+                        }),
+                        _ => None,
+                    };
+                    if progress.is_some() {
+                        unreported_rows = 0;
+                    }
+                    let rows = aligned.len() as u64;
+                    if tx_clone.blocking_send(Message::Rows(aligned, progress)).is_err() {
+                        return Ok(()); // Channel closed
                     }
+                    reader_sent.fetch_add(rows, Ordering::Relaxed);
+                }
+                
+                // Rows set aside after the last batch
+                aligner.reject_rows(&reader.column_names(), reader.take_rejected_rows())?;
+
+                // JSON and compressed CSV byte progress is only known per file
+                if reader.position(file.size).is_none() {
+                    counters.add_bytes(file.size);
+                    runtime.block_on(progress.update_file_progress(file.size, 0))?;
                 }
+                if let Some(file_progress) = &file_progress {
+                    file_progress.finish();
+                }
+                runtime.block_on(progress.mark_file_complete(&path))?;
+                
+                if checkpointing {
+                    let done = InputProgress::Done {
+                        path: path.clone(),
+                        size: file.size,
+                        rows: unreported_rows,
+                    };
+                    // A closed channel means the writer already failed
+                    let _ = tx_clone.blocking_send(Message::Done(done));
+                }
+                
                 Ok(())
             });
             
-            handles.push(handle);
+            handles.push((reader_path, sent, handle));
         }
         
         Ok(handles)
     }
 
+    /// Picks the CSV output delimiter: an explicit `--out-delimiter`, else the
+    /// input `--delimiter`, else the one the output's extension implies (`|`
+    /// for `.psv`), else whatever the first CSV input uses, so a semicolon
+    /// or tab file round-trips without changing format. Inputs with
+    /// differing delimiters are all written with that one.
+    fn output_delimiter(&self, input_files: &[InputFile], output_path: &Path) -> Result<u8> {
+        if let Some(delimiter) = self.cli.out_delimiter.or(self.cli.delimiter) {
+            return Ok(delimiter as u8);
+        }
+        if let Some(delimiter) = extension_delimiter(output_path) {
+            return Ok(delimiter);
+        }
+
+        match input_files
+            .iter()
+            .find(|f| f.format == FileFormat::Csv && f.path.to_string_lossy() != "-")
+        {
+            Some(file) => {
+                for spec in &self.cli.delimiter_for {
+                    let (glob, delimiter) = parse_delimiter_override(spec)?;
+                    if glob.is_match(&file.path) {
+                        return Ok(delimiter);
+                    }
+                }
+                input_delimiter(&file.path)
+            }
+            None => Ok(b','),
+        }
+    }
+
+    fn csv_writer_config(&self, input_files: &[InputFile], output_path: &Path) -> Result<CsvWriterConfig> {
+        let terminator = match self.cli.line_terminator {
+            LineTerminator::Lf => csv::Terminator::Any(b'\n'),
+            LineTerminator::Crlf => csv::Terminator::CRLF,
+        };
+        
+        let quote_style = match self.cli.quote_style {
+            QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+            QuoteStyle::Always => csv::QuoteStyle::Always,
+            QuoteStyle::Never => csv::QuoteStyle::Never,
+            QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+        };
+        
+        Ok(CsvWriterConfig {
+            delimiter: self.output_delimiter(input_files, output_path)?,
+            terminator,
+            quote_style,
+            write_bom: self.cli.write_bom,
+            float_format: self.cli.float_format,
+            float_precision: self.cli.float_precision,
+            nan_token: self.cli.nan_as.clone(),
+            inf_token: self.cli.inf_as.clone(),
+            non_finite_as_null: self.cli.non_finite_as_null,
+            fsync: self.cli.fsync,
+            overwrite: self.cli.overwrite || self.restarted.load(Ordering::Relaxed),
+            checksum: self.cli.checksum.map(Into::into),
+            compression: self.output_compression(&OutputFormat::Csv),
+            compression_level: self.compression_level(&OutputFormat::Csv)?,
+            append: self.is_resuming(),
+            buffer_size: self.cli.writer_buffer * 1024 * 1024,
+            ..CsvWriterConfig::default()
+        })
+    }
+
+    /// The codec for output in `format`: for CSV, implied by a compressed
+    /// extension such as `.csv.gz`, else `--compression`, else the format's
+    /// default.
+    fn output_compression(&self, format: &OutputFormat) -> Compression {
+        let from_extension = match format {
+            OutputFormat::Csv => self.cli.out.as_deref().and_then(codec_from_extension),
+            OutputFormat::Parquet => None,
+        };
+        from_extension
+            .or_else(|| self.cli.compression.clone())
+            .unwrap_or_else(|| format.default_compression())
+    }
+
+    fn compression_level(&self, format: &OutputFormat) -> Result<Option<u32>> {
+        resolve_level(&self.output_compression(format), self.cli.compression_level, self.cli.zstd_level)
+    }
+
+    /// The transforms that rows aligned to `aligned` go through, which
+    /// `execute` runs and `plan` describes.
+    fn transforms(&self, aligned: &Schema) -> Result<Transforms> {
+        let unpivot = match &self.cli.unpivot {
+            Some(spec) => Some(Unpivot::new(
+                spec,
+                aligned,
+                self.cli.stringify_conflicts,
+                self.cli.unpivot_drop_nulls,
+            )?),
+            None => None,
+        };
+        let pivot = match &self.cli.pivot {
+            Some(spec) => {
+                let input = unpivot.as_ref().map_or(aligned, |u| u.output_schema());
+                Some(Pivot::new(spec, input, self.cli.mem_budget * 1024 * 1024)?)
+            }
+            None => None,
+        };
+        let sampler = match &self.cli.sample {
+            Some(spec) => Some(Sampler::new(spec, self.cli.seed)?),
+            None => None,
+        };
+        Ok(Transforms {
+            unpivot,
+            sampler,
+            pivot,
+            row_numbers: self.row_numbers(),
+        })
+    }
+
+    /// The numbering of written rows that `--row-number` asks for.
+    fn row_numbers(&self) -> Option<RowNumbers> {
+        let name = self.cli.row_number.clone()?;
+        Some(RowNumbers::new(name, self.cli.row_number_last))
+    }
+
+    /// The format of the output named by `--out`, or of the default output.
+    fn output_format(&self) -> Result<OutputFormat> {
+        let output_path = self.cli.out.clone().unwrap_or_else(|| PathBuf::from("output"));
+        self.determine_output_format(&output_path)
+    }
+
+    fn parquet_writer_config(&self) -> Result<ParquetWriterConfig> {
+        if self.cli.parquet_encode_threads == 0 {
+            return Err(MawError::Config("--parquet-encode-threads must be at least 1".to_string()));
+        }
+        let compression = self.output_compression(&OutputFormat::Parquet);
+        let mut config = ParquetWriterConfig {
+            encode_threads: self.cli.parquet_encode_threads,
+            fsync: self.cli.fsync,
+            overwrite: self.cli.overwrite,
+            checksum: self.cli.checksum.map(Into::into),
+            temp_dir: self.cli.temp_dir.clone(),
+            buffer_size: self.cli.writer_buffer * 1024 * 1024,
+            verify_schema: self.cli.verify_schema,
+            compression: match compression {
+                Compression::None => parquet2::compression::Compression::Uncompressed,
+                Compression::Snappy => parquet2::compression::Compression::Snappy,
+                Compression::Gzip => parquet2::compression::Compression::Gzip,
+                Compression::Zstd => parquet2::compression::Compression::Zstd,
+            },
+            ..ParquetWriterConfig::default()
+        };
+        
+        if let (Compression::Zstd, Some(level)) = (&compression, self.compression_level(&OutputFormat::Parquet)?) {
+            config.zstd_level = level;
+        }
+        
+        Ok(config)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn spawn_writer(
         &self,
+        input_files: &[InputFile],
         output_path: &PathBuf,
         output_format: OutputFormat,
-        mut rx: mpsc::Receiver<Chunk<Box<dyn Array>>>,
+        output_schema: Arc<Schema>,
+        mut sampler: Option<Sampler>,
+        pivot: Option<Pivot>,
+        mut row_numbers: Option<RowNumbers>,
+        mut rx: mpsc::Receiver<Message>,
+        state: Option<SharedState>,
+        counters: &Counters,
     ) -> Result<tokio::task::JoinHandle<Result<()>>> {
         let output_path = output_path.clone();
+        let counters = counters.clone();
+        let csv_config = self.csv_writer_config(input_files, &output_path)?;
+        let parquet_config = self.parquet_writer_config()?;
+        let flush_interval = self
+            .cli
+            .flush_interval
+            .map(|secs| parse_interval("--flush-interval", secs))
+            .transpose()?;
+        let partition_by = self.cli.partition_by.as_deref().map(split_list);
+        let max_open_files = self.cli.max_open_files;
+        let mem_budget = self.cli.mem_budget * 1024 * 1024;
+        let roll = self.roll_limits()?;
+        let template = self.name_template()?;
+        let append = self.cli.append;
         
         let handle = tokio::task::spawn_blocking(move || {
+            let runtime = tokio::runtime::Handle::current();
+            // Progress the readers reported, held until the rows it covers
+            // are durably written, which is once the batch it came with is
+            let unwritten = RefCell::new(Vec::new());
+            let commit = || -> Result<()> {
+                let progress = std::mem::take(&mut *unwritten.borrow_mut());
+                if let Some(state) = &state {
+                    let mut state = state.lock().map_err(|_| poisoned_state())?;
+                    for progress in progress {
+                        state.apply(progress);
+                    }
+                }
+                Ok(())
+            };
+            // A sampler holds rows back until the end, so nothing read can
+            // count as written before then
+            let commit_per_batch = sampler.is_none();
+            let mut receive = |deadline: Option<Instant>| loop {
+                let message = match deadline {
+                    None => rx.blocking_recv(),
+                    Some(deadline) => {
+                        match runtime.block_on(tokio::time::timeout_at(deadline, rx.recv())) {
+                            Ok(message) => message,
+                            Err(_) => return Received::Idle,
+                        }
+                    }
+                };
+                match message {
+                    Some(Message::Rows(batch, progress)) => {
+                        unwritten.borrow_mut().extend(progress);
+                        return Received::Batch(batch);
+                    }
+                    Some(Message::Done(progress)) => unwritten.borrow_mut().push(progress),
+                    None => return Received::Closed,
+                }
+            };
+            let mut next_input = |deadline| loop {
+                let Some(sampler) = sampler.as_mut() else {
+                    return receive(deadline);
+                };
+                match receive(deadline) {
+                    Received::Batch(batch) => {
+                        if let Some(sampled) = sampler.sample(&batch) {
+                            return Received::Batch(sampled);
+                        }
+                    }
+                    Received::Idle => return Received::Idle,
+                    Received::Closed => {
+                        return sampler.finish().map_or(Received::Closed, Received::Batch)
+                    }
+                }
+            };
+            
+            // A pivot's columns are only known once every row has arrived,
+            // so it is written as one chunk after the readers finish
+            let (output_schema, mut pivoted) = match pivot {
+                Some(mut pivot) => {
+                    while let Received::Batch(batch) = next_input(None) {
+                        pivot.push(&batch)?;
+                    }
+                    let (schema, chunk) = pivot.finish()?;
+                    (Arc::new(schema), Some(chunk))
+                }
+                None => (output_schema, None),
+            };
+            // Rows are numbered here, in the order they are written
+            let output_schema = match &row_numbers {
+                Some(numbers) => Arc::new(numbers.output_schema(&output_schema)?),
+                None => output_schema,
+            };
+            let pivoting = pivoted.is_some();
+            let mut next_batch = |deadline| {
+                let received = if pivoting {
+                    pivoted.take().map_or(Received::Closed, Received::Batch)
+                } else {
+                    next_input(deadline)
+                };
+                match (received, row_numbers.as_mut()) {
+                    (Received::Batch(batch), Some(numbers)) => Received::Batch(numbers.number(batch)),
+                    (received, _) => received,
+                }
+            };
+            
+            let files = OutputFiles {
+                schema: output_schema.clone(),
+                format: output_format.clone(),
+                csv_config: csv_config.clone(),
+                parquet_config: parquet_config.clone(),
+                template,
+                roll,
+                append,
+            };
+            if let Some(partition_by) = partition_by {
+                let mut writer = PartitionedWriter::new(output_path, &partition_by, files, max_open_files, mem_budget)?;
+                while let Received::Batch(batch) = next_batch(None) {
+                    writer.write_batch(&batch)?;
+                    counters.add_rows(batch.len() as u64);
+                }
+                // Partitions are written in the background, so rows are
+                // only known to be written once they all finish
+                writer.finish()?;
+                return commit();
+            }
+            if !roll.is_none() {
+                std::fs::create_dir_all(&output_path)?;
+                let first_index = files.first_index(&output_path, "")?;
+                let csv = matches!(files.format, OutputFormat::Csv);
+                let mut writer = RollingWriter::new(output_path, "", files, first_index);
+                while let Received::Batch(batch) = next_batch(None) {
+                    let closed = writer.closed_files();
+                    writer.write_batch(&batch)?;
+                    counters.add_rows(batch.len() as u64);
+                    // CSV files are flushed after every batch, Parquet files
+                    // only once closed, which takes every earlier batch
+                    // with them
+                    if commit_per_batch && (csv || writer.closed_files() > closed) {
+                        commit()?;
+                    }
+                }
+                writer.finish()?;
+                return commit();
+            }
+            
             match output_format {
                 OutputFormat::Csv => {
-                    let config = CsvWriterConfig::default();
+                    let config = csv_config;
                     let mut writer = CsvWriter::new(&output_path, &config)?;
+                    writer.set_headers(output_schema.fields.iter().map(|f| f.name.clone()).collect());
                     
-                    while let Some(batch) = rx.blocking_recv() {
+                    // Every batch is flushed as it is written
+                    while let Received::Batch(batch) = next_batch(None) {
                         writer.write_batch(&batch)?;
+                        counters.add_rows(batch.len() as u64);
+                        if commit_per_batch {
+                            commit()?;
+                        }
                     }
                     
                     writer.finish()?;
                 }
                 OutputFormat::Parquet => {
-                    // For Parquet, we need the schema - this is simplified
-                    let schema = arrow2::datatypes::Schema::from(vec![]);
-                    let config = ParquetWriterConfig::default();
-                    let mut writer = ParquetWriter::new(&output_path, Arc::new(schema), &config)?;
+                    let config = parquet_config;
+                    let mut writer = ParquetWriter::new(&output_path, output_schema, &config)?;
                     
-                    while let Some(batch) = rx.blocking_recv() {
-                        writer.write_batch(&batch)?;
+                    let mut flushed_at = Instant::now();
+                    loop {
+                        let deadline = flush_interval.map(|interval| flushed_at + interval);
+                        let received = next_batch(deadline);
+                        // A batch that comes in after the deadline starts
+                        // the next row group
+                        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                            writer.flush()?;
+                            flushed_at = Instant::now();
+                        }
+                        match received {
+                            Received::Batch(batch) => {
+                                writer.write_batch(&batch)?;
+                                counters.add_rows(batch.len() as u64);
+                            }
+                            Received::Idle => {}
+                            Received::Closed => break,
+                        }
                     }
                     
+                    // Until then the rows are only in a temporary file
                     writer.finish()?;
                 }
             }
-            Ok(())
+            commit()
         });
         
         Ok(handle)
     }
 }
 
+/// Runs readers one at a time in the order they were spawned.
+#[derive(Default)]
+struct Turns {
+    next: Mutex<usize>,
+    changed: Condvar,
+}
+
+impl Turns {
+    /// Blocks until it is `turn`'s go. The turn passes on when the returned
+    /// guard is dropped, whether or not the reader succeeded.
+    fn wait(&self, turn: usize) -> TurnGuard<'_> {
+        let next = self.next.lock().unwrap();
+        drop(self.changed.wait_while(next, |next| *next != turn).unwrap());
+        TurnGuard { turns: self }
+    }
+}
+
+struct TurnGuard<'a> {
+    turns: &'a Turns,
+}
+
+impl Drop for TurnGuard<'_> {
+    fn drop(&mut self) {
+        *self.turns.next.lock().unwrap() += 1;
+        self.turns.changed.notify_all();
+    }
+}
+
+fn poisoned_state() -> MawError {
+    MawError::State("Processing state was poisoned".to_string())
+}
+
+/// Reads a `SECS` option as a non-zero duration.
+fn parse_interval(flag: &str, secs: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(secs)
+        .ok()
+        .filter(|d| !d.is_zero())
+        .ok_or_else(|| MawError::Config(format!("Invalid {} interval: {}", flag, secs)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::discover::{FileFormat, InputFile};
+    use clap::Parser;
     use std::path::PathBuf;
     use tempfile::tempdir;
 
@@ -220,4 +1399,172 @@ mod tests {
         let format = pipeline.determine_output_format(&parquet_path).unwrap();
         assert!(matches!(format, OutputFormat::Parquet));
     }
+
+    #[test]
+    fn test_unknown_output_extension() {
+        let cli = Cli::parse_from(&["maw", "test.csv", "-o", "out.parquett"]);
+        let pipeline = Pipeline::new(cli);
+
+        let err = pipeline
+            .determine_output_format(&PathBuf::from("out.parquett"))
+            .unwrap_err();
+        assert!(err.to_string().contains("supported formats: csv, parquet"));
+
+        let cli = Cli::parse_from(&["maw", "test.csv", "-o", "out.parquett", "--out-format", "parquet"]);
+        let pipeline = Pipeline::new(cli);
+        let format = pipeline
+            .determine_output_format(&PathBuf::from("out.parquett"))
+            .unwrap();
+        assert!(matches!(format, OutputFormat::Parquet));
+    }
+
+    #[test]
+    fn test_compressed_parquet_extension() {
+        let cli = Cli::parse_from(&["maw", "test.csv", "-o", "out.parquet.gz"]);
+        let pipeline = Pipeline::new(cli);
+
+        let err = pipeline
+            .determine_output_format(&PathBuf::from("out.parquet.gz"))
+            .unwrap_err();
+        assert!(err.to_string().contains("name it 'out.parquet' and pass --compression gzip"));
+    }
+
+    #[test]
+    fn test_buffer_sizes() {
+        let cli = Cli::parse_from(["maw", "in.csv", "--read-buffer", "4", "--writer-buffer", "2"]);
+        let builder = StreamBuilder::from_cli(&cli).unwrap();
+        assert_eq!(builder.get_csv_config().read_buffer, Some(4 * 1024 * 1024));
+
+        let pipeline = Pipeline::new(cli);
+        let csv_config = pipeline.csv_writer_config(&[], Path::new("out.csv")).unwrap();
+        assert_eq!(csv_config.buffer_size, 2 * 1024 * 1024);
+        assert_eq!(pipeline.parquet_writer_config().unwrap().buffer_size, 2 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_default_compression() {
+        let pipeline = Pipeline::new(Cli::parse_from(["maw", "in.csv", "-o", "out.parquet"]));
+        let config = pipeline.parquet_writer_config().unwrap();
+        assert_eq!(config.compression, parquet2::compression::Compression::Snappy);
+
+        let pipeline = Pipeline::new(Cli::parse_from(["maw", "in.csv", "-o", "out.csv"]));
+        let config = pipeline.csv_writer_config(&[], Path::new("out.csv")).unwrap();
+        assert!(matches!(config.compression, Compression::None));
+
+        // Unless given explicitly, including as none
+        let pipeline = Pipeline::new(Cli::parse_from(["maw", "in.csv", "-o", "out.parquet", "--compression", "none"]));
+        let config = pipeline.parquet_writer_config().unwrap();
+        assert_eq!(config.compression, parquet2::compression::Compression::Uncompressed);
+        let pipeline = Pipeline::new(Cli::parse_from(["maw", "in.csv", "-o", "out.csv", "--compression", "zstd"]));
+        let config = pipeline.csv_writer_config(&[], Path::new("out.csv")).unwrap();
+        assert!(matches!(config.compression, Compression::Zstd));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flush_interval() {
+        use arrow2::{array::Int64Array, datatypes::{DataType, Field}};
+
+        let temp_dir = tempdir().unwrap();
+        let schema = Arc::new(Schema::from(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = || Chunk::new(vec![Int64Array::from_slice([1, 2, 3]).boxed()]);
+
+        // A producer that sends a batch, stalls past the interval, then
+        // sends another
+        let row_groups = |flags: &[&str], name: &str| {
+            let out = temp_dir.path().join(name);
+            let mut args = vec!["maw", "in.csv", "-o", out.to_str().unwrap()];
+            args.extend(flags);
+            let pipeline = Pipeline::new(Cli::parse_from(&args));
+            let schema = schema.clone();
+            async move {
+                let (tx, rx) = mpsc::channel(1);
+                let writer = pipeline
+                    .spawn_writer(&[], &out, OutputFormat::Parquet, schema, None, None, None, rx, None, &Counters::default())
+                    .await
+                    .unwrap();
+                tx.send(Message::Rows(batch(), None)).await.unwrap();
+                // With a single slot, the writer has dealt with the batch
+                // once it takes the message after it
+                let done = InputProgress::Done { path: "in.csv".to_string(), size: 0, rows: 3 };
+                tx.send(Message::Done(done)).await.unwrap();
+                let permit = tx.reserve().await.unwrap();
+                tokio::time::advance(Duration::from_millis(300)).await;
+                permit.send(Message::Rows(batch(), None));
+                drop(tx);
+                writer.await.unwrap().unwrap();
+
+                let mut file = std::fs::File::open(&out).unwrap();
+                parquet2::read::read_metadata(&mut file).unwrap().row_groups.len()
+            }
+        };
+
+        assert_eq!(row_groups(&[], "buffered.parquet").await, 1);
+        assert_eq!(row_groups(&["--flush-interval", "0.05"], "flushed.parquet").await, 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_resume_after_writer_failure() {
+        use crate::writer_parquet::{ParquetWriter, ParquetWriterConfig};
+        use arrow2::{
+            array::{Int64Array, PrimitiveArray},
+            datatypes::{DataType, Field, TimeUnit},
+        };
+
+        let temp_dir = tempdir().unwrap();
+        let input = temp_dir.path().join("input.parquet");
+        let out = temp_dir.path().join("out.csv");
+        let state_file = temp_dir.path().join("state.json");
+        let schema = Arc::new(Schema::from(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("at", DataType::Timestamp(TimeUnit::Second, None), true),
+        ]));
+        let group = |ids: &[i64], at: Option<i64>| {
+            let at = PrimitiveArray::<i64>::from(vec![at; ids.len()]).to(DataType::Timestamp(TimeUnit::Second, None));
+            Chunk::new(vec![Int64Array::from_slice(ids).boxed(), at.boxed()])
+        };
+
+        // Four row groups of two rows
+        let groups: Vec<_> = [[1, 2], [3, 4], [5, 6], [7, 8]].iter().map(|ids| group(ids, None)).collect();
+        let mut writer = ParquetWriter::new(&input, schema.clone(), &ParquetWriterConfig::default()).unwrap();
+        for chunk in &groups {
+            writer.write_batch(chunk).unwrap();
+            writer.flush().unwrap();
+        }
+        writer.finish().unwrap();
+
+        let key = input.to_string_lossy().to_string();
+        let mut state = ProcessingState::new(out.to_string_lossy().to_string(), "csv".to_string());
+        state.add_file(key.clone(), "parquet".to_string(), std::fs::metadata(&input).unwrap().len());
+        let state = Arc::new(Mutex::new(state));
+
+        // The third row group reaches the writer with a timestamp it can't
+        // format, so the run stops with that group's progress received but
+        // its rows unwritten
+        let pipeline = Pipeline::new(Cli::parse_from(["maw", "in.csv", "-o", out.to_str().unwrap()]));
+        let (tx, rx) = mpsc::channel(4);
+        let writer = pipeline
+            .spawn_writer(&[], &out, OutputFormat::Csv, schema, None, None, None, rx, Some(state.clone()), &Counters::default())
+            .await
+            .unwrap();
+        let sent = [groups[0].clone(), groups[1].clone(), group(&[5, 6], Some(i64::MAX))];
+        for (end, chunk) in sent.into_iter().enumerate() {
+            let progress = InputProgress::RowGroups { path: key.clone(), end: end + 1, rows: 2 };
+            let _ = tx.send(Message::Rows(chunk, Some(progress))).await;
+        }
+        drop(tx);
+        assert!(writer.await.unwrap().is_err());
+
+        let state = state.lock().unwrap().clone();
+        assert_eq!(state.resume_point(&key).row_group, 2);
+        StateManager::new(Some(state_file.to_string_lossy().to_string())).save_state(&state).unwrap();
+
+        let state_arg = state_file.to_string_lossy().to_string();
+        let cli = Cli::parse_from(["maw", &key, "-o", out.to_str().unwrap(), "--state", &state_arg, "--resume"]);
+        Pipeline::new(cli).execute().await.unwrap();
+
+        // Every row once: the failed group was read again, nothing before it
+        let content = std::fs::read_to_string(&out).unwrap();
+        let ids: Vec<i64> = content.lines().skip(1).map(|line| line.split(',').next().unwrap().parse().unwrap()).collect();
+        assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+    }
 }
@@ -0,0 +1,68 @@
+use crate::{cli::PlanFormat, error::Result};
+use serde::Serialize;
+
+/// One step of an execution plan (`--plan`), with the steps it's made of.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanNode {
+    pub label: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<PlanNode>,
+}
+
+impl PlanNode {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, child: PlanNode) {
+        self.children.push(child);
+    }
+
+    /// Adds a leaf with `label`.
+    pub fn push_leaf(&mut self, label: impl Into<String>) {
+        self.push(PlanNode::new(label));
+    }
+
+    /// Renders the tree as text indented two spaces per level, or as JSON.
+    pub fn render(&self, format: PlanFormat) -> Result<String> {
+        match format {
+            PlanFormat::Text => {
+                let mut text = String::new();
+                self.render_text(0, &mut text);
+                Ok(text)
+            }
+            PlanFormat::Json => Ok(serde_json::to_string_pretty(self)? + "\n"),
+        }
+    }
+
+    fn render_text(&self, depth: usize, text: &mut String) {
+        text.push_str(&"  ".repeat(depth));
+        text.push_str(&self.label);
+        text.push('\n');
+        for child in &self.children {
+            child.render_text(depth + 1, text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut root = PlanNode::new("plan");
+        let mut inputs = PlanNode::new("inputs");
+        inputs.push_leaf("a.csv");
+        root.push(inputs);
+        root.push_leaf("output");
+
+        assert_eq!(root.render(PlanFormat::Text).unwrap(), "plan\n  inputs\n    a.csv\n  output\n");
+        let json: serde_json::Value = serde_json::from_str(&root.render(PlanFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["children"][0]["children"][0]["label"], "a.csv");
+        assert!(json["children"][1].get("children").is_none());
+    }
+}
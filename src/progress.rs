@@ -1,18 +1,30 @@
-use crate::error::Result;
-use indicatif::{ProgressBar, ProgressStyle};
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use crate::{cli::ProgressStyle, discover::InputFile, error::Result};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle as BarStyle};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+use tokio::{sync::RwLock, task::JoinHandle};
+use tracing::info;
 
 pub struct ProgressTracker {
     pub global_progress: Arc<RwLock<GlobalProgress>>,
     pub progress_bar: Option<ProgressBar>,
+    /// Holds the overall bar and any per-file sub-bars in `Bar` style
+    multi: Option<MultiProgress>,
+    /// `None` when progress is disabled
+    style: Option<ProgressStyle>,
 }
 
 #[derive(Debug, Clone)]
 pub struct GlobalProgress {
     pub total_files: usize,
     pub processed_files: usize,
-    pub total_bytes: u64,
+    /// Combined size of the inputs whose size is known
+    pub total_known_bytes: u64,
+    /// Inputs of unknown size, such as stdin
+    pub unknown_count: usize,
     pub processed_bytes: u64,
     pub total_rows: u64,
     pub processed_rows: u64,
@@ -24,7 +36,8 @@ impl GlobalProgress {
         Self {
             total_files,
             processed_files: 0,
-            total_bytes,
+            total_known_bytes: total_bytes,
+            unknown_count: 0,
             processed_bytes: 0,
             total_rows: 0,
             processed_rows: 0,
@@ -32,6 +45,23 @@ impl GlobalProgress {
         }
     }
 
+    pub fn for_inputs(files: &[InputFile]) -> Self {
+        let mut progress = Self::new(files.len(), 0);
+        for file in files {
+            match file.known_size() {
+                Some(size) => progress.total_known_bytes += size,
+                None => progress.unknown_count += 1,
+            }
+        }
+        progress
+    }
+
+    /// Whether the total is the size of every input, so a percentage and
+    /// ETA mean something.
+    pub fn total_is_known(&self) -> bool {
+        self.unknown_count == 0
+    }
+
     pub fn get_throughput_mbps(&self) -> f64 {
         let elapsed = self.start_time.elapsed().as_secs_f64();
         if elapsed > 0.0 {
@@ -42,47 +72,88 @@ impl GlobalProgress {
     }
 
     pub fn get_eta_seconds(&self) -> Option<u64> {
-        if self.processed_bytes > 0 && self.processed_bytes < self.total_bytes {
+        let total = self.total_is_known().then_some(self.total_known_bytes)?;
+        if self.processed_bytes > 0 && self.processed_bytes < total {
             let elapsed = self.start_time.elapsed().as_secs_f64();
             let rate = self.processed_bytes as f64 / elapsed;
-            let remaining_bytes = self.total_bytes - self.processed_bytes;
+            let remaining_bytes = total - self.processed_bytes;
             Some((remaining_bytes as f64 / rate) as u64)
         } else {
             None
         }
     }
 
-    pub fn get_progress_percentage(&self) -> f64 {
-        if self.total_bytes == 0 {
-            0.0
+    /// `None` while any input's size is unknown.
+    pub fn get_progress_percentage(&self) -> Option<f64> {
+        if !self.total_is_known() {
+            None
+        } else if self.total_known_bytes == 0 {
+            Some(0.0)
         } else {
-            (self.processed_bytes as f64 / self.total_bytes as f64) * 100.0
+            Some((self.processed_bytes as f64 / self.total_known_bytes as f64) * 100.0)
         }
     }
 }
 
 impl ProgressTracker {
     pub fn new(show_progress: bool, total_files: usize, total_bytes: u64) -> Self {
-        let global_progress = Arc::new(RwLock::new(GlobalProgress::new(total_files, total_bytes)));
-        
-        let progress_bar = if show_progress {
-            let pb = ProgressBar::new(total_bytes);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%) {msg}")
-                    .unwrap()
-                    .progress_chars("#>-"),
-            );
+        let style = show_progress.then_some(ProgressStyle::Bar);
+        Self::with_style(style, GlobalProgress::new(total_files, total_bytes))
+    }
+
+    /// A tracker reporting in `style`, or silently when `style` is `None`.
+    /// The overall bar becomes a spinner when any input's size is unknown.
+    pub fn with_style(style: Option<ProgressStyle>, progress: GlobalProgress) -> Self {
+        let multi = (style == Some(ProgressStyle::Bar)).then(MultiProgress::new);
+
+        let progress_bar = if let Some(multi) = &multi {
+            let pb = if progress.total_is_known() {
+                let pb = ProgressBar::new(progress.total_known_bytes);
+                pb.set_style(
+                    BarStyle::default_bar()
+                        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%) {msg}")
+                        .unwrap()
+                        .progress_chars("#>-"),
+                );
+                pb
+            } else {
+                let pb = ProgressBar::new_spinner();
+                pb.set_style(
+                    BarStyle::default_spinner()
+                        .template("{spinner:.green} [{elapsed_precise}] {bytes} {msg}")
+                        .unwrap(),
+                );
+                pb
+            };
+            let pb = multi.add(pb);
             pb.set_message("Processing files...");
             Some(pb)
         } else {
             None
         };
+        let global_progress = Arc::new(RwLock::new(progress));
 
         Self {
             global_progress,
             progress_bar,
+            multi,
+            style,
+        }
+    }
+
+    /// A tracker for one file, drawn as a sub-bar below the overall bar in
+    /// `Bar` style and not drawn otherwise.
+    pub fn file_tracker(&self, file_name: String, file_size: Option<u64>) -> FileProgressTracker {
+        let mut tracker = FileProgressTracker::new(file_name, file_size, self.multi.is_some());
+        if let (Some(multi), Some(pb)) = (&self.multi, tracker.progress_bar.take()) {
+            tracker.progress_bar = Some(multi.add(pb));
         }
+        tracker
+    }
+
+    /// Adds rows known to be coming, e.g. from a Parquet file's metadata.
+    pub async fn add_total_rows(&self, rows: u64) {
+        self.global_progress.write().await.total_rows += rows;
     }
 
     pub async fn update_file_progress(&self, bytes_processed: u64, rows_processed: u64) -> Result<()> {
@@ -93,7 +164,8 @@ impl ProgressTracker {
         if let Some(pb) = &self.progress_bar {
             pb.set_position(progress.processed_bytes);
             pb.set_message(format!(
-                "Throughput: {:.1} MB/s, ETA: {}",
+                "{} rows, Throughput: {:.1} MB/s, ETA: {}",
+                progress.processed_rows,
                 progress.get_throughput_mbps(),
                 format_eta(progress.get_eta_seconds())
             ));
@@ -102,9 +174,36 @@ impl ProgressTracker {
         Ok(())
     }
 
-    pub async fn mark_file_complete(&self) -> Result<()> {
+    pub async fn mark_file_complete(&self, file_name: &str) -> Result<()> {
         let mut progress = self.global_progress.write().await;
         progress.processed_files += 1;
+
+        match self.style {
+            Some(ProgressStyle::Plain) => eprintln!(
+                "Completed {} ({}/{} files, {} bytes, {} rows, {:.1} MB/s)",
+                file_name,
+                progress.processed_files,
+                progress.total_files,
+                progress.processed_bytes,
+                progress.processed_rows,
+                progress.get_throughput_mbps()
+            ),
+            Some(ProgressStyle::Json) => eprintln!(
+                "{}",
+                serde_json::json!({
+                    "file": file_name,
+                    "processed_files": progress.processed_files,
+                    "total_files": progress.total_files,
+                    "processed_bytes": progress.processed_bytes,
+                    "total_known_bytes": progress.total_known_bytes,
+                    "unknown_count": progress.unknown_count,
+                    "processed_rows": progress.processed_rows,
+                    "total_rows": progress.total_rows,
+                    "throughput_mbps": progress.get_throughput_mbps(),
+                })
+            ),
+            _ => {}
+        }
         
         if let Some(pb) = &self.progress_bar {
             pb.set_message(format!(
@@ -135,6 +234,61 @@ impl ProgressTracker {
     }
 }
 
+/// Lock-free row/byte counters shared between reader and writer tasks.
+#[derive(Debug, Clone, Default)]
+pub struct Counters {
+    rows: Arc<AtomicU64>,
+    bytes: Arc<AtomicU64>,
+}
+
+impl Counters {
+    pub fn add_rows(&self, rows: u64) {
+        self.rows.fetch_add(rows, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.rows.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// Spawns a task that logs a single progress line every `interval`.
+/// Unlike the progress bar this is plain log output, so it stays readable
+/// in CI and other non-TTY environments. Abort the handle to stop it.
+pub fn spawn_heartbeat(counters: Counters, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            info!("{}", format_heartbeat(counters.rows(), counters.bytes(), start.elapsed()));
+        }
+    })
+}
+
+fn format_heartbeat(rows: u64, bytes: u64, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    let mbps = if secs > 0.0 {
+        (bytes as f64 / 1_000_000.0) / secs
+    } else {
+        0.0
+    };
+    format!(
+        "Heartbeat: {} rows, {} bytes processed, {:.1} MB/s",
+        rows, bytes, mbps
+    )
+}
+
 fn format_eta(eta_seconds: Option<u64>) -> String {
     match eta_seconds {
         Some(seconds) => {
@@ -156,20 +310,34 @@ fn format_eta(eta_seconds: Option<u64>) -> String {
 
 pub struct FileProgressTracker {
     file_name: String,
-    file_size: u64,
     progress_bar: Option<ProgressBar>,
 }
 
 impl FileProgressTracker {
-    pub fn new(file_name: String, file_size: u64, show_progress: bool) -> Self {
+    /// A bar for a file of `file_size` bytes, or a spinner if that's unknown.
+    pub fn new(file_name: String, file_size: Option<u64>, show_progress: bool) -> Self {
         let progress_bar = if show_progress {
-            let pb = ProgressBar::new(file_size);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{spinner:.green} {msg} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%)")
-                    .unwrap()
-                    .progress_chars("#>-"),
-            );
+            let pb = match file_size {
+                Some(size) => {
+                    let pb = ProgressBar::new(size);
+                    pb.set_style(
+                        BarStyle::default_bar()
+                            .template("{spinner:.green} {msg} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%)")
+                            .unwrap()
+                            .progress_chars("#>-"),
+                    );
+                    pb
+                }
+                None => {
+                    let pb = ProgressBar::new_spinner();
+                    pb.set_style(
+                        BarStyle::default_spinner()
+                            .template("{spinner:.green} {msg} [{elapsed_precise}] {bytes}")
+                            .unwrap(),
+                    );
+                    pb
+                }
+            };
             pb.set_message(file_name.clone());
             Some(pb)
         } else {
@@ -178,7 +346,6 @@ impl FileProgressTracker {
 
         Self {
             file_name,
-            file_size,
             progress_bar,
         }
     }
@@ -213,6 +380,78 @@ mod tests {
         assert_eq!(stats.processed_rows, 30);
     }
 
+    #[tokio::test]
+    async fn test_file_trackers() {
+        for style in [None, Some(ProgressStyle::Plain), Some(ProgressStyle::Json)] {
+            let tracker = ProgressTracker::with_style(style, GlobalProgress::new(2, 300));
+            assert!(tracker.progress_bar.is_none());
+
+            let file = tracker.file_tracker("a.csv".to_string(), Some(100));
+            assert!(file.progress_bar.is_none());
+            file.update(50);
+            file.finish();
+            tracker.update_file_progress(100, 5).await.unwrap();
+            tracker.mark_file_complete("a.csv").await.unwrap();
+            tracker.finish().await.unwrap();
+
+            let stats = tracker.get_stats().await;
+            assert_eq!(stats.processed_files, 1);
+            assert_eq!(stats.processed_bytes, 100);
+        }
+
+        let tracker = ProgressTracker::with_style(Some(ProgressStyle::Bar), GlobalProgress::new(2, 300));
+        let file = tracker.file_tracker("b.csv".to_string(), Some(200));
+        file.update(200);
+        assert_eq!(file.progress_bar.as_ref().unwrap().position(), 200);
+        file.finish();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_size_uses_spinner() {
+        use crate::discover::FileFormat;
+        use std::path::PathBuf;
+
+        let files = [
+            InputFile {
+                path: PathBuf::from("-"),
+                format: FileFormat::Csv,
+                size: 0,
+            },
+            InputFile {
+                path: PathBuf::from("a.csv"),
+                format: FileFormat::Csv,
+                size: 500,
+            },
+        ];
+        let progress = GlobalProgress::for_inputs(&files);
+        assert_eq!(progress.total_known_bytes, 500);
+        assert_eq!(progress.unknown_count, 1);
+
+        let tracker = ProgressTracker::with_style(Some(ProgressStyle::Bar), progress);
+        assert_eq!(tracker.progress_bar.as_ref().unwrap().length(), None);
+        let stdin = tracker.file_tracker("-".to_string(), files[0].known_size());
+        assert_eq!(stdin.progress_bar.as_ref().unwrap().length(), None);
+
+        tracker.update_file_progress(1000, 10).await.unwrap();
+        let stats = tracker.get_stats().await;
+        assert_eq!(stats.get_progress_percentage(), None);
+        assert_eq!(stats.get_eta_seconds(), None);
+
+        let known = GlobalProgress::for_inputs(&files[1..]);
+        assert_eq!(known.get_progress_percentage(), Some(0.0));
+    }
+
+    #[test]
+    fn test_heartbeat_formatting() {
+        let counters = Counters::default();
+        counters.add_rows(10);
+        counters.add_rows(5);
+        counters.add_bytes(2_000_000);
+
+        let line = format_heartbeat(counters.rows(), counters.bytes(), Duration::from_secs(2));
+        assert_eq!(line, "Heartbeat: 15 rows, 2000000 bytes processed, 1.0 MB/s");
+    }
+
     #[test]
     fn test_eta_formatting() {
         assert_eq!(format_eta(Some(0)), "0s");
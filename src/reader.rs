@@ -0,0 +1,246 @@
+use crate::{
+    csv_in::{CsvConfig, CsvReader},
+    discover::{FileFormat, InputFile},
+    error::{MawError, Result},
+    json_in::JsonReader,
+    parquet_in::ParquetReader,
+    rejects::RejectedRow,
+    state::ResumePoint,
+};
+use arrow2::{
+    array::Array,
+    chunk::Chunk,
+    datatypes::{DataType, Field, Schema},
+};
+
+enum Source {
+    Csv(CsvReader),
+    Parquet(ParquetReader),
+    Json(JsonReader),
+}
+
+/// A reader for any supported input format. The first batch can be peeked
+/// to learn the file's schema without losing it, which matters for stdin.
+pub struct InputReader {
+    source: Source,
+    pending: Option<Chunk<Box<dyn Array>>>,
+}
+
+impl InputReader {
+    pub fn open(file: &InputFile, csv_config: &CsvConfig, batch_size: usize) -> Result<Self> {
+        Self::open_at(file, csv_config, batch_size, ResumePoint::default())
+    }
+
+    /// Opens an input at `start`, skipping its first row groups if it is a
+    /// Parquet file or its first bytes if it is a CSV file. JSON inputs
+    /// start at the top.
+    pub fn open_at(
+        file: &InputFile,
+        csv_config: &CsvConfig,
+        batch_size: usize,
+        start: ResumePoint,
+    ) -> Result<Self> {
+        Self::open_source(file, csv_config, batch_size, start).map_err(|e| match e {
+            // In a live directory a discovered file can be gone by the time
+            // it is read
+            MawError::Io(io) if io.kind() == std::io::ErrorKind::NotFound && !file.path.exists() => {
+                MawError::Vanished
+            }
+            e => e,
+        })
+    }
+
+    fn open_source(
+        file: &InputFile,
+        csv_config: &CsvConfig,
+        batch_size: usize,
+        start: ResumePoint,
+    ) -> Result<Self> {
+        let source = match file.format {
+            FileFormat::Csv => {
+                let config = CsvConfig {
+                    batch_size,
+                    ..csv_config.clone()
+                };
+                let mut reader = CsvReader::new(&file.path, &config)?;
+                if start.offset > 0 {
+                    reader.skip_to(start.offset)?;
+                }
+                Source::Csv(reader)
+            }
+            FileFormat::Parquet => Source::Parquet(ParquetReader::starting_at_row_group(
+                &file.path,
+                batch_size,
+                start.row_group,
+            )?),
+            FileFormat::Json => Source::Json(
+                JsonReader::new(&file.path, batch_size, csv_config.read_buffer)?.reject_rows(csv_config.reject_rows),
+            ),
+        };
+
+        Ok(Self {
+            source,
+            pending: None,
+        })
+    }
+
+    pub fn read_batch(&mut self) -> Result<Option<Chunk<Box<dyn Array>>>> {
+        if let Some(batch) = self.pending.take() {
+            return Ok(Some(batch));
+        }
+
+        match &mut self.source {
+            Source::Csv(reader) => reader.read_batch(),
+            Source::Parquet(reader) => reader.read_batch(),
+            Source::Json(reader) => reader.read_batch(),
+        }
+    }
+
+    /// Returns the schema of this input. Text formats infer types from the
+    /// first batch, which is kept and returned by the next `read_batch`.
+    pub fn peek_schema(&mut self) -> Result<Schema> {
+        if let Source::Parquet(reader) = &self.source {
+            return Ok(reader.get_schema().clone());
+        }
+
+        if self.pending.is_none() {
+            self.pending = self.read_batch()?;
+        }
+
+        let names = self.column_names();
+        let fields = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let data_type = self
+                    .pending
+                    .as_ref()
+                    .and_then(|batch| batch.arrays().get(i))
+                    .map(|array| array.data_type().clone())
+                    .unwrap_or(DataType::Null);
+                Field::new(name, data_type, true)
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Schema::from(fields))
+    }
+
+    /// The batch `peek_schema` read to infer a text input's types, until
+    /// it is returned by `read_batch`.
+    pub fn sampled_batch(&self) -> Option<&Chunk<Box<dyn Array>>> {
+        self.pending.as_ref()
+    }
+
+    /// The rows nulls were counted over and the null count of each column:
+    /// the whole file for Parquet, from its metadata, or the sampled batch
+    /// of a text input. None for a text input not yet sampled.
+    pub fn null_counts(&self) -> Option<(usize, Vec<(String, usize)>)> {
+        if let Source::Parquet(reader) = &self.source {
+            return Some((reader.total_rows() as usize, reader.null_counts().to_vec()));
+        }
+        let batch = self.sampled_batch()?;
+        let counts = self
+            .column_names()
+            .into_iter()
+            .zip(batch.arrays())
+            .map(|(name, array)| (name, array.null_count()))
+            .collect();
+        Some((batch.len(), counts))
+    }
+
+    /// Column names of the chunks this reader produces, in order.
+    pub fn column_names(&self) -> Vec<String> {
+        match &self.source {
+            Source::Csv(reader) => reader.get_headers().to_vec(),
+            Source::Parquet(reader) => reader
+                .get_schema()
+                .fields
+                .iter()
+                .map(|f| f.name.clone())
+                .collect(),
+            Source::Json(reader) => reader.get_headers().to_vec(),
+        }
+    }
+
+    /// Rows set aside since the last call: CSV rows with the wrong field
+    /// count, and JSON lines that aren't objects.
+    pub fn take_rejected_rows(&mut self) -> Vec<RejectedRow> {
+        match &mut self.source {
+            Source::Csv(reader) => reader.take_rejected_rows(),
+            Source::Json(reader) => reader.take_rejected_rows(),
+            Source::Parquet(_) => Vec::new(),
+        }
+    }
+
+    /// Row groups fully read so far, for Parquet inputs.
+    pub fn completed_row_groups(&self) -> Option<usize> {
+        match &self.source {
+            Source::Parquet(reader) => Some(reader.completed_row_groups()),
+            _ => None,
+        }
+    }
+
+    /// Where a CSV input's next batch starts, which `open_at` can resume
+    /// from once every batch before it is written.
+    pub fn record_offset(&self) -> Option<u64> {
+        match &self.source {
+            Source::Csv(reader) if self.pending.is_none() => Some(reader.record_offset()),
+            _ => None,
+        }
+    }
+
+    /// Input bytes consumed so far, when the format can report it.
+    pub fn bytes_read(&self) -> Option<u64> {
+        match &self.source {
+            Source::Csv(reader) => reader.bytes_read(),
+            _ => None,
+        }
+    }
+
+    /// Progress through an input of `file_size` bytes: bytes consumed for
+    /// CSV, or estimated from rows read for Parquet. JSON reports none.
+    pub fn position(&self, file_size: u64) -> Option<u64> {
+        match &self.source {
+            Source::Parquet(reader) => Some(reader.estimated_position(file_size)),
+            _ => self.bytes_read(),
+        }
+    }
+
+    /// Rows in the input, for formats that record them up front.
+    pub fn total_rows(&self) -> Option<u64> {
+        match &self.source {
+            Source::Parquet(reader) => Some(reader.total_rows()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_peek_schema_keeps_first_batch() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("test.csv");
+        fs::write(&csv_file, "a,b\n1,x\n2,y\n").unwrap();
+
+        let file = InputFile {
+            path: csv_file,
+            format: FileFormat::Csv,
+            size: 0,
+        };
+        let mut reader = InputReader::open(&file, &CsvConfig::default(), 1000).unwrap();
+
+        let schema = reader.peek_schema().unwrap();
+        assert_eq!(schema.fields[0].name, "a");
+        assert_eq!(schema.fields[0].data_type(), &DataType::Int64);
+        assert_eq!(schema.fields[1].data_type(), &DataType::Utf8);
+
+        let batch = reader.read_batch().unwrap().unwrap();
+        assert_eq!(batch.len(), 2);
+        assert!(reader.read_batch().unwrap().is_none());
+    }
+}
@@ -0,0 +1,49 @@
+use crate::error::Result;
+use crate::fsutil::create_output_file;
+use csv::Writer;
+use std::{
+    fs::File,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// A rejects file shared by every reader's aligner.
+pub type SharedRejects = Arc<Mutex<RejectsWriter>>;
+
+/// A row a reader set aside for the rejects file rather than failing on
+/// it: its fields, as far as they could be split, and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectedRow {
+    pub fields: Vec<String>,
+    pub reason: String,
+}
+
+/// Writes rows dropped by `--coerce-errors skip`, and rows the readers
+/// couldn't read, to a CSV file (`--rejects`) instead of losing them: the
+/// output columns as text, with unconvertible values as they were read,
+/// then a `reason` column.
+pub struct RejectsWriter {
+    writer: Writer<File>,
+    rows: u64,
+}
+
+impl RejectsWriter {
+    pub fn create(path: &Path, columns: &[String], overwrite: bool) -> Result<Self> {
+        let mut writer = Writer::from_writer(create_output_file(path, overwrite)?);
+        writer.write_record(columns.iter().map(String::as_str).chain(["reason"]))?;
+        Ok(Self { writer, rows: 0 })
+    }
+
+    pub fn write(&mut self, values: &[String], reason: &str) -> Result<()> {
+        self.writer
+            .write_record(values.iter().map(String::as_str).chain([reason]))?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Flushes the file and returns how many rows were rejected.
+    pub fn finish(&mut self) -> Result<u64> {
+        self.writer.flush()?;
+        Ok(self.rows)
+    }
+}
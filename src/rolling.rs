@@ -0,0 +1,230 @@
+use crate::{
+    cli::{Compression, OutputFormat},
+    error::Result,
+    naming::NameTemplate,
+    transform::approximate_bytes,
+    writer_csv::{CsvWriter, CsvWriterConfig},
+    writer_parquet::{ParquetWriter, ParquetWriterConfig},
+};
+use arrow2::{array::Array, chunk::Chunk, datatypes::Schema};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// When to start the next output file (`--roll-by-rows`, `--roll-by-bytes`,
+/// `--coalesce`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RollLimits {
+    pub rows: Option<u64>,
+    /// Measured by the rows' size in memory, which is close to their size
+    /// in an uncompressed CSV file
+    pub bytes: Option<u64>,
+    /// Start the next file between batches rather than partway through
+    /// one, so that an input's row groups stay whole and files hold about
+    /// `rows` rows rather than exactly that (`--coalesce`). A batch of more
+    /// than `rows` rows on its own is still split.
+    pub whole_batches: bool,
+}
+
+impl RollLimits {
+    pub fn is_none(&self) -> bool {
+        self.rows.is_none() && self.bytes.is_none()
+    }
+}
+
+/// What the files of a directory output (rolled or partitioned) are
+/// written with.
+#[derive(Clone)]
+pub struct OutputFiles {
+    pub schema: Arc<Schema>,
+    pub format: OutputFormat,
+    pub csv_config: CsvWriterConfig,
+    pub parquet_config: ParquetWriterConfig,
+    pub template: NameTemplate,
+    pub roll: RollLimits,
+    /// Number new files after the ones already in their directory
+    /// (`--append`), instead of from 0
+    pub append: bool,
+}
+
+impl OutputFiles {
+    /// Extension of each file, with the suffix of its compression.
+    pub fn extension(&self) -> &'static str {
+        match (&self.format, &self.csv_config.compression) {
+            (OutputFormat::Parquet, _) => "parquet",
+            (OutputFormat::Csv, Compression::None) => "csv",
+            (OutputFormat::Csv, Compression::Gzip) => "csv.gz",
+            (OutputFormat::Csv, Compression::Zstd) => "csv.zst",
+            (OutputFormat::Csv, Compression::Snappy) => "csv.sz",
+        }
+    }
+
+    /// The index the first new file in `dir` gets: with `append`, one past
+    /// the highest index of the files of `partition` already there, else 0.
+    pub fn first_index(&self, dir: &Path, partition: &str) -> Result<usize> {
+        if !self.append {
+            return Ok(0);
+        }
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut next = 0;
+        for entry in entries {
+            let name = entry?.file_name();
+            let index = self.template.index_of(&name.to_string_lossy(), partition, self.extension());
+            if let Some(index) = index {
+                next = next.max(index + 1);
+            }
+        }
+        Ok(next)
+    }
+}
+
+/// Writes rows into files in a directory named by the template, starting
+/// the next file whenever one reaches the roll limits.
+pub struct RollingWriter {
+    dir: PathBuf,
+    partition: String,
+    files: OutputFiles,
+    next_index: usize,
+    current: Option<OpenFile>,
+    closed: usize,
+}
+
+struct OpenFile {
+    writer: FileWriter,
+    rows: u64,
+    bytes: u64,
+}
+
+enum FileWriter {
+    Csv(Box<CsvWriter>),
+    Parquet(Box<ParquetWriter>),
+}
+
+impl RollingWriter {
+    /// Files are numbered from `first_index`. `partition` is the directory
+    /// of the partition the rows belong to, if any, for the template.
+    pub fn new(dir: PathBuf, partition: &str, files: OutputFiles, first_index: usize) -> Self {
+        Self {
+            dir,
+            partition: partition.to_string(),
+            files,
+            next_index: first_index,
+            current: None,
+            closed: 0,
+        }
+    }
+
+    pub fn write_batch(&mut self, batch: &Chunk<Box<dyn Array>>) -> Result<()> {
+        let mut offset = 0;
+        while offset < batch.len() {
+            let remaining = batch.len() - offset;
+            let roll = self.files.roll;
+            let whole = roll.whole_batches && roll.rows.is_none_or(|rows| remaining as u64 <= rows);
+            if self
+                .current
+                .as_ref()
+                .is_some_and(|file| self.is_full(file) || (whole && self.overshoots(file, remaining as u64)))
+            {
+                self.close()?;
+            }
+            if self.current.is_none() {
+                self.open()?;
+            }
+            let file = self.current.as_mut().expect("a file was just opened");
+
+            let room = match roll.rows {
+                Some(rows) if !whole => (rows - file.rows) as usize,
+                _ => usize::MAX,
+            };
+            let length = room.min(remaining);
+            let rows = if length == batch.len() {
+                batch.clone()
+            } else {
+                Chunk::new(batch.arrays().iter().map(|a| a.sliced(offset, length)).collect())
+            };
+            file.rows += length as u64;
+            file.bytes += rows.arrays().iter().map(|a| approximate_bytes(a.as_ref())).sum::<usize>() as u64;
+            match &mut file.writer {
+                FileWriter::Csv(writer) => writer.write_batch(&rows)?,
+                FileWriter::Parquet(writer) => writer.write_batch(&rows)?,
+            }
+            offset += length;
+        }
+        Ok(())
+    }
+
+    /// Closes the current file, first creating one if nothing was written,
+    /// and returns the index the next file would have.
+    pub fn finish(mut self) -> Result<usize> {
+        if self.current.is_none() {
+            self.open()?;
+        }
+        self.close()?;
+        Ok(self.next_index)
+    }
+
+    /// How many files have been closed so far, all of whose rows are then
+    /// durably written.
+    pub fn closed_files(&self) -> usize {
+        self.closed
+    }
+
+    fn is_full(&self, file: &OpenFile) -> bool {
+        let roll = self.files.roll;
+        roll.rows.is_some_and(|rows| file.rows >= rows) || roll.bytes.is_some_and(|bytes| file.bytes >= bytes)
+    }
+
+    /// Whether adding `rows` more rows to `file` would take it further
+    /// past the row limit than it is short of it now.
+    fn overshoots(&self, file: &OpenFile, rows: u64) -> bool {
+        self.files.roll.rows.is_some_and(|limit| {
+            let total = file.rows + rows;
+            file.rows > 0 && total > limit && total - limit > limit - file.rows
+        })
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let name = self
+            .files
+            .template
+            .render(self.next_index, &self.partition, self.files.extension());
+        let path = self.dir.join(name);
+        let writer = match self.files.format {
+            OutputFormat::Csv => {
+                let mut writer = CsvWriter::new(&path, &self.files.csv_config)?;
+                writer.set_headers(self.files.schema.fields.iter().map(|f| f.name.clone()).collect());
+                FileWriter::Csv(Box::new(writer))
+            }
+            OutputFormat::Parquet => FileWriter::Parquet(Box::new(ParquetWriter::new(
+                &path,
+                self.files.schema.clone(),
+                &self.files.parquet_config,
+            )?)),
+        };
+        // Only the first file carries on from an earlier one
+        self.files.csv_config.append = false;
+        self.next_index += 1;
+        self.current = Some(OpenFile {
+            writer,
+            rows: 0,
+            bytes: 0,
+        });
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        match self.current.take().map(|file| file.writer) {
+            Some(FileWriter::Csv(writer)) => writer.finish()?,
+            Some(FileWriter::Parquet(writer)) => writer.finish()?,
+            None => return Ok(()),
+        }
+        self.closed += 1;
+        Ok(())
+    }
+}
@@ -1,7 +1,15 @@
-use crate::error::{MawError, Result};
-use arrow2::datatypes::{DataType, Field, Schema};
+use crate::{
+    error::{MawError, Result},
+    fsutil::{create_temp_file, persist_temp_file, sync_parent_dir, temp_dir_for},
+};
+use arrow2::datatypes::{DataType, Field, Schema, TimeUnit};
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TypeKind {
@@ -15,10 +23,47 @@ pub enum TypeKind {
     F64,
     Utf8,
     Date,
-    Datetime,
+    /// A timestamp with its unit and optional timezone
+    Datetime(TimestampUnit, Option<String>),
+    /// A fixed-point decimal with its precision and scale
+    Decimal(usize, usize),
     Binary,
 }
 
+/// The unit of a timestamp column, coarsest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TimestampUnit {
+    Second,
+    Millisecond,
+    Microsecond,
+    Nanosecond,
+}
+
+impl From<TimeUnit> for TimestampUnit {
+    fn from(unit: TimeUnit) -> Self {
+        match unit {
+            TimeUnit::Second => TimestampUnit::Second,
+            TimeUnit::Millisecond => TimestampUnit::Millisecond,
+            TimeUnit::Microsecond => TimestampUnit::Microsecond,
+            TimeUnit::Nanosecond => TimestampUnit::Nanosecond,
+        }
+    }
+}
+
+impl From<TimestampUnit> for TimeUnit {
+    fn from(unit: TimestampUnit) -> Self {
+        match unit {
+            TimestampUnit::Second => TimeUnit::Second,
+            TimestampUnit::Millisecond => TimeUnit::Millisecond,
+            TimestampUnit::Microsecond => TimeUnit::Microsecond,
+            TimestampUnit::Nanosecond => TimeUnit::Nanosecond,
+        }
+    }
+}
+
+/// Largest precision an Arrow 128-bit decimal can hold.
+pub(crate) const MAX_DECIMAL_PRECISION: usize = 38;
+
 impl TypeKind {
     pub fn from_arrow_type(dt: &DataType) -> Self {
         match dt {
@@ -30,11 +75,13 @@ impl TypeKind {
             DataType::Int64 => TypeKind::I64,
             DataType::Float32 => TypeKind::F32,
             DataType::Float64 => TypeKind::F64,
-            DataType::Utf8 => TypeKind::Utf8,
-            DataType::Binary => TypeKind::Binary,
+            // Large offsets only matter for arrays over 2GB; the values are the same
+            DataType::Utf8 | DataType::LargeUtf8 => TypeKind::Utf8,
+            DataType::Binary | DataType::LargeBinary => TypeKind::Binary,
             DataType::Date32 => TypeKind::Date,
-            DataType::Date64 => TypeKind::Datetime,
-            DataType::Timestamp(_, _) => TypeKind::Datetime,
+            DataType::Date64 => TypeKind::Datetime(TimestampUnit::Millisecond, None),
+            DataType::Timestamp(unit, tz) => TypeKind::Datetime((*unit).into(), tz.clone()),
+            DataType::Decimal(precision, scale) => TypeKind::Decimal(*precision, *scale),
             _ => TypeKind::Utf8, // Default to string for unknown types
         }
     }
@@ -51,7 +98,8 @@ impl TypeKind {
             TypeKind::F64 => DataType::Float64,
             TypeKind::Utf8 => DataType::Utf8,
             TypeKind::Date => DataType::Date32,
-            TypeKind::Datetime => DataType::Timestamp(arrow2::datatypes::TimeUnit::Millisecond, None),
+            TypeKind::Datetime(unit, tz) => DataType::Timestamp((*unit).into(), tz.clone()),
+            TypeKind::Decimal(precision, scale) => DataType::Decimal(*precision, *scale),
             TypeKind::Binary => DataType::Binary,
         }
     }
@@ -60,16 +108,33 @@ impl TypeKind {
 #[derive(Debug, Clone)]
 pub struct UnifiedSchema {
     pub schema: Schema,
-    pub column_mapping: HashMap<String, String>, // original -> unified name
+    pub column_mapping: ColumnRenames, // original -> unified name
     pub type_mapping: HashMap<String, TypeKind>, // column -> type
+    /// Utf8 columns to dictionary encode (`--dictionary-strings`)
+    pub dictionary_columns: HashSet<String>,
+    /// Unifications that lost precision or stringified values, in column
+    /// order
+    pub warnings: Vec<CoercionWarning>,
+    /// Columns whose inputs had different types, in column order
+    pub promotions: Vec<TypePromotion>,
+    /// Column -> fraction of the sampled rows where it is null or missing,
+    /// for columns of sampled text inputs
+    pub null_fractions: HashMap<String, f64>,
+    /// Columns left out for being mostly null (`--drop-null-columns`)
+    pub dropped_columns: Vec<String>,
 }
 
 impl UnifiedSchema {
     pub fn new() -> Self {
         Self {
             schema: Schema::from(vec![]),
-            column_mapping: HashMap::new(),
+            column_mapping: ColumnRenames::default(),
             type_mapping: HashMap::new(),
+            dictionary_columns: HashSet::new(),
+            warnings: Vec::new(),
+            promotions: Vec::new(),
+            null_fractions: HashMap::new(),
+            dropped_columns: Vec::new(),
         }
     }
 
@@ -77,41 +142,78 @@ impl UnifiedSchema {
         schemas: &[Schema],
         stringify_conflicts: bool,
     ) -> Result<Self> {
-        let mut unified = Self::new();
-        let mut column_types: HashMap<String, TypeKind> = HashMap::new();
-
-        // Collect all columns and their types
-        for schema in schemas {
-            for field in &schema.fields {
-                let column_name = &field.name;
-                let type_kind = TypeKind::from_arrow_type(field.data_type());
-                
-                if let Some(existing_type) = column_types.get(column_name) {
-                    // Type conflict - need to widen
-                    let widened = widen_types(existing_type, &type_kind, stringify_conflicts)?;
-                    column_types.insert(column_name.clone(), widened);
-                } else {
-                    column_types.insert(column_name.clone(), type_kind);
-                }
+        let mut accumulator = SchemaAccumulator::new(stringify_conflicts);
+        for (file_index, schema) in schemas.iter().enumerate() {
+            accumulator.register(file_index, schema)?;
+        }
+        Ok(accumulator.finish())
+    }
+
+    /// Sorts the unified columns alphabetically (`--reorder`).
+    pub fn sort_columns(&mut self) {
+        self.schema.fields.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Puts the listed columns first, in the given order (`--column-order`).
+    /// Unlisted columns follow in their current order, or are dropped with
+    /// `drop_unlisted`.
+    pub fn order_columns(&mut self, order: &[String], drop_unlisted: bool) -> Result<()> {
+        let mut fields = Vec::with_capacity(self.schema.fields.len());
+        for name in order {
+            let field = self.schema.fields.iter().find(|f| &f.name == name).ok_or_else(|| {
+                MawError::Config(format!("--column-order column '{}' is not in the input", name))
+            })?;
+            if !fields.iter().any(|f: &Field| &f.name == name) {
+                fields.push(field.clone());
             }
         }
+        if !drop_unlisted {
+            fields.extend(self.schema.fields.iter().filter(|f| !order.contains(&f.name)).cloned());
+        }
+        self.schema.fields = fields;
+        Ok(())
+    }
 
-        // Build unified schema
-        let mut fields = Vec::new();
-        let mut sorted_columns: Vec<_> = column_types.keys().collect();
-        sorted_columns.sort();
+    /// Leaves out the columns that are null or missing in more than
+    /// `threshold` of the sampled rows (`--drop-null-columns`). Columns no
+    /// sample covered are kept.
+    pub fn drop_null_columns(&mut self, threshold: f64) {
+        let null_fractions = &self.null_fractions;
+        let (dropped, kept): (Vec<Field>, Vec<Field>) = std::mem::take(&mut self.schema.fields)
+            .into_iter()
+            .partition(|f| null_fractions.get(&f.name).is_some_and(|fraction| *fraction > threshold));
+        self.schema.fields = kept;
+        self.dropped_columns = dropped.into_iter().map(|f| f.name).collect();
+    }
 
-        for column_name in sorted_columns {
-            let type_kind = &column_types[column_name];
-            let arrow_type = type_kind.to_arrow_type();
-            let field = Field::new(column_name, arrow_type, true); // nullable
-            fields.push(field);
+    /// Appends a column that no input has, such as the source path column.
+    pub fn add_column(&mut self, name: &str, type_kind: TypeKind) -> Result<()> {
+        if self.type_mapping.contains_key(name) {
+            return Err(MawError::Schema(format!("Column '{}' is already in the input", name)));
         }
+        self.schema.fields.push(Field::new(name, type_kind.to_arrow_type(), true));
+        self.type_mapping.insert(name.to_string(), type_kind);
+        Ok(())
+    }
 
-        unified.schema = Schema::from(fields);
-        unified.type_mapping = column_types;
+    /// Gives every decimal column the largest precision, keeping its scale.
+    /// Decimals read from text are typed batch by batch, so a later batch
+    /// can need more integer digits than the sample did.
+    pub fn widen_decimal_precision(&mut self) {
+        for field in &mut self.schema.fields {
+            if let DataType::Decimal(_, scale) = field.data_type {
+                field.data_type = DataType::Decimal(MAX_DECIMAL_PRECISION, scale);
+            }
+        }
+        for kind in self.type_mapping.values_mut() {
+            if let TypeKind::Decimal(_, scale) = *kind {
+                *kind = TypeKind::Decimal(MAX_DECIMAL_PRECISION, scale);
+            }
+        }
+    }
 
-        Ok(unified)
+    pub fn column_names(&self) -> Vec<String> {
+        self.schema.fields.iter().map(|f| f.name.clone()).collect()
     }
 
     pub fn get_column_type(&self, column: &str) -> Option<&TypeKind> {
@@ -119,9 +221,521 @@ impl UnifiedSchema {
     }
 
     pub fn get_unified_column_name(&self, original: &str) -> String {
-        self.column_mapping.get(original)
-            .cloned()
-            .unwrap_or_else(|| original.to_string())
+        self.column_mapping.apply(original)
+    }
+}
+
+/// Maps source column names to output names: a literal `--rename` first,
+/// then every `--rename-regex` substitution in the order given, then any
+/// case normalization.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnRenames {
+    literal: HashMap<String, String>,
+    substitutions: Vec<Substitution>,
+    case: Option<ColumnCase>,
+}
+
+/// Case that every column name is converted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnCase {
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone)]
+struct Substitution {
+    /// The `s/PATTERN/REPLACEMENT/FLAGS` it was given as
+    spec: String,
+    pattern: Regex,
+    replacement: String,
+    /// Replace every match rather than only the first
+    global: bool,
+}
+
+impl ColumnRenames {
+    pub fn insert(&mut self, from: impl Into<String>, to: impl Into<String>) {
+        self.literal.insert(from.into(), to.into());
+    }
+
+    /// Adds a sed-style `s/PATTERN/REPLACEMENT/FLAGS` substitution. Any
+    /// character can take the place of `/`. Flags are `g` to replace every
+    /// match instead of the first and `i` to ignore case; `$1` in the
+    /// replacement inserts a capture group.
+    pub fn add_substitution(&mut self, spec: &str) -> Result<()> {
+        let invalid = |reason: &str| {
+            MawError::Config(format!("Invalid --rename-regex '{}': {}", spec, reason))
+        };
+        let expected = "expected s/pattern/replacement/";
+
+        let mut chars = spec.chars();
+        let delimiter = match (chars.next(), chars.next()) {
+            (Some('s'), Some(delimiter)) => delimiter,
+            _ => return Err(invalid(expected)),
+        };
+        let parts: Vec<&str> = chars.as_str().split(delimiter).collect();
+        let (pattern, replacement, flags) = match parts[..] {
+            [pattern, replacement] => (pattern, replacement, ""),
+            [pattern, replacement, flags] => (pattern, replacement, flags),
+            _ => return Err(invalid(expected)),
+        };
+
+        let mut global = false;
+        let mut case_insensitive = false;
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' => case_insensitive = true,
+                _ => return Err(invalid(&format!("unknown flag '{}'", flag))),
+            }
+        }
+        let pattern = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|e| invalid(&e.to_string()))?;
+
+        self.substitutions.push(Substitution {
+            spec: spec.to_string(),
+            pattern,
+            replacement: replacement.to_string(),
+            global,
+        });
+        Ok(())
+    }
+
+    pub fn set_case(&mut self, case: ColumnCase) {
+        self.case = Some(case);
+    }
+
+    /// One line per renaming step, in the order they apply.
+    pub fn describe(&self) -> Vec<String> {
+        let mut literal: Vec<_> = self.literal.iter().collect();
+        literal.sort();
+        let mut steps: Vec<String> = literal
+            .into_iter()
+            .map(|(from, to)| format!("rename {} to {}", from, to))
+            .collect();
+        steps.extend(self.substitutions.iter().map(|s| format!("rename {}", s.spec)));
+        match self.case {
+            Some(ColumnCase::Lower) => steps.push("lowercase column names".to_string()),
+            Some(ColumnCase::Upper) => steps.push("uppercase column names".to_string()),
+            None => {}
+        }
+        steps
+    }
+
+    /// The output name of a source column.
+    pub fn apply(&self, name: &str) -> String {
+        let mut name = self.literal.get(name).cloned().unwrap_or_else(|| name.to_string());
+        for substitution in &self.substitutions {
+            let replacement = substitution.replacement.as_str();
+            let renamed = if substitution.global {
+                substitution.pattern.replace_all(&name, replacement)
+            } else {
+                substitution.pattern.replace(&name, replacement)
+            };
+            name = renamed.into_owned();
+        }
+        match self.case {
+            Some(ColumnCase::Lower) => name.to_lowercase(),
+            Some(ColumnCase::Upper) => name.to_uppercase(),
+            None => name,
+        }
+    }
+
+    /// Renames the columns of one input's schema. Two of its columns that
+    /// end up with the same name are an error rather than silently merged.
+    pub fn apply_to_schema(&self, schema: Schema) -> Result<Schema> {
+        let mut originals: HashMap<String, String> = HashMap::new();
+        let mut fields = Vec::with_capacity(schema.fields.len());
+        for mut field in schema.fields {
+            let name = self.apply(&field.name);
+            match originals.get(&name) {
+                Some(original) if *original != field.name => {
+                    return Err(MawError::Schema(format!(
+                        "Columns '{}' and '{}' are both renamed to '{}'",
+                        original, field.name, name
+                    )));
+                }
+                _ => {
+                    originals.insert(name.clone(), field.name.clone());
+                }
+            }
+            field.name = name;
+            fields.push(field);
+        }
+        Ok(Schema::from(fields))
+    }
+}
+
+impl From<HashMap<String, String>> for ColumnRenames {
+    fn from(literal: HashMap<String, String>) -> Self {
+        Self {
+            literal,
+            substitutions: Vec::new(),
+            case: None,
+        }
+    }
+}
+
+/// Builds a unified schema from inputs registered in any order, e.g. by
+/// reader tasks sampling files concurrently behind an `Arc<Mutex<_>>`.
+/// Columns keep the position they first have in input order, not arrival
+/// order, so the result doesn't depend on which reader finishes first.
+#[derive(Debug)]
+pub struct SchemaAccumulator {
+    stringify_conflicts: bool,
+    /// Fail on a column whose inputs differ in type, other than by nulls
+    no_widening: bool,
+    /// Keep columns non-nullable where every input has them so
+    preserve_non_null: bool,
+    /// Inputs registered so far
+    inputs: HashSet<usize>,
+    /// Column -> (inputs that have it, whether all of them declare it
+    /// non-nullable)
+    non_null: HashMap<String, (usize, bool)>,
+    /// Column -> (file index, field index) of its first appearance
+    positions: HashMap<String, (usize, usize)>,
+    types: HashMap<String, TypeKind>,
+    /// Column -> whether every sample of it looked categorical
+    categorical: HashMap<String, bool>,
+    /// Column -> each distinct input type, with the first file that has it
+    sources: HashMap<String, Vec<(usize, TypeKind)>>,
+    /// Rows sampled over every input that counted its nulls
+    sampled_rows: u64,
+    /// Column -> (sampled rows that had it, how many of those were null)
+    nulls: HashMap<String, (u64, u64)>,
+}
+
+impl SchemaAccumulator {
+    pub fn new(stringify_conflicts: bool) -> Self {
+        Self {
+            stringify_conflicts,
+            no_widening: false,
+            preserve_non_null: false,
+            inputs: HashSet::new(),
+            non_null: HashMap::new(),
+            positions: HashMap::new(),
+            types: HashMap::new(),
+            categorical: HashMap::new(),
+            sources: HashMap::new(),
+            sampled_rows: 0,
+            nulls: HashMap::new(),
+        }
+    }
+
+    /// Fails registering an input whose column has another type than an
+    /// earlier input's, instead of widening the column to hold both
+    /// (`--no-widening`). An all-null column still takes the other type.
+    pub fn no_widening(mut self, no_widening: bool) -> Self {
+        self.no_widening = no_widening;
+        self
+    }
+
+    /// Keeps a column non-nullable when every input has it and declares it
+    /// non-nullable (`--preserve-non-null`), rather than making every
+    /// column nullable. A column some input lacks is filled with nulls
+    /// there, so it is nullable either way.
+    pub fn preserve_non_null(mut self, preserve_non_null: bool) -> Self {
+        self.preserve_non_null = preserve_non_null;
+        self
+    }
+
+    /// Records whether one file's sample of a text column repeated its
+    /// values enough to dictionary encode. The column is encoded only if
+    /// every sample that voted agreed and it is still text once unified.
+    pub fn vote_categorical(&mut self, column: &str, categorical: bool) {
+        *self.categorical.entry(column.to_string()).or_insert(true) &= categorical;
+    }
+
+    /// Records one file's sample of `rows` rows and the null count of each
+    /// of its columns. A column the file doesn't have counts as null in all
+    /// of them.
+    pub fn count_nulls(&mut self, rows: usize, columns: &[(String, usize)]) {
+        self.sampled_rows += rows as u64;
+        for (column, nulls) in columns {
+            let counts = self.nulls.entry(column.clone()).or_default();
+            counts.0 += rows as u64;
+            counts.1 += *nulls as u64;
+        }
+    }
+
+    /// Registers every column of the `file_index`th input.
+    pub fn register(&mut self, file_index: usize, schema: &Schema) -> Result<()> {
+        self.inputs.insert(file_index);
+        for (field_index, field) in schema.fields.iter().enumerate() {
+            self.widen_field(file_index, field_index, field)?;
+        }
+        Ok(())
+    }
+
+    /// Widens the accumulated type of `field`'s column to also hold `field`.
+    pub fn widen_field(&mut self, file_index: usize, field_index: usize, field: &Field) -> Result<()> {
+        let type_kind = TypeKind::from_arrow_type(field.data_type());
+        let widened = match self.types.get(&field.name) {
+            Some(existing) => {
+                let widened = widen_types(existing, &type_kind, self.stringify_conflicts)?;
+                let drifted = *existing != TypeKind::Null && type_kind != TypeKind::Null && *existing != type_kind;
+                if self.no_widening && drifted {
+                    return Err(MawError::Schema(format!(
+                        "Column '{}' is {:?} in this input but {:?} in another; --no-widening forbids widening it to {:?}",
+                        field.name,
+                        type_kind.to_arrow_type(),
+                        existing.to_arrow_type(),
+                        widened.to_arrow_type()
+                    )));
+                }
+                widened
+            }
+            None => type_kind.clone(),
+        };
+        self.types.insert(field.name.clone(), widened);
+        let non_null = self.non_null.entry(field.name.clone()).or_insert((0, true));
+        non_null.0 += 1;
+        non_null.1 &= !field.is_nullable;
+
+        let sources = self.sources.entry(field.name.clone()).or_default();
+        match sources.iter_mut().find(|(_, kind)| *kind == type_kind) {
+            Some((first, _)) => *first = (*first).min(file_index),
+            None => sources.push((file_index, type_kind)),
+        }
+
+        let position = (file_index, field_index);
+        self.positions
+            .entry(field.name.clone())
+            .and_modify(|first| *first = (*first).min(position))
+            .or_insert(position);
+        Ok(())
+    }
+
+    pub fn finish(self) -> UnifiedSchema {
+        let mut columns: Vec<_> = self.positions.into_iter().collect();
+        columns.sort_by_key(|(_, position)| *position);
+
+        let fields = columns
+            .iter()
+            .map(|(column_name, _)| {
+                let arrow_type = self.types[column_name].to_arrow_type();
+                let non_null = self.preserve_non_null
+                    && self.non_null.get(column_name).is_some_and(|&(inputs, non_null)| {
+                        non_null && inputs == self.inputs.len()
+                    });
+                Field::new(column_name, arrow_type, !non_null)
+            })
+            .collect::<Vec<_>>();
+
+        // Replayed in input order, so the warnings don't depend on which
+        // input registered first
+        let mut warnings = Vec::new();
+        let mut promotions = Vec::new();
+        for (column, _) in &columns {
+            let mut sources = self.sources[column].clone();
+            sources.sort_by_key(|(file_index, _)| *file_index);
+            let inputs: Vec<TypeKind> = sources
+                .iter()
+                .map(|(_, kind)| kind.clone())
+                .filter(|kind| kind != &TypeKind::Null)
+                .collect();
+            if inputs.len() > 1 {
+                let promotion = TypePromotion {
+                    column: column.clone(),
+                    inputs,
+                    unified: self.types[column].clone(),
+                };
+                tracing::info!("{}", promotion);
+                promotions.push(promotion);
+            }
+            let mut kinds = sources.into_iter().map(|(_, kind)| kind);
+            let Some(mut widened) = kinds.next() else {
+                continue;
+            };
+            for kind in kinds {
+                // Widening isn't associative, so inputs registered in another
+                // order can pair types that don't widen directly; those are
+                // checked against the column's unified type instead
+                let next = widen_types(&widened, &kind, self.stringify_conflicts)
+                    .unwrap_or_else(|_| self.types[column].clone());
+                if let Some(warning) = CoercionWarning::check(column, &widened, &kind, &next) {
+                    tracing::warn!("{}", warning);
+                    warnings.push(warning);
+                }
+                widened = next;
+            }
+        }
+
+        let dictionary_columns = self
+            .categorical
+            .into_iter()
+            .filter(|(column, categorical)| *categorical && self.types.get(column) == Some(&TypeKind::Utf8))
+            .map(|(column, _)| column)
+            .collect();
+
+        let sampled_rows = self.sampled_rows;
+        let null_fractions = if sampled_rows == 0 {
+            HashMap::new()
+        } else {
+            self.nulls
+                .into_iter()
+                .map(|(column, (rows, nulls))| {
+                    let missing = sampled_rows - rows;
+                    (column, (nulls + missing) as f64 / sampled_rows as f64)
+                })
+                .collect()
+        };
+
+        UnifiedSchema {
+            schema: Schema::from(fields),
+            column_mapping: ColumnRenames::default(),
+            type_mapping: self.types,
+            dictionary_columns,
+            warnings,
+            promotions,
+            null_fractions,
+            dropped_columns: Vec::new(),
+        }
+    }
+}
+
+/// What a lossy unification did to a column's values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Coercion {
+    /// Integers or decimals became floats, which can't hold every value
+    LostPrecision,
+    /// Values of another type became text
+    Stringified,
+}
+
+/// A unification of two types of a column that changed what its values
+/// mean, so it isn't made silently.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoercionWarning {
+    pub column: String,
+    pub left: TypeKind,
+    pub right: TypeKind,
+    pub unified: TypeKind,
+    pub coercion: Coercion,
+}
+
+impl CoercionWarning {
+    /// The warning for unifying `left` and `right` as `unified`, if that
+    /// loses anything.
+    pub fn check(column: &str, left: &TypeKind, right: &TypeKind, unified: &TypeKind) -> Option<Self> {
+        use TypeKind::*;
+
+        let loses = |kind: &TypeKind| match (kind, unified) {
+            (Null, _) => false,
+            (kind, Utf8) => kind != &Utf8,
+            (I64 | Decimal(_, _), F32 | F64) | (I32, F32) => true,
+            _ => false,
+        };
+        if !loses(left) && !loses(right) {
+            return None;
+        }
+        let coercion = if unified == &Utf8 { Coercion::Stringified } else { Coercion::LostPrecision };
+        Some(Self {
+            column: column.to_string(),
+            left: left.clone(),
+            right: right.clone(),
+            unified: unified.clone(),
+            coercion,
+        })
+    }
+}
+
+impl std::fmt::Display for CoercionWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Column '{}': {:?} and {:?} unified as {:?}",
+            self.column,
+            self.left.to_arrow_type(),
+            self.right.to_arrow_type(),
+            self.unified.to_arrow_type()
+        )?;
+        match self.coercion {
+            Coercion::LostPrecision => write!(f, "; values may lose precision"),
+            Coercion::Stringified => write!(f, "; values are written as text"),
+        }
+    }
+}
+
+/// A column whose inputs disagree on its type, such as Int32 in one
+/// Parquet file and Int64 in another, and the type they were promoted to.
+/// Each input's values are converted to it as they are read.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TypePromotion {
+    pub column: String,
+    /// Each distinct type of the inputs, in input order
+    pub inputs: Vec<TypeKind>,
+    pub unified: TypeKind,
+}
+
+impl std::fmt::Display for TypePromotion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inputs: Vec<String> = self.inputs.iter().map(|kind| format!("{:?}", kind.to_arrow_type())).collect();
+        write!(
+            f,
+            "Column '{}': {} promoted to {:?}",
+            self.column,
+            inputs.join(", "),
+            self.unified.to_arrow_type()
+        )
+    }
+}
+
+/// The column types of a CSV output, written next to it as
+/// `<output>.schema.json` (`--write-schema-sidecar`) so that loaders can
+/// type the text again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaSidecar {
+    pub columns: Vec<SidecarColumn>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SidecarColumn {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_kind: TypeKind,
+    pub nullable: bool,
+}
+
+impl SchemaSidecar {
+    pub fn new(schema: &Schema) -> Self {
+        let columns = schema
+            .fields
+            .iter()
+            .map(|field| SidecarColumn {
+                name: field.name.clone(),
+                type_kind: TypeKind::from_arrow_type(&field.data_type),
+                nullable: field.is_nullable,
+            })
+            .collect();
+        Self { columns }
+    }
+
+    /// Path of the sidecar for an output file, e.g. `out.csv.schema.json`.
+    pub fn path(output: &Path) -> PathBuf {
+        let mut sidecar = output.as_os_str().to_owned();
+        sidecar.push(".schema.json");
+        PathBuf::from(sidecar)
+    }
+
+    /// Writes the sidecar for `output` to a temporary file in `temp_dir`
+    /// and moves it into place, so a reader never sees half of it. Unless
+    /// `overwrite` is set, an existing sidecar is kept and reported.
+    pub fn write(&self, output: &Path, temp_dir: Option<&Path>, overwrite: bool, fsync: bool) -> Result<()> {
+        let path = Self::path(output);
+        let mut temp = create_temp_file(&temp_dir_for(&path, temp_dir))?;
+        temp.write_all((serde_json::to_string_pretty(self)? + "\n").as_bytes())?;
+        if fsync {
+            temp.as_file().sync_all()?;
+        }
+        let file = persist_temp_file(temp, &path, overwrite)?;
+        if fsync {
+            file.sync_all()?;
+            sync_parent_dir(&path)?;
+        }
+        Ok(())
     }
 }
 
@@ -178,7 +792,32 @@ pub fn widen_types(
         (F32, F64) | (F64, F32) => Ok(F64),
 
         // Date + Datetime -> Datetime
-        (Date, Datetime) | (Datetime, Date) => Ok(Datetime),
+        (Date, Datetime(unit, tz)) | (Datetime(unit, tz), Date) => Ok(Datetime(*unit, tz.clone())),
+
+        // Timestamps keep the finer unit; instants in different zones are
+        // all comparable in UTC, but naive and zoned ones are not
+        (Datetime(left_unit, left_tz), Datetime(right_unit, right_tz))
+            if left_tz.is_some() == right_tz.is_some() =>
+        {
+            let tz = if left_tz == right_tz {
+                left_tz.clone()
+            } else {
+                Some("UTC".to_string())
+            };
+            Ok(Datetime(*left_unit.max(right_unit), tz))
+        }
+
+        // Decimals keep enough integer digits and scale for both sides
+        (Decimal(lp, ls), Decimal(rp, rs)) => {
+            Ok(widen_decimal((lp - ls).max(rp - rs), *ls.max(rs)))
+        }
+        (Decimal(precision, scale), other) | (other, Decimal(precision, scale))
+            if integer_digits(other).is_some() =>
+        {
+            let digits = integer_digits(other).unwrap();
+            Ok(widen_decimal((precision - scale).max(digits), *scale))
+        }
+        (Decimal(_, _), F32 | F64) | (F32 | F64, Decimal(_, _)) => Ok(F64),
 
         // String conflicts
         (Utf8, _) | (_, Utf8) if stringify_conflicts => Ok(Utf8),
@@ -192,6 +831,71 @@ pub fn widen_types(
     }
 }
 
+/// Decimal digits needed for the integer part of any value of an integer type.
+fn integer_digits(kind: &TypeKind) -> Option<usize> {
+    match kind {
+        TypeKind::I8 => Some(3),
+        TypeKind::I16 => Some(5),
+        TypeKind::I32 => Some(10),
+        TypeKind::I64 => Some(19),
+        _ => None,
+    }
+}
+
+/// A decimal with `integer_digits` before the point and `scale` after it, or
+/// Float64 if that needs more precision than a decimal can hold.
+fn widen_decimal(integer_digits: usize, scale: usize) -> TypeKind {
+    let precision = integer_digits + scale;
+    if precision > MAX_DECIMAL_PRECISION {
+        tracing::warn!(
+            "Widened decimal needs precision {}, above the maximum of {}; using Float64 instead",
+            precision,
+            MAX_DECIMAL_PRECISION
+        );
+        return TypeKind::F64;
+    }
+    TypeKind::Decimal(precision, scale)
+}
+
+/// Parses a fixed-point number such as `-12.50` into its unscaled value
+/// (`-1250`), the digits it needs before the point and the digits after it
+/// (its scale). Exponents, NaN and infinities aren't fixed-point, and
+/// neither is a number of more digits than a decimal can hold.
+pub fn parse_decimal(value: &str) -> Option<(i128, usize, usize)> {
+    let unsigned = value.strip_prefix(['-', '+']).unwrap_or(value);
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    if !integer.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let integer = integer.trim_start_matches('0');
+    if integer.len() + fraction.len() > MAX_DECIMAL_PRECISION {
+        return None;
+    }
+    let digits = format!("{}{}", integer, fraction);
+    let unscaled: i128 = if digits.is_empty() { 0 } else { digits.parse().ok()? };
+    let sign = if value.starts_with('-') { -1 } else { 1 };
+    Some((sign * unscaled, integer.len(), fraction.len()))
+}
+
+/// Rescales a decimal's unscaled value from `from_scale` to `scale`, if it
+/// fits in `precision` digits without dropping any nonzero ones.
+pub fn rescale_decimal(value: i128, from_scale: usize, precision: usize, scale: usize) -> Option<i128> {
+    let rescaled = if scale >= from_scale {
+        value.checked_mul(10i128.checked_pow((scale - from_scale) as u32)?)?
+    } else {
+        let divisor = 10i128.checked_pow((from_scale - scale) as u32)?;
+        if value % divisor != 0 {
+            return None;
+        }
+        value / divisor
+    };
+    let limit = 10i128.checked_pow(precision as u32).unwrap_or(i128::MAX);
+    (rescaled.unsigned_abs() < limit.unsigned_abs()).then_some(rescaled)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -203,7 +907,89 @@ mod tests {
         assert_eq!(widen_types(&TypeKind::I32, &TypeKind::I64, false).unwrap(), TypeKind::I64);
         assert_eq!(widen_types(&TypeKind::I32, &TypeKind::F64, false).unwrap(), TypeKind::F64);
         assert_eq!(widen_types(&TypeKind::Bool, &TypeKind::I32, false).unwrap(), TypeKind::I32);
-        assert_eq!(widen_types(&TypeKind::Date, &TypeKind::Datetime, false).unwrap(), TypeKind::Datetime);
+        let datetime = TypeKind::Datetime(TimestampUnit::Millisecond, None);
+        assert_eq!(widen_types(&TypeKind::Date, &datetime, false).unwrap(), datetime);
+    }
+
+    #[test]
+    fn test_decimal_widening() {
+        let decimal = TypeKind::from_arrow_type(&DataType::Decimal(10, 2));
+        assert_eq!(decimal, TypeKind::Decimal(10, 2));
+        assert_eq!(decimal.to_arrow_type(), DataType::Decimal(10, 2));
+
+        assert_eq!(
+            widen_types(&decimal, &TypeKind::Decimal(6, 4), false).unwrap(),
+            TypeKind::Decimal(12, 4)
+        );
+        assert_eq!(
+            widen_types(&TypeKind::I32, &decimal, false).unwrap(),
+            TypeKind::Decimal(12, 2)
+        );
+        assert_eq!(widen_types(&decimal, &TypeKind::F64, false).unwrap(), TypeKind::F64);
+        assert_eq!(
+            widen_types(&TypeKind::Decimal(38, 0), &TypeKind::Decimal(38, 10), false).unwrap(),
+            TypeKind::F64
+        );
+    }
+
+    #[test]
+    fn test_timestamp_unit_and_timezone() {
+        let utc_millis = DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".to_string()));
+        let utc_micros = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".to_string()));
+        let paris_secs = DataType::Timestamp(TimeUnit::Second, Some("Europe/Paris".to_string()));
+        let naive = DataType::Timestamp(TimeUnit::Nanosecond, None);
+
+        let schemas = [
+            Schema::from(vec![Field::new("t", utc_millis, true)]),
+            Schema::from(vec![Field::new("t", utc_micros.clone(), true)]),
+        ];
+        let unified = UnifiedSchema::from_schemas(&schemas, false).unwrap();
+        assert_eq!(unified.schema.fields[0].data_type(), &utc_micros);
+
+        let zoned = TypeKind::from_arrow_type(&utc_micros);
+        assert_eq!(
+            widen_types(&zoned, &TypeKind::from_arrow_type(&paris_secs), false).unwrap(),
+            TypeKind::Datetime(TimestampUnit::Microsecond, Some("UTC".to_string()))
+        );
+        assert!(widen_types(&zoned, &TypeKind::from_arrow_type(&naive), false).is_err());
+    }
+
+    #[test]
+    fn test_from_schemas_keeps_first_seen_order() {
+        let first = Schema::from(vec![
+            Field::new("b", DataType::Int64, true),
+            Field::new("a", DataType::Utf8, true),
+        ]);
+        let second = Schema::from(vec![
+            Field::new("c", DataType::Boolean, true),
+            Field::new("b", DataType::Float64, true),
+        ]);
+
+        let mut unified = UnifiedSchema::from_schemas(&[first, second], false).unwrap();
+        assert_eq!(unified.column_names(), vec!["b", "a", "c"]);
+        assert_eq!(unified.get_column_type("b"), Some(&TypeKind::F64));
+
+        unified.sort_columns();
+        assert_eq!(unified.column_names(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_order_columns() {
+        let schema = Schema::from(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, true),
+            Field::new("c", DataType::Int64, true),
+        ]);
+        let order = vec!["c".to_string(), "a".to_string()];
+
+        let mut unified = UnifiedSchema::from_schemas(std::slice::from_ref(&schema), false).unwrap();
+        unified.order_columns(&order, false).unwrap();
+        assert_eq!(unified.column_names(), ["c", "a", "b"]);
+
+        let mut unified = UnifiedSchema::from_schemas(&[schema], false).unwrap();
+        unified.order_columns(&order, true).unwrap();
+        assert_eq!(unified.column_names(), ["c", "a"]);
+        assert!(unified.order_columns(&["d".to_string()], false).is_err());
     }
 
     #[test]
@@ -211,4 +997,154 @@ mod tests {
         assert_eq!(widen_types(&TypeKind::I32, &TypeKind::Utf8, true).unwrap(), TypeKind::Utf8);
         assert!(widen_types(&TypeKind::I32, &TypeKind::Utf8, false).is_err());
     }
+
+    #[test]
+    fn test_no_widening() {
+        let register = |types: &[DataType]| {
+            let mut accumulator = SchemaAccumulator::new(false).no_widening(true);
+            for (file_index, data_type) in types.iter().enumerate() {
+                accumulator.register(file_index, &Schema::from(vec![Field::new("x", data_type.clone(), true)]))?;
+            }
+            Ok::<_, MawError>(accumulator.finish())
+        };
+
+        // An all-null column takes the other input's type, either way round
+        let unified = register(&[DataType::Null, DataType::Int32, DataType::Null]).unwrap();
+        assert_eq!(unified.schema.fields[0].data_type, DataType::Int32);
+        assert!(register(&[DataType::Int64, DataType::Int64]).is_ok());
+
+        match register(&[DataType::Int32, DataType::Float64]) {
+            Err(MawError::Schema(message)) => assert_eq!(
+                message,
+                "Column 'x' is Float64 in this input but Int32 in another; --no-widening forbids widening it to Float64"
+            ),
+            other => panic!("expected a schema error, got {:?}", other.map(|_| ())),
+        }
+        assert!(register(&[DataType::Int32, DataType::Int64]).is_err());
+    }
+
+    #[test]
+    fn test_preserve_non_null() {
+        let unify = |inputs: &[&[(&str, bool)]], preserve_non_null: bool| {
+            let mut accumulator = SchemaAccumulator::new(false).preserve_non_null(preserve_non_null);
+            for (file_index, fields) in inputs.iter().enumerate() {
+                let fields = fields.iter().map(|(name, nullable)| Field::new(*name, DataType::Int64, *nullable));
+                accumulator.register(file_index, &Schema::from(fields.collect::<Vec<_>>())).unwrap();
+            }
+            let unified = accumulator.finish();
+            unified.schema.fields.iter().map(|f| (f.name.clone(), f.is_nullable)).collect::<HashMap<_, _>>()
+        };
+
+        let first: &[(&str, bool)] = &[("id", false), ("score", false), ("extra", false)];
+        let second: &[(&str, bool)] = &[("id", false), ("score", true)];
+        let nullable = unify(&[first, second], true);
+        assert!(!nullable["id"]);
+        // Nullable in one input, or missing from one
+        assert!(nullable["score"]);
+        assert!(nullable["extra"]);
+
+        assert!(unify(&[first, second], false).values().all(|&nullable| nullable));
+    }
+
+    #[test]
+    fn test_warnings_after_out_of_order_registration() {
+        // Boolean and Decimal don't widen directly, but each widens with
+        // the Float64 registered first
+        let mut accumulator = SchemaAccumulator::new(false);
+        for (file_index, data_type) in [(2, DataType::Float64), (0, DataType::Boolean), (1, DataType::Decimal(10, 2))] {
+            accumulator.register(file_index, &Schema::from(vec![Field::new("x", data_type, true)])).unwrap();
+        }
+
+        let unified = accumulator.finish();
+        assert_eq!(unified.get_column_type("x"), Some(&TypeKind::F64));
+        assert_eq!(unified.warnings.len(), 1);
+        assert_eq!(unified.warnings[0].right, TypeKind::Decimal(10, 2));
+        assert_eq!(unified.warnings[0].unified, TypeKind::F64);
+    }
+
+    #[test]
+    fn test_coercion_warnings() {
+        let first = Schema::from(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("code", DataType::Int64, true),
+        ]);
+        let second = Schema::from(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("code", DataType::Utf8, true),
+        ]);
+
+        let unified = UnifiedSchema::from_schemas(&[first, second], true).unwrap();
+        // Int32 to Int64 loses nothing, so only the conflict is reported
+        assert_eq!(
+            unified.warnings,
+            [CoercionWarning {
+                column: "code".to_string(),
+                left: TypeKind::I64,
+                right: TypeKind::Utf8,
+                unified: TypeKind::Utf8,
+                coercion: Coercion::Stringified,
+            }]
+        );
+        assert_eq!(
+            unified.warnings[0].to_string(),
+            "Column 'code': Int64 and Utf8 unified as Utf8; values are written as text"
+        );
+        // Lossless promotions are still recorded
+        let promoted: Vec<String> = unified.promotions.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            promoted,
+            [
+                "Column 'id': Int32, Int64 promoted to Int64",
+                "Column 'code': Int64, Utf8 promoted to Utf8"
+            ]
+        );
+
+        let decimal = Schema::from(vec![Field::new("price", DataType::Decimal(10, 2), true)]);
+        let float = Schema::from(vec![Field::new("price", DataType::Float64, true)]);
+        let unified = UnifiedSchema::from_schemas(&[decimal, float], false).unwrap();
+        assert_eq!(unified.warnings[0].coercion, Coercion::LostPrecision);
+    }
+
+    #[test]
+    fn test_concurrent_accumulator_is_deterministic() {
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+
+        let schemas: Vec<Schema> = (0..32)
+            .map(|i| {
+                let value_type = if i % 2 == 0 { DataType::Int32 } else { DataType::Float64 };
+                Schema::from(vec![
+                    Field::new(format!("only_{}", i), DataType::Utf8, true),
+                    Field::new("value", value_type, true),
+                    Field::new("id", DataType::Int64, true),
+                ])
+            })
+            .collect();
+        let expected = UnifiedSchema::from_schemas(&schemas, false).unwrap();
+
+        for _ in 0..10 {
+            let accumulator = Arc::new(Mutex::new(SchemaAccumulator::new(false)));
+            let handles: Vec<_> = schemas
+                .iter()
+                .cloned()
+                .enumerate()
+                .rev()
+                .map(|(i, schema)| {
+                    let accumulator = Arc::clone(&accumulator);
+                    thread::spawn(move || accumulator.lock().unwrap().register(i, &schema).unwrap())
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+
+            let accumulator = Arc::try_unwrap(accumulator).unwrap().into_inner().unwrap();
+            let unified = accumulator.finish();
+            assert_eq!(unified.column_names(), expected.column_names());
+            assert_eq!(unified.schema.fields, expected.schema.fields);
+            assert_eq!(unified.warnings, expected.warnings);
+            assert_eq!(unified.column_names()[..3], ["only_0", "value", "id"]);
+            assert_eq!(unified.get_column_type("value"), Some(&TypeKind::F64));
+        }
+    }
 }
@@ -0,0 +1,166 @@
+use crate::{
+    coercion::is_nested,
+    error::Result,
+    schema::TypeKind,
+};
+use arrow2::datatypes::{DataType, Field, Schema};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    time::SystemTime,
+};
+use tracing::debug;
+
+/// Sampled schemas from earlier runs (`--schema-cache`), so files that
+/// haven't changed aren't sampled again. An entry is used only while its
+/// file's size and modification time match, and the whole cache is dropped
+/// when it was written with different sampling options.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaCache {
+    /// Fingerprint of the options that affect what sampling finds
+    options: String,
+    files: HashMap<String, CachedSchema>,
+    #[serde(skip)]
+    hits: usize,
+    #[serde(skip)]
+    misses: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedSchema {
+    size: u64,
+    modified: SystemTime,
+    columns: Vec<CachedColumn>,
+}
+
+/// What unification uses of a sampled column: its name, its type kind,
+/// whether it is nested, which `--nested` decides the fate of, and whether
+/// it is declared non-nullable, for `--preserve-non-null`.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedColumn {
+    name: String,
+    kind: TypeKind,
+    nested: bool,
+    /// Missing from caches written before it was recorded, which are read
+    /// as nullable
+    #[serde(default)]
+    not_null: bool,
+}
+
+impl SchemaCache {
+    pub fn new(options: String) -> Self {
+        Self {
+            options,
+            files: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Reads the cache at `path`. A missing or unreadable cache, or one
+    /// written with other options, gives an empty one.
+    pub fn load(path: &Path, options: String) -> Self {
+        let loaded = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<SchemaCache>(&content).ok());
+        match loaded {
+            Some(cache) if cache.options == options => cache,
+            Some(_) => {
+                debug!("Sampling options changed; discarding schema cache {}", path.display());
+                Self::new(options)
+            }
+            None => Self::new(options),
+        }
+    }
+
+    /// The cached schema of the file at `path`, if it hasn't changed since
+    /// it was sampled.
+    pub fn get(&mut self, path: &Path) -> Option<Schema> {
+        let cached = self
+            .files
+            .get(&cache_key(path))
+            .filter(|cached| file_stamp(path) == Some((cached.size, cached.modified)));
+        let Some(cached) = cached else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+
+        let fields = cached
+            .columns
+            .iter()
+            .map(|column| {
+                // Only the nesting matters, as nested columns unify as text
+                let data_type = if column.nested {
+                    DataType::List(Box::new(Field::new("item", DataType::Null, true)))
+                } else {
+                    column.kind.to_arrow_type()
+                };
+                Field::new(&column.name, data_type, !column.not_null)
+            })
+            .collect::<Vec<_>>();
+        Some(Schema::from(fields))
+    }
+
+    /// Records the schema sampled from the file at `path`.
+    pub fn insert(&mut self, path: &Path, schema: &Schema) {
+        let Some((size, modified)) = file_stamp(path) else {
+            return;
+        };
+        let columns = schema
+            .fields
+            .iter()
+            .map(|field| CachedColumn {
+                name: field.name.clone(),
+                kind: TypeKind::from_arrow_type(field.data_type()),
+                nested: is_nested(field.data_type()),
+                not_null: !field.is_nullable,
+            })
+            .collect();
+        self.files.insert(
+            cache_key(path),
+            CachedSchema {
+                size,
+                modified,
+                columns,
+            },
+        );
+    }
+
+    /// Writes the cache beside `path` and renames it into place, like the
+    /// state file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, content)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+/// Entries are keyed by absolute path, so runs from other directories
+/// share them.
+fn cache_key(path: &Path) -> String {
+    fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// A file's size and modification time, which together stand in for its
+/// contents.
+fn file_stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
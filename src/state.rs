@@ -1,11 +1,13 @@
-use crate::error::Result;
+use crate::error::{MawError, Result};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     fs,
     path::Path,
-    time::SystemTime,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
+use tokio::task::JoinHandle;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileState {
@@ -19,6 +21,31 @@ pub struct FileState {
     pub last_modified: SystemTime,
 }
 
+/// How far a reader has got through one input. Readers send it to the
+/// writer behind the rows it covers, and the writer applies it to the
+/// state once those rows are durably written, so a killed run never
+/// records rows that didn't reach the output.
+#[derive(Debug, Clone)]
+pub enum InputProgress {
+    /// Row groups before `end` of a Parquet input were read, `rows` rows
+    /// since the last update
+    RowGroups { path: String, end: usize, rows: u64 },
+    /// A CSV input was read up to the record starting at byte `offset`,
+    /// `rows` rows since the last update
+    Offset { path: String, offset: u64, rows: u64 },
+    /// The whole input was read, `rows` rows since the last update
+    Done { path: String, size: u64, rows: u64 },
+}
+
+/// Where a resumed run picks an input up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResumePoint {
+    /// Parquet row groups to skip
+    pub row_group: usize,
+    /// Bytes of a CSV input to skip
+    pub offset: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessingState {
     pub version: String,
@@ -50,6 +77,7 @@ impl ProcessingState {
     }
 
     pub fn add_file(&mut self, path: String, format: String, size: u64) {
+        let last_modified = modified_time(&path).unwrap_or_else(SystemTime::now);
         let file_state = FileState {
             path: path.clone(),
             format,
@@ -58,7 +86,7 @@ impl ProcessingState {
             last_row_group: None,
             bytes_processed: 0,
             rows_processed: 0,
-            last_modified: SystemTime::now(),
+            last_modified,
         };
         
         self.files.insert(path, file_state);
@@ -86,6 +114,44 @@ impl ProcessingState {
         self.updated_at = SystemTime::now();
     }
 
+    /// Records that row group `row_group` of a Parquet input, holding
+    /// `rows` rows, has been fully written.
+    pub fn record_row_group(&mut self, path: &str, row_group: usize, rows: u64) {
+        if let Some(file_state) = self.files.get_mut(path) {
+            file_state.last_row_group = Some(row_group);
+            file_state.rows_processed += rows;
+        }
+        self.updated_at = SystemTime::now();
+    }
+
+    /// Applies progress whose rows the writer has durably written.
+    pub fn apply(&mut self, progress: InputProgress) {
+        match progress {
+            InputProgress::RowGroups { path, end, rows } => self.record_row_group(&path, end - 1, rows),
+            InputProgress::Offset { path, offset, rows } => {
+                self.update_file_progress(&path, offset, None);
+                if let Some(file_state) = self.files.get_mut(&path) {
+                    file_state.rows_processed += rows;
+                }
+            }
+            InputProgress::Done { path, size, rows } => {
+                let rows = self.files.get(&path).map_or(0, |f| f.rows_processed) + rows;
+                self.mark_file_processed(&path, size, rows);
+            }
+        }
+    }
+
+    /// Where a resumed run picks `path` up: after the last row group
+    /// written from a Parquet input, or at the last offset written from a
+    /// CSV input.
+    pub fn resume_point(&self, path: &str) -> ResumePoint {
+        let (offset, row_group) = self.get_resume_point(path).unwrap_or((0, None));
+        ResumePoint {
+            row_group: row_group.map_or(0, |row_group| row_group + 1),
+            offset,
+        }
+    }
+
     pub fn is_file_processed(&self, path: &str) -> bool {
         self.files.get(path)
             .map(|f| f.processed)
@@ -101,6 +167,37 @@ impl ProcessingState {
             .map(|f| (f.last_offset.unwrap_or(0), f.last_row_group))
     }
 
+    /// Checks that this state was written by a compatible version and
+    /// adopts the current version. Versions are compatible when their
+    /// major (or, before 1.0, minor) numbers match.
+    pub fn check_version(&mut self) -> Result<()> {
+        let current = env!("CARGO_PKG_VERSION");
+        if self.version == current {
+            return Ok(());
+        }
+
+        if compatibility_key(&self.version) != compatibility_key(current) {
+            return Err(MawError::State(format!(
+                "State file was written by maw v{}, which is incompatible with v{}",
+                self.version, current
+            )));
+        }
+
+        tracing::info!("Migrating state file from v{} to v{}", self.version, current);
+        self.version = current.to_string();
+        Ok(())
+    }
+
+    /// The recorded files whose modification time no longer matches, so
+    /// rows already written from them may be stale.
+    pub fn changed_files(&self) -> Vec<String> {
+        self.files
+            .values()
+            .filter(|f| modified_time(&f.path).is_some_and(|modified| modified != f.last_modified))
+            .map(|f| f.path.clone())
+            .collect()
+    }
+
     pub fn is_complete(&self) -> bool {
         self.processed_files == self.total_files
     }
@@ -114,6 +211,21 @@ impl ProcessingState {
     }
 }
 
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn compatibility_key(version: &str) -> (u64, u64) {
+    let mut parts = version.split('.').map(|p| p.parse::<u64>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+    if major == 0 {
+        (0, minor)
+    } else {
+        (major, 0)
+    }
+}
+
 pub struct StateManager {
     state_path: Option<String>,
     state: Option<ProcessingState>,
@@ -139,10 +251,14 @@ impl StateManager {
         Ok(None)
     }
 
+    /// Writes the state file. It is written beside the target and renamed
+    /// over it, so a run killed mid-save still leaves the previous state.
     pub fn save_state(&mut self, state: &ProcessingState) -> Result<()> {
         if let Some(path) = &self.state_path {
             let content = serde_json::to_string_pretty(state)?;
-            fs::write(path, content)?;
+            let temp_path = format!("{}.tmp", path);
+            fs::write(&temp_path, content)?;
+            fs::rename(&temp_path, path)?;
             self.state = Some(state.clone());
         }
         Ok(())
@@ -168,6 +284,35 @@ impl StateManager {
     }
 }
 
+/// Spawns a task that saves a snapshot of `state` to `path` every
+/// `interval` (`--checkpoint-interval`), so a run that is killed can still
+/// be resumed from recent progress. Abort the handle to stop it.
+pub fn spawn_checkpoint(
+    state: Arc<Mutex<ProcessingState>>,
+    path: String,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut manager = StateManager::new(Some(path));
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately
+        ticker.tick().await;
+        let mut last_saved = None;
+
+        loop {
+            ticker.tick().await;
+            let snapshot = state.lock().unwrap().clone();
+            if last_saved == Some(snapshot.updated_at) {
+                continue;
+            }
+            match manager.save_state(&snapshot) {
+                Ok(()) => last_saved = Some(snapshot.updated_at),
+                Err(e) => tracing::warn!("Failed to checkpoint state: {}", e),
+            }
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,6 +339,67 @@ mod tests {
         assert_eq!(state.get_progress_percentage(), 100.0);
     }
 
+    #[test]
+    fn test_record_row_group() {
+        let mut state = ProcessingState::new("output.csv".to_string(), "csv".to_string());
+        state.add_file("file1.parquet".to_string(), "parquet".to_string(), 1000);
+        assert_eq!(state.resume_point("file1.parquet").row_group, 0);
+
+        state.record_row_group("file1.parquet", 0, 10);
+        state.record_row_group("file1.parquet", 1, 5);
+        assert_eq!(state.resume_point("file1.parquet").row_group, 2);
+        assert_eq!(state.get_file_state("file1.parquet").unwrap().rows_processed, 15);
+        assert_eq!(state.resume_point("missing.parquet").row_group, 0);
+    }
+
+    #[test]
+    fn test_apply_progress() {
+        let mut state = ProcessingState::new("output.csv".to_string(), "csv".to_string());
+        state.add_file("file1.csv".to_string(), "csv".to_string(), 100);
+
+        state.apply(InputProgress::Offset { path: "file1.csv".to_string(), offset: 40, rows: 3 });
+        state.apply(InputProgress::Offset { path: "file1.csv".to_string(), offset: 70, rows: 2 });
+        assert_eq!(state.resume_point("file1.csv"), ResumePoint { row_group: 0, offset: 70 });
+        assert!(!state.is_file_processed("file1.csv"));
+
+        state.apply(InputProgress::Done { path: "file1.csv".to_string(), size: 100, rows: 1 });
+        assert!(state.is_file_processed("file1.csv"));
+        assert_eq!(state.get_file_state("file1.csv").unwrap().rows_processed, 6);
+    }
+
+    #[test]
+    fn test_changed_files() {
+        let temp_dir = tempdir().unwrap();
+        let input = temp_dir.path().join("file1.csv");
+        fs::write(&input, "a\n1\n").unwrap();
+        let path = input.to_string_lossy().to_string();
+
+        let mut state = ProcessingState::new("output.csv".to_string(), "csv".to_string());
+        state.add_file(path.clone(), "csv".to_string(), 4);
+        state.mark_file_processed(&path, 4, 1);
+        assert!(state.changed_files().is_empty());
+
+        let file = fs::File::options().write(true).open(&input).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(60)).unwrap();
+
+        assert_eq!(state.changed_files(), vec![path.clone()]);
+    }
+
+    #[test]
+    fn test_check_version() {
+        let mut state = ProcessingState::new("output.csv".to_string(), "csv".to_string());
+        state.check_version().unwrap();
+
+        state.version = "999.0.0".to_string();
+        assert!(matches!(state.check_version(), Err(MawError::State(_))));
+
+        let current = env!("CARGO_PKG_VERSION");
+        let (major, minor) = compatibility_key(current);
+        state.version = format!("{}.{}.999", major, minor);
+        state.check_version().unwrap();
+        assert_eq!(state.version, current);
+    }
+
     #[test]
     fn test_state_manager() {
         let temp_dir = tempdir().unwrap();
@@ -208,4 +414,43 @@ mod tests {
         assert_eq!(loaded.output_path, "output.csv");
         assert_eq!(loaded.output_format, "csv");
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_checkpoint() {
+        let temp_dir = tempdir().unwrap();
+        let state_path = temp_dir.path().join("state.json");
+        let mut state = ProcessingState::new("output.csv".to_string(), "csv".to_string());
+        state.add_file("file1.csv".to_string(), "csv".to_string(), 1000);
+        let state = Arc::new(Mutex::new(state));
+
+        let handle = spawn_checkpoint(
+            state.clone(),
+            state_path.to_string_lossy().to_string(),
+            Duration::from_secs(10),
+        );
+        let read_saved = || {
+            let content = fs::read_to_string(&state_path).unwrap();
+            serde_json::from_str::<ProcessingState>(&content).unwrap()
+        };
+        // With the clock paused, a sleep only ends once every tick due
+        // before it has been handled
+        let sleep = |secs| tokio::time::sleep(Duration::from_secs(secs));
+
+        state.lock().unwrap().update_file_progress("file1.csv", 200, None);
+        sleep(5).await;
+        assert!(!state_path.exists());
+        sleep(10).await;
+        assert_eq!(read_saved().get_resume_point("file1.csv"), Some((200, None)));
+
+        // Nothing is saved again until the state changes
+        fs::remove_file(&state_path).unwrap();
+        sleep(10).await;
+        assert!(!state_path.exists());
+
+        state.lock().unwrap().update_file_progress("file1.csv", 600, None);
+        sleep(10).await;
+        assert_eq!(read_saved().get_resume_point("file1.csv"), Some((600, None)));
+
+        handle.abort();
+    }
 }
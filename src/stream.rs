@@ -0,0 +1,966 @@
+use crate::{
+    cli::{Cli, CoerceErrors, EmptyAs, Nested},
+    coercion::{is_nested, BatchAligner},
+    csv_in::{parse_delimiter_override, parse_encoding_override, CsvConfig, InferColumns},
+    discover::{discover_inputs, normalize_path, DiscoveryConfig, InputFile},
+    error::{MawError, Result},
+    expr::Computed,
+    reader::InputReader,
+    schema::{ColumnCase, ColumnRenames, SchemaAccumulator, TypeKind, UnifiedSchema},
+    schema_cache::SchemaCache,
+    timezone::Zone,
+    transform::approximate_bytes,
+};
+use arrow2::{
+    array::{Array, Utf8Array},
+    chunk::Chunk,
+    datatypes::{DataType, Schema},
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Everything needed to turn a list of inputs into aligned, schema-unified
+/// chunks: discovery, CSV parsing and column selection options.
+pub struct StreamBuilder {
+    inputs: Vec<String>,
+    discovery: DiscoveryConfig,
+    csv: CsvConfig,
+    batch_size: usize,
+    infer_rows: usize,
+    include_columns: Option<Vec<String>>,
+    exclude_columns: Option<Vec<String>>,
+    renames: ColumnRenames,
+    reorder: bool,
+    column_order: Option<(Vec<String>, bool)>,
+    stringify_conflicts: bool,
+    no_widening: bool,
+    preserve_non_null: bool,
+    coerce_errors: CoerceErrors,
+    nested: Nested,
+    continue_on_error: bool,
+    schema_cache: Option<PathBuf>,
+    fills: Vec<(String, String)>,
+    computed: Vec<Computed>,
+    tz_convert: Option<String>,
+    assume_tz: Option<String>,
+    mem_budget: Option<usize>,
+    dictionary_strings: bool,
+    source_column: Option<String>,
+    relative_to: Option<PathBuf>,
+    drop_null_columns: Option<f64>,
+}
+
+/// The unified schema of a set of inputs. Sampling stdin consumes its first
+/// batch, so that reader is handed back for the data pass to continue from.
+pub struct Unified {
+    pub schema: Arc<UnifiedSchema>,
+    pub stdin_reader: Option<InputReader>,
+    /// Inputs that couldn't be sampled and are left out, when continuing on
+    /// errors
+    pub failed: Vec<PathBuf>,
+    /// Each sampled input's own schema, after renames, in input order
+    pub samples: Vec<(PathBuf, Schema)>,
+}
+
+impl StreamBuilder {
+    pub fn new<S: Into<String>>(inputs: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            inputs: inputs.into_iter().map(Into::into).collect(),
+            discovery: DiscoveryConfig::default(),
+            csv: CsvConfig::default(),
+            batch_size: 64_000,
+            infer_rows: 1000,
+            include_columns: None,
+            exclude_columns: None,
+            renames: ColumnRenames::default(),
+            reorder: false,
+            column_order: None,
+            stringify_conflicts: false,
+            no_widening: false,
+            preserve_non_null: false,
+            coerce_errors: CoerceErrors::default(),
+            nested: Nested::default(),
+            continue_on_error: false,
+            schema_cache: None,
+            fills: Vec::new(),
+            computed: Vec::new(),
+            tz_convert: None,
+            assume_tz: None,
+            mem_budget: None,
+            dictionary_strings: false,
+            source_column: None,
+            relative_to: None,
+            drop_null_columns: None,
+        }
+    }
+
+    /// Builds the stream options from parsed command line arguments.
+    pub fn from_cli(cli: &Cli) -> Result<Self> {
+        let csv = CsvConfig {
+            delimiter: cli.delimiter.map(|c| c as u8),
+            delimiter_overrides: cli
+                .delimiter_for
+                .iter()
+                .map(|spec| parse_delimiter_override(spec))
+                .collect::<Result<_>>()?,
+            quote: cli.quote.map(|c| c as u8),
+            has_headers: !cli.no_headers,
+            detect_header: cli.detect_header,
+            encoding: cli.encoding.clone(),
+            encoding_overrides: cli
+                .encoding_for
+                .iter()
+                .map(|spec| parse_encoding_override(spec))
+                .collect::<Result<_>>()?,
+            na_values: cli.na.split(',').map(|s| s.to_string()).collect(),
+            strip_bom: cli.strip_bom,
+            empty_as_null: matches!(cli.empty_as, EmptyAs::Null),
+            nan_token: cli.nan_as.clone(),
+            inf_token: cli.inf_as.clone(),
+            non_finite_as_null: cli.non_finite_as_null,
+            infer_types: !cli.no_type_inference,
+            infer_columns: cli.infer_columns.as_deref().map(InferColumns::parse),
+            infer_decimal: cli.infer_decimal,
+            decimal_columns: cli.decimal_columns.as_deref().map(split_list).unwrap_or_default(),
+            max_columns: cli.max_columns,
+            validate_field_count: cli.validate_field_count,
+            ragged: cli.ragged,
+            read_buffer: cli.read_buffer.map(|mb| mb * 1024 * 1024),
+            ..CsvConfig::default()
+        };
+
+        let discovery = DiscoveryConfig {
+            recursive: !cli.no_recursive,
+            follow_symlinks: cli.follow_symlinks,
+            max_depth: None,
+            max_files: cli.first_n_files,
+            stdin_format: cli.stdin_format.into(),
+            include_hidden: cli.include_hidden,
+        };
+
+        if cli.batch_size == 0 {
+            return Err(MawError::Config("--batch-size must be at least 1".to_string()));
+        }
+
+        let mut builder = Self::new(cli.inputs.clone())
+            .discovery_config(discovery)
+            .csv_config(csv)
+            .batch_size(cli.batch_size)
+            .infer_rows(cli.infer_rows)
+            .reorder(cli.reorder)
+            .stringify_conflicts(cli.stringify_conflicts)
+            .no_widening(cli.no_widening)
+            .preserve_non_null(cli.preserve_non_null)
+            .coerce_errors(cli.coerce_errors)
+            .nested(cli.nested)
+            .continue_on_error(cli.continue_on_error)
+            .dictionary_strings(cli.dictionary_strings)
+            .mem_budget(cli.mem_budget * 1024 * 1024);
+
+        if let Some(path) = &cli.schema_cache {
+            builder = builder.schema_cache(path.clone());
+        }
+        if let Some(column) = &cli.source_column {
+            builder = builder.source_column(column.clone());
+        }
+        if let Some(dir) = &cli.relative_to {
+            builder = builder.relative_to(dir)?;
+        }
+        if let Some(threshold) = cli.drop_null_columns {
+            builder = builder.drop_null_columns(threshold)?;
+        }
+        if let Some(columns) = &cli.columns {
+            builder = builder.columns(split_list(columns));
+        }
+        if let Some(exclude) = &cli.exclude {
+            builder = builder.exclude(split_list(exclude));
+        }
+        if let Some(order) = &cli.column_order {
+            builder = builder.column_order(split_list(order), cli.drop_unlisted);
+        }
+        for rename in &cli.rename {
+            let (from, to) = rename.split_once('=').ok_or_else(|| {
+                MawError::Config(format!("Invalid --rename '{}', expected old=new", rename))
+            })?;
+            builder = builder.rename(from.trim(), to.trim());
+        }
+        for spec in &cli.rename_regex {
+            builder = builder.rename_regex(spec)?;
+        }
+        for fill in cli.fill.iter().flat_map(|list| split_list(list)) {
+            let (column, value) = fill.split_once('=').ok_or_else(|| {
+                MawError::Config(format!("Invalid --fill '{}', expected column=value", fill))
+            })?;
+            builder = builder.fill(column.trim(), value);
+        }
+        for spec in &cli.compute {
+            builder = builder.compute(spec)?;
+        }
+        if let Some(tz) = &cli.tz_convert {
+            builder = builder.tz_convert(tz)?;
+        }
+        if let Some(tz) = &cli.assume_tz {
+            builder = builder.assume_tz(tz)?;
+        }
+        if cli.lowercase_columns {
+            builder = builder.column_case(ColumnCase::Lower);
+        } else if cli.uppercase_columns {
+            builder = builder.column_case(ColumnCase::Upper);
+        }
+
+        Ok(builder)
+    }
+
+    /// Replaces the inputs to read.
+    pub fn inputs<S: Into<String>>(mut self, inputs: impl IntoIterator<Item = S>) -> Self {
+        self.inputs = inputs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets rows the readers can't read aside for a rejects file rather
+    /// than failing on them. Only a pipeline that writes the rejects file
+    /// should turn this on.
+    pub fn reject_rows(mut self, reject: bool) -> Self {
+        self.csv.reject_rows = reject;
+        self
+    }
+
+    pub fn discovery_config(mut self, config: DiscoveryConfig) -> Self {
+        self.discovery = config;
+        self
+    }
+
+    pub fn csv_config(mut self, config: CsvConfig) -> Self {
+        self.csv = config;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn infer_rows(mut self, infer_rows: usize) -> Self {
+        self.infer_rows = infer_rows;
+        self
+    }
+
+    pub fn columns(mut self, columns: Vec<String>) -> Self {
+        self.include_columns = Some(columns);
+        self
+    }
+
+    pub fn exclude(mut self, columns: Vec<String>) -> Self {
+        self.exclude_columns = Some(columns);
+        self
+    }
+
+    pub fn rename(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.renames.insert(from, to);
+        self
+    }
+
+    /// Adds a sed-style substitution (`s/^col_//`) applied to every column
+    /// name after the literal renames.
+    pub fn rename_regex(mut self, spec: &str) -> Result<Self> {
+        self.renames.add_substitution(spec)?;
+        Ok(self)
+    }
+
+    /// Converts every column name to one case, after the renames.
+    pub fn column_case(mut self, case: ColumnCase) -> Self {
+        self.renames.set_case(case);
+        self
+    }
+
+    /// Replaces nulls in `column` (named after renames) with `value`,
+    /// which must parse as the column's unified type.
+    pub fn fill(mut self, column: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fills.push((column.into(), value.into()));
+        self
+    }
+
+    /// Adds a column computed per row from an arithmetic expression over
+    /// numeric columns (named after renames), as `name = expression`.
+    pub fn compute(mut self, spec: &str) -> Result<Self> {
+        self.computed.push(Computed::parse(spec)?);
+        Ok(self)
+    }
+
+    /// Relabels timestamp columns that have a timezone with `tz`. Their
+    /// values are UTC instants, so only how they're shown changes.
+    pub fn tz_convert(mut self, tz: &str) -> Result<Self> {
+        Zone::parse(tz)?;
+        self.tz_convert = Some(tz.to_string());
+        Ok(self)
+    }
+
+    /// Reads timestamps without a timezone as wall-clock times in `tz`,
+    /// converting them to UTC instants.
+    pub fn assume_tz(mut self, tz: &str) -> Result<Self> {
+        Zone::parse(tz)?;
+        self.assume_tz = Some(tz.to_string());
+        Ok(self)
+    }
+
+    pub fn reorder(mut self, reorder: bool) -> Self {
+        self.reorder = reorder;
+        self
+    }
+
+    /// Puts `columns` first, in order, with the rest following unless
+    /// `drop_unlisted`. Names refer to columns after renames.
+    pub fn column_order(mut self, columns: Vec<String>, drop_unlisted: bool) -> Self {
+        self.column_order = Some((columns, drop_unlisted));
+        self
+    }
+
+    pub fn stringify_conflicts(mut self, stringify_conflicts: bool) -> Self {
+        self.stringify_conflicts = stringify_conflicts;
+        self
+    }
+
+    /// Fails if a column's type differs between inputs, rather than
+    /// widening it; a column that is all null in some inputs is still fine.
+    pub fn no_widening(mut self, no_widening: bool) -> Self {
+        self.no_widening = no_widening;
+        self
+    }
+
+    /// Keeps columns that every input declares non-nullable so in the
+    /// unified schema; otherwise every column is nullable.
+    pub fn preserve_non_null(mut self, preserve_non_null: bool) -> Self {
+        self.preserve_non_null = preserve_non_null;
+        self
+    }
+
+    /// Dictionary encodes text columns whose sampled values repeat, in
+    /// every text input that has them. Parquet inputs and schemas from the
+    /// schema cache have no sample to judge by and don't count.
+    pub fn dictionary_strings(mut self, dictionary_strings: bool) -> Self {
+        self.dictionary_strings = dictionary_strings;
+        self
+    }
+
+    /// Leaves out columns that are null or missing in more than `threshold`
+    /// (a fraction from 0 to 1) of the sampled rows. Like
+    /// `dictionary_strings`, only text inputs sampled this run count, so
+    /// the schema cache isn't used.
+    pub fn drop_null_columns(mut self, threshold: f64) -> Result<Self> {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(MawError::Config(format!(
+                "--drop-null-columns must be a fraction from 0 to 1, not {}",
+                threshold
+            )));
+        }
+        self.drop_null_columns = Some(threshold);
+        Ok(self)
+    }
+
+    /// Adds a text column of this name holding the path of the input each
+    /// row came from.
+    pub fn source_column(mut self, column: String) -> Self {
+        self.source_column = Some(column);
+        self
+    }
+
+    /// Writes source column paths relative to `dir`. Inputs outside it keep
+    /// their absolute path. Fails if `dir` doesn't exist.
+    pub fn relative_to(mut self, dir: &Path) -> Result<Self> {
+        let dir = dir.canonicalize().map_err(|e| {
+            MawError::Config(format!("--relative-to {}: {}", dir.display(), e))
+        })?;
+        self.relative_to = Some(normalize_path(&dir));
+        Ok(self)
+    }
+
+    pub fn coerce_errors(mut self, coerce_errors: CoerceErrors) -> Self {
+        self.coerce_errors = coerce_errors;
+        self
+    }
+
+    /// How nested columns are handled. They are written as JSON text by
+    /// default; the other policies apply while sampling schemas.
+    pub fn nested(mut self, nested: Nested) -> Self {
+        self.nested = nested;
+        self
+    }
+
+    pub fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    /// Caps how many bytes `collect_table` may hold in memory.
+    pub fn mem_budget(mut self, bytes: usize) -> Self {
+        self.mem_budget = Some(bytes);
+        self
+    }
+
+    /// Reuses schemas sampled by earlier runs from files that haven't
+    /// changed, and records the rest, in the cache file at `path`.
+    pub fn schema_cache(mut self, path: PathBuf) -> Self {
+        self.schema_cache = Some(path);
+        self
+    }
+
+    pub fn get_csv_config(&self) -> &CsvConfig {
+        &self.csv
+    }
+
+    pub fn get_computed(&self) -> &[Computed] {
+        &self.computed
+    }
+
+    pub fn get_batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub fn discover(&self) -> Result<Vec<InputFile>> {
+        discover_inputs(&self.inputs, &self.discovery)
+    }
+
+    /// Samples every input and widens their schemas into one. Files are
+    /// sampled by a pool of threads that each register the columns they find
+    /// with a shared accumulator; stdin is sampled on the calling thread so
+    /// its reader can be handed back.
+    pub fn unify(&self, files: &[InputFile]) -> Result<Unified> {
+        let accumulator = Arc::new(Mutex::new(
+            SchemaAccumulator::new(self.stringify_conflicts)
+                .no_widening(self.no_widening)
+                .preserve_non_null(self.preserve_non_null),
+        ));
+        let cache = self
+            .schema_cache
+            .as_ref()
+            .map(|path| Mutex::new(SchemaCache::load(path, self.sampling_options())));
+        let mut stdin_reader = None;
+        let mut samples: Vec<(usize, Schema)> = Vec::new();
+        let mut errors: Vec<(usize, MawError)> = Vec::new();
+
+        let is_stdin = |file: &InputFile| file.path.to_string_lossy() == "-";
+        let next_file = AtomicUsize::new(0);
+        let workers = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(files.len());
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    let accumulator = Arc::clone(&accumulator);
+                    let next_file = &next_file;
+                    let cache = cache.as_ref();
+                    scope.spawn(move || {
+                        let mut samples = Vec::new();
+                        let mut errors = Vec::new();
+                        loop {
+                            let index = next_file.fetch_add(1, Ordering::Relaxed);
+                            let Some(file) = files.get(index) else {
+                                return (samples, errors);
+                            };
+                            if is_stdin(file) {
+                                continue;
+                            }
+                            match self.register_file(index, file, &accumulator, cache) {
+                                Ok(schema) => samples.push((index, schema)),
+                                Err(e) => {
+                                    errors.push((index, e));
+                                    if !self.continue_on_error {
+                                        return (samples, errors);
+                                    }
+                                }
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            for (index, file) in files.iter().enumerate() {
+                if is_stdin(file) {
+                    match self.sample_stdin(index, file, &accumulator) {
+                        Ok((reader, schema)) => {
+                            stdin_reader = Some(reader);
+                            samples.push((index, schema));
+                        }
+                        Err(e) => errors.push((index, e)),
+                    }
+                }
+            }
+
+            for handle in handles {
+                let (sampled, failed) = handle.join().expect("schema sampling thread panicked");
+                samples.extend(sampled);
+                errors.extend(failed);
+            }
+        });
+
+        if let (Some(cache), Some(path)) = (cache, &self.schema_cache) {
+            let cache = cache
+                .into_inner()
+                .map_err(|_| MawError::Schema("Schema cache was poisoned".to_string()))?;
+            tracing::info!("Schema cache: {} hits, {} misses", cache.hits(), cache.misses());
+            // The cache only saves work, so failing to update it doesn't fail the run
+            if let Err(e) = cache.save(path) {
+                tracing::warn!("Failed to write schema cache {}: {}", path.display(), e);
+            }
+        }
+
+        errors.sort_by_key(|(index, _)| *index);
+        let mut failed = Vec::new();
+        for (index, error) in errors {
+            // Report the error of the earliest failing input, like a sequential pass
+            if !self.continue_on_error {
+                return Err(error);
+            }
+            tracing::warn!("Skipping input that failed: {}", error);
+            failed.push(files[index].path.clone());
+        }
+
+        let accumulator = Arc::try_unwrap(accumulator)
+            .expect("schema sampling threads have finished")
+            .into_inner()
+            .map_err(|_| MawError::Schema("Schema accumulator was poisoned".to_string()))?;
+        let mut unified = accumulator.finish();
+        unified.column_mapping = self.renames.clone();
+        if self.csv.infer_decimal || !self.csv.decimal_columns.is_empty() {
+            unified.widen_decimal_precision();
+        }
+        if let Some(threshold) = self.drop_null_columns {
+            unified.drop_null_columns(threshold);
+            if !unified.dropped_columns.is_empty() {
+                tracing::warn!(
+                    "Dropping columns that are mostly null: {}",
+                    unified.dropped_columns.join(", ")
+                );
+            }
+        }
+        if let Some(column) = &self.source_column {
+            unified.add_column(column, TypeKind::Utf8)?;
+        }
+        for computed in &self.computed {
+            let result = computed.result_type(&|column| unified.get_column_type(column).cloned())?;
+            unified.add_column(&computed.name, result.type_kind())?;
+        }
+        if self.reorder {
+            unified.sort_columns();
+        }
+        if let Some((order, drop_unlisted)) = &self.column_order {
+            unified.order_columns(order, *drop_unlisted)?;
+        }
+
+        samples.sort_by_key(|(index, _)| *index);
+        Ok(Unified {
+            schema: Arc::new(unified),
+            stdin_reader,
+            failed,
+            samples: samples
+                .into_iter()
+                .map(|(index, schema)| (files[index].path.clone(), schema))
+                .collect(),
+        })
+    }
+
+    fn register_file(
+        &self,
+        index: usize,
+        file: &InputFile,
+        accumulator: &Mutex<SchemaAccumulator>,
+        cache: Option<&Mutex<SchemaCache>>,
+    ) -> Result<Schema> {
+        let path = file.path.to_string_lossy();
+        let cached = cache
+            .filter(|_| self.drop_null_columns.is_none())
+            .and_then(|cache| cache.lock().ok()?.get(&file.path));
+        let mut categorical = Vec::new();
+        let mut nulls = None;
+        let schema = match cached {
+            Some(schema) => {
+                tracing::debug!("Using cached schema for {}", path);
+                schema
+            }
+            None => {
+                let mut reader = InputReader::open(file, &self.csv, self.infer_rows).map_err(|e| e.in_file(path.clone()))?;
+                let schema = reader.peek_schema().map_err(|e| e.in_file(path.clone()))?;
+                if let Some(mut cache) = cache.and_then(|cache| cache.lock().ok()) {
+                    cache.insert(&file.path, &schema);
+                }
+                categorical = self.categorical_columns(&reader);
+                nulls = self.null_counts(&reader);
+                schema
+            }
+        };
+        let schema = self.prepare_schema(schema).map_err(|e| e.in_file(path.clone()))?;
+        register_schema(accumulator, index, &schema, &categorical, nulls).map_err(|e| e.in_file(path))?;
+        Ok(schema)
+    }
+
+    fn sample_stdin(
+        &self,
+        index: usize,
+        file: &InputFile,
+        accumulator: &Mutex<SchemaAccumulator>,
+    ) -> Result<(InputReader, Schema)> {
+        let mut reader = InputReader::open(file, &self.csv, self.batch_size).map_err(|e| e.in_file("-"))?;
+        let schema = reader.peek_schema().map_err(|e| e.in_file("-"))?;
+        let schema = self.prepare_schema(schema).map_err(|e| e.in_file("-"))?;
+        register_schema(
+            accumulator,
+            index,
+            &schema,
+            &self.categorical_columns(&reader),
+            self.null_counts(&reader),
+        )
+        .map_err(|e| e.in_file("-"))?;
+        Ok((reader, schema))
+    }
+
+    /// Whether each text column of a reader's sampled batch, by its renamed
+    /// name, repeats its values enough to dictionary encode: at most half
+    /// as many distinct values as non-null ones. Empty unless
+    /// `dictionary_strings`.
+    fn categorical_columns(&self, reader: &InputReader) -> Vec<(String, bool)> {
+        let Some(batch) = reader.sampled_batch().filter(|_| self.dictionary_strings) else {
+            return Vec::new();
+        };
+        reader
+            .column_names()
+            .iter()
+            .zip(batch.arrays())
+            .filter_map(|(name, array)| {
+                let array = array.as_any().downcast_ref::<Utf8Array<i32>>()?;
+                let values: Vec<&str> = array.iter().flatten().collect();
+                let distinct: HashSet<&str> = values.iter().copied().collect();
+                let categorical = !values.is_empty() && distinct.len() * 2 <= values.len();
+                Some((self.renames.apply(name), categorical))
+            })
+            .collect()
+    }
+
+    /// The rows a reader's nulls were counted over and the null count of
+    /// each of its columns, by renamed name: a text input's sampled batch,
+    /// or the whole of a Parquet file, from its metadata. None unless
+    /// `drop_null_columns`.
+    fn null_counts(&self, reader: &InputReader) -> Option<(usize, Vec<(String, usize)>)> {
+        self.drop_null_columns?;
+        let (rows, counts) = reader.null_counts()?;
+        let columns = counts
+            .into_iter()
+            .map(|(name, nulls)| (self.renames.apply(&name), nulls))
+            .collect();
+        Some((rows, columns))
+    }
+
+    /// An aligner to `schema` with these options. Fails if a `fill` value
+    /// doesn't parse as its column's type.
+    pub fn aligner(&self, schema: Arc<UnifiedSchema>) -> Result<BatchAligner> {
+        let mut aligner = BatchAligner::new(
+            schema,
+            self.renames.clone(),
+            self.include_columns.clone(),
+            self.exclude_columns.clone(),
+            self.stringify_conflicts,
+        )
+        .coerce_errors(self.coerce_errors);
+        if let Some(tz) = &self.assume_tz {
+            aligner = aligner.assume_tz(Zone::parse(tz)?);
+        }
+        if let Some(column) = &self.source_column {
+            aligner = aligner.source_column(column.clone(), self.relative_to.clone());
+        }
+        aligner.compute(&self.computed)?.fill(&self.fills)
+    }
+
+    /// One line per step rows go through between being read and being
+    /// aligned to `unified`, in order, for `--plan`.
+    pub fn describe_transforms(&self, unified: &Arc<UnifiedSchema>) -> Result<Vec<String>> {
+        let mut steps = Vec::new();
+        if let Some(max) = self.csv.max_columns {
+            steps.push(format!("fail on csv rows of over {} columns", max));
+        }
+        if self.csv.validate_field_count {
+            let action = if self.csv.reject_rows { "reject" } else { "fail on" };
+            steps.push(format!("{} csv rows with more or fewer fields than the header", action));
+        }
+        if let Some(threshold) = self.drop_null_columns {
+            let dropped = if unified.dropped_columns.is_empty() {
+                "none".to_string()
+            } else {
+                unified.dropped_columns.join(",")
+            };
+            steps.push(format!("drop columns over {} null in the samples: {}", threshold, dropped));
+        }
+        if let Some(tz) = &self.tz_convert {
+            steps.push(format!("show timestamps in {}", tz));
+        }
+        steps.extend(self.aligner(Arc::clone(unified))?.describe());
+        Ok(steps)
+    }
+
+    /// Fingerprint of the options that decide what sampling a file finds,
+    /// so a schema cache written with other options isn't reused.
+    fn sampling_options(&self) -> String {
+        let options = format!("{:?} {}", self.csv, self.infer_rows);
+        format!("{:016x}", xxhash_rust::xxh3::xxh3_64(options.as_bytes()))
+    }
+
+    /// Applies the nested column policy, timezone options and renames to a
+    /// sampled schema.
+    fn prepare_schema(&self, schema: Schema) -> Result<Schema> {
+        let mut fields = schema.fields;
+        for field in &mut fields {
+            if let DataType::Timestamp(unit, tz) = &field.data_type {
+                let tz = tz
+                    .clone()
+                    .or_else(|| self.assume_tz.clone())
+                    .map(|tz| self.tz_convert.clone().unwrap_or(tz));
+                field.data_type = DataType::Timestamp(*unit, tz);
+            }
+        }
+        if let Some(field) = fields.iter().find(|f| is_nested(&f.data_type)) {
+            match self.nested {
+                Nested::Json => {}
+                Nested::Error => {
+                    return Err(MawError::Schema(format!(
+                        "Column '{}' is nested ({:?}); pass --nested json or --nested drop",
+                        field.name, field.data_type
+                    )))
+                }
+                Nested::Drop => fields.retain(|f| !is_nested(&f.data_type)),
+            }
+        }
+        self.renames.apply_to_schema(Schema::from(fields))
+    }
+}
+
+fn register_schema(
+    accumulator: &Mutex<SchemaAccumulator>,
+    index: usize,
+    schema: &Schema,
+    categorical: &[(String, bool)],
+    nulls: Option<(usize, Vec<(String, usize)>)>,
+) -> Result<()> {
+    let mut accumulator = accumulator
+        .lock()
+        .map_err(|_| MawError::Schema("Schema accumulator was poisoned".to_string()))?;
+    accumulator.register(index, schema)?;
+    for (column, is_categorical) in categorical {
+        accumulator.vote_categorical(column, *is_categorical);
+    }
+    if let Some((rows, columns)) = nulls {
+        accumulator.count_nulls(rows, &columns);
+    }
+    Ok(())
+}
+
+pub(crate) fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(|s| s.trim().to_string()).collect()
+}
+
+/// An iterator of aligned chunks over every discovered input, in order.
+pub struct ChunkStream {
+    files: std::vec::IntoIter<InputFile>,
+    current: Option<InputReader>,
+    stdin_reader: Option<InputReader>,
+    aligner: BatchAligner,
+    csv: CsvConfig,
+    batch_size: usize,
+    schema: Schema,
+}
+
+impl ChunkStream {
+    /// The schema every yielded chunk conforms to.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+impl Iterator for ChunkStream {
+    type Item = Result<Chunk<Box<dyn Array>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.current.is_none() {
+                let file = self.files.next()?;
+                let reader = match self.stdin_reader.take() {
+                    Some(reader) if file.path.to_string_lossy() == "-" => Ok(reader),
+                    other => {
+                        self.stdin_reader = other;
+                        InputReader::open(&file, &self.csv, self.batch_size)
+                    }
+                };
+
+                self.aligner.set_source(&file.path);
+                match reader {
+                    Ok(reader) => self.current = Some(reader),
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+
+            let reader = self.current.as_mut()?;
+            match reader.read_batch() {
+                Ok(Some(batch)) => {
+                    let names = reader.column_names();
+                    return Some(self.aligner.align_batch(batch, &names));
+                }
+                Ok(None) => self.current = None,
+                Err(e) => {
+                    self.current = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Streams aligned, schema-unified chunks from all inputs without writing
+/// anything, for embedders that want to consume the data in-process.
+pub fn stream_chunks(builder: StreamBuilder) -> Result<ChunkStream> {
+    let files = builder.discover()?;
+    if files.is_empty() {
+        return Err(MawError::InvalidInput("No input files found".to_string()));
+    }
+
+    let unified = builder.unify(&files)?;
+    let aligner = builder.aligner(unified.schema)?;
+    let schema = aligner.output_schema();
+
+    Ok(ChunkStream {
+        files: files.into_iter(),
+        current: None,
+        stdin_reader: unified.stdin_reader,
+        aligner,
+        csv: builder.csv,
+        batch_size: builder.batch_size,
+        schema,
+    })
+}
+
+/// A whole table in memory: its schema and its chunks.
+pub type Table = (Schema, Vec<Chunk<Box<dyn Array>>>);
+
+/// Reads every input into memory as one table: the unified schema and
+/// the aligned chunks, in input order. Fails once the chunks pass the
+/// builder's memory budget, rather than holding more than it allows.
+pub fn collect_table(builder: StreamBuilder) -> Result<Table> {
+    let budget = builder.mem_budget;
+    let mut stream = stream_chunks(builder)?;
+    let mut chunks = Vec::new();
+    let mut bytes = 0;
+
+    for chunk in &mut stream {
+        let chunk = chunk?;
+        bytes += chunk.arrays().iter().map(|a| approximate_bytes(a.as_ref())).sum::<usize>();
+        if let Some(budget) = budget.filter(|&budget| bytes > budget) {
+            return Err(MawError::InvalidInput(format!(
+                "Collected table exceeded the memory budget of {} MB",
+                budget / (1024 * 1024)
+            )));
+        }
+        chunks.push(chunk);
+    }
+
+    Ok((stream.schema, chunks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_stream_chunks() {
+        let temp_dir = tempdir().unwrap();
+        let csv1 = temp_dir.path().join("file1.csv");
+        let csv2 = temp_dir.path().join("file2.csv");
+        fs::write(&csv1, "id,name\n1,x\n2,y\n").unwrap();
+        fs::write(&csv2, "name,id,score\nz,3,1.5\n").unwrap();
+
+        let builder = StreamBuilder::new([
+            csv1.to_string_lossy().to_string(),
+            csv2.to_string_lossy().to_string(),
+        ]);
+        let stream = stream_chunks(builder).unwrap();
+
+        let names: Vec<_> = stream.schema().fields.iter().map(|f| f.name.clone()).collect();
+        assert_eq!(names, vec!["id", "name", "score"]);
+
+        let chunks: Vec<_> = stream.collect::<Result<_>>().unwrap();
+        let total_rows: usize = chunks.iter().map(|c| c.len()).sum();
+        assert_eq!(total_rows, 3);
+        assert!(chunks.iter().all(|c| c.arrays().len() == 3));
+    }
+
+    #[test]
+    fn test_batch_size_from_cli() {
+        let temp_dir = tempdir().unwrap();
+        let csv1 = temp_dir.path().join("file1.csv");
+        let json1 = temp_dir.path().join("file2.jsonl");
+        fs::write(&csv1, "id\n1\n2\n3\n4\n5\n").unwrap();
+        fs::write(&json1, "{\"id\": 6}\n{\"id\": 7}\n{\"id\": 8}\n").unwrap();
+        let inputs = [csv1.to_string_lossy().to_string(), json1.to_string_lossy().to_string()];
+
+        let cli = Cli::parse_from(["maw", &inputs[0], &inputs[1], "--batch-size", "2"]);
+        let chunks: Vec<_> = stream_chunks(StreamBuilder::from_cli(&cli).unwrap())
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let lengths: Vec<_> = chunks.iter().map(|c| c.len()).collect();
+        assert_eq!(lengths, [2, 2, 1, 2, 1]);
+
+        let cli = Cli::parse_from(["maw", &inputs[0], "--batch-size", "0"]);
+        let error = StreamBuilder::from_cli(&cli).err().unwrap();
+        assert_eq!(error.to_string(), "Configuration error: --batch-size must be at least 1");
+    }
+
+    #[test]
+    fn test_input_removed_after_discovery() {
+        let temp_dir = tempdir().unwrap();
+        let kept = temp_dir.path().join("kept.csv");
+        let removed = temp_dir.path().join("removed.csv");
+        fs::write(&kept, "id\n1\n").unwrap();
+        fs::write(&removed, "id\n2\n").unwrap();
+        let builder = StreamBuilder::new([temp_dir.path().to_string_lossy().to_string()]);
+        let files = builder.discover().unwrap();
+        fs::remove_file(&removed).unwrap();
+
+        let error = builder.unify(&files).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!("{}: The file was removed or renamed after it was discovered", removed.display())
+        );
+
+        let unified = builder.continue_on_error(true).unify(&files).unwrap();
+        assert_eq!(unified.failed, [removed]);
+        assert_eq!(unified.schema.column_names(), ["id"]);
+    }
+
+    #[test]
+    fn test_collect_table() {
+        let temp_dir = tempdir().unwrap();
+        let csv1 = temp_dir.path().join("file1.csv");
+        let csv2 = temp_dir.path().join("file2.csv");
+        fs::write(&csv1, "id,name\n1,x\n2,y\n").unwrap();
+        fs::write(&csv2, "id,name\n3,z\n").unwrap();
+        let inputs = [
+            csv1.to_string_lossy().to_string(),
+            csv2.to_string_lossy().to_string(),
+        ];
+
+        let (schema, chunks) = collect_table(StreamBuilder::new(inputs.clone())).unwrap();
+        let names: Vec<_> = schema.fields.iter().map(|f| f.name.clone()).collect();
+        assert_eq!(names, vec!["id", "name"]);
+        assert_eq!(chunks.iter().map(|c| c.len()).sum::<usize>(), 3);
+
+        let error = collect_table(StreamBuilder::new(inputs).mem_budget(8)).unwrap_err();
+        assert!(error.to_string().contains("memory budget"), "{}", error);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::error::{MawError, Result};
+use chrono::{FixedOffset, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+
+/// A timezone as Arrow timestamps name them: `UTC`, a fixed offset such as
+/// `+02:00`, or an IANA name such as `Europe/Paris`.
+#[derive(Clone, Copy, Debug)]
+pub enum Zone {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl Zone {
+    pub fn parse(name: &str) -> Result<Self> {
+        if let Some(offset) = parse_fixed_offset(name) {
+            return Ok(Zone::Fixed(offset));
+        }
+        name.parse::<Tz>().map(Zone::Named).map_err(|_| {
+            MawError::Config(format!(
+                "Unknown timezone '{}'; use UTC, an offset like +02:00, or a name like Europe/Paris",
+                name
+            ))
+        })
+    }
+
+    /// The zone's offset at a UTC instant.
+    pub fn offset_at_utc(&self, utc: &NaiveDateTime) -> FixedOffset {
+        match self {
+            Zone::Fixed(offset) => *offset,
+            Zone::Named(tz) => tz.offset_from_utc_datetime(utc).fix(),
+        }
+    }
+
+    /// The zone's offset at a wall-clock time, taking the earlier one when
+    /// a clock change makes it ambiguous. `None` for a time the clocks
+    /// skipped.
+    pub fn offset_at_local(&self, local: &NaiveDateTime) -> Option<FixedOffset> {
+        match self {
+            Zone::Fixed(offset) => Some(*offset),
+            Zone::Named(tz) => tz.offset_from_local_datetime(local).earliest().map(|o| o.fix()),
+        }
+    }
+}
+
+impl std::fmt::Display for Zone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Zone::Fixed(offset) => write!(f, "{}", offset),
+            Zone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+/// Parses `UTC` or `[+-]HH:MM`. Unlike arrow2's `parse_offset`, the sign
+/// applies to the minutes too.
+fn parse_fixed_offset(name: &str) -> Option<FixedOffset> {
+    if name == "UTC" {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, offset) = match name.strip_prefix('-') {
+        Some(offset) => (-1, offset),
+        None => (1, name.strip_prefix('+').unwrap_or(name)),
+    };
+    let (hours, minutes) = offset.split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    if hours >= 24 || minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_zone_offsets() {
+        let summer = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        let winter = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+
+        let fixed = Zone::parse("-05:30").unwrap();
+        assert_eq!(fixed.offset_at_utc(&summer).local_minus_utc(), -(5 * 3600 + 1800));
+
+        let paris = Zone::parse("Europe/Paris").unwrap();
+        assert_eq!(paris.offset_at_utc(&summer).local_minus_utc(), 7200);
+        assert_eq!(paris.offset_at_local(&winter).unwrap().local_minus_utc(), 3600);
+
+        // 02:30 doesn't exist on the day clocks go forward
+        let skipped = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap().and_hms_opt(2, 30, 0).unwrap();
+        assert!(paris.offset_at_local(&skipped).is_none());
+
+        assert!(Zone::parse("Mars/Olympus").is_err());
+    }
+}
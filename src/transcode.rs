@@ -0,0 +1,79 @@
+use crate::{
+    cli::Ragged,
+    compression,
+    csv_in::{input_delimiter, CsvConfig},
+    discover::{FileFormat, InputFile},
+    error::{MawError, Result},
+    writer_csv::CsvWriter,
+};
+use csv::{ByteRecord, Reader, ReaderBuilder};
+use std::path::Path;
+
+/// Why inputs can't be transcoded field by field (`--transcode`), or None
+/// if they can: every input must be a CSV file, not stdin, in UTF-8, with
+/// the same header, and rows must keep their fields as they are.
+pub fn blocker(files: &[InputFile], csv: &CsvConfig) -> Result<Option<String>> {
+    if csv.ragged != Ragged::Error {
+        return Ok(Some("--ragged rows are padded or cut to the header".to_string()));
+    }
+    if csv.validate_field_count {
+        return Ok(Some("--validate-field-count checks rows as they are parsed".to_string()));
+    }
+    if csv.max_columns.is_some() {
+        return Ok(Some("--max-columns checks rows as they are parsed".to_string()));
+    }
+    let mut first_header: Option<ByteRecord> = None;
+    for file in files {
+        let path = file.path.display();
+        if file.format != FileFormat::Csv {
+            return Ok(Some(format!("{} is {}, not delimited text", path, file.format.name())));
+        }
+        if file.path.to_string_lossy() == "-" {
+            return Ok(Some("stdin's header can't be checked ahead of reading it".to_string()));
+        }
+        if !matches!(csv.encoding_for(&file.path).to_lowercase().as_str(), "utf8" | "utf-8") {
+            return Ok(Some(format!("{} isn't UTF-8", path)));
+        }
+
+        let header = open(&file.path, csv)?.byte_headers()?.clone();
+        match &first_header {
+            Some(first) if *first != header => {
+                return Ok(Some(format!("{} has a different header from the first input", path)));
+            }
+            Some(_) => {}
+            None => first_header = Some(header),
+        }
+    }
+    Ok(None)
+}
+
+/// Copies every row of `files`, which `blocker` passed, to `writer` as
+/// read, leaving the writer to quote fields for its own delimiter. Returns
+/// the number of rows copied.
+pub fn transcode(files: &[InputFile], csv: &CsvConfig, writer: &mut CsvWriter) -> Result<u64> {
+    let mut rows = 0;
+    let mut record = ByteRecord::new();
+    for file in files {
+        let path = file.path.to_string_lossy();
+        let mut reader = open(&file.path, csv).map_err(|e| e.in_file(path.clone()))?;
+        writer.write_text_header(reader.byte_headers().map_err(|e| MawError::from(e).in_file(path.clone()))?)?;
+        while reader.read_byte_record(&mut record).map_err(|e| MawError::from(e).in_file(path.clone()))? {
+            writer.write_text_record(&record)?;
+            rows += 1;
+        }
+    }
+    Ok(rows)
+}
+
+fn open(path: &Path, csv: &CsvConfig) -> Result<Reader<Box<dyn std::io::Read + Send>>> {
+    let delimiter = match csv.delimiter_for(path) {
+        Some(delimiter) => delimiter,
+        None => input_delimiter(path)?,
+    };
+    let mut builder = ReaderBuilder::new();
+    builder.delimiter(delimiter);
+    if let Some(quote) = csv.quote {
+        builder.quote(quote);
+    }
+    Ok(builder.from_reader(compression::open_input(path, csv.read_buffer)?))
+}
@@ -0,0 +1,720 @@
+use crate::{
+    coercion::BatchAligner,
+    error::{MawError, Result},
+    schema::{widen_types, CoercionWarning, TypeKind},
+};
+use arrow2::{
+    array::{get_display, growable::make_growable, new_empty_array, Array, BinaryArray, Int64Array, Utf8Array},
+    chunk::Chunk,
+    datatypes::{DataType, Field, Schema},
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// Name of the column holding each value's original column name.
+const VARIABLE_COLUMN: &str = "variable";
+/// Name of the column holding the values themselves.
+const VALUE_COLUMN: &str = "value";
+
+/// Reshapes wide chunks into long ones (`--unpivot`). Every row becomes one
+/// row per value column, holding the id columns, the value column's name as
+/// `variable` and its value as `value`. Value columns are coerced to one
+/// type, widened like the columns of different inputs are.
+pub struct Unpivot {
+    id_indices: Vec<usize>,
+    value_indices: Vec<usize>,
+    value_names: Vec<String>,
+    value_type: DataType,
+    drop_nulls: bool,
+    schema: Schema,
+}
+
+impl Unpivot {
+    /// Builds the transform for chunks of `input` from a spec of the form
+    /// `id1,id2:value1,value2`. Leaving out the value columns (`id1,id2:`)
+    /// unpivots every column that isn't an id.
+    pub fn new(
+        spec: &str,
+        input: &Schema,
+        stringify_conflicts: bool,
+        drop_nulls: bool,
+    ) -> Result<Self> {
+        let (ids, values) = spec.split_once(':').ok_or_else(|| {
+            MawError::Config(format!(
+                "Invalid --unpivot '{}', expected id_cols:value_cols",
+                spec
+            ))
+        })?;
+        let ids = split_columns(ids);
+        let mut values = split_columns(values);
+        if values.is_empty() {
+            values = input
+                .fields
+                .iter()
+                .map(|f| f.name.clone())
+                .filter(|name| !ids.contains(name))
+                .collect();
+        }
+        if values.is_empty() {
+            return Err(MawError::Config("--unpivot needs at least one value column".to_string()));
+        }
+
+        let position = |name: &String| {
+            input.fields.iter().position(|f| &f.name == name).ok_or_else(|| {
+                MawError::Config(format!("--unpivot column '{}' is not in the input", name))
+            })
+        };
+        let id_indices = ids.iter().map(position).collect::<Result<Vec<_>>>()?;
+        let value_indices = values.iter().map(position).collect::<Result<Vec<_>>>()?;
+
+        if let Some(name) = ids.iter().find(|n| *n == VARIABLE_COLUMN || *n == VALUE_COLUMN) {
+            return Err(MawError::Config(format!(
+                "--unpivot id column '{}' clashes with an output column name",
+                name
+            )));
+        }
+
+        let mut value_kind = TypeKind::Null;
+        for &i in &value_indices {
+            let kind = TypeKind::from_arrow_type(input.fields[i].data_type());
+            let widened = widen_types(&value_kind, &kind, stringify_conflicts)?;
+            if let Some(warning) = CoercionWarning::check(VALUE_COLUMN, &value_kind, &kind, &widened) {
+                tracing::warn!("{}", warning);
+            }
+            value_kind = widened;
+        }
+        let value_type = value_kind.to_arrow_type();
+
+        let mut fields: Vec<Field> = id_indices.iter().map(|&i| input.fields[i].clone()).collect();
+        fields.push(Field::new(VARIABLE_COLUMN, DataType::Utf8, false));
+        fields.push(Field::new(VALUE_COLUMN, value_type.clone(), true));
+
+        Ok(Self {
+            id_indices,
+            value_indices,
+            value_names: values,
+            value_type,
+            drop_nulls,
+            schema: Schema::from(fields),
+        })
+    }
+
+    /// The schema of unpivoted chunks.
+    pub fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// What the transform does, for `--plan`.
+    pub fn describe(&self) -> String {
+        let ids: Vec<&str> = self.schema.fields[..self.id_indices.len()]
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect();
+        let nulls = if self.drop_nulls { ", dropping nulls" } else { "" };
+        format!(
+            "unpivot {} into {} and {} by {}{}",
+            self.value_names.join(","),
+            VARIABLE_COLUMN,
+            VALUE_COLUMN,
+            ids.join(","),
+            nulls
+        )
+    }
+
+    /// Unpivots an aligned chunk, using `aligner` to coerce the value columns.
+    pub fn apply(
+        &self,
+        chunk: &Chunk<Box<dyn Array>>,
+        aligner: &BatchAligner,
+    ) -> Result<Chunk<Box<dyn Array>>> {
+        let rows = chunk.len();
+        let values = self
+            .value_indices
+            .iter()
+            .map(|&i| aligner.coerce_column(chunk.arrays()[i].as_ref(), &self.value_type, rows))
+            .collect::<Result<Vec<_>>>()?;
+
+        // (row, value column) of every output row, in row-major order
+        let mut cells = Vec::with_capacity(rows * values.len());
+        for row in 0..rows {
+            for (j, value) in values.iter().enumerate() {
+                if !(self.drop_nulls && value.is_null(row)) {
+                    cells.push((row, j));
+                }
+            }
+        }
+
+        let mut columns: Vec<Box<dyn Array>> = Vec::with_capacity(self.schema.fields.len());
+        for &i in &self.id_indices {
+            let id = chunk.arrays()[i].as_ref();
+            let mut growable = make_growable(&[id], true, cells.len());
+            for &(row, _) in &cells {
+                growable.extend(0, row, 1);
+            }
+            columns.push(growable.as_box());
+        }
+
+        let variables = cells.iter().map(|&(_, j)| Some(self.value_names[j].as_str()));
+        columns.push(Utf8Array::<i32>::from_trusted_len_iter(variables).boxed());
+
+        let sources: Vec<&dyn Array> = values.iter().map(|v| v.as_ref()).collect();
+        let mut growable = make_growable(&sources, true, cells.len());
+        for &(row, j) in &cells {
+            growable.extend(j, row, 1);
+        }
+        columns.push(growable.as_box());
+
+        Ok(Chunk::new(columns))
+    }
+}
+
+/// Most distinct values a `--pivot` column may have, as each becomes an
+/// output column.
+const MAX_PIVOT_COLUMNS: usize = 10_000;
+
+/// Reshapes long chunks into wide ones (`--pivot`). Rows with the same
+/// index values become one row, with a column per distinct value of the
+/// pivot column holding the matching value. The output columns are only
+/// known once every row has been seen, so rows are buffered up to a memory
+/// budget and emitted as one chunk by `finish`.
+pub struct Pivot {
+    index_names: Vec<String>,
+    index_indices: Vec<usize>,
+    column_index: usize,
+    value_index: usize,
+    /// Names of the pivot and value columns
+    column_name: String,
+    value_name: String,
+    index_fields: Vec<Field>,
+    value_type: DataType,
+    max_bytes: usize,
+    /// Index, pivot and value columns of every chunk seen
+    chunks: Vec<Vec<Box<dyn Array>>>,
+    buffered_bytes: usize,
+    /// Output row of each distinct index, and where its index was first seen
+    rows: HashMap<Vec<Option<String>>, usize>,
+    row_sources: Vec<(usize, usize)>,
+    /// Pivot value -> (output row -> (chunk, row) of its value)
+    cells: BTreeMap<String, HashMap<usize, (usize, usize)>>,
+}
+
+impl Pivot {
+    /// Builds the transform for chunks of `input` from a spec of the form
+    /// `index1,index2:column:value`, buffering at most `max_bytes` of input.
+    pub fn new(spec: &str, input: &Schema, max_bytes: usize) -> Result<Self> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let [index, column, value] = parts[..] else {
+            return Err(MawError::Config(format!(
+                "Invalid --pivot '{}', expected index:columns:values",
+                spec
+            )));
+        };
+
+        let position = |name: &str| {
+            input.fields.iter().position(|f| f.name == name).ok_or_else(|| {
+                MawError::Config(format!("--pivot column '{}' is not in the input", name))
+            })
+        };
+        let index_names = split_columns(index);
+        let index_indices = index_names.iter().map(|n| position(n)).collect::<Result<Vec<_>>>()?;
+        let column_index = position(column.trim())?;
+        let value_index = position(value.trim())?;
+
+        Ok(Self {
+            index_fields: index_indices.iter().map(|&i| input.fields[i].clone()).collect(),
+            index_names,
+            index_indices,
+            column_index,
+            value_index,
+            column_name: column.trim().to_string(),
+            value_name: value.trim().to_string(),
+            value_type: input.fields[value_index].data_type().clone(),
+            max_bytes,
+            chunks: Vec::new(),
+            buffered_bytes: 0,
+            rows: HashMap::new(),
+            row_sources: Vec::new(),
+            cells: BTreeMap::new(),
+        })
+    }
+
+    /// What the transform does, for `--plan`.
+    pub fn describe(&self) -> String {
+        format!(
+            "pivot the values of {} into a column per {} by {}, buffering at most {} MB",
+            self.value_name,
+            self.column_name,
+            self.index_names.join(","),
+            self.max_bytes / (1024 * 1024)
+        )
+    }
+
+    /// Buffers the rows of an aligned chunk.
+    pub fn push(&mut self, chunk: &Chunk<Box<dyn Array>>) -> Result<()> {
+        let chunk_id = self.chunks.len();
+        let mut kept: Vec<Box<dyn Array>> = self
+            .index_indices
+            .iter()
+            .map(|&i| chunk.arrays()[i].clone())
+            .collect();
+        kept.push(chunk.arrays()[self.column_index].clone());
+        kept.push(chunk.arrays()[self.value_index].clone());
+
+        self.buffered_bytes += kept.iter().map(|a| approximate_bytes(a.as_ref())).sum::<usize>();
+        if self.buffered_bytes > self.max_bytes {
+            return Err(MawError::InvalidInput(format!(
+                "--pivot buffers its input, which exceeded the --mem-budget of {} MB",
+                self.max_bytes / (1024 * 1024)
+            )));
+        }
+
+        self.index_rows(chunk_id, &kept, chunk.len())?;
+        self.chunks.push(kept);
+        Ok(())
+    }
+
+    fn index_rows(&mut self, chunk_id: usize, kept: &[Box<dyn Array>], len: usize) -> Result<()> {
+        let index_count = self.index_indices.len();
+        let index_displays: Vec<_> = kept[..index_count]
+            .iter()
+            .map(|a| get_display::<String>(a.as_ref(), ""))
+            .collect();
+        let columns = kept[index_count].as_ref();
+        let column_display = get_display::<String>(columns, "");
+
+        for row in 0..len {
+            // Rows without a pivot value have nowhere to go
+            if columns.is_null(row) {
+                continue;
+            }
+            let column = render(&column_display, row);
+
+            let key: Vec<Option<String>> = kept[..index_count]
+                .iter()
+                .zip(&index_displays)
+                .map(|(array, display)| (!array.is_null(row)).then(|| render(display, row)))
+                .collect();
+            let next_row = self.row_sources.len();
+            let output_row = *self.rows.entry(key).or_insert(next_row);
+            if output_row == next_row {
+                self.row_sources.push((chunk_id, row));
+            }
+
+            if !self.cells.contains_key(&column) && self.cells.len() == MAX_PIVOT_COLUMNS {
+                return Err(MawError::InvalidInput(format!(
+                    "--pivot column has more than {} distinct values",
+                    MAX_PIVOT_COLUMNS
+                )));
+            }
+            let cells = self.cells.entry(column.clone()).or_default();
+            if cells.insert(output_row, (chunk_id, row)).is_some() {
+                return Err(MawError::InvalidInput(format!(
+                    "--pivot found more than one value for '{}' at the same {}",
+                    column,
+                    self.index_names.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The wide chunk and its schema: the index columns, then one column per
+    /// pivot value in sorted order.
+    pub fn finish(self) -> Result<(Schema, Chunk<Box<dyn Array>>)> {
+        let mut fields = self.index_fields.clone();
+        fields.extend(
+            self.cells
+                .keys()
+                .map(|name| Field::new(name, self.value_type.clone(), true)),
+        );
+        if let Some(clash) = fields[self.index_fields.len()..]
+            .iter()
+            .find(|f| self.index_names.contains(&f.name))
+        {
+            return Err(MawError::Config(format!(
+                "--pivot value '{}' clashes with an index column name",
+                clash.name
+            )));
+        }
+
+        // Without any input there are no pivot values, and no rows to copy
+        // the index columns from
+        if self.chunks.is_empty() {
+            let columns = self.index_fields.iter().map(|f| new_empty_array(f.data_type().clone())).collect();
+            return Ok((Schema::from(fields), Chunk::new(columns)));
+        }
+
+        let index_count = self.index_indices.len();
+        let mut columns = Vec::with_capacity(fields.len());
+        for i in 0..index_count {
+            let sources: Vec<&dyn Array> = self.chunks.iter().map(|c| c[i].as_ref()).collect();
+            let mut growable = make_growable(&sources, true, self.row_sources.len());
+            for &(chunk, row) in &self.row_sources {
+                growable.extend(chunk, row, 1);
+            }
+            columns.push(growable.as_box());
+        }
+
+        let values: Vec<&dyn Array> = self.chunks.iter().map(|c| c[index_count + 1].as_ref()).collect();
+        for cells in self.cells.values() {
+            let mut growable = make_growable(&values, true, self.row_sources.len());
+            for output_row in 0..self.row_sources.len() {
+                match cells.get(&output_row) {
+                    Some(&(chunk, row)) => growable.extend(chunk, row, 1),
+                    None => growable.extend_validity(1),
+                }
+            }
+            columns.push(growable.as_box());
+        }
+
+        Ok((Schema::from(fields), Chunk::new(columns)))
+    }
+}
+
+pub(crate) fn render(display: &dyn Fn(&mut String, usize) -> std::fmt::Result, row: usize) -> String {
+    let mut value = String::new();
+    // Writing to a String can't fail
+    let _ = display(&mut value, row);
+    value
+}
+
+/// Keeps a random subset of rows (`--sample`): either each row with a fixed
+/// probability, or a fixed number of rows chosen by reservoir sampling so
+/// that every row is equally likely to be kept without buffering the input.
+pub struct Sampler {
+    size: SampleSize,
+    seed: Option<u64>,
+    rng: fastrand::Rng,
+    seen: usize,
+    reservoir: Option<Chunk<Box<dyn Array>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SampleSize {
+    Fraction(f64),
+    Count(usize),
+}
+
+impl Sampler {
+    /// Parses `0.01` as a fraction of rows or `1000` as a row count. The
+    /// same `seed` always picks the same rows from the same input.
+    pub fn new(spec: &str, seed: Option<u64>) -> Result<Self> {
+        let invalid = || {
+            MawError::Config(format!(
+                "Invalid --sample '{}', expected a fraction in (0, 1] or a row count",
+                spec
+            ))
+        };
+        let size = match spec.trim().parse::<usize>() {
+            Ok(count) => SampleSize::Count(count),
+            Err(_) => match spec.trim().parse::<f64>() {
+                Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => SampleSize::Fraction(fraction),
+                _ => return Err(invalid()),
+            },
+        };
+
+        Ok(Self {
+            size,
+            seed,
+            rng: seed.map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed),
+            seen: 0,
+            reservoir: None,
+        })
+    }
+
+    /// What the transform does, for `--plan`.
+    pub fn describe(&self) -> String {
+        let size = match self.size {
+            SampleSize::Fraction(fraction) => format!("sample each row with probability {}", fraction),
+            SampleSize::Count(count) => format!("sample {} rows", count),
+        };
+        match self.seed {
+            Some(seed) => format!("{} (seed {})", size, seed),
+            None => size,
+        }
+    }
+
+    /// Samples an incoming chunk. Fractions yield their rows straight away;
+    /// counts hold them back until `finish`.
+    pub fn sample(&mut self, chunk: &Chunk<Box<dyn Array>>) -> Option<Chunk<Box<dyn Array>>> {
+        match self.size {
+            SampleSize::Fraction(fraction) => {
+                let rows: Vec<_> = (0..chunk.len())
+                    .filter(|_| self.rng.f64() < fraction)
+                    .map(|row| (0, row))
+                    .collect();
+                (!rows.is_empty()).then(|| gather_rows(&[chunk], &rows))
+            }
+            SampleSize::Count(count) => {
+                let reservoir = self.reservoir.take();
+                let mut slots: Vec<_> = (0..reservoir.as_ref().map_or(0, |r| r.len()))
+                    .map(|row| (1, row))
+                    .collect();
+                for row in 0..chunk.len() {
+                    if slots.len() < count {
+                        slots.push((0, row));
+                    } else {
+                        let slot = self.rng.usize(..=self.seen);
+                        if slot < count {
+                            slots[slot] = (0, row);
+                        }
+                    }
+                    self.seen += 1;
+                }
+
+                let sources: Vec<_> = std::iter::once(chunk).chain(reservoir.as_ref()).collect();
+                self.reservoir = (!slots.is_empty()).then(|| gather_rows(&sources, &slots));
+                None
+            }
+        }
+    }
+
+    /// The rows held back by a count sample.
+    pub fn finish(&mut self) -> Option<Chunk<Box<dyn Array>>> {
+        self.reservoir.take()
+    }
+}
+
+/// Numbers rows from 0 in the order they are written (`--row-number`),
+/// carrying on from chunk to chunk so that the numbers run on across inputs
+/// and across rolled or partitioned output files.
+pub struct RowNumbers {
+    name: String,
+    /// Whether the column goes after the others rather than before
+    last: bool,
+    next: i64,
+}
+
+impl RowNumbers {
+    pub fn new(name: String, last: bool) -> Self {
+        Self { name, last, next: 0 }
+    }
+
+    /// What the transform does, for `--plan`.
+    pub fn describe(&self) -> String {
+        let position = if self.last { "last" } else { "first" };
+        format!("number rows from 0 in column {}, placed {}", self.name, position)
+    }
+
+    /// `schema` with the row number column added. Fails if it already has
+    /// a column of that name.
+    pub fn output_schema(&self, schema: &Schema) -> Result<Schema> {
+        if schema.fields.iter().any(|f| f.name == self.name) {
+            return Err(MawError::Config(format!(
+                "--row-number column '{}' is already in the output",
+                self.name
+            )));
+        }
+        let mut fields = schema.fields.clone();
+        let field = Field::new(&self.name, DataType::Int64, false);
+        if self.last {
+            fields.push(field);
+        } else {
+            fields.insert(0, field);
+        }
+        Ok(Schema::from(fields).with_metadata(schema.metadata.clone()))
+    }
+
+    /// Adds the numbers of `chunk`'s rows, which follow the last chunk's.
+    pub fn number(&mut self, chunk: Chunk<Box<dyn Array>>) -> Chunk<Box<dyn Array>> {
+        let rows = chunk.len() as i64;
+        let numbers = Int64Array::from_vec((self.next..self.next + rows).collect()).boxed();
+        self.next += rows;
+        let mut arrays = chunk.into_arrays();
+        if self.last {
+            arrays.push(numbers);
+        } else {
+            arrays.insert(0, numbers);
+        }
+        Chunk::new(arrays)
+    }
+}
+
+/// Copies the given `(chunk, row)` rows into a new chunk.
+pub(crate) fn gather_rows(
+    chunks: &[&Chunk<Box<dyn Array>>],
+    rows: &[(usize, usize)],
+) -> Chunk<Box<dyn Array>> {
+    let columns = (0..chunks[0].arrays().len())
+        .map(|column| {
+            let sources: Vec<&dyn Array> =
+                chunks.iter().map(|c| c.arrays()[column].as_ref()).collect();
+            let mut growable = make_growable(&sources, false, rows.len());
+            for &(chunk, row) in rows {
+                growable.extend(chunk, row, 1);
+            }
+            growable.as_box()
+        })
+        .collect();
+    Chunk::new(columns)
+}
+
+/// Rough in-memory size of an array, for enforcing a memory budget.
+pub(crate) fn approximate_bytes(array: &dyn Array) -> usize {
+    if let Some(strings) = array.as_any().downcast_ref::<Utf8Array<i32>>() {
+        return strings.values().len() + 4 * array.len();
+    }
+    if let Some(bytes) = array.as_any().downcast_ref::<BinaryArray<i32>>() {
+        return bytes.values().len() + 4 * array.len();
+    }
+    8 * array.len()
+}
+
+fn split_columns(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::UnifiedSchema;
+    use arrow2::array::{Float64Array, Int64Array};
+    use std::{collections::HashMap, sync::Arc};
+
+    #[test]
+    fn test_unpivot() {
+        let schema = Schema::from(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("region", DataType::Utf8, true),
+            Field::new("q1", DataType::Int64, true),
+            Field::new("q2", DataType::Float64, true),
+            Field::new("q3", DataType::Int64, true),
+        ]);
+        let unified = UnifiedSchema::from_schemas(std::slice::from_ref(&schema), false).unwrap();
+        let aligner = BatchAligner::new(Arc::new(unified), HashMap::new(), None, None, false);
+
+        let chunk = Chunk::new(vec![
+            Int64Array::from_slice([1, 2]).boxed(),
+            Utf8Array::<i32>::from_slice(["n", "s"]).boxed(),
+            Int64Array::from_slice([10, 20]).boxed(),
+            Float64Array::from([Some(1.5), None]).boxed(),
+            Int64Array::from_slice([30, 40]).boxed(),
+        ]);
+
+        let unpivot = Unpivot::new("id,region:q1,q2,q3", &schema, false, false).unwrap();
+        let names: Vec<_> = unpivot.output_schema().fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["id", "region", "variable", "value"]);
+        assert_eq!(unpivot.output_schema().fields[3].data_type(), &DataType::Float64);
+
+        let long = unpivot.apply(&chunk, &aligner).unwrap();
+        assert_eq!(long.len(), 6);
+        let ids = long.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(ids.values().as_slice(), [1, 1, 1, 2, 2, 2]);
+        let variables = long.arrays()[2].as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(variables.value(4), "q2");
+        let values = long.arrays()[3].as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(values.iter().collect::<Vec<_>>(), [
+            Some(&10.0), Some(&1.5), Some(&30.0), Some(&20.0), None, Some(&40.0),
+        ]);
+
+        // Dropping nulls, with the value columns left implicit
+        let unpivot = Unpivot::new("id,region:", &schema, false, true).unwrap();
+        assert_eq!(unpivot.apply(&chunk, &aligner).unwrap().len(), 5);
+
+        assert!(Unpivot::new("id", &schema, false, false).is_err());
+        assert!(Unpivot::new("id:missing", &schema, false, false).is_err());
+    }
+
+    #[test]
+    fn test_pivot() {
+        let schema = Schema::from(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("quarter", DataType::Utf8, true),
+            Field::new("sales", DataType::Float64, true),
+        ]);
+        let first = Chunk::new(vec![
+            Int64Array::from_slice([1, 1, 2]).boxed(),
+            Utf8Array::<i32>::from_slice(["q2", "q1", "q1"]).boxed(),
+            Float64Array::from_slice([1.5, 1.0, 2.0]).boxed(),
+        ]);
+        let second = Chunk::new(vec![
+            Int64Array::from_slice([2, 3]).boxed(),
+            Utf8Array::<i32>::from_slice(["q2", "q3"]).boxed(),
+            Float64Array::from_slice([2.5, 3.0]).boxed(),
+        ]);
+
+        let mut pivot = Pivot::new("id:quarter:sales", &schema, usize::MAX).unwrap();
+        pivot.push(&first).unwrap();
+        pivot.push(&second).unwrap();
+        let (wide_schema, wide) = pivot.finish().unwrap();
+
+        let names: Vec<_> = wide_schema.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["id", "q1", "q2", "q3"]);
+        assert_eq!(wide.len(), 3);
+        let ids = wide.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(ids.values().as_slice(), [1, 2, 3]);
+        let q2 = wide.arrays()[2].as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(q2.iter().collect::<Vec<_>>(), [Some(&1.5), Some(&2.5), None]);
+
+        // Two values for the same cell are ambiguous
+        let mut pivot = Pivot::new("id:quarter:sales", &schema, usize::MAX).unwrap();
+        pivot.push(&first).unwrap();
+        assert!(pivot.push(&first).is_err());
+
+        let mut pivot = Pivot::new("id:quarter:sales", &schema, 8).unwrap();
+        assert!(pivot.push(&first).is_err());
+
+        assert!(Pivot::new("id:quarter", &schema, usize::MAX).is_err());
+
+        // No input gives no rows, with only the index columns
+        let pivot = Pivot::new("id:quarter:sales", &schema, usize::MAX).unwrap();
+        let (wide_schema, wide) = pivot.finish().unwrap();
+        let names: Vec<_> = wide_schema.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["id"]);
+        assert_eq!(wide.len(), 0);
+        assert_eq!(wide.arrays()[0].data_type(), &DataType::Int64);
+    }
+
+    #[test]
+    fn test_sample() {
+        let chunks: Vec<_> = (0..10)
+            .map(|c| Chunk::new(vec![Int64Array::from_vec((c * 100..c * 100 + 100).collect()).boxed()]))
+            .collect();
+        let sample = |spec: &str, seed: u64| {
+            let mut sampler = Sampler::new(spec, Some(seed)).unwrap();
+            let mut kept: Vec<i64> = Vec::new();
+            let mut sampled: Vec<_> = chunks.iter().filter_map(|c| sampler.sample(c)).collect();
+            sampled.extend(sampler.finish());
+            for chunk in sampled {
+                let ids = chunk.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+                kept.extend(ids.values().iter());
+            }
+            kept
+        };
+
+        let counted = sample("25", 7);
+        assert_eq!(counted.len(), 25);
+        assert_eq!(counted, sample("25", 7));
+        // Later chunks can displace earlier rows
+        assert!(counted.iter().any(|&id| id >= 100));
+
+        let fraction = sample("0.1", 7);
+        assert_eq!(fraction, sample("0.1", 7));
+        assert!((50..150).contains(&fraction.len()));
+        assert_eq!(sample("1.0", 7).len(), 1000);
+
+        assert_eq!(sample("5000", 7).len(), 1000);
+        assert!(Sampler::new("1.5", None).is_err());
+        assert!(Sampler::new("-3", None).is_err());
+    }
+
+    #[test]
+    fn test_row_numbers() {
+        let schema = Schema::from(vec![Field::new("id", DataType::Int64, true)]);
+        let chunk = || Chunk::new(vec![Int64Array::from_vec(vec![7, 8, 9]).boxed()]);
+
+        let mut numbers = RowNumbers::new("n".to_string(), false);
+        assert_eq!(numbers.output_schema(&schema).unwrap().fields[0].name, "n");
+        numbers.number(chunk());
+        let numbered = numbers.number(chunk());
+        let values = numbered.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(values.values().as_slice(), [3, 4, 5]);
+
+        let mut numbers = RowNumbers::new("n".to_string(), true);
+        assert_eq!(numbers.output_schema(&schema).unwrap().fields[1].name, "n");
+        assert_eq!(numbers.number(chunk()).arrays()[1].len(), 3);
+        assert!(RowNumbers::new("id".to_string(), false).output_schema(&schema).is_err());
+    }
+}
@@ -1,28 +1,71 @@
-use crate::error::Result;
+use crate::checksum::{ChecksumAlgorithm, ChecksumWriter};
+use crate::cli::{Compression, FloatFormat};
+use crate::coercion::downcast;
+use crate::compression::Encoder;
+use crate::error::{MawError, Result};
+use crate::fsutil::{create_output_file, is_stdout, OutputSink};
+use crate::timezone::Zone;
 use arrow2::{
     array::*,
-    datatypes::DataType,
+    datatypes::{DataType, TimeUnit},
     chunk::Chunk,
+    temporal_conversions::{
+        date32_to_date_opt, timestamp_ms_to_datetime_opt,
+        timestamp_ns_to_datetime_opt, timestamp_s_to_datetime_opt, timestamp_us_to_datetime_opt,
+    },
 };
-use csv::{Writer, WriterBuilder};
+use chrono::TimeZone;
+use csv::{ByteRecord, QuoteStyle, Terminator, Writer, WriterBuilder};
 use std::{
-    fs::{File, OpenOptions},
-    io::BufWriter,
-    path::Path,
+    fmt::{Display, LowerExp},
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
 };
 
 pub struct CsvWriter {
-    writer: Writer<BufWriter<File>>,
+    writer: Writer<BufWriter<Encoder<ChecksumWriter<OutputSink>>>>,
+    path: PathBuf,
+    headers: Option<Vec<String>>,
     headers_written: bool,
     delimiter: u8,
     quote: u8,
     na_string: String,
+    float_format: FloatFormat,
+    float_precision: Option<usize>,
+    nan_token: String,
+    inf_token: String,
+    non_finite_as_null: bool,
+    fsync: bool,
 }
 
+#[derive(Clone)]
 pub struct CsvWriterConfig {
     pub delimiter: u8,
     pub quote: u8,
     pub na_string: String,
+    pub terminator: Terminator,
+    pub quote_style: QuoteStyle,
+    pub write_bom: bool,
+    pub float_format: FloatFormat,
+    /// Digits after the point for fixed notation, or of the mantissa for
+    /// scientific
+    pub float_precision: Option<usize>,
+    pub nan_token: String,
+    /// Written for positive infinity; negative infinity gets a leading '-'
+    pub inf_token: String,
+    /// Write NaN and infinite floats as `na_string`
+    pub non_finite_as_null: bool,
+    pub fsync: bool,
+    pub overwrite: bool,
+    pub checksum: Option<ChecksumAlgorithm>,
+    pub compression: Compression,
+    pub compression_level: Option<u32>,
+    /// Append to an existing output, e.g. when resuming, instead of
+    /// creating a new file
+    pub append: bool,
+    /// Bytes of output buffered before they are written to the file
+    pub buffer_size: usize,
 }
 
 impl Default for CsvWriterConfig {
@@ -31,32 +74,81 @@ impl Default for CsvWriterConfig {
             delimiter: b',',
             quote: b'"',
             na_string: "".to_string(),
+            // LF regardless of host OS so output is reproducible
+            terminator: Terminator::Any(b'\n'),
+            quote_style: QuoteStyle::Necessary,
+            write_bom: false,
+            float_format: FloatFormat::Auto,
+            float_precision: None,
+            nan_token: "NaN".to_string(),
+            inf_token: "inf".to_string(),
+            non_finite_as_null: false,
+            fsync: false,
+            overwrite: false,
+            checksum: None,
+            compression: Compression::None,
+            compression_level: None,
+            append: false,
+            buffer_size: 8 * 1024,
         }
     }
 }
 
 impl CsvWriter {
     pub fn new<P: AsRef<Path>>(path: P, config: &CsvWriterConfig) -> Result<Self> {
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)?;
+        let path = path.as_ref();
+        let (file, has_content) = if is_stdout(path) {
+            (OutputSink::Stdout(std::io::stdout()), false)
+        } else if config.append {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let has_content = file.metadata()?.len() > 0;
+            (OutputSink::File(file), has_content)
+        } else {
+            (OutputSink::File(create_output_file(path, config.overwrite)?), false)
+        };
+
+        // The checksum covers the bytes on disk, i.e. after compression
+        let encoder = Encoder::new(
+            ChecksumWriter::new(file, config.checksum),
+            &config.compression,
+            config.compression_level,
+        )?;
+
+        let mut buffered = BufWriter::with_capacity(config.buffer_size, encoder);
+        if config.write_bom && !has_content {
+            // Every output file (including rolled parts) gets its own BOM
+            buffered.write_all(&[0xEF, 0xBB, 0xBF])?;
+        }
 
         let writer = WriterBuilder::new()
             .delimiter(config.delimiter)
             .quote(config.quote)
-            .from_writer(BufWriter::new(file));
+            .terminator(config.terminator)
+            .quote_style(config.quote_style)
+            .from_writer(buffered);
 
         Ok(Self {
             writer,
-            headers_written: false,
+            path: path.to_path_buf(),
+            headers: None,
+            headers_written: has_content,
             delimiter: config.delimiter,
             quote: config.quote,
             na_string: config.na_string.clone(),
+            float_format: config.float_format,
+            float_precision: config.float_precision,
+            nan_token: config.nan_token.clone(),
+            inf_token: config.inf_token.clone(),
+            non_finite_as_null: config.non_finite_as_null,
+            fsync: config.fsync,
         })
     }
 
+    /// Sets the column names written as the header row.
+    pub fn set_headers(&mut self, headers: Vec<String>) {
+        self.headers = Some(headers);
+    }
+
     pub fn write_batch(&mut self, batch: &Chunk<Box<dyn Array>>) -> Result<()> {
         // Write headers if not already written
         if !self.headers_written {
@@ -64,13 +156,19 @@ impl CsvWriter {
             self.headers_written = true;
         }
 
+        // Timestamp zones are parsed once per column, not once per value
+        let zones: Vec<ShownZone> = batch
+            .arrays()
+            .iter()
+            .map(|array| ShownZone::of(array.data_type()))
+            .collect();
+
         // Write data rows
         for row_idx in 0..batch.len() {
             let mut record = Vec::new();
             
-            for col_idx in 0..batch.arrays().len() {
-                let array = &*batch.arrays()[col_idx];
-                let value = self.array_value_to_string(array, row_idx)?;
+            for (array, zone) in batch.arrays().iter().zip(&zones) {
+                let value = self.array_value_to_string(&**array, row_idx, *zone)?;
                 record.push(value);
             }
             
@@ -81,38 +179,87 @@ impl CsvWriter {
         Ok(())
     }
 
+    /// Writes a header row read from delimited text, unless the output
+    /// already has one.
+    pub fn write_text_header(&mut self, headers: &ByteRecord) -> Result<()> {
+        if !self.headers_written {
+            self.writer.write_byte_record(headers)?;
+            self.headers_written = true;
+        }
+        Ok(())
+    }
+
+    /// Writes a row as read from delimited text, without parsing its
+    /// values; fields are quoted as this writer's delimiter needs.
+    pub fn write_text_record(&mut self, record: &ByteRecord) -> Result<()> {
+        self.writer.write_byte_record(record)?;
+        Ok(())
+    }
+
     fn write_headers(&mut self, batch: &Chunk<Box<dyn Array>>) -> Result<()> {
-        // For now, use generic column names
-        let headers: Vec<String> = (0..batch.arrays().len())
-            .map(|i| format!("col_{}", i + 1))
-            .collect();
+        // Fall back to generic column names when none were given
+        let headers: Vec<String> = match &self.headers {
+            Some(headers) => headers.clone(),
+            None => (0..batch.arrays().len())
+                .map(|i| format!("col_{}", i + 1))
+                .collect(),
+        };
         
         self.writer.write_record(&headers)?;
         Ok(())
     }
 
-    fn array_value_to_string(&self, array: &dyn Array, row_idx: usize) -> Result<String> {
+    /// Renders one value; `zone` is how the column's timestamps are shown.
+    fn array_value_to_string(&self, array: &dyn Array, row_idx: usize, zone: ShownZone) -> Result<String> {
         if array.is_null(row_idx) {
             return Ok(self.na_string.clone());
         }
 
         match array.data_type() {
             DataType::Utf8 => {
-                let string_array = array.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+                let string_array = downcast::<Utf8Array<i32>>(array)?;
                 Ok(string_array.value(row_idx).to_string())
             }
             DataType::Int64 => {
-                let int_array = array.as_any().downcast_ref::<Int64Array>().unwrap();
+                let int_array = downcast::<Int64Array>(array)?;
                 Ok(int_array.value(row_idx).to_string())
             }
             DataType::Float64 => {
-                let float_array = array.as_any().downcast_ref::<Float64Array>().unwrap();
-                Ok(float_array.value(row_idx).to_string())
+                let float_array = downcast::<Float64Array>(array)?;
+                Ok(self.format_float(float_array.value(row_idx)))
             }
             DataType::Boolean => {
-                let bool_array = array.as_any().downcast_ref::<BooleanArray>().unwrap();
+                let bool_array = downcast::<BooleanArray>(array)?;
                 Ok(bool_array.value(row_idx).to_string())
             }
+            DataType::Int8 => Ok(downcast::<Int8Array>(array)?.value(row_idx).to_string()),
+            DataType::Int16 => Ok(downcast::<Int16Array>(array)?.value(row_idx).to_string()),
+            DataType::Int32 => Ok(downcast::<Int32Array>(array)?.value(row_idx).to_string()),
+            DataType::Float32 => Ok(self.format_float(downcast::<Float32Array>(array)?.value(row_idx))),
+            DataType::Date32 => {
+                let days = downcast::<Int32Array>(array)?.value(row_idx);
+                date32_to_date_opt(days)
+                    .map(|date| date.to_string())
+                    .ok_or_else(|| MawError::Arrow(format!("Date32 value {} is out of range", days)))
+            }
+            DataType::Date64 => {
+                let millis = downcast::<Int64Array>(array)?.value(row_idx);
+                timestamp_ms_to_datetime_opt(millis)
+                    .map(|datetime| datetime.date().to_string())
+                    .ok_or_else(|| MawError::Arrow(format!("Date64 value {} is out of range", millis)))
+            }
+            DataType::Timestamp(unit, _) => {
+                let value = downcast::<Int64Array>(array)?.value(row_idx);
+                format_timestamp(value, *unit, zone)
+            }
+            DataType::Decimal(_, scale) => {
+                let decimal_array = downcast::<Int128Array>(array)?;
+                Ok(format_decimal(decimal_array.value(row_idx), *scale))
+            }
+            DataType::Dictionary(..) => {
+                let dictionary = downcast::<DictionaryArray<i32>>(array)?;
+                self.array_value_to_string(dictionary.values().as_ref(), dictionary.key_value(row_idx), zone)
+            }
             _ => {
                 // Default to string representation
                 Ok("unknown".to_string())
@@ -120,19 +267,116 @@ impl CsvWriter {
         }
     }
 
-    pub fn finish(self) -> Result<()> {
-        // Writer is automatically closed when dropped
+    /// Formats a float in the configured notation. Generic so Float32
+    /// values keep their own shortest representation.
+    fn format_float<F: Into<f64> + Display + LowerExp + Copy>(&self, value: F) -> String {
+        let wide: f64 = value.into();
+        if !wide.is_finite() && self.non_finite_as_null {
+            return self.na_string.clone();
+        }
+        if wide.is_nan() {
+            return self.nan_token.clone();
+        }
+        if wide.is_infinite() {
+            return if wide < 0.0 {
+                format!("-{}", self.inf_token)
+            } else {
+                self.inf_token.clone()
+            };
+        }
+
+        match (self.float_format, self.float_precision) {
+            (FloatFormat::Auto, None) => value.to_string(),
+            (FloatFormat::Auto, Some(precision)) => format!("{:.*}", precision, value),
+            (FloatFormat::Fixed, precision) => format!("{:.*}", precision.unwrap_or(6), value),
+            (FloatFormat::Scientific, None) => format!("{:e}", value),
+            (FloatFormat::Scientific, Some(precision)) => format!("{:.*e}", precision, value),
+        }
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+
+        let mut sink = self.writer
+            .into_inner()
+            .map_err(|e| MawError::Io(e.into_error()))?
+            .into_inner()
+            .map_err(|e| MawError::Io(e.into_error()))?
+            .finish()?
+            .finish(&self.path)?;
+
+        // Compressed trailers are written after the last flush above
+        sink.flush()?;
+        if self.fsync {
+            sink.sync(&self.path)?;
+        }
+
         Ok(())
     }
 }
 
+/// How a column's timestamps are shown. Zoned timestamps are UTC instants;
+/// they are shown with their zone's offset at that instant, or in UTC when
+/// the zone isn't recognised.
+#[derive(Clone, Copy)]
+enum ShownZone {
+    Naive,
+    Zoned(Zone),
+    Utc,
+}
+
+impl ShownZone {
+    /// The zone of a timestamp column, or of a dictionary's timestamp
+    /// values. Other columns have none.
+    fn of(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Timestamp(_, Some(tz)) => Zone::parse(tz).map_or(ShownZone::Utc, ShownZone::Zoned),
+            DataType::Dictionary(_, values, _) => Self::of(values),
+            _ => ShownZone::Naive,
+        }
+    }
+}
+
+/// Renders a timestamp as ISO 8601, in `zone`.
+fn format_timestamp(value: i64, unit: TimeUnit, zone: ShownZone) -> Result<String> {
+    let datetime = match unit {
+        TimeUnit::Second => timestamp_s_to_datetime_opt(value),
+        TimeUnit::Millisecond => timestamp_ms_to_datetime_opt(value),
+        TimeUnit::Microsecond => timestamp_us_to_datetime_opt(value),
+        TimeUnit::Nanosecond => timestamp_ns_to_datetime_opt(value),
+    }
+    .ok_or_else(|| MawError::Arrow(format!("Timestamp value {} is out of range", value)))?;
+
+    Ok(match zone {
+        ShownZone::Naive => datetime.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+        ShownZone::Zoned(zone) => zone
+            .offset_at_utc(&datetime)
+            .from_utc_datetime(&datetime)
+            .format("%Y-%m-%dT%H:%M:%S%.f%:z")
+            .to_string(),
+        ShownZone::Utc => datetime.format("%Y-%m-%dT%H:%M:%S%.fZ").to_string(),
+    })
+}
+
+/// Renders a decimal's unscaled value with `scale` digits after the point.
+fn format_decimal(value: i128, scale: usize) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let sign = if value < 0 { "-" } else { "" };
+    if scale == 0 {
+        return format!("{}{}", sign, digits);
+    }
+
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    format!("{}{}.{}", sign, integer, fraction)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use arrow2::{
         array::{Int64Array, Utf8Array},
         datatypes::{DataType, Field, Schema},
-        record_batch::RecordBatch,
     };
     use std::fs;
     use tempfile::tempdir;
@@ -142,17 +386,18 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let csv_file = temp_dir.path().join("output.csv");
         
-        let schema = Schema::new(vec![
+        let schema = Schema::from(vec![
             Field::new("a", DataType::Int64, false),
             Field::new("b", DataType::Utf8, false),
         ]);
         
         let a = Int64Array::from_slice([1, 2, 3]);
         let b = Utf8Array::<i32>::from_slice(["x", "y", "z"]);
-        let batch = RecordBatch::new(Arc::new(schema), vec![Arc::new(a), Arc::new(b)]);
+        let batch = Chunk::new(vec![a.boxed(), b.boxed()]);
 
         let config = CsvWriterConfig::default();
         let mut writer = CsvWriter::new(&csv_file, &config).unwrap();
+        writer.set_headers(schema.fields.iter().map(|f| f.name.clone()).collect());
         writer.write_batch(&batch).unwrap();
         writer.finish().unwrap();
 
@@ -162,4 +407,120 @@ mod tests {
         assert!(content.contains("2,y"));
         assert!(content.contains("3,z"));
     }
+
+    #[test]
+    fn test_format_decimal() {
+        assert_eq!(format_decimal(12_345, 2), "123.45");
+        assert_eq!(format_decimal(-5, 3), "-0.005");
+        assert_eq!(format_decimal(42, 0), "42");
+    }
+
+    #[test]
+    fn test_narrow_and_temporal_types() {
+        let temp_dir = tempdir().unwrap();
+        let csv_file = temp_dir.path().join("output.csv");
+
+        let batch = Chunk::new(vec![
+            Int8Array::from_slice([-8]).boxed(),
+            Int16Array::from_slice([16]).boxed(),
+            Int32Array::from_slice([32]).boxed(),
+            Float32Array::from_slice([1.5]).boxed(),
+            Int32Array::from_slice([18_628]).to(DataType::Date32).boxed(),
+            Int64Array::from_slice([1_609_459_200_000]).to(DataType::Date64).boxed(),
+            Int64Array::from_slice([1_609_459_200_123])
+                .to(DataType::Timestamp(TimeUnit::Millisecond, None))
+                .boxed(),
+            Int64Array::from_slice([1_609_459_200])
+                .to(DataType::Timestamp(TimeUnit::Second, Some("+02:00".to_string())))
+                .boxed(),
+            Int64Array::from_slice([1_609_459_200_000_000])
+                .to(DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".to_string())))
+                .boxed(),
+            Int64Array::from_slice([1_609_459_200])
+                .to(DataType::Timestamp(TimeUnit::Second, Some("Asia/Tokyo".to_string())))
+                .boxed(),
+        ]);
+
+        let mut writer = CsvWriter::new(&csv_file, &CsvWriterConfig::default()).unwrap();
+        writer.set_headers(["i8", "i16", "i32", "f32", "d32", "d64", "ts", "ts_off", "ts_utc", "ts_tokyo"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect());
+        writer.write_batch(&batch).unwrap();
+        writer.finish().unwrap();
+
+        let content = fs::read_to_string(&csv_file).unwrap();
+        assert_eq!(
+            content.lines().nth(1).unwrap(),
+            "-8,16,32,1.5,2021-01-01,2021-01-01,2021-01-01T00:00:00.123,\
+             2021-01-01T02:00:00+02:00,2021-01-01T00:00:00+00:00,2021-01-01T09:00:00+09:00"
+        );
+    }
+
+    #[test]
+    fn test_float_formatting() {
+        let temp_dir = tempdir().unwrap();
+        let batch = Chunk::new(vec![
+            Float64Array::from_slice([1.0 / 3.0, 1500.0, f64::NAN, f64::NEG_INFINITY]).boxed(),
+        ]);
+
+        let render = |config: CsvWriterConfig| {
+            let csv_file = temp_dir.path().join("output.csv");
+            let mut writer = CsvWriter::new(&csv_file, &CsvWriterConfig { overwrite: true, ..config }).unwrap();
+            writer.set_headers(vec!["x".to_string()]);
+            writer.write_batch(&batch).unwrap();
+            writer.finish().unwrap();
+            fs::read_to_string(&csv_file).unwrap()
+        };
+
+        assert_eq!(
+            render(CsvWriterConfig::default()),
+            "x\n0.3333333333333333\n1500\nNaN\n-inf\n"
+        );
+        assert_eq!(
+            render(CsvWriterConfig {
+                float_precision: Some(2),
+                nan_token: "nan".to_string(),
+                inf_token: "Infinity".to_string(),
+                ..CsvWriterConfig::default()
+            }),
+            "x\n0.33\n1500.00\nnan\n-Infinity\n"
+        );
+        assert_eq!(
+            render(CsvWriterConfig {
+                float_format: FloatFormat::Scientific,
+                float_precision: Some(1),
+                ..CsvWriterConfig::default()
+            }),
+            "x\n3.3e-1\n1.5e3\nNaN\n-inf\n"
+        );
+        assert_eq!(
+            render(CsvWriterConfig {
+                na_string: "NA".to_string(),
+                non_finite_as_null: true,
+                ..CsvWriterConfig::default()
+            }),
+            "x\n0.3333333333333333\n1500\nNA\nNA\n"
+        );
+    }
+
+    #[test]
+    fn test_buffer_size() {
+        let temp_dir = tempdir().unwrap();
+        let batch = Chunk::new(vec![Int64Array::from_slice([1, 2, 3]).boxed()]);
+
+        for buffer_size in [1, 1024 * 1024] {
+            let csv_file = temp_dir.path().join(format!("buffered_{}.csv", buffer_size));
+            let config = CsvWriterConfig {
+                buffer_size,
+                ..CsvWriterConfig::default()
+            };
+            let mut writer = CsvWriter::new(&csv_file, &config).unwrap();
+            assert_eq!(writer.writer.get_ref().capacity(), buffer_size);
+            writer.set_headers(vec!["a".to_string()]);
+            writer.write_batch(&batch).unwrap();
+            writer.finish().unwrap();
+            assert_eq!(fs::read_to_string(&csv_file).unwrap(), "a\n1\n2\n3\n");
+        }
+    }
 }
@@ -1,32 +1,71 @@
+use crate::checksum::{ChecksumAlgorithm, ChecksumWriter};
 use crate::error::{MawError, Result};
+use crate::fsutil::{
+    check_no_clobber, create_temp_file, is_stdout, persist_temp_file, sync_parent_dir,
+    temp_dir_for,
+};
 use arrow2::{
     array::Array,
-    datatypes::Schema,
+    datatypes::{DataType, Field, Schema},
     chunk::Chunk,
+    io::parquet::read::infer_schema,
+    io::parquet::write::{
+        array_to_columns, compress, to_parquet_type, transverse,
+        CompressedPage, CompressionOptions, Compressor, DynIter, DynStreamingIterator, Encoding,
+        FallibleStreamingIterator, FileWriter, Page, ParquetType, Version, WriteOptions, ZstdLevel,
+    },
 };
-use parquet2::{
-    compression::Compression,
-    write::{FileWriter, Version, WriteOptions},
-    metadata::SchemaDescriptor,
-};
+use parquet2::{compression::Compression, read::read_metadata};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
 use std::{
     fs::File,
-    io::BufWriter,
-    path::Path,
+    io::{self, BufWriter, Seek, Write},
+    path::{Path, PathBuf},
     sync::Arc,
 };
+use tempfile::NamedTempFile;
 
+/// Writes a Parquet file under a temporary name and moves it into place on
+/// `finish`, so a failed run never leaves a file without its footer. For
+/// `-` the finished file is copied to stdout instead.
 pub struct ParquetWriter {
-    writer: FileWriter<BufWriter<File>>,
+    writer: FileWriter<BufWriter<ChecksumWriter<NamedTempFile>>>,
+    path: PathBuf,
     schema: Arc<Schema>,
+    parquet_types: Vec<ParquetType>,
+    encodings: Vec<Vec<Encoding>>,
+    options: WriteOptions,
+    /// Encoded pages of the row group being built, one list per leaf column
+    pending_pages: Vec<Vec<Page>>,
+    pending_bytes: usize,
     row_group_size: usize,
-    compression: Compression,
+    /// Encodes and compresses columns in parallel, with more than one
+    /// encode thread
+    pool: Option<ThreadPool>,
+    fsync: bool,
+    overwrite: bool,
+    verify_schema: bool,
 }
 
+#[derive(Clone)]
 pub struct ParquetWriterConfig {
     pub row_group_size: usize,
     pub compression: Compression,
     pub zstd_level: u32,
+    pub fsync: bool,
+    pub overwrite: bool,
+    pub checksum: Option<ChecksumAlgorithm>,
+    /// Where the file is written until it is complete; defaults to the
+    /// output's directory
+    pub temp_dir: Option<PathBuf>,
+    /// Bytes of output buffered before they are written to the file
+    pub buffer_size: usize,
+    /// Read back the finished file's schema and fail unless it is the
+    /// schema it was written with
+    pub verify_schema: bool,
+    /// Threads that encode a batch's columns and compress a row group's
+    /// column chunks; with 1 they are done on the writer's own thread
+    pub encode_threads: usize,
 }
 
 impl Default for ParquetWriterConfig {
@@ -35,81 +74,294 @@ impl Default for ParquetWriterConfig {
             row_group_size: 128 * 1024 * 1024, // 128MB
             compression: Compression::Uncompressed,
             zstd_level: 3,
+            fsync: false,
+            overwrite: false,
+            checksum: None,
+            temp_dir: None,
+            buffer_size: 8 * 1024,
+            verify_schema: false,
+            encode_threads: 1,
         }
     }
 }
 
 impl ParquetWriter {
     pub fn new<P: AsRef<Path>>(path: P, schema: Arc<Schema>, config: &ParquetWriterConfig) -> Result<Self> {
-        let file = File::create(path)?;
-        let writer = BufWriter::new(file);
+        let path = path.as_ref();
+        if !is_stdout(path) {
+            check_no_clobber(path, config.overwrite)?;
+        }
+        let file = create_temp_file(&temp_dir_for(path, config.temp_dir.as_deref()))?;
+        let writer = BufWriter::with_capacity(config.buffer_size, ChecksumWriter::new(file, config.checksum));
 
-        let write_options = WriteOptions {
+        let compression = match config.compression {
+            Compression::Zstd => {
+                let level = ZstdLevel::try_new(config.zstd_level as i32).map_err(|_| {
+                    MawError::Config(format!(
+                        "Invalid zstd compression level {}: must be between 1 and 22",
+                        config.zstd_level
+                    ))
+                })?;
+                CompressionOptions::Zstd(Some(level))
+            }
+            Compression::Snappy => CompressionOptions::Snappy,
+            Compression::Gzip => CompressionOptions::Gzip(None),
+            _ => CompressionOptions::Uncompressed,
+        };
+
+        let options = WriteOptions {
             write_statistics: true,
+            compression,
             version: Version::V2,
+            data_pagesize_limit: None,
         };
 
-        let _compression_options = match config.compression {
-            Compression::Zstd => parquet2::compression::CompressionOptions::Zstd(Some(parquet2::compression::ZstdLevel::try_new(config.zstd_level as i32).unwrap_or_default())),
-            Compression::Snappy => parquet2::compression::CompressionOptions::Snappy,
-            Compression::Gzip => parquet2::compression::CompressionOptions::Gzip(None),
-            _ => parquet2::compression::CompressionOptions::Uncompressed,
-        };
+        let parquet_types = schema
+            .fields
+            .iter()
+            .map(to_parquet_type)
+            .collect::<arrow2::error::Result<Vec<_>>>()
+            .map_err(|e| MawError::Parquet(e.to_string()))?;
+        let encodings = schema
+            .fields
+            .iter()
+            .map(|f| {
+                transverse(&f.data_type, |data_type| match data_type {
+                    DataType::Dictionary(..) => Encoding::RleDictionary,
+                    _ => Encoding::Plain,
+                })
+            })
+            .collect::<Vec<_>>();
+        let num_leaves = encodings.iter().map(|e| e.len()).sum();
 
-        // For now, create a simple schema descriptor - in a real implementation we'd convert from Arrow schema
-        let schema_descriptor = SchemaDescriptor::new("root".to_string(), vec![]);
-        
-        let writer = FileWriter::new(
-            writer,
-            schema_descriptor,
-            write_options,
-            None, // compression_options - simplified for now
-        );
+        let writer = FileWriter::try_new(writer, schema.as_ref().clone(), options)
+            .map_err(|e| MawError::Parquet(e.to_string()))?;
+        let pool = match config.encode_threads {
+            0 | 1 => None,
+            threads => Some(
+                ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .thread_name(|i| format!("parquet-encode-{}", i))
+                    .build()
+                    .map_err(|e| MawError::Parquet(e.to_string()))?,
+            ),
+        };
 
         Ok(Self {
             writer,
+            path: path.to_path_buf(),
             schema,
+            parquet_types,
+            encodings,
+            options,
+            pending_pages: (0..num_leaves).map(|_| Vec::new()).collect(),
+            pending_bytes: 0,
             row_group_size: config.row_group_size,
-            compression: config.compression,
+            pool,
+            fsync: config.fsync,
+            overwrite: config.overwrite,
+            verify_schema: config.verify_schema,
         })
     }
 
+    /// Encodes a batch into pages of the current row group, which is written
+    /// out once its encoded size reaches `row_group_size` bytes.
     pub fn write_batch(&mut self, batch: &Chunk<Box<dyn Array>>) -> Result<()> {
-        // Convert RecordBatch to row group iterator
-        let _row_groups = self.batch_to_row_groups(batch)?;
-        
-        // For now, skip writing - in a real implementation we'd convert the batch to row groups
-        // for row_group in row_groups {
-        //     self.writer.write(row_group)?;
-        // }
+        if batch.arrays().len() != self.schema.fields.len() {
+            return Err(MawError::Schema(format!(
+                "Batch has {} columns but the output schema has {}",
+                batch.arrays().len(),
+                self.schema.fields.len()
+            )));
+        }
+
+        let options = self.options;
+        let encode = |((array, type_), encoding): ((&dyn Array, &ParquetType), &Vec<Encoding>)| {
+            array_to_columns(array.to_boxed(), type_.clone(), options, encoding)
+                .map_err(|e| MawError::Parquet(e.to_string()))?
+                .into_iter()
+                .map(|pages| pages.collect::<arrow2::error::Result<Vec<Page>>>())
+                .collect::<arrow2::error::Result<Vec<_>>>()
+                .map_err(|e| MawError::Parquet(e.to_string()))
+        };
+        let fields = batch
+            .arrays()
+            .iter()
+            .map(|array| array.as_ref())
+            .zip(&self.parquet_types)
+            .zip(&self.encodings);
+        let encoded = match &self.pool {
+            Some(pool) => pool.install(|| {
+                fields.collect::<Vec<_>>().into_par_iter().map(encode).collect::<Result<Vec<_>>>()
+            })?,
+            None => fields.map(encode).collect::<Result<Vec<_>>>()?,
+        };
+
+        for (leaf, pages) in encoded.into_iter().flatten().enumerate() {
+            for page in pages {
+                self.pending_bytes += match &page {
+                    Page::Data(page) => page.buffer().len(),
+                    Page::Dict(page) => page.buffer.len(),
+                };
+                self.pending_pages[leaf].push(page);
+            }
+        }
+
+        if self.pending_bytes >= self.row_group_size {
+            self.flush_row_group()?;
+        }
 
         Ok(())
     }
 
-    fn batch_to_row_groups(&self, _batch: &Chunk<Box<dyn Array>>) -> Result<Vec<()>> {
-        // This is a simplified implementation
-        // In a real implementation, we would properly convert the RecordBatch
-        // to Parquet row groups with the correct compression and statistics
-        
-        // For now, return empty vector as placeholder
-        Ok(vec![])
+    /// Writes out the row group being built, however small it is so far.
+    pub fn flush(&mut self) -> Result<()> {
+        self.flush_row_group()
+    }
+
+    fn flush_row_group(&mut self) -> Result<()> {
+        if self.pending_pages.iter().all(|pages| pages.is_empty()) {
+            return Ok(());
+        }
+
+        let empty = self.pending_pages.iter().map(|_| Vec::new()).collect();
+        let pages = std::mem::replace(&mut self.pending_pages, empty);
+        self.pending_bytes = 0;
+
+        let compression = self.options.compression;
+        let written = match &self.pool {
+            // Compress every column chunk up front, then write them in order
+            Some(pool) => {
+                let compressed = pool
+                    .install(|| {
+                        pages
+                            .into_par_iter()
+                            .map(|pages| {
+                                pages
+                                    .into_iter()
+                                    .map(|page| compress(page, vec![], compression))
+                                    .collect::<parquet2::error::Result<Vec<CompressedPage>>>()
+                            })
+                            .collect::<parquet2::error::Result<Vec<_>>>()
+                    })
+                    .map_err(MawError::Parquet2)?;
+                let columns = compressed.into_iter().map(|pages| {
+                    Ok(DynStreamingIterator::new(CompressedPages {
+                        pages: pages.into_iter(),
+                        current: None,
+                    }))
+                });
+                self.writer.write(DynIter::new(columns))
+            }
+            None => {
+                let columns = pages.into_iter().map(move |pages| {
+                    let pages = DynIter::new(pages.into_iter().map(Ok));
+                    let compressed = Compressor::new(pages, compression, vec![])
+                        .map_err(arrow2::error::Error::from);
+                    Ok(DynStreamingIterator::new(compressed))
+                });
+                self.writer.write(DynIter::new(columns))
+            }
+        };
+        written.map_err(|e| MawError::Parquet(e.to_string()))
     }
 
     pub fn finish(mut self) -> Result<()> {
-        self.writer.end(None).map_err(|e| MawError::Parquet2(e))?;
+        self.flush_row_group()?;
+        self.writer.end(None).map_err(|e| MawError::Parquet(e.to_string()))?;
+
+        let mut temp = self.writer
+            .into_inner()
+            .into_inner()
+            .map_err(|e| MawError::Io(e.into_error()))?
+            .finish(&self.path)?;
+        if self.verify_schema {
+            verify_schema(temp.as_file_mut(), &self.schema)?;
+        }
+
+        if is_stdout(&self.path) {
+            let file = temp.as_file_mut();
+            file.rewind()?;
+            let mut stdout = io::stdout().lock();
+            io::copy(file, &mut stdout)?;
+            stdout.flush()?;
+            return Ok(());
+        }
+
+        if self.fsync {
+            temp.as_file().sync_all()?;
+        }
+        let file = persist_temp_file(temp, &self.path, self.overwrite)?;
+        if self.fsync {
+            file.sync_all()?;
+            sync_parent_dir(&self.path)?;
+        }
+
         Ok(())
     }
 }
 
+/// Pages compressed ahead of writing, handed to the file writer one at a
+/// time as `Compressor` would hand them over.
+struct CompressedPages {
+    pages: std::vec::IntoIter<CompressedPage>,
+    current: Option<CompressedPage>,
+}
+
+impl FallibleStreamingIterator for CompressedPages {
+    type Item = CompressedPage;
+    type Error = arrow2::error::Error;
+
+    fn advance(&mut self) -> std::result::Result<(), Self::Error> {
+        self.current = self.pages.next();
+        Ok(())
+    }
+
+    fn get(&self) -> Option<&CompressedPage> {
+        self.current.as_ref()
+    }
+}
+
+/// Fails unless the schema read back from a Parquet file matches
+/// `expected` field for field, in name, type and nullability.
+fn verify_schema(file: &mut File, expected: &Schema) -> Result<()> {
+    file.rewind()?;
+    let metadata = read_metadata(file).map_err(MawError::Parquet2)?;
+    let written = infer_schema(&metadata).map_err(|e| MawError::Arrow(e.to_string()))?;
+    if written.fields.len() != expected.fields.len() {
+        return Err(MawError::Schema(format!(
+            "The Parquet output has {} columns, but was written with {}",
+            written.fields.len(),
+            expected.fields.len()
+        )));
+    }
+    let describe = |field: &Field| {
+        let nullability = if field.is_nullable { "nullable" } else { "not null" };
+        format!("'{}' {:?} ({})", field.name, field.data_type, nullability)
+    };
+    for (written, expected) in written.fields.iter().zip(&expected.fields) {
+        let matches = written.name == expected.name
+            && written.data_type == expected.data_type
+            && written.is_nullable == expected.is_nullable;
+        if !matches {
+            return Err(MawError::Schema(format!(
+                "The Parquet output has column {}, but was written as {}",
+                describe(written),
+                describe(expected)
+            )));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parquet_in::ParquetReader;
     use arrow2::{
         array::{Int64Array, Utf8Array},
-        datatypes::{DataType, Field, Schema},
-        record_batch::RecordBatch,
+        datatypes::{DataType, Field, TimeUnit},
     };
-    use std::fs;
     use tempfile::tempdir;
 
     #[test]
@@ -117,21 +369,178 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let parquet_file = temp_dir.path().join("output.parquet");
         
-        let schema = Arc::new(Schema::new(vec![
+        let schema = Arc::new(Schema::from(vec![
             Field::new("a", DataType::Int64, false),
-            Field::new("b", DataType::Utf8, false),
+            Field::new("b", DataType::Utf8, true),
         ]));
         
         let a = Int64Array::from_slice([1, 2, 3]);
-        let b = Utf8Array::<i32>::from_slice(["x", "y", "z"]);
-        let batch = RecordBatch::new(schema.clone(), vec![Arc::new(a), Arc::new(b)]);
+        let b = Utf8Array::<i32>::from([Some("x"), None, Some("z")]);
+        let batch = Chunk::new(vec![a.boxed(), b.boxed()]);
 
-        let config = ParquetWriterConfig::default();
+        // A tiny row group size puts every batch in its own row group
+        let config = ParquetWriterConfig {
+            row_group_size: 1,
+            ..ParquetWriterConfig::default()
+        };
         let mut writer = ParquetWriter::new(&parquet_file, schema, &config).unwrap();
         writer.write_batch(&batch).unwrap();
+        writer.write_batch(&batch).unwrap();
         writer.finish().unwrap();
 
-        // Verify file was created
-        assert!(parquet_file.exists());
+        let mut reader = ParquetReader::new(&parquet_file, 1000).unwrap();
+        assert_eq!(reader.get_schema().fields[1].name, "b");
+
+        let mut rows = 0;
+        while let Some(batch) = reader.read_batch().unwrap() {
+            assert!(batch.arrays()[1].is_null(1));
+            rows += batch.len();
+        }
+        assert_eq!(rows, 6);
+        assert_eq!(reader.completed_row_groups(), 2);
+    }
+
+    #[test]
+    fn test_footer() {
+        let temp_dir = tempdir().unwrap();
+        let parquet_file = temp_dir.path().join("output.parquet");
+
+        // Parquet alone would read the zone back as an offset
+        let schema = Arc::new(Schema::from(vec![Field::new(
+            "at",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("Europe/Paris".to_string())),
+            true,
+        )]));
+        let at = Int64Array::from([Some(0), None]).to(schema.fields[0].data_type.clone());
+        let mut writer = ParquetWriter::new(&parquet_file, schema.clone(), &ParquetWriterConfig::default()).unwrap();
+        writer.write_batch(&Chunk::new(vec![at.boxed()])).unwrap();
+        writer.finish().unwrap();
+
+        // arrow2 names itself without a version, so --reproducible output
+        // doesn't change with the library's release
+        let metadata = read_metadata(&mut File::open(&parquet_file).unwrap()).unwrap();
+        assert_eq!(
+            metadata.created_by.as_deref(),
+            Some("Arrow2 - Native Rust implementation of Arrow")
+        );
+        assert_eq!(infer_schema(&metadata).unwrap(), *schema);
+    }
+
+    #[test]
+    fn test_parallel_encoding() {
+        let temp_dir = tempdir().unwrap();
+        let schema = Arc::new(Schema::from(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Utf8, true),
+            Field::new("c", DataType::Int64, true),
+        ]));
+        let a = Int64Array::from_vec((0..10_000).collect());
+        let b = Utf8Array::<i32>::from_iter((0..10_000).map(|i| (i % 7 != 0).then(|| format!("row {}", i))));
+        let c = Int64Array::from_iter((0..10_000).map(|i| (i % 3 != 0).then_some(i * 31)));
+        let batch = Chunk::new(vec![a.boxed(), b.boxed(), c.boxed()]);
+
+        // Several row groups, each compressed column by column on the pool,
+        // come out exactly as one thread writes them
+        let write = |name: &str, encode_threads: usize| {
+            let path = temp_dir.path().join(name);
+            let config = ParquetWriterConfig {
+                row_group_size: 64 * 1024,
+                compression: Compression::Zstd,
+                encode_threads,
+                ..ParquetWriterConfig::default()
+            };
+            let mut writer = ParquetWriter::new(&path, schema.clone(), &config).unwrap();
+            for _ in 0..5 {
+                writer.write_batch(&batch).unwrap();
+            }
+            writer.finish().unwrap();
+            std::fs::read(path).unwrap()
+        };
+        let serial = write("serial.parquet", 1);
+        let parallel = write("parallel.parquet", 4);
+        assert!(serial == parallel, "parallel encoding changed the output");
+
+        let mut reader = ParquetReader::new(temp_dir.path().join("parallel.parquet"), 100_000).unwrap();
+        let mut rows = 0;
+        while let Some(read) = reader.read_batch().unwrap() {
+            rows += read.len();
+        }
+        assert_eq!(rows, 50_000);
+        assert!(reader.completed_row_groups() > 1);
+    }
+
+    #[test]
+    fn test_verify_schema() {
+        let temp_dir = tempdir().unwrap();
+        let schema = Arc::new(Schema::from(vec![Field::new("a", DataType::Int64, false)]));
+        let batch = Chunk::new(vec![Int64Array::from_slice([1, 2]).boxed()]);
+        let config = ParquetWriterConfig {
+            verify_schema: true,
+            ..ParquetWriterConfig::default()
+        };
+
+        let verified = temp_dir.path().join("verified.parquet");
+        let mut writer = ParquetWriter::new(&verified, schema.clone(), &config).unwrap();
+        writer.write_batch(&batch).unwrap();
+        writer.finish().unwrap();
+        assert!(verified.exists());
+
+        // A writer that meant the column to be nullable notices it isn't
+        let mismatched = temp_dir.path().join("mismatched.parquet");
+        let mut writer = ParquetWriter::new(&mismatched, schema, &config).unwrap();
+        writer.write_batch(&batch).unwrap();
+        writer.schema = Arc::new(Schema::from(vec![Field::new("a", DataType::Int64, true)]));
+        match writer.finish() {
+            Err(MawError::Schema(message)) => assert_eq!(
+                message,
+                "The Parquet output has column 'a' Int64 (not null), but was written as 'a' Int64 (nullable)"
+            ),
+            other => panic!("expected a schema error, got {:?}", other.map(|_| ())),
+        }
+        assert!(!mismatched.exists());
+    }
+
+    #[test]
+    fn test_writes_through_temp_dir() {
+        let temp_dir = tempdir().unwrap();
+        let scratch = temp_dir.path().join("scratch");
+        std::fs::create_dir(&scratch).unwrap();
+        let parquet_file = temp_dir.path().join("output.parquet");
+
+        let schema = Arc::new(Schema::from(vec![Field::new("a", DataType::Int64, false)]));
+        let config = ParquetWriterConfig {
+            row_group_size: 1,
+            temp_dir: Some(scratch.clone()),
+            ..ParquetWriterConfig::default()
+        };
+        let mut writer = ParquetWriter::new(&parquet_file, schema, &config).unwrap();
+        writer.write_batch(&Chunk::new(vec![Int64Array::from_slice([1]).boxed()])).unwrap();
+
+        // Until it is finished, the file only exists in the temp dir
+        let temps: Vec<_> = std::fs::read_dir(&scratch).unwrap().collect();
+        assert_eq!(temps.len(), 1);
+        assert!(!parquet_file.exists());
+
+        writer.finish().unwrap();
+        assert_eq!(std::fs::read_dir(&scratch).unwrap().count(), 0);
+        assert_eq!(ParquetReader::new(&parquet_file, 1000).unwrap().get_schema().fields.len(), 1);
+    }
+    #[test]
+    fn test_invalid_zstd_level() {
+        let temp_dir = tempdir().unwrap();
+        let parquet_file = temp_dir.path().join("output.parquet");
+        let schema = Arc::new(Schema::from(vec![Field::new("a", DataType::Int64, false)]));
+        let config = ParquetWriterConfig {
+            compression: Compression::Zstd,
+            zstd_level: 30,
+            ..ParquetWriterConfig::default()
+        };
+
+        match ParquetWriter::new(&parquet_file, schema, &config) {
+            Err(MawError::Config(message)) => {
+                assert_eq!(message, "Invalid zstd compression level 30: must be between 1 and 22")
+            }
+            other => panic!("expected a config error, got {:?}", other.map(|_| ())),
+        }
     }
 }
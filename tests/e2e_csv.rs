@@ -18,10 +18,10 @@ fn test_csv_to_csv_identity() {
     // Run maw
     let mut cmd = Command::cargo_bin("maw").unwrap();
     let assert = cmd
-        .arg(csv1.to_string_lossy())
-        .arg(csv2.to_string_lossy())
+        .arg(&csv1)
+        .arg(&csv2)
         .arg("-o")
-        .arg(output.to_string_lossy())
+        .arg(&output)
         .assert();
     
     assert.success();
@@ -45,7 +45,7 @@ fn test_plan_mode() {
     let mut cmd = Command::cargo_bin("maw").unwrap();
     let assert = cmd
         .arg("--plan")
-        .arg(csv1.to_string_lossy())
+        .arg(&csv1)
         .assert();
     
     assert.success().stdout(predicate::str::contains("Plan mode"));
@@ -61,8 +61,1980 @@ fn test_dry_run() {
     let mut cmd = Command::cargo_bin("maw").unwrap();
     let assert = cmd
         .arg("--dry-run")
-        .arg(csv1.to_string_lossy())
+        .arg(&csv1)
         .assert();
     
     assert.success().stdout(predicate::str::contains("Dry run mode"));
 }
+
+#[test]
+fn test_fsync_output() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a,b,c\n1,2,3\n4,5,6\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    let assert = cmd
+        .arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--fsync")
+        .assert();
+    
+    assert.success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    assert!(content.contains("1,2,3"));
+    assert!(content.contains("4,5,6"));
+}
+
+#[test]
+fn test_write_bom() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a,b\n1,2\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    let assert = cmd
+        .arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--write-bom")
+        .assert();
+    
+    assert.success();
+    
+    let bytes = fs::read(&output).unwrap();
+    assert_eq!(&bytes[..3], &[0xEF, 0xBB, 0xBF]);
+    assert!(String::from_utf8_lossy(&bytes[3..]).contains("1,2"));
+}
+
+#[test]
+fn test_crlf_line_terminator() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a,b\n1,2\n3,4\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    let assert = cmd
+        .arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--line-terminator")
+        .arg("crlf")
+        .assert();
+    
+    assert.success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    assert!(content.contains("1,2\r\n3,4\r\n"));
+}
+
+#[test]
+fn test_quote_style() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    fs::write(&csv1, "a,b\n1,x\n").unwrap();
+    
+    let necessary = temp_dir.path().join("necessary.csv");
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&necessary)
+        .arg("--quote-style")
+        .arg("necessary")
+        .assert()
+        .success();
+    
+    let always = temp_dir.path().join("always.csv");
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&always)
+        .arg("--quote-style")
+        .arg("always")
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&necessary).unwrap();
+    assert!(content.contains("1,x"));
+    
+    let content = fs::read_to_string(&always).unwrap();
+    assert!(content.contains("\"1\",\"x\""));
+}
+
+#[test]
+fn test_semicolon_round_trip() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a;b;c\n1;2;3\n4;5;6\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    let assert = cmd
+        .arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .assert();
+    
+    assert.success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    assert!(content.contains("1;2;3"));
+    assert!(content.contains("4;5;6"));
+    assert!(!content.contains(','));
+}
+
+#[test]
+fn test_unknown_output_extension() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.parquett");
+    fs::write(&csv1, "a,b\n1,2\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("supported formats: csv, parquet"));
+    
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_existing_output_refused() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a,b\n1,2\n").unwrap();
+    fs::write(&output, "existing").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("already exists"));
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--no-clobber")
+        .assert()
+        .failure();
+    
+    assert_eq!(fs::read_to_string(&output).unwrap(), "existing");
+}
+
+#[test]
+fn test_explicit_overwrite() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a,b\n1,2\n").unwrap();
+    fs::write(&output, "existing").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--overwrite")
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    assert!(content.contains("1,2"));
+    assert!(!content.contains("existing"));
+}
+
+#[test]
+fn test_checksum_sidecar() {
+    use sha2::{Digest, Sha256};
+    
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a,b\n1,2\n3,4\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--checksum")
+        .arg("sha256")
+        .assert()
+        .success();
+    
+    let expected: String = Sha256::digest(fs::read(&output).unwrap())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let sidecar = fs::read_to_string(temp_dir.path().join("output.csv.sha256")).unwrap();
+    assert_eq!(sidecar, format!("{}  output.csv\n", expected));
+}
+
+#[test]
+fn test_gzip_csv_output() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv.gz");
+    fs::write(&csv1, "a,b\n1,2\n3,4\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    
+    let mut content = String::new();
+    GzDecoder::new(fs::File::open(&output).unwrap())
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "a,b\n1,2\n3,4\n");
+}
+
+#[test]
+fn test_inspect_subcommand() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file.csv");
+    fs::write(&csv1, "id,name\n1,a\n2,b\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg("inspect")
+        .arg(&csv1)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("id: Int64"))
+        .stdout(predicate::str::contains("name: Utf8"))
+        .stdout(predicate::str::contains("(csv): 2 rows"));
+}
+
+#[test]
+fn test_head_and_tail_subcommands() {
+    let temp_dir = tempdir().unwrap();
+    let csv1 = temp_dir.path().join("file.csv");
+    let rows: String = (1..=20).map(|i| format!("{},name{}\n", i, i)).collect();
+    fs::write(&csv1, format!("id,name\n{}", rows)).unwrap();
+
+    let output = Command::cargo_bin("maw")
+        .unwrap()
+        .args(["head", "-n", "3"])
+        .arg(&csv1)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    // A header, its rule, then exactly three data rows
+    assert_eq!(lines.len(), 5, "{}", stdout);
+    assert_eq!(lines[0], "id | name");
+    assert_eq!(lines[2], "1  | name1");
+    assert_eq!(lines[4], "3  | name3");
+
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.args(["tail", "-n", "2"])
+        .arg(&csv1)
+        .assert()
+        .success()
+        .stdout("id | name\n---+-------\n19 | name19\n20 | name20\n");
+}
+
+#[test]
+fn test_convert_subcommand() {
+    use maw::parquet_in::ParquetReader;
+    
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("in.csv");
+    let csv2 = temp_dir.path().join("other.csv");
+    let output = temp_dir.path().join("out.parquet");
+    fs::write(&csv1, "id,name\n1,a\n2,b\n").unwrap();
+    fs::write(&csv2, "id,name\n3,c\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg("convert")
+        .arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    
+    let mut reader = ParquetReader::new(&output, 1000).unwrap();
+    let names: Vec<_> = reader.get_schema().fields.iter().map(|f| f.name.clone()).collect();
+    assert_eq!(names, vec!["id", "name"]);
+    let mut rows = 0;
+    while let Some(batch) = reader.read_batch().unwrap() {
+        rows += batch.len();
+    }
+    assert_eq!(rows, 2);
+    
+    // convert is strictly one input to one output
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg("convert")
+        .arg(&csv1)
+        .arg(&csv2)
+        .arg("-o")
+        .arg(temp_dir.path().join("merged.parquet"))
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("exactly one input file, found 2"));
+}
+
+#[test]
+fn test_float_precision() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "x\n0.126\n2.5\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--float-precision")
+        .arg("2")
+        .assert()
+        .success();
+    
+    assert_eq!(fs::read_to_string(&output).unwrap(), "x\n0.13\n2.50\n");
+}
+
+#[test]
+fn test_progress_style() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let csv2 = temp_dir.path().join("file2.csv");
+    fs::write(&csv1, "a\n1\n").unwrap();
+    fs::write(&csv2, "a\n2\n").unwrap();
+    
+    // Disabled progress prints nothing, whatever the style
+    let output = temp_dir.path().join("quiet.csv");
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg(&csv2)
+        .arg("-o")
+        .arg(&output)
+        .arg("--no-progress")
+        .arg("--progress-style")
+        .arg("plain")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Completed").not());
+    
+    let output = temp_dir.path().join("json.csv");
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg(&csv2)
+        .arg("-o")
+        .arg(&output)
+        .arg("--progress-style")
+        .arg("json")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("\"total_files\":2"));
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("--progress-style")
+        .arg("fancy")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_dry_run_estimate() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a,b\n1,x\n2,y\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Estimated rows: 2"))
+        .stdout(predicate::str::contains("Estimated CSV output: ~12 bytes"));
+    
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_unpivot() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("wide.csv");
+    let output = temp_dir.path().join("long.csv");
+    fs::write(&csv1, "id,region,q1,q2,q3\n1,n,10,11,12\n2,s,20,,22\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--unpivot")
+        .arg("id,region:q1,q2,q3")
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 1 + 2 * 3);
+    assert_eq!(lines[0], "id,region,variable,value");
+    assert_eq!(lines[1], "1,n,q1,10");
+    assert_eq!(lines[5], "2,s,q2,");
+    
+    let output = temp_dir.path().join("long_no_nulls.csv");
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--unpivot")
+        .arg("id,region:q1,q2,q3")
+        .arg("--unpivot-drop-nulls")
+        .assert()
+        .success();
+    
+    assert_eq!(fs::read_to_string(&output).unwrap().lines().count(), 1 + 5);
+}
+
+#[test]
+fn test_pivot() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("long.csv");
+    let output = temp_dir.path().join("wide.csv");
+    fs::write(&csv1, "id,quarter,sales\n1,q1,10\n1,q2,11\n2,q2,21\n2,q3,22\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--pivot")
+        .arg("id:quarter:sales")
+        .assert()
+        .success();
+    
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "id,q1,q2,q3\n1,10,11,\n2,,21,22\n"
+    );
+}
+
+#[test]
+fn test_sample_with_seed() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("rows.csv");
+    let mut content = String::from("id,value\n");
+    for i in 0..500 {
+        content.push_str(&format!("{},{}\n", i, i * 2));
+    }
+    fs::write(&csv1, content).unwrap();
+    
+    let sample = |name: &str| {
+        let output = temp_dir.path().join(name);
+        Command::cargo_bin("maw").unwrap()
+            .arg(&csv1)
+            .arg("-o")
+            .arg(&output)
+            .arg("--sample")
+            .arg("40")
+            .arg("--seed")
+            .arg("42")
+            .assert()
+            .success();
+        fs::read_to_string(&output).unwrap()
+    };
+    
+    let first = sample("first.csv");
+    assert_eq!(first.lines().count(), 41);
+    assert_eq!(first, sample("second.csv"));
+}
+
+#[test]
+fn test_sample_with_seed_across_inputs() {
+    let temp_dir = tempdir().unwrap();
+    
+    // Several files read concurrently in small batches, so their rows
+    // reach the sampler interleaved differently from run to run
+    let inputs: Vec<_> = (0..3)
+        .map(|file| {
+            let path = temp_dir.path().join(format!("part{}.csv", file));
+            let mut content = String::from("file,id\n");
+            for i in 0..300 {
+                content.push_str(&format!("{},{}\n", file, i));
+            }
+            fs::write(&path, content).unwrap();
+            path
+        })
+        .collect();
+    
+    let sample = |name: &str| {
+        let output = temp_dir.path().join(name);
+        Command::cargo_bin("maw").unwrap()
+            .args(&inputs)
+            .arg("-o")
+            .arg(&output)
+            .arg("--batch-size")
+            .arg("16")
+            .arg("--sample")
+            .arg("50")
+            .arg("--seed")
+            .arg("7")
+            .assert()
+            .success();
+        fs::read_to_string(&output).unwrap()
+    };
+    
+    let first = sample("first.csv");
+    assert_eq!(first.lines().count(), 51);
+    for file in ["0,", "1,", "2,"] {
+        assert!(first.lines().any(|line| line.starts_with(file)));
+    }
+    for run in 0..4 {
+        assert_eq!(sample(&format!("run{}.csv", run)), first);
+    }
+}
+
+#[test]
+fn test_column_order() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("input.csv");
+    let output = temp_dir.path().join("ordered.csv");
+    fs::write(&csv1, "a,b,c,d\n1,2,3,4\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--exclude")
+        .arg("d")
+        .arg("--rename")
+        .arg("a=alpha")
+        .arg("--column-order")
+        .arg("c,alpha")
+        .assert()
+        .success();
+    
+    // b isn't listed, so it follows the requested columns
+    assert_eq!(fs::read_to_string(&output).unwrap(), "c,alpha,b\n3,1,2\n");
+}
+
+#[test]
+fn test_rename_regex() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("input.csv");
+    let output = temp_dir.path().join("renamed.csv");
+    fs::write(&csv1, "col_id,col_name,COL_Age,score\n1,alice,30,9\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--rename-regex")
+        .arg("s/^col_//i")
+        .assert()
+        .success();
+    
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id,name,Age,score\n1,alice,30,9\n");
+    
+    // Two columns of one input can't end up with the same name
+    let clash = temp_dir.path().join("clash.csv");
+    fs::write(&clash, "col_id,id\n1,2\n").unwrap();
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&clash)
+        .arg("-o")
+        .arg(temp_dir.path().join("clash_out.csv"))
+        .arg("--rename-regex")
+        .arg("s/^col_//")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Columns 'col_id' and 'id' are both renamed to 'id'"));
+}
+
+#[test]
+fn test_lowercase_columns() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("input.csv");
+    let output = temp_dir.path().join("lower.csv");
+    fs::write(&csv1, "ID,Name\n1,alice\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--lowercase-columns")
+        .assert()
+        .success();
+    
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id,name\n1,alice\n");
+    
+    let clash = temp_dir.path().join("clash.csv");
+    fs::write(&clash, "Id,ID\n1,2\n").unwrap();
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&clash)
+        .arg("-o")
+        .arg(temp_dir.path().join("clash_out.csv"))
+        .arg("--uppercase-columns")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Columns 'Id' and 'ID' are both renamed to 'ID'"));
+}
+
+#[test]
+fn test_schema_cache() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let csv2 = temp_dir.path().join("file2.csv");
+    let cache = temp_dir.path().join("schemas.json");
+    fs::write(&csv1, "id,name\n1,alice\n").unwrap();
+    fs::write(&csv2, "id,score\n2,1.5\n").unwrap();
+    
+    let run = |expected: &str| {
+        let mut cmd = Command::cargo_bin("maw").unwrap();
+        cmd.arg(&csv1)
+            .arg(&csv2)
+            .arg("-o")
+            .arg(temp_dir.path().join("output.csv"))
+            .arg("--overwrite")
+            .arg("--schema-cache")
+            .arg(&cache)
+            .arg("-v")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(expected));
+    };
+    
+    run("Schema cache: 0 hits, 2 misses");
+    assert!(cache.exists());
+    
+    // Unchanged files aren't sampled again
+    run("Schema cache: 2 hits, 0 misses");
+    
+    // A changed file is sampled again
+    fs::write(&csv2, "id,score,flag\n2,1.5,true\n").unwrap();
+    run("Schema cache: 1 hits, 1 misses");
+    let output = fs::read_to_string(temp_dir.path().join("output.csv")).unwrap();
+    assert!(output.starts_with("id,name,score,flag\n"), "{}", output);
+}
+
+#[test]
+fn test_rejects() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("input.csv");
+    let output = temp_dir.path().join("output.csv");
+    let rejects = temp_dir.path().join("rejects.csv");
+    // Sampling only the first two rows types `age` as an integer
+    fs::write(&csv1, "id,age\n1,30\n2,31\n3,abc\n4,40\n5,x\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--infer-rows")
+        .arg("2")
+        .arg("--coerce-errors")
+        .arg("skip")
+        .arg("--rejects")
+        .arg(&rejects)
+        .assert()
+        .success();
+    
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id,age\n1,30\n2,31\n4,40\n");
+    assert_eq!(
+        fs::read_to_string(&rejects).unwrap(),
+        "id,age,reason\n\
+         3,abc,Cannot convert 'abc' in column 'age' to Int64\n\
+         5,x,Cannot convert 'x' in column 'age' to Int64\n"
+    );
+}
+
+#[test]
+fn test_validate_field_count() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("input.csv");
+    let output = temp_dir.path().join("output.csv");
+    let rejects = temp_dir.path().join("rejects.csv");
+    fs::write(&csv1, "id,name\n1,a\n2\n3,c\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--validate-field-count")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Line 3 has 1 fields, but the header has 2"));
+    
+    // With a rejects file the short row is set aside and the rest written
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--overwrite")
+        .arg("--validate-field-count")
+        .arg("--rejects")
+        .arg(&rejects)
+        .assert()
+        .success();
+    
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id,name\n1,a\n3,c\n");
+    assert_eq!(
+        fs::read_to_string(&rejects).unwrap(),
+        "id,name,reason\n2,,\"Line 3 has 1 fields, but the header has 2\"\n"
+    );
+
+    // Subcommands that write no rejects file still fail on the row, rather
+    // than dropping it
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg("head")
+        .arg(&csv1)
+        .arg("--validate-field-count")
+        .arg("--rejects")
+        .arg(&rejects)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Line 3 has 1 fields, but the header has 2"));
+}
+
+#[test]
+fn test_rejects_unreadable_rows() {
+    let temp_dir = tempdir().unwrap();
+
+    let csv1 = temp_dir.path().join("input.csv");
+    let output = temp_dir.path().join("output.csv");
+    let rejects = temp_dir.path().join("rejects.csv");
+    fs::write(&csv1, "id,name\n1,a\n2\n3,c\n").unwrap();
+
+    // A ragged row goes to the rejects file without --validate-field-count
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--rejects")
+        .arg(&rejects)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id,name\n1,a\n3,c\n");
+    assert_eq!(
+        fs::read_to_string(&rejects).unwrap(),
+        "id,name,reason\n2,,\"Line 3 has 1 fields, but the header has 2\"\n"
+    );
+
+    // So does a JSON line that isn't an object
+    let json1 = temp_dir.path().join("input.jsonl");
+    fs::write(&json1, "{\"id\": 1}\nnot json\n{\"id\": 3}\n").unwrap();
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&json1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--overwrite")
+        .arg("--rejects")
+        .arg(&rejects)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id\n1\n3\n");
+    assert_eq!(
+        fs::read_to_string(&rejects).unwrap(),
+        "id,reason\n,Line 2 is not a JSON object: not json\n"
+    );
+}
+
+#[test]
+fn test_compute_column() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("input.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "item,price,qty\na,2.5,4\nb,10,\nc,3,0\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--compute")
+        .arg("total = price * qty")
+        .arg("--compute")
+        .arg("each = qty / qty")
+        .assert()
+        .success();
+    
+    // A null operand or a division by zero gives null
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "item,price,qty,total,each\na,2.5,4,10,1\nb,10,,,\nc,3,0,0,\n"
+    );
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--compute")
+        .arg("total = price * qty")
+        .arg("--plan")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("    total: Float64\n      computed from price * qty\n"))
+        .stdout(predicate::str::contains("lacks it").not());
+}
+
+#[test]
+fn test_transcode_tsv_to_csv() {
+    let temp_dir = tempdir().unwrap();
+    
+    let tsv1 = temp_dir.path().join("a.tsv");
+    let tsv2 = temp_dir.path().join("b.tsv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&tsv1, "id\tnote\n1\tred, green\n2\tsaid \"hi\"\n").unwrap();
+    fs::write(&tsv2, "id\tnote\n007\tNA\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&tsv1)
+        .arg(&tsv2)
+        .arg("-o")
+        .arg(&output)
+        .arg("--tsv-to-csv")
+        .arg("-v")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Transcoded 3 rows field by field"));
+    
+    // Fields with commas or quotes are quoted for the new delimiter, and
+    // values are kept exactly as written
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "id,note\n1,\"red, green\"\n2,\"said \"\"hi\"\"\"\n007,NA\n"
+    );
+    
+    // Inputs with different headers take the normal path
+    fs::write(&tsv2, "id\tother\n3\tx\n").unwrap();
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&tsv1)
+        .arg(&tsv2)
+        .arg("-o")
+        .arg(&output)
+        .arg("--overwrite")
+        .arg("--transcode")
+        .arg("-v")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Not transcoding field by field, as"));
+    
+    // So do runs that check each row as it is parsed, and fail the check
+    fs::write(&tsv2, "id\tnote\n3\tx\n").unwrap();
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&tsv1)
+        .arg(&tsv2)
+        .arg("-o")
+        .arg(&output)
+        .arg("--overwrite")
+        .arg("--transcode")
+        .arg("--max-columns")
+        .arg("1")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("more than --max-columns 1"));
+}
+
+#[test]
+fn test_schema_sidecar() {
+    use maw::{
+        schema::{SchemaSidecar, TypeKind},
+        stream::{stream_chunks, StreamBuilder},
+    };
+    
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("input.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "id,name,score,day\n1,a,1.5,2024-01-31\n2,,2,2024-02-01\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg("-o")
+        .arg(&output)
+        .arg("--write-schema-sidecar")
+        .assert()
+        .success();
+    
+    let sidecar_path = temp_dir.path().join("output.csv.schema.json");
+    let sidecar: SchemaSidecar = serde_json::from_str(&fs::read_to_string(&sidecar_path).unwrap()).unwrap();
+    let stream = stream_chunks(StreamBuilder::new([csv1.to_string_lossy().to_string()])).unwrap();
+    assert_eq!(sidecar, SchemaSidecar::new(stream.schema()));
+    let types: Vec<_> = sidecar.columns.iter().map(|c| (c.name.as_str(), c.type_kind.clone())).collect();
+    assert_eq!(types[0], ("id", TypeKind::I64));
+    assert_eq!(types[2], ("score", TypeKind::F64));
+    
+    // A sidecar left behind is not replaced without --overwrite
+    fs::remove_file(&output).unwrap();
+    fs::write(&sidecar_path, "{}").unwrap();
+    let write = |overwrite: bool| {
+        let mut cmd = Command::cargo_bin("maw").unwrap();
+        cmd.arg(&csv1).arg("-o").arg(&output).arg("--write-schema-sidecar");
+        if overwrite {
+            cmd.arg("--overwrite");
+        }
+        cmd.assert()
+    };
+    write(false)
+        .failure()
+        .stdout(predicate::str::contains("output.csv.schema.json' already exists"));
+    assert_eq!(fs::read_to_string(&sidecar_path).unwrap(), "{}");
+    assert!(!output.exists());
+    write(true).success();
+    let replaced: SchemaSidecar = serde_json::from_str(&fs::read_to_string(&sidecar_path).unwrap()).unwrap();
+    assert_eq!(replaced, sidecar);
+}
+
+#[test]
+fn test_bzip2_input() {
+    use bzip2::{write::BzEncoder, Compression};
+    use std::io::Write;
+    
+    let temp_dir = tempdir().unwrap();
+    
+    let compressed = temp_dir.path().join("input.csv.bz2");
+    let output = temp_dir.path().join("output.csv");
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(b"id;name\n1;alice\n2;bob\n").unwrap();
+    fs::write(&compressed, encoder.finish().unwrap()).unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(temp_dir.path())
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    
+    // The delimiter is sniffed from the decompressed data
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id;name\n1;alice\n2;bob\n");
+}
+
+#[test]
+fn test_count() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let csv2 = temp_dir.path().join("file2.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a,b\n1,x\n2,y\n3,z\n").unwrap();
+    fs::write(&csv2, "a,c\n4,true\n5,false\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg(&csv2)
+        .arg("-o")
+        .arg(&output)
+        .arg("--count")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file1.csv: 3 rows"))
+        .stdout(predicate::str::contains("file2.csv: 2 rows"))
+        .stdout(predicate::str::contains("Total: 5 rows"));
+    
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_input_list() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("c.csv");
+    let csv2 = temp_dir.path().join("a.csv");
+    let csv3 = temp_dir.path().join("b.csv");
+    fs::write(&csv1, "id\n1\n").unwrap();
+    fs::write(&csv2, "id\n2\n3\n").unwrap();
+    fs::write(&csv3, "id\n4\n5\n6\n").unwrap();
+    
+    let list = temp_dir.path().join("inputs.txt");
+    fs::write(
+        &list,
+        format!(
+            "# inputs, in order\n{}\n\n{}\n{}\n",
+            csv1.display(),
+            csv2.display(),
+            csv3.display()
+        ),
+    )
+    .unwrap();
+    
+    let output = Command::cargo_bin("maw").unwrap()
+        .arg(format!("@{}", list.display()))
+        .arg("--count")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let counts: Vec<&str> = stdout.lines().filter(|line| line.contains(" rows")).collect();
+    assert_eq!(counts.len(), 4, "{}", stdout);
+    assert!(counts[0].ends_with("c.csv: 1 rows"));
+    assert!(counts[1].ends_with("a.csv: 2 rows"));
+    assert!(counts[2].ends_with("b.csv: 3 rows"));
+    assert_eq!(counts[3], "Total: 6 rows");
+}
+
+#[test]
+fn test_output_to_stdout() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let csv2 = temp_dir.path().join("file2.csv");
+    fs::write(&csv1, "id,name\n1,alice\n2,bob\n").unwrap();
+    fs::write(&csv2, "id,name\n3,carol\n").unwrap();
+    
+    // Only the CSV goes to stdout; logs go to stderr
+    let output = Command::cargo_bin("maw").unwrap()
+        .arg(&csv1)
+        .arg(&csv2)
+        .args(["-o", "-", "-v"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!output.stderr.is_empty());
+    
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines: Vec<&str> = stdout.lines().collect();
+    lines[1..].sort();
+    assert_eq!(lines, ["id,name", "1,alice", "2,bob", "3,carol"]);
+    
+    // Piped into another process that reads stdin
+    let mut producer = std::process::Command::new(assert_cmd::cargo::cargo_bin("maw"))
+        .arg(&csv1)
+        .args(["-o", "-"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let piped = temp_dir.path().join("piped.csv");
+    let status = std::process::Command::new(assert_cmd::cargo::cargo_bin("maw"))
+        .args(["-", "-o"])
+        .arg(&piped)
+        .stdin(producer.stdout.take().unwrap())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(producer.wait().unwrap().success());
+    assert_eq!(fs::read_to_string(&piped).unwrap(), "id,name\n1,alice\n2,bob\n");
+}
+
+#[test]
+fn test_content_hash() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let csv2 = temp_dir.path().join("file2.csv");
+    let changed = temp_dir.path().join("changed.csv");
+    fs::write(&csv1, "id,name\n1,alice\n2,bob\n").unwrap();
+    fs::write(&csv2, "name,id\ncarol,3\nbob,2\n").unwrap();
+    fs::write(&changed, "name,id\ncarol,3\nbob,4\n").unwrap();
+    
+    let content_hash = |inputs: &[&std::path::Path]| {
+        let output = Command::cargo_bin("maw").unwrap()
+            .args(inputs)
+            .arg("--content-hash")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    
+    let forward = content_hash(&[&csv1, &csv2]);
+    assert!(forward.contains("(4 rows)"), "{}", forward);
+    assert_eq!(forward, content_hash(&[&csv2, &csv1]));
+    assert_ne!(forward, content_hash(&[&csv1, &changed]));
+}
+
+#[test]
+fn test_encoding_for() {
+    let temp_dir = tempdir().unwrap();
+    
+    let inputs = temp_dir.path().join("inputs");
+    let legacy = inputs.join("legacy");
+    let output = temp_dir.path().join("output.csv");
+    fs::create_dir_all(&legacy).unwrap();
+    fs::write(inputs.join("current.csv"), "name\ncafé\n").unwrap();
+    // "naïve" in Latin-1
+    fs::write(legacy.join("old.csv"), b"name\nna\xefve\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&inputs)
+        .arg("-o")
+        .arg(&output)
+        .arg("--encoding-for")
+        .arg("legacy/*.csv=latin1")
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    assert_eq!(lines, vec!["café", "name", "naïve"]);
+}
+
+#[test]
+fn test_reproducible_parquet() {
+    let temp_dir = tempdir().unwrap();
+    
+    let mut inputs = Vec::new();
+    for i in 0..4 {
+        let input = temp_dir.path().join(format!("part{}.csv", i));
+        let rows: String = (0..500).map(|n| format!("{},name{}\n", i * 1000 + n, n)).collect();
+        fs::write(&input, format!("id,name\n{}", rows)).unwrap();
+        inputs.push(input);
+    }
+    
+    let write = |name: &str| {
+        let output = temp_dir.path().join(name);
+        Command::cargo_bin("maw").unwrap()
+            .args(&inputs)
+            .arg("-o")
+            .arg(&output)
+            .arg("--reproducible")
+            .arg("--batch-size")
+            .arg("100")
+            .assert()
+            .success();
+        fs::read(&output).unwrap()
+    };
+    
+    assert_eq!(write("first.parquet"), write("second.parquet"));
+}
+
+#[test]
+fn test_preserve_order() {
+    let temp_dir = tempdir().unwrap();
+    
+    let mut inputs = Vec::new();
+    let mut expected = String::from("id,name\n");
+    for i in 0..4 {
+        let input = temp_dir.path().join(format!("part{}.csv", i));
+        let rows: String = (0..300).map(|n| format!("{},name{}\n", i * 1000 + n, n)).collect();
+        fs::write(&input, format!("id,name\n{}", rows)).unwrap();
+        expected.push_str(&rows);
+        inputs.push(input);
+    }
+    
+    let write = |name: &str, flag: &[&str]| {
+        let output = temp_dir.path().join(name);
+        Command::cargo_bin("maw").unwrap()
+            .args(&inputs)
+            .arg("-o")
+            .arg(&output)
+            .args(flag)
+            .arg("--batch-size")
+            .arg("50")
+            .assert()
+            .success();
+        fs::read_to_string(&output).unwrap()
+    };
+    
+    // Rows come out in input order, on every run
+    let first = write("first.csv", &["--preserve-order"]);
+    assert_eq!(first, expected);
+    assert_eq!(write("second.csv", &["--preserve-order"]), first);
+    // A seed implies it
+    assert_eq!(write("seeded.csv", &["--seed", "7"]), first);
+}
+
+#[test]
+fn test_join_subcommand() {
+    let temp_dir = tempdir().unwrap();
+    
+    let people = temp_dir.path().join("people.csv");
+    let cities = temp_dir.path().join("cities.csv");
+    fs::write(&people, "id,name\n1,alice\n2,bob\n3,carol\n").unwrap();
+    fs::write(&cities, "id,city\n1,paris\n3,rome\n3,oslo\n4,lima\n").unwrap();
+    
+    let join = |join_type: &str| {
+        let output = temp_dir.path().join(format!("{}.csv", join_type));
+        Command::cargo_bin("maw").unwrap()
+            .arg("join")
+            .arg("--left")
+            .arg(&people)
+            .arg("--right")
+            .arg(&cities)
+            .arg("--on")
+            .arg("id")
+            .arg("--join-type")
+            .arg(join_type)
+            .arg("-o")
+            .arg(&output)
+            .assert()
+            .success();
+        let content = fs::read_to_string(&output).unwrap();
+        let mut lines: Vec<String> = content.lines().map(String::from).collect();
+        lines.sort();
+        lines
+    };
+    
+    assert_eq!(
+        join("inner"),
+        vec!["1,alice,paris", "3,carol,oslo", "3,carol,rome", "id,name,city"]
+    );
+    assert_eq!(
+        join("left"),
+        vec!["1,alice,paris", "2,bob,", "3,carol,oslo", "3,carol,rome", "id,name,city"]
+    );
+}
+
+#[test]
+fn test_fill() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let csv2 = temp_dir.path().join("file2.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "id,score,name\n1,10,alice\n2,,\n").unwrap();
+    // No name column at all
+    fs::write(&csv2, "id,score\n3,\n4,7\n").unwrap();
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&csv1)
+        .arg(&csv2)
+        .arg("-o")
+        .arg(&output)
+        .arg("--fill")
+        .arg("score=0,name=unknown")
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        vec!["1,10,alice", "2,0,unknown", "3,0,unknown", "4,7,unknown", "id,score,name"]
+    );
+    
+    let rejected = temp_dir.path().join("rejected.csv");
+    Command::cargo_bin("maw").unwrap()
+        .arg(&csv1)
+        .arg("-o")
+        .arg(&rejected)
+        .arg("--fill")
+        .arg("score=none")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("--fill value 'none'"));
+    assert!(!rejected.exists());
+}
+
+#[test]
+fn test_first_n_files() {
+    let temp_dir = tempdir().unwrap();
+    
+    let inputs = temp_dir.path().join("inputs");
+    let output = temp_dir.path().join("output.csv");
+    fs::create_dir(&inputs).unwrap();
+    for name in ["d", "b", "e", "a", "c"] {
+        fs::write(inputs.join(format!("{}.csv", name)), format!("file\n{}\n", name)).unwrap();
+    }
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&inputs)
+        .arg("-o")
+        .arg(&output)
+        .arg("--first-n-files")
+        .arg("2")
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    assert_eq!(lines, vec!["a", "b", "file"]);
+}
+
+#[test]
+fn test_dictionary_strings() {
+    use arrow2::datatypes::DataType;
+    use maw::parquet_in::ParquetReader;
+    
+    let temp_dir = tempdir().unwrap();
+    
+    let csv = temp_dir.path().join("input.csv");
+    let parquet = temp_dir.path().join("output.parquet");
+    let roundtrip = temp_dir.path().join("roundtrip.csv");
+    let input = "id,region,note\n1,north,a\n2,south,b\n3,north,c\n4,north,d\n5,,e\n6,south,f\n";
+    fs::write(&csv, input).unwrap();
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&csv)
+        .arg("-o")
+        .arg(&parquet)
+        .arg("--dictionary-strings")
+        .assert()
+        .success();
+    
+    // Only the column that repeats its values is encoded
+    let mut reader = ParquetReader::new(&parquet, 1000).unwrap();
+    let types: Vec<_> = reader.get_schema().fields.iter().map(|f| f.data_type().clone()).collect();
+    assert!(matches!(types[1], DataType::Dictionary(..)));
+    assert_eq!(types[2], DataType::Utf8);
+    let batch = reader.read_batch().unwrap().unwrap();
+    assert!(matches!(batch.arrays()[1].data_type(), DataType::Dictionary(..)));
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&parquet)
+        .arg("-o")
+        .arg(&roundtrip)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&roundtrip).unwrap(), input);
+}
+
+#[test]
+fn test_psv_round_trip() {
+    let temp_dir = tempdir().unwrap();
+    
+    let input = temp_dir.path().join("input.psv");
+    let parquet = temp_dir.path().join("middle.parquet");
+    let output = temp_dir.path().join("output.psv");
+    // The comma in one value doesn't sway the delimiter
+    let content = "id|name|note\n1|alice|x,y\n2|bob|z\n";
+    fs::write(&input, content).unwrap();
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&input)
+        .arg("-o")
+        .arg(&parquet)
+        .assert()
+        .success();
+    
+    // The output's own extension picks the delimiter, with no CSV input to follow
+    Command::cargo_bin("maw").unwrap()
+        .arg(&parquet)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output).unwrap(), content);
+}
+
+#[test]
+fn test_plan_tree() {
+    let temp_dir = tempdir().unwrap();
+    
+    let csv1 = temp_dir.path().join("file1.csv");
+    let csv2 = temp_dir.path().join("file2.csv");
+    let output = temp_dir.path().join("output.parquet");
+    fs::write(&csv1, "id,score\n1,10\n").unwrap();
+    fs::write(&csv2, "id,score,name\n2,2.5,bob\n").unwrap();
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&csv1)
+        .arg(&csv2)
+        .arg("-o")
+        .arg(&output)
+        .arg("--fill")
+        .arg("name=unknown")
+        .arg("--plan")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("inputs: 2 discovered"))
+        .stdout(predicate::str::contains("csv reader: "))
+        .stdout(predicate::str::contains("    score: Float64\n      cast from Int64 in "))
+        .stdout(predicate::str::contains("nulls in "))
+        .stdout(predicate::str::contains("    fill name=unknown"))
+        .stdout(predicate::str::contains("parquet writer (compression snappy)"));
+    assert!(!output.exists());
+    
+    let assert = Command::cargo_bin("maw").unwrap()
+        .arg(&csv1)
+        .arg(&csv2)
+        .arg("--explain")
+        .arg("--plan-format")
+        .arg("json")
+        .assert()
+        .success();
+    let plan: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let unify = &plan["children"][1];
+    assert_eq!(unify["label"], "unify: 3 columns");
+    assert_eq!(unify["children"][1]["label"], "score: Float64");
+    let cast = unify["children"][1]["children"][0]["label"].as_str().unwrap();
+    assert!(cast.starts_with("cast from Int64 in "), "{}", cast);
+    
+    // The transforms are listed in the order rows go through them
+    Command::cargo_bin("maw").unwrap()
+        .arg(&csv1)
+        .arg(&csv2)
+        .arg("--max-columns")
+        .arg("50")
+        .arg("--validate-field-count")
+        .arg("--drop-null-columns")
+        .arg("0.9")
+        .arg("--rename")
+        .arg("id=key")
+        .arg("--sample")
+        .arg("5")
+        .arg("--plan")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "  transforms\n    fail on csv rows of over 50 columns\n    \
+             fail on csv rows with more or fewer fields than the header\n    \
+             drop columns over 0.9 null in the samples: none\n    \
+             rename id to key\n    sample 5 rows\n",
+        ));
+}
+
+#[test]
+fn test_partition_by_more_partitions_than_open_files() {
+    use maw::parquet_in::ParquetReader;
+    
+    let temp_dir = tempdir().unwrap();
+    
+    let input = temp_dir.path().join("input.csv");
+    // Partitions interleave, so with two files open they're closed and
+    // reopened over and over
+    let mut content = String::from("id,region\n");
+    for id in 0..20 {
+        content.push_str(&format!("{},r{}\n", id, id % 5));
+    }
+    content.push_str("20,\n");
+    fs::write(&input, content).unwrap();
+    
+    let output = temp_dir.path().join("by_region");
+    Command::cargo_bin("maw").unwrap()
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--partition-by")
+        .arg("region")
+        .arg("--max-open-files")
+        .arg("2")
+        .arg("--batch-size")
+        .arg("3")
+        .assert()
+        .success();
+    
+    for region in 0..5 {
+        let part = output.join(format!("region=r{}", region)).join("part-00000.csv");
+        let written = fs::read_to_string(&part).unwrap();
+        let expected: String = (0..20)
+            .filter(|id| id % 5 == region)
+            .map(|id| format!("{},r{}\n", id, region))
+            .collect();
+        assert_eq!(written, format!("id,region\n{}", expected));
+    }
+    let nulls = output.join("region=__HIVE_DEFAULT_PARTITION__").join("part-00000.csv");
+    assert_eq!(fs::read_to_string(nulls).unwrap(), "id,region\n20,\n");
+    
+    // Parquet can't be appended to, so a reopened partition gets a new part
+    let output = temp_dir.path().join("parquet");
+    Command::cargo_bin("maw").unwrap()
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--out-format")
+        .arg("parquet")
+        .arg("--partition-by")
+        .arg("region")
+        .arg("--max-open-files")
+        .arg("2")
+        .arg("--batch-size")
+        .arg("3")
+        .assert()
+        .success();
+    
+    let partition = output.join("region=r0");
+    let mut parts: Vec<_> = fs::read_dir(&partition).unwrap().map(|e| e.unwrap().path()).collect();
+    parts.sort();
+    assert!(parts.len() > 1);
+    let mut rows = 0;
+    for part in parts {
+        let mut reader = ParquetReader::new(&part, 1000).unwrap();
+        while let Some(batch) = reader.read_batch().unwrap() {
+            rows += batch.len();
+        }
+    }
+    assert_eq!(rows, 4);
+}
+
+#[test]
+fn test_source_column_relative_to() {
+    let temp_dir = tempdir().unwrap();
+    let data = temp_dir.path().join("data");
+    fs::create_dir_all(data.join("sub")).unwrap();
+    fs::write(data.join("sub").join("a.csv"), "id\n1\n").unwrap();
+    fs::write(data.join("b.csv"), "id\n2\n").unwrap();
+    let outside = temp_dir.path().join("other.csv");
+    fs::write(&outside, "id\n3\n").unwrap();
+    let output = temp_dir.path().join("output.csv");
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&data)
+        .arg(&outside)
+        .arg("-o")
+        .arg(&output)
+        .arg("--source-column")
+        .arg("src")
+        .arg("--relative-to")
+        .arg(&data)
+        .arg("-v")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("is outside --relative-to"));
+    
+    let written = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = written.lines().collect();
+    assert_eq!(lines.remove(0), "id,src");
+    lines.sort();
+    let outside = outside.canonicalize().unwrap();
+    assert_eq!(
+        lines,
+        vec![
+            "1,sub/a.csv".to_string(),
+            "2,b.csv".to_string(),
+            format!("3,{}", outside.display()),
+        ]
+    );
+}
+
+#[test]
+fn test_roll_by_rows_with_name_template() {
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("input.csv");
+    let rows: String = (0..10).map(|id| format!("{}\n", id)).collect();
+    fs::write(&input, format!("id\n{}", rows)).unwrap();
+    let output = temp_dir.path().join("rolled");
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--roll-by-rows")
+        .arg("4")
+        .arg("--name-template")
+        .arg("chunk-{index:03}.{ext}")
+        .arg("--batch-size")
+        .arg("3")
+        .assert()
+        .success();
+    
+    let mut names: Vec<String> = fs::read_dir(&output)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["chunk-000.csv", "chunk-001.csv", "chunk-002.csv"]);
+    assert_eq!(fs::read_to_string(output.join("chunk-001.csv")).unwrap(), "id\n4\n5\n6\n7\n");
+    assert_eq!(fs::read_to_string(output.join("chunk-002.csv")).unwrap(), "id\n8\n9\n");
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&input)
+        .arg("-o")
+        .arg(temp_dir.path().join("invalid"))
+        .arg("--roll-by-rows")
+        .arg("4")
+        .arg("--name-template")
+        .arg("chunk-{month}.{ext}")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("unknown placeholder {month}"));
+    assert!(!temp_dir.path().join("invalid").exists());
+}
+
+#[test]
+fn test_mixed_delimiters() {
+    let temp_dir = tempdir().unwrap();
+    
+    let inputs = temp_dir.path().join("inputs");
+    let europe = inputs.join("europe");
+    fs::create_dir_all(&europe).unwrap();
+    fs::write(inputs.join("comma.csv"), "id,price\n1,2.5\n").unwrap();
+    fs::write(europe.join("semicolon.csv"), "id;price\n2;3.75\n").unwrap();
+    
+    // Each input's delimiter is sniffed on its own
+    let sniffed = temp_dir.path().join("sniffed.csv");
+    Command::cargo_bin("maw").unwrap()
+        .arg(&inputs)
+        .arg("-o")
+        .arg(&sniffed)
+        .arg("--out-delimiter")
+        .arg(",")
+        .assert()
+        .success();
+    let content = fs::read_to_string(&sniffed).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    assert_eq!(lines, vec!["1,2.5", "2,3.75", "id,price"]);
+    
+    // An override wins over the global delimiter
+    let overridden = temp_dir.path().join("overridden.csv");
+    Command::cargo_bin("maw").unwrap()
+        .arg(&inputs)
+        .arg("-o")
+        .arg(&overridden)
+        .arg("--delimiter")
+        .arg(",")
+        .arg("--delimiter-for")
+        .arg("europe/*.csv=;")
+        .assert()
+        .success();
+    let content = fs::read_to_string(&overridden).unwrap();
+    let mut overridden_lines: Vec<&str> = content.lines().collect();
+    overridden_lines.sort();
+    assert_eq!(overridden_lines, lines);
+}
+
+#[test]
+fn test_max_columns() {
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("report.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&input, "sale date;store name;total paid\n2024-01-01;main street;5\n").unwrap();
+    
+    // Splitting on spaces instead of semicolons makes more columns
+    Command::cargo_bin("maw").unwrap()
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--delimiter")
+        .arg(" ")
+        .arg("--max-columns")
+        .arg("3")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("report.csv"))
+        .stdout(predicate::str::contains("The header has 4 columns, more than --max-columns 3"));
+    assert!(!output.exists());
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--max-columns")
+        .arg("3")
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_drop_null_columns() {
+    let temp_dir = tempdir().unwrap();
+    let first = temp_dir.path().join("first.csv");
+    let second = temp_dir.path().join("second.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&first, "id,name,notes\n1,a,\n2,b,\n").unwrap();
+    fs::write(&second, "id,name\n3,c\n4,\n").unwrap();
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&first)
+        .arg(&second)
+        .arg("-o")
+        .arg(&output)
+        .arg("--drop-null-columns")
+        .arg("0.9")
+        .arg("-v")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Dropping columns that are mostly null: notes"));
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    // name is null in a quarter of the rows, under the threshold
+    assert_eq!(lines, ["1,a", "2,b", "3,c", "4,", "id,name"]);
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg("inspect")
+        .arg(&first)
+        .arg(&second)
+        .arg("--drop-null-columns")
+        .arg("0.9")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Dropped (mostly null):\n  notes: 100.0% null"));
+    
+    // Parquet inputs count their nulls from the file's metadata
+    let csv = temp_dir.path().join("sparse.csv");
+    let parquet = temp_dir.path().join("sparse.parquet");
+    fs::write(&csv, "id,notes\n1,\n2,\n3,\n4,x\n").unwrap();
+    Command::cargo_bin("maw").unwrap()
+        .arg(&csv)
+        .arg("-o")
+        .arg(&parquet)
+        .assert()
+        .success();
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&parquet)
+        .arg("-o")
+        .arg(&output)
+        .arg("--overwrite")
+        .arg("--drop-null-columns")
+        .arg("0.7")
+        .arg("-v")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Left out 1 mostly-null columns: notes"));
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id\n1\n2\n3\n4\n");
+}
+
+#[test]
+fn test_decimal_round_trip() {
+    use arrow2::{array::Int128Array, datatypes::DataType};
+    use maw::parquet_in::ParquetReader;
+    
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("payments.csv");
+    let parquet = temp_dir.path().join("payments.parquet");
+    let output = temp_dir.path().join("output.csv");
+    // The first amount needs more digits than a float has
+    fs::write(&input, "id,amount\n1,9007199254740993.01\n2,0.10\n3,-12.5\n").unwrap();
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&input)
+        .arg("-o")
+        .arg(&parquet)
+        .arg("--infer-decimal")
+        .assert()
+        .success();
+    
+    let mut reader = ParquetReader::new(&parquet, 1000).unwrap();
+    let batch = reader.read_batch().unwrap().unwrap();
+    let amount = batch.arrays()[1].as_any().downcast_ref::<Int128Array>().unwrap();
+    // Full precision, as later batches may need more integer digits
+    assert_eq!(amount.data_type(), &DataType::Decimal(38, 2));
+    assert_eq!(amount.values().as_slice(), [900719925474099301, 10, -1250]);
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg(&parquet)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "id,amount\n1,9007199254740993.01\n2,0.10\n3,-12.50\n"
+    );
+}
+
+#[test]
+fn test_decimal_batches_after_sample() {
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("input.csv");
+    let output = temp_dir.path().join("output.csv");
+    // The sample types `amount` as Decimal(2, 1)
+    fs::write(&input, "id,amount\n1,1.5\n2,2.5\n3,12345.5\n").unwrap();
+
+    let run = || {
+        let mut cmd = Command::cargo_bin("maw").unwrap();
+        cmd.arg(&input)
+            .arg("-o")
+            .arg(&output)
+            .arg("--overwrite")
+            .args(["--infer-decimal", "--infer-rows", "2", "--batch-size", "2"]);
+        cmd
+    };
+    run().assert().success();
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id,amount\n1,1.5\n2,2.5\n3,12345.5\n");
+
+    // More digits after the point than the sample can't be held, so the
+    // run fails rather than writing a null
+    fs::write(&input, "id,amount\n1,1.5\n2,2.5\n3,12345.25\n").unwrap();
+    run()
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Column 'amount' has 12345.25"));
+}
+
+#[test]
+fn test_default_compression_per_format() {
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("input.csv");
+    let parquet = temp_dir.path().join("output.parquet");
+    let csv = temp_dir.path().join("output.csv");
+    fs::write(&input, "id,name\n1,a\n2,b\n").unwrap();
+    
+    for output in [&parquet, &csv] {
+        Command::cargo_bin("maw").unwrap()
+            .arg(&input)
+            .arg("-o")
+            .arg(output)
+            .assert()
+            .success();
+    }
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg("inspect")
+        .arg(&parquet)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("id: Snappy"))
+        .stdout(predicate::str::contains("name: Snappy"));
+    assert_eq!(fs::read_to_string(&csv).unwrap(), "id,name\n1,a\n2,b\n");
+}
+
+#[test]
+fn test_append_to_partitioned_output() {
+    let temp_dir = tempdir().unwrap();
+    let first = temp_dir.path().join("first.csv");
+    let second = temp_dir.path().join("second.csv");
+    let output = temp_dir.path().join("by_region");
+    fs::write(&first, "id,region\n1,eu\n2,us\n").unwrap();
+    let mut content = String::from("id,region\n");
+    for id in 3..12 {
+        content.push_str(&format!("{},{}\n", id, ["eu", "us", "apac"][id % 3]));
+    }
+    fs::write(&second, content).unwrap();
+    
+    let run = |input: &std::path::Path, append: bool| {
+        let mut cmd = Command::cargo_bin("maw").unwrap();
+        cmd.arg(input)
+            .arg("-o")
+            .arg(&output)
+            .arg("--partition-by")
+            .arg("region")
+            .arg("--max-open-files")
+            .arg("1")
+            .arg("--batch-size")
+            .arg("2");
+        if append {
+            cmd.arg("--append");
+        }
+        cmd.assert()
+    };
+    run(&first, false).success();
+    
+    // Without --append the second run would reuse the first one's names
+    run(&second, false)
+        .failure()
+        .stdout(predicate::str::contains("already exists"));
+    
+    run(&second, true).success();
+    let read = |region: &str, part: &str| {
+        fs::read_to_string(output.join(format!("region={}", region)).join(part)).unwrap()
+    };
+    assert_eq!(read("eu", "part-00000.csv"), "id,region\n1,eu\n");
+    assert_eq!(read("us", "part-00000.csv"), "id,region\n2,us\n");
+    assert_eq!(read("eu", "part-00001.csv"), "id,region\n3,eu\n6,eu\n9,eu\n");
+    assert_eq!(read("us", "part-00001.csv"), "id,region\n4,us\n7,us\n10,us\n");
+    // A partition new to this run starts from 0
+    assert_eq!(read("apac", "part-00000.csv"), "id,region\n5,apac\n8,apac\n11,apac\n");
+}
+
+#[test]
+fn test_no_widening() {
+    let temp_dir = tempdir().unwrap();
+    
+    let ints = temp_dir.path().join("ints.csv");
+    let nulls = temp_dir.path().join("nulls.jsonl");
+    let floats = temp_dir.path().join("floats.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&ints, "id,amount\n1,10\n2,20\n").unwrap();
+    fs::write(&nulls, "{\"id\": 3, \"amount\": null}\n").unwrap();
+    fs::write(&floats, "id,amount\n4,2.5\n").unwrap();
+    
+    // A column that is all null in one input isn't widened
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&ints)
+        .arg(&nulls)
+        .arg("-o")
+        .arg(&output)
+        .arg("--reproducible")
+        .arg("--no-widening")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id,amount\n1,10\n2,20\n3,\n");
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&ints)
+        .arg(&floats)
+        .arg("-o")
+        .arg(&output)
+        .arg("--overwrite")
+        .arg("--no-widening")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Column 'amount' is"))
+        .stdout(predicate::str::contains("--no-widening forbids widening it to Float64"));
+}
+
+#[test]
+fn test_row_number() {
+    let temp_dir = tempdir().unwrap();
+    
+    let first = temp_dir.path().join("first.csv");
+    let second = temp_dir.path().join("second.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&first, "id\na\nb\nc\n").unwrap();
+    fs::write(&second, "id\nd\ne\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&first)
+        .arg(&second)
+        .arg("-o")
+        .arg(&output)
+        .arg("--row-number")
+        .assert()
+        .success();
+    
+    // Whichever input is read first, the numbers run on across both
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines = content.lines();
+    assert_eq!(lines.next(), Some("row_number,id"));
+    let rows: Vec<(usize, &str)> = lines
+        .map(|line| {
+            let (number, id) = line.split_once(',').unwrap();
+            (number.parse().unwrap(), id)
+        })
+        .collect();
+    assert_eq!(rows.iter().map(|(number, _)| *number).collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+    let mut ids: Vec<&str> = rows.iter().map(|(_, id)| *id).collect();
+    ids.sort();
+    assert_eq!(ids, ["a", "b", "c", "d", "e"]);
+    
+    // And on across rolled files
+    let rolled = temp_dir.path().join("rolled");
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&first)
+        .arg(&second)
+        .arg("-o")
+        .arg(&rolled)
+        .arg("--reproducible")
+        .arg("--row-number=n")
+        .arg("--row-number-last")
+        .arg("--roll-by-rows")
+        .arg("2")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(rolled.join("part-00001.csv")).unwrap(), "id,n\nc,2\nd,3\n");
+    assert_eq!(fs::read_to_string(rolled.join("part-00002.csv")).unwrap(), "id,n\ne,4\n");
+}
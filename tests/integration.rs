@@ -1,8 +1,20 @@
+use arrow2::{array::Array, chunk::Chunk, datatypes::Schema};
 use assert_cmd::Command;
+use maw::writer_parquet::{ParquetWriter, ParquetWriterConfig};
 use predicates::prelude::*;
-use std::fs;
+use std::{fs, path::Path, sync::Arc};
 use tempfile::tempdir;
 
+/// Writes `chunks` to a Parquet file at `path`, each in its own row group.
+fn write_parquet(path: &Path, schema: Schema, chunks: &[Chunk<Box<dyn Array>>]) {
+    let mut writer = ParquetWriter::new(path, Arc::new(schema), &ParquetWriterConfig::default()).unwrap();
+    for chunk in chunks {
+        writer.write_batch(chunk).unwrap();
+        writer.flush().unwrap();
+    }
+    writer.finish().unwrap();
+}
+
 #[test]
 fn test_csv_concatenation() {
     let temp_dir = tempdir().unwrap();
@@ -18,10 +30,10 @@ fn test_csv_concatenation() {
     // Run maw
     let mut cmd = Command::cargo_bin("maw").unwrap();
     let assert = cmd
-        .arg(csv1.to_string_lossy())
-        .arg(csv2.to_string_lossy())
+        .arg(&csv1)
+        .arg(&csv2)
         .arg("-o")
-        .arg(output.to_string_lossy())
+        .arg(&output)
         .assert();
     
     assert.success();
@@ -39,6 +51,39 @@ fn test_csv_concatenation() {
     assert!(lines.contains(&"10,11,12"));
 }
 
+#[test]
+fn test_concatenation_writes_one_header() {
+    let temp_dir = tempdir().unwrap();
+    let output = temp_dir.path().join("output.csv");
+    
+    // Text columns, so a header read as data would still fit the schema
+    let mut inputs = Vec::new();
+    for (i, rows) in ["ann,oslo\nbob,rome\n", "cat,lima\n", "dan,kiev\neve,baku\nfay,nuuk\n"].iter().enumerate() {
+        let path = temp_dir.path().join(format!("file{}.csv", i));
+        fs::write(&path, format!("name,city\n{}", rows)).unwrap();
+        inputs.push(path);
+    }
+    
+    Command::cargo_bin("maw").unwrap()
+        .args(&inputs)
+        .arg("-o")
+        .arg(&output)
+        .arg("--batch-size")
+        .arg("1")
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.iter().filter(|line| **line == "name,city").count(), 1);
+    assert_eq!(lines.remove(0), "name,city");
+    lines.sort();
+    assert_eq!(
+        lines,
+        ["ann,oslo", "bob,rome", "cat,lima", "dan,kiev", "eve,baku", "fay,nuuk"]
+    );
+}
+
 #[test]
 fn test_directory_processing() {
     let temp_dir = tempdir().unwrap();
@@ -56,9 +101,9 @@ fn test_directory_processing() {
     // Process directory
     let mut cmd = Command::cargo_bin("maw").unwrap();
     let assert = cmd
-        .arg(subdir.to_string_lossy())
+        .arg(&subdir)
         .arg("-o")
-        .arg(output.to_string_lossy())
+        .arg(&output)
         .assert();
     
     assert.success();
@@ -79,7 +124,7 @@ fn test_plan_mode() {
     let mut cmd = Command::cargo_bin("maw").unwrap();
     let assert = cmd
         .arg("--plan")
-        .arg(csv_file.to_string_lossy())
+        .arg(&csv_file)
         .assert();
     
     assert.success().stdout(predicate::str::contains("Plan mode"));
@@ -94,7 +139,7 @@ fn test_dry_run() {
     let mut cmd = Command::cargo_bin("maw").unwrap();
     let assert = cmd
         .arg("--dry-run")
-        .arg(csv_file.to_string_lossy())
+        .arg(&csv_file)
         .assert();
     
     assert.success().stdout(predicate::str::contains("Dry run mode"));
@@ -124,3 +169,701 @@ fn test_version() {
     
     assert.success().stdout(predicate::str::contains("maw"));
 }
+
+#[test]
+fn test_heartbeat_logging() {
+    let temp_dir = tempdir().unwrap();
+    let csv_file = temp_dir.path().join("large.csv");
+    let output = temp_dir.path().join("output.csv");
+    
+    // Several read batches worth of rows
+    let mut data = String::from("id,name,value\n");
+    for i in 0..300_000 {
+        data.push_str(&format!("{},name_{},{}\n", i, i, i * 2));
+    }
+    fs::write(&csv_file, data).unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    let assert = cmd
+        .env("RUST_LOG", "maw=info")
+        .arg(&csv_file)
+        .arg("-o")
+        .arg(&output)
+        .arg("--no-progress")
+        .arg("--heartbeat")
+        .arg("0.01")
+        .assert();
+    
+    assert.success().stdout(predicate::str::contains("Heartbeat:"));
+}
+
+#[test]
+fn test_stdin_json_lines() {
+    let temp_dir = tempdir().unwrap();
+    let output = temp_dir.path().join("output.csv");
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    let assert = cmd
+        .arg("-")
+        .arg("--stdin-format")
+        .arg("json")
+        .arg("-o")
+        .arg(&output)
+        .write_stdin("{\"a\": 1, \"b\": \"x\"}\n{\"a\": 2, \"b\": \"y\"}\n")
+        .assert();
+    
+    assert.success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    assert!(content.contains("1,x"));
+    assert!(content.contains("2,y"));
+}
+
+#[test]
+fn test_stdin_given_twice() {
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    let assert = cmd.arg("-").arg("-").assert();
+    
+    assert.failure();
+}
+
+#[test]
+fn test_resume_parquet_mid_file() {
+    use arrow2::{
+        array::Int64Array,
+        datatypes::{DataType, Field},
+    };
+    use maw::state::{ProcessingState, StateManager};
+    
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("input.parquet");
+    let output = temp_dir.path().join("output.csv");
+    let state_file = temp_dir.path().join("state.json");
+    
+    // Two row groups: [1, 2, 3] and [4, 5]
+    write_parquet(
+        &input,
+        Schema::from(vec![Field::new("id", DataType::Int64, false)]),
+        &[
+            Chunk::new(vec![Int64Array::from_slice([1, 2, 3]).boxed()]),
+            Chunk::new(vec![Int64Array::from_slice([4, 5]).boxed()]),
+        ],
+    );
+    
+    // An earlier run emitted the first row group before being interrupted
+    fs::write(&output, "id\n1\n2\n3\n").unwrap();
+    let input_key = input.to_string_lossy().to_string();
+    let mut state = ProcessingState::new(output.to_string_lossy().to_string(), "csv".to_string());
+    state.add_file(input_key.clone(), "parquet".to_string(), fs::metadata(&input).unwrap().len());
+    state.record_row_group(&input_key, 0, 3);
+    let mut manager = StateManager::new(Some(state_file.to_string_lossy().to_string()));
+    manager.save_state(&state).unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--state")
+        .arg(&state_file)
+        .arg("--resume")
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    assert_eq!(content, "id\n1\n2\n3\n4\n5\n");
+    
+    let loaded = manager.load_state().unwrap().unwrap();
+    assert!(loaded.is_file_processed(&input_key));
+    assert_eq!(loaded.get_file_state(&input_key).unwrap().last_row_group, Some(1));
+}
+
+/// Runs maw over `input` and then a CSV input that fails on its second
+/// row, past the one row sampled for its schema, which stops the run
+/// while rows read from `input` may still be queued for the writer. Resumes from the state without the failing
+/// input and returns the ids written from `input`, sorted.
+fn run_interrupted_and_resume(input: &Path, dir: &Path) -> Vec<i64> {
+    let failing = dir.join("failing.csv");
+    let output = dir.join("output.csv");
+    let state_file = dir.join("state.json");
+    fs::write(&failing, "id\n100\n100,x\n").unwrap();
+    
+    let run = |inputs: &[&Path], resume: bool| {
+        let mut cmd = Command::cargo_bin("maw").unwrap();
+        cmd.args(inputs)
+            .arg("-o")
+            .arg(&output)
+            .arg("--batch-size")
+            .arg("1")
+            .arg("--infer-rows")
+            .arg("1")
+            .arg("--reproducible")
+            .arg("--state")
+            .arg(&state_file);
+        if resume {
+            cmd.arg("--resume");
+        }
+        cmd.assert()
+    };
+    run(&[input, &failing], false).failure();
+    run(&[input], true).success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let mut ids: Vec<i64> = content
+        .lines()
+        .skip(1)
+        .map(|line| line.parse().unwrap())
+        .filter(|id| *id != 100)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn test_resume_csv_after_interrupted_run() {
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("input.csv");
+    let ids: Vec<String> = (1..=50).map(|id| id.to_string()).collect();
+    fs::write(&input, format!("id\n{}\n", ids.join("\n"))).unwrap();
+    
+    // Rows that were queued but not written are read again, from the
+    // offset of the last row written
+    assert_eq!(run_interrupted_and_resume(&input, temp_dir.path()), (1..=50).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_resume_reprocesses_changed_input() {
+    let temp_dir = tempdir().unwrap();
+    let unchanged = temp_dir.path().join("file1.csv");
+    let changed = temp_dir.path().join("file2.csv");
+    let output = temp_dir.path().join("output.csv");
+    let state_file = temp_dir.path().join("state.json");
+    fs::write(&unchanged, "id\n1\n").unwrap();
+    fs::write(&changed, "id\n2\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&unchanged)
+        .arg(&changed)
+        .arg("-o")
+        .arg(&output)
+        .arg("--state")
+        .arg(&state_file)
+        .assert()
+        .success();
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    assert_eq!(lines, vec!["1", "2", "id"]);
+    
+    // Rewrite one input with a later modification time
+    fs::write(&changed, "id\n3\n").unwrap();
+    let file = fs::File::options().write(true).open(&changed).unwrap();
+    file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+        .unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&unchanged)
+        .arg(&changed)
+        .arg("-o")
+        .arg(&output)
+        .arg("--state")
+        .arg(&state_file)
+        .arg("--resume")
+        .assert()
+        .success();
+    
+    // The run starts over, so the changed input's old row is gone
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    assert_eq!(lines, vec!["1", "3", "id"]);
+}
+
+#[test]
+fn test_parquet_with_differing_schemas() {
+    use arrow2::{
+        array::{Int32Array, Int64Array, Utf8Array},
+        datatypes::{DataType, Field},
+    };
+    
+    let temp_dir = tempdir().unwrap();
+    let narrow = temp_dir.path().join("a.parquet");
+    let wide = temp_dir.path().join("b.parquet");
+    let output = temp_dir.path().join("output.csv");
+    
+    // The wide file also widens `id` from Int32 to Int64
+    write_parquet(
+        &narrow,
+        Schema::from(vec![Field::new("id", DataType::Int32, true)]),
+        &[Chunk::new(vec![Int32Array::from_slice([1, 2]).boxed()])],
+    );
+    write_parquet(
+        &wide,
+        Schema::from(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::Utf8, true),
+        ]),
+        &[Chunk::new(vec![
+            Int64Array::from_slice([5_000_000_000]).boxed(),
+            Utf8Array::<i32>::from_slice(["c"]).boxed(),
+        ])],
+    );
+    
+    Command::cargo_bin("maw").unwrap()
+        .arg("inspect")
+        .arg(&narrow)
+        .arg(&wide)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("id: Int64"))
+        .stdout(predicate::str::contains("Column 'id': Int32, Int64 promoted to Int64"));
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&narrow)
+        .arg(&wide)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "id,name");
+    assert!(lines.contains(&"1,"));
+    assert!(lines.contains(&"2,"));
+    assert!(lines.contains(&"5000000000,c"));
+}
+
+#[test]
+fn test_parquet_large_utf8_column() {
+    use arrow2::{
+        array::{Int64Array, Utf8Array},
+        datatypes::{DataType, Field},
+    };
+    
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("large.parquet");
+    let output = temp_dir.path().join("output.csv");
+    
+    write_parquet(
+        &input,
+        Schema::from(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::LargeUtf8, true),
+        ]),
+        &[Chunk::new(vec![
+            Int64Array::from_slice([1, 2]).boxed(),
+            Utf8Array::<i64>::from([Some("a"), None]).boxed(),
+        ])],
+    );
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id,name\n1,a\n2,\n");
+}
+
+#[test]
+fn test_temp_dir() {
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("input.csv");
+    let scratch = temp_dir.path().join("scratch");
+    let output = temp_dir.path().join("output.parquet");
+    fs::write(&input, "id\n1\n2\n").unwrap();
+    
+    // A missing temp dir is rejected before any output is created
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--temp-dir")
+        .arg(&scratch)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("does not exist"));
+    assert!(!output.exists());
+    
+    fs::create_dir(&scratch).unwrap();
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--temp-dir")
+        .arg(&scratch)
+        .assert()
+        .success();
+    
+    // The finished file was moved out of the temp dir into place
+    assert!(output.exists());
+    assert_eq!(fs::read_dir(&scratch).unwrap().count(), 0);
+}
+
+#[test]
+fn test_error_names_file_and_line() {
+    let temp_dir = tempdir().unwrap();
+    let csv1 = temp_dir.path().join("file1.csv");
+    let csv2 = temp_dir.path().join("file2.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a,b\n1,2\n").unwrap();
+    fs::write(&csv2, "a,b\n3,4\n5,6,7\n").unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg(&csv2)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(format!("{}:line 3:", csv2.display())));
+}
+
+#[test]
+fn test_continue_on_error() {
+    let temp_dir = tempdir().unwrap();
+    let csv1 = temp_dir.path().join("file1.csv");
+    let corrupt = temp_dir.path().join("file2.parquet");
+    let csv3 = temp_dir.path().join("file3.csv");
+    let output = temp_dir.path().join("output.csv");
+    fs::write(&csv1, "a\n1\n").unwrap();
+    fs::write(&corrupt, "not a parquet file").unwrap();
+    fs::write(&csv3, "a\n3\n").unwrap();
+    
+    // Fail-fast is the default
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg(&corrupt)
+        .arg(&csv3)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .code(1);
+    assert!(!output.exists());
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg(&corrupt)
+        .arg(&csv3)
+        .arg("-o")
+        .arg(&output)
+        .arg("--continue-on-error")
+        .assert()
+        .code(2)
+        .stdout(predicate::str::contains("1 of 3 inputs failed"))
+        .stdout(predicate::str::contains("file2.parquet"));
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    assert_eq!(lines, vec!["1", "3", "a"]);
+    
+    // A file that fails partway has its first rows written already
+    let ragged = temp_dir.path().join("file2.csv");
+    fs::write(&ragged, "a\n10\n11\n12\n13,14\n").unwrap();
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&csv1)
+        .arg(&ragged)
+        .arg(&csv3)
+        .arg("-o")
+        .arg(&output)
+        .arg("--overwrite")
+        .arg("--infer-rows")
+        .arg("2")
+        .arg("--batch-size")
+        .arg("2")
+        .arg("--continue-on-error")
+        .assert()
+        .code(2)
+        .stdout(predicate::str::contains("file2.csv (its first 2 rows were written)"));
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    assert_eq!(lines, vec!["1", "10", "11", "3", "a"]);
+}
+
+#[test]
+fn test_parquet_list_column_to_csv() {
+    use arrow2::{
+        array::{Int64Array, ListArray, MutableListArray, MutablePrimitiveArray, TryExtend},
+        datatypes::{DataType, Field},
+    };
+    
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("nested.parquet");
+    let output = temp_dir.path().join("output.csv");
+    
+    let mut tags = MutableListArray::<i32, MutablePrimitiveArray<i64>>::new();
+    tags.try_extend([Some(vec![Some(1), Some(2)]), None, Some(vec![None])]).unwrap();
+    let tags: ListArray<i32> = tags.into();
+    let schema = Schema::from(vec![
+        Field::new("id", DataType::Int64, true),
+        Field::new("tags", tags.data_type().clone(), true),
+    ]);
+    write_parquet(
+        &input,
+        schema,
+        &[Chunk::new(vec![Int64Array::from_slice([1, 2, 3]).boxed(), tags.boxed()])],
+    );
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    
+    let mut reader = csv::Reader::from_path(&output).unwrap();
+    let cells: Vec<String> = reader.records().map(|r| r.unwrap()[1].to_string()).collect();
+    assert_eq!(cells, ["[1,2]", "", "[null]"]);
+    let parsed: serde_json::Value = serde_json::from_str(&cells[0]).unwrap();
+    assert_eq!(parsed, serde_json::json!([1, 2]));
+    
+    // The other policies reject or leave out the column
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--nested")
+        .arg("error")
+        .arg("--overwrite")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Column 'tags' is nested"));
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--nested")
+        .arg("drop")
+        .arg("--overwrite")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output).unwrap(), "id\n1\n2\n3\n");
+}
+
+#[test]
+fn test_tz_convert() {
+    use arrow2::{
+        array::Int64Array,
+        datatypes::{DataType, Field, TimeUnit},
+    };
+    
+    let temp_dir = tempdir().unwrap();
+    let zoned = temp_dir.path().join("zoned.parquet");
+    let naive = temp_dir.path().join("naive.parquet");
+    let output = temp_dir.path().join("output.csv");
+    
+    let write = |path: &Path, tz: Option<&str>, seconds: i64| {
+        let data_type = DataType::Timestamp(TimeUnit::Second, tz.map(String::from));
+        write_parquet(
+            path,
+            Schema::from(vec![Field::new("at", data_type.clone(), true)]),
+            &[Chunk::new(vec![Int64Array::from_slice([seconds]).to(data_type).boxed()])],
+        );
+    };
+    // 2023-11-15T00:13:20+02:00
+    write(&zoned, Some("+02:00"), 1_700_000_000);
+    // 2023-11-14T12:00:00, a wall-clock time in New York
+    write(&naive, None, 1_699_963_200);
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&zoned)
+        .arg(&naive)
+        .arg("-o")
+        .arg(&output)
+        .arg("--tz-convert")
+        .arg("UTC")
+        .arg("--assume-tz")
+        .arg("America/New_York")
+        .assert()
+        .success();
+    
+    let content = fs::read_to_string(&output).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        vec!["2023-11-14T17:00:00+00:00", "2023-11-14T22:13:20+00:00", "at"]
+    );
+    
+    // Without --assume-tz the naive column can't be combined with the zoned one
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&zoned)
+        .arg(&naive)
+        .arg("-o")
+        .arg(temp_dir.path().join("mixed.csv"))
+        .arg("--tz-convert")
+        .arg("UTC")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_validate() {
+    use arrow2::{
+        array::Int64Array,
+        datatypes::{DataType, Field},
+    };
+    
+    let temp_dir = tempdir().unwrap();
+    let lake = temp_dir.path().join("lake");
+    let valid = lake.join("valid.parquet");
+    let truncated = lake.join("truncated.parquet");
+    fs::create_dir(&lake).unwrap();
+    
+    write_parquet(
+        &valid,
+        Schema::from(vec![Field::new("id", DataType::Int64, true)]),
+        &[Chunk::new(vec![Int64Array::from_vec((0..1000).collect()).boxed()])],
+    );
+    let bytes = fs::read(&valid).unwrap();
+    fs::write(&truncated, &bytes[..bytes.len() / 2]).unwrap();
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&valid)
+        .arg("--validate")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("OK"))
+        .stdout(predicate::str::contains("1 of 1 Parquet files passed"));
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&lake)
+        .arg("--validate")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(format!("FAILED {}", truncated.display())))
+        .stdout(predicate::str::contains("1 of 2 Parquet files passed"));
+    
+    // Nothing is written
+    assert!(!temp_dir.path().join("output").exists());
+}
+
+#[test]
+fn test_coalesce_small_parquet_files() {
+    use arrow2::{
+        array::Int64Array,
+        datatypes::{DataType, Field},
+    };
+    use maw::parquet_in::ParquetReader;
+    
+    let temp_dir = tempdir().unwrap();
+    let inputs = temp_dir.path().join("inputs");
+    let output = temp_dir.path().join("coalesced");
+    fs::create_dir(&inputs).unwrap();
+    
+    // 20 files of one 10-row row group each
+    for file in 0..20 {
+        write_parquet(
+            &inputs.join(format!("part-{:02}.parquet", file)),
+            Schema::from(vec![Field::new("id", DataType::Int64, true)]),
+            &[Chunk::new(vec![Int64Array::from_vec((file * 10..file * 10 + 10).collect()).boxed()])],
+        );
+    }
+    
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&inputs)
+        .arg("-o")
+        .arg(&output)
+        .arg("--out-format")
+        .arg("parquet")
+        .arg("--coalesce")
+        .arg("45")
+        .assert()
+        .success();
+    
+    // 45 rows is nearer to 50 whole row groups than to 40, so each file
+    // takes five of them
+    let mut names: Vec<String> = fs::read_dir(&output)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["part-00000.parquet", "part-00001.parquet", "part-00002.parquet", "part-00003.parquet"]
+    );
+    let mut ids = Vec::new();
+    for name in &names {
+        let mut reader = ParquetReader::new(output.join(name), 1000).unwrap();
+        let mut rows = 0;
+        while let Some(batch) = reader.read_batch().unwrap() {
+            let values = batch.arrays()[0].as_any().downcast_ref::<Int64Array>().unwrap();
+            ids.extend(values.values().iter().copied());
+            rows += batch.len();
+        }
+        assert_eq!(rows, 50, "{}", name);
+    }
+    ids.sort();
+    assert_eq!(ids, (0..200).collect::<Vec<i64>>());
+
+    // Row groups are still read in batches, and without --out-format the
+    // directory output is CSV
+    let output = temp_dir.path().join("coalesced-csv");
+    let mut cmd = Command::cargo_bin("maw").unwrap();
+    cmd.arg(&inputs)
+        .arg("-o")
+        .arg(&output)
+        .arg("--coalesce")
+        .arg("45")
+        .arg("--batch-size")
+        .arg("4")
+        .assert()
+        .success();
+
+    let mut rows = Vec::new();
+    for entry in fs::read_dir(&output).unwrap() {
+        let path = entry.unwrap().path();
+        assert_eq!(path.extension().unwrap(), "csv");
+        rows.push(fs::read_to_string(&path).unwrap().lines().count() - 1);
+    }
+    assert_eq!(rows.iter().sum::<usize>(), 200);
+    // Files end between batches of four, which don't line up with the
+    // 10-row row groups
+    assert!(rows.iter().any(|rows| rows % 10 != 0), "{:?}", rows);
+}
+
+#[test]
+fn test_preserve_non_null() {
+    use arrow2::{
+        array::Int64Array,
+        datatypes::{DataType, Field},
+    };
+    use maw::parquet_in::ParquetReader;
+    
+    let temp_dir = tempdir().unwrap();
+    let write = |name: &str, nullable: bool| {
+        let path = temp_dir.path().join(name);
+        write_parquet(
+            &path,
+            Schema::from(vec![Field::new("id", DataType::Int64, nullable)]),
+            &[Chunk::new(vec![Int64Array::from_slice([1, 2]).boxed()])],
+        );
+        path
+    };
+    let required = write("required.parquet", false);
+    let also_required = write("also_required.parquet", false);
+    let optional = write("optional.parquet", true);
+    
+    let concat_nullable = |inputs: &[&Path], name: &str| {
+        let output = temp_dir.path().join(name);
+        let mut cmd = Command::cargo_bin("maw").unwrap();
+        cmd.args(inputs)
+            .arg("-o")
+            .arg(&output)
+            .arg("--preserve-non-null")
+            .assert()
+            .success();
+        ParquetReader::new(&output, 1000).unwrap().get_schema().fields[0].is_nullable
+    };
+    
+    // Non-nullable in every input stays non-nullable
+    assert!(!concat_nullable(&[&required, &also_required], "both_required.parquet"));
+    // Nullable in any input makes it nullable
+    assert!(concat_nullable(&[&required, &optional], "mixed.parquet"));
+}